@@ -1,11 +1,12 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use font_loader::system_fonts;
 use image::{ImageBuffer, Rgba};
 use imageproc::drawing;
 use log::error;
 use sensor_core::{
-    get_cache_dir, hex_to_rgba, text_renderer, ElementType, SensorType, SensorValue,
-    SensorValueModifier, TextAlign, TextConfig,
+    get_cache_dir, graph_renderer, hex_to_rgba, render_lcd_image_with_font_cache, text_renderer,
+    try_render_lcd_image, DisplayConfig, ElementConfig, ElementType, FontCache, GraphConfig,
+    RenderOptions, SensorType, SensorValue, SensorValueModifier, TextAlign, TextConfig,
 };
 use std::collections::HashMap;
 use std::fs;
@@ -27,6 +28,7 @@ fn criterion_benchmark(criterion: &mut Criterion) {
         height: 100,
         format: "{value} {unit}".to_string(),
         alignment: TextAlign::Left,
+        ..Default::default()
     };
     let x = 0;
     let y = 0;
@@ -36,6 +38,7 @@ fn criterion_benchmark(criterion: &mut Criterion) {
         unit: "test".to_string(),
         label: "".to_string(),
         sensor_type: SensorType::Text,
+        ..Default::default()
     };
 
     // Get font cache dir
@@ -95,6 +98,189 @@ fn criterion_benchmark(criterion: &mut Criterion) {
             )
         })
     });
+
+    let glyph_cache = text_renderer::GlyphCache::new();
+    criterion.bench_function("draw text neo glyph cache", |bencher| {
+        bencher.iter(|| {
+            text_renderer::render_with_glyph_cache(
+                black_box(base_image.width()),
+                black_box(base_image.height()),
+                black_box(&text_config),
+                black_box(&[vec![sensor_value.clone()]]),
+                black_box(&font),
+                black_box(&glyph_cache),
+            )
+        })
+    });
+
+    criterion.bench_function("draw text neo large display", |bencher| {
+        bencher.iter(|| {
+            text_renderer::render(
+                black_box(1920),
+                black_box(480),
+                black_box(&text_config),
+                black_box(&[vec![sensor_value.clone()]]),
+                black_box(&font),
+            )
+        })
+    });
+
+    // Render a text aggregate and a graph over a very long history, with and without cap
+    let display_config = DisplayConfig {
+        resolution_width: 320,
+        resolution_height: 240,
+        elements: vec![
+            ElementConfig {
+                id: "text".to_string(),
+                element_type: ElementType::Text,
+                text_config: Some(TextConfig {
+                    format: "{value-avg} {value-min} {value-max}".to_string(),
+                    ..text_config.clone()
+                }),
+                ..Default::default()
+            },
+            ElementConfig {
+                id: "graph".to_string(),
+                element_type: ElementType::Graph,
+                y: 100,
+                graph_config: Some(GraphConfig {
+                    sensor_id: "number".to_string(),
+                    width: 320,
+                    height: 100,
+                    graph_color: "#FFFFFFFF".to_string(),
+                    graph_stroke_width: 1,
+                    background_color: "#00000000".to_string(),
+                    border_color: "#00000000".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    let long_history: Vec<Vec<SensorValue>> = (0..100_000)
+        .map(|index| {
+            vec![SensorValue {
+                id: "number".to_string(),
+                value: (index % 100).to_string(),
+                sensor_type: SensorType::Number,
+                ..Default::default()
+            }]
+        })
+        .collect();
+    let fonts_data = font_data_mutex.lock().unwrap().clone();
+
+    criterion.bench_function("render long history", |bencher| {
+        bencher.iter(|| {
+            try_render_lcd_image(
+                black_box(display_config.clone()),
+                black_box(&long_history),
+                black_box(&fonts_data),
+                black_box(&RenderOptions::default()),
+            )
+        })
+    });
+
+    let capped_options = RenderOptions {
+        max_history: Some(320),
+        ..Default::default()
+    };
+    criterion.bench_function("render long history capped", |bencher| {
+        bencher.iter(|| {
+            try_render_lcd_image(
+                black_box(display_config.clone()),
+                black_box(&long_history),
+                black_box(&fonts_data),
+                black_box(&capped_options),
+            )
+        })
+    });
+
+    // Render a graph with an anti-aliased border, the static background is cached after the first frame
+    let bordered_graph_config = GraphConfig {
+        sensor_values: (0..320).map(|index| (index % 100) as f64).collect(),
+        border_color: "#FFFFFFFF".to_string(),
+        border_width: 2.5,
+        antialias: true,
+        ..display_config.elements[1].graph_config.clone().unwrap()
+    };
+    criterion.bench_function("render graph cached background", |bencher| {
+        bencher.iter(|| graph_renderer::render(black_box(&bordered_graph_config)))
+    });
+
+    // Render the same graph with a different background on each iteration, as a baseline
+    // The 256 backgrounds exceed the capacity of the background cache, so none is taken from it
+    let mut background_index = 0u32;
+    criterion.bench_function("render graph uncached background", |bencher| {
+        bencher.iter_batched(
+            || {
+                background_index = (background_index + 1) % 256;
+                GraphConfig {
+                    background_color: format!("#0000{:02X}", background_index),
+                    ..bordered_graph_config.clone()
+                }
+            },
+            |graph_config| graph_renderer::render(black_box(&graph_config)),
+            BatchSize::SmallInput,
+        )
+    });
+
+    // Render at full and at half internal resolution
+    let half_scale_options = RenderOptions {
+        max_history: Some(320),
+        render_scale: 0.5,
+        ..Default::default()
+    };
+    criterion.bench_function("render half scale", |bencher| {
+        bencher.iter(|| {
+            try_render_lcd_image(
+                black_box(display_config.clone()),
+                black_box(&long_history),
+                black_box(&fonts_data),
+                black_box(&half_scale_options),
+            )
+        })
+    });
+
+    // Render many text elements, parsing the fonts on every frame or once using the font cache
+    let text_display_config = DisplayConfig {
+        resolution_width: 320,
+        resolution_height: 240,
+        elements: (0..20)
+            .map(|index| ElementConfig {
+                id: format!("text-{}", index),
+                element_type: ElementType::Text,
+                y: index * 12,
+                text_config: Some(text_config.clone()),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let history = [vec![sensor_value.clone()]];
+    criterion.bench_function("render text uncached fonts", |bencher| {
+        bencher.iter(|| {
+            try_render_lcd_image(
+                black_box(text_display_config.clone()),
+                black_box(&history),
+                black_box(&fonts_data),
+                black_box(&RenderOptions::default()),
+            )
+        })
+    });
+
+    let font_cache = FontCache::new();
+    criterion.bench_function("render text cached fonts", |bencher| {
+        bencher.iter(|| {
+            render_lcd_image_with_font_cache(
+                black_box(text_display_config.clone()),
+                black_box(&history),
+                black_box(&fonts_data),
+                black_box(&RenderOptions::default()),
+                black_box(&font_cache),
+            )
+        })
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -109,7 +295,7 @@ fn draw_text_fs(
     sensor_value: Option<&SensorValue>,
 ) {
     let font_scale = rusttype::Scale::uniform(text_config.font_size as f32);
-    let font_color: Rgba<u8> = hex_to_rgba(&text_config.font_color);
+    let font_color: Rgba<u8> = hex_to_rgba(&text_config.font_color).unwrap();
     let text_format = text_config.format;
 
     let (value, unit): (&str, &str) = match sensor_value {
@@ -144,7 +330,7 @@ fn draw_text_memory(
     sensor_value: Option<&SensorValue>,
 ) {
     let font_scale = rusttype::Scale::uniform(text_config.font_size as f32);
-    let font_color: Rgba<u8> = hex_to_rgba(&text_config.font_color);
+    let font_color: Rgba<u8> = hex_to_rgba(&text_config.font_color).unwrap();
     let text_format = text_config.format;
 
     let (value, unit): (&str, &str) = match sensor_value {
@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use image::RgbaImage;
+use rusttype::Font;
+
+use crate::{text_renderer, ClockConfig, RenderError, TextConfig};
+
+/// Format of the time, if the clock has no format configured
+const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+/// Renders the clock showing the given time
+/// The formatted time is laid out like a text element, so alignment and fonts behave the same.
+pub fn render(
+    image_width: u32,
+    image_height: u32,
+    clock_config: &ClockConfig,
+    time: DateTime<Utc>,
+    font: &Font,
+) -> Result<RgbaImage, RenderError> {
+    let text_config = TextConfig {
+        format: format_time(clock_config, time)?,
+        font_family: clock_config.font_family.clone(),
+        font_size: clock_config.font_size,
+        font_color: clock_config.font_color.clone(),
+        width: clock_config.width,
+        height: clock_config.height,
+        alignment: clock_config.alignment.clone(),
+        ..Default::default()
+    };
+
+    Ok(text_renderer::render(
+        image_width,
+        image_height,
+        &text_config,
+        &[],
+        font,
+    ))
+}
+
+/// Formats the time with the strftime format of the clock, in the time zone of the clock
+/// Without a time zone the local time zone is used.
+/// Returns an error if the time zone is unknown or the format has invalid specifiers.
+pub fn format_time(clock_config: &ClockConfig, time: DateTime<Utc>) -> Result<String, RenderError> {
+    let format = if clock_config.format.is_empty() {
+        DEFAULT_TIME_FORMAT
+    } else {
+        clock_config.format.as_str()
+    };
+
+    // Invalid format specifiers are reported as formatting error, instead of panicking
+    let mut text = String::new();
+    let result = match &clock_config.timezone {
+        Some(timezone) => {
+            let timezone = timezone
+                .parse::<Tz>()
+                .map_err(|_| RenderError::InvalidTimezone {
+                    timezone: timezone.clone(),
+                })?;
+            write!(text, "{}", time.with_timezone(&timezone).format(format))
+        }
+        None => write!(text, "{}", time.with_timezone(&Local).format(format)),
+    };
+    result.map_err(|_| RenderError::InvalidTimeFormat {
+        format: format.to_string(),
+    })?;
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{has_visible_pixels, load_font};
+    use chrono::TimeZone;
+
+    fn fixed_time() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()
+    }
+
+    #[test]
+    fn test_fixed_time_is_formatted_in_the_time_zone() {
+        let clock_config = |format: &str, timezone: Option<&str>| ClockConfig {
+            format: format.to_string(),
+            timezone: timezone.map(str::to_string),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format_time(&clock_config("", Some("Europe/Berlin")), fixed_time()),
+            Ok("04:04".to_string())
+        );
+        assert_eq!(
+            format_time(
+                &clock_config("%Y-%m-%d %H:%M:%S", Some("UTC")),
+                fixed_time()
+            ),
+            Ok("2024-01-02 03:04:05".to_string())
+        );
+        // Without a time zone the local time is shown
+        assert_eq!(
+            format_time(&clock_config("%H:%M", None), fixed_time()),
+            Ok(fixed_time()
+                .with_timezone(&Local)
+                .format("%H:%M")
+                .to_string())
+        );
+        assert_eq!(
+            format_time(&clock_config("", Some("Mars/Olympus")), fixed_time()),
+            Err(RenderError::InvalidTimezone {
+                timezone: "Mars/Olympus".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_clock_renders_like_a_text_of_the_time() {
+        let font = load_font();
+        let clock_config = ClockConfig {
+            timezone: Some("UTC".to_string()),
+            font_size: 20,
+            font_color: "#FFFFFF".to_string(),
+            width: 100,
+            height: 30,
+            ..Default::default()
+        };
+        let text_config = TextConfig {
+            format: "03:04".to_string(),
+            font_size: 20,
+            font_color: "#FFFFFF".to_string(),
+            width: 100,
+            height: 30,
+            ..Default::default()
+        };
+
+        let image = render(100, 30, &clock_config, fixed_time(), &font).unwrap();
+
+        assert!(has_visible_pixels(&image));
+        assert!(image == text_renderer::render(100, 30, &text_config, &[], &font));
+    }
+}
@@ -1,30 +1,121 @@
+use std::collections::HashMap;
 use std::ffi::OsString;
+use std::io::{BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
 use std::{cmp, fs};
 
+use image::{Rgba, RgbaImage};
 use log::error;
 
-use crate::{ConditionalImageConfig, ElementType, SensorType};
+use crate::{
+    ConditionalImageConfig, ConditionalImageRequest, ElementType, ImageAtlasConfig, SensorType,
+    SensorValue,
+};
 
 /// Get the image data based on the current sensor value and type
+/// If an asset root is given, the images are selected from the configured images path relative to it.
+/// Otherwise the prepared images are selected from the cache dir.
 pub fn render(
     element_id: &str,
     sensor_type: &SensorType,
     conditional_image_config: &ConditionalImageConfig,
+    asset_root: Option<&Path>,
 ) -> Option<Vec<u8>> {
-    let cache_image_folder = crate::get_cache_dir(element_id, &ElementType::ConditionalImage);
-    let cache_image_folder = cache_image_folder.to_str().unwrap();
+    render_with_distance(
+        element_id,
+        sensor_type,
+        conditional_image_config,
+        asset_root,
+    )
+    .map(|(image_data, _)| image_data)
+}
+
+/// Get the image data based on the current sensor value and type, like `render`
+/// Additionally returns the distance of the selected image to the sensor value, as match quality.
+/// For text sensors this is the Levenshtein distance of the image name,
+/// for number sensors the distance of the image number to the transformed sensor value.
+/// An exact match has a distance of zero.
+pub fn render_with_distance(
+    element_id: &str,
+    sensor_type: &SensorType,
+    conditional_image_config: &ConditionalImageConfig,
+    asset_root: Option<&Path>,
+) -> Option<(Vec<u8>, f64)> {
+    let cache_image_folder = get_images_folder(element_id, conditional_image_config, asset_root);
+
+    if let Some(atlas_config) = &conditional_image_config.atlas {
+        let (cell_image, distance) = render_atlas(
+            element_id,
+            sensor_type,
+            conditional_image_config,
+            atlas_config,
+            &cache_image_folder,
+        )?;
+        return encode_png(&cell_image).map(|image_data| (image_data, distance));
+    }
 
     match sensor_type {
-        SensorType::Text => render_text_sensor(conditional_image_config, cache_image_folder),
-        SensorType::Number => render_number_sensor(conditional_image_config, cache_image_folder),
+        SensorType::Text => render_text_sensor(conditional_image_config, &cache_image_folder),
+        SensorType::Number => render_number_sensor(conditional_image_config, &cache_image_folder),
+    }
+}
+
+/// Get the decoded image based on the current sensor value and type, like `render`
+/// Atlas cells are cropped from the cached atlas image and returned without encoding them.
+/// Returns None if no image matches the sensor value, or an error if the image can not be decoded.
+pub fn render_image(
+    element_id: &str,
+    sensor_type: &SensorType,
+    conditional_image_config: &ConditionalImageConfig,
+    asset_root: Option<&Path>,
+) -> Result<Option<RgbaImage>, image::ImageError> {
+    if let Some(atlas_config) = &conditional_image_config.atlas {
+        let cache_image_folder =
+            get_images_folder(element_id, conditional_image_config, asset_root);
+        return Ok(render_atlas(
+            element_id,
+            sensor_type,
+            conditional_image_config,
+            atlas_config,
+            &cache_image_folder,
+        )
+        .map(|(cell_image, _)| cell_image));
+    }
+
+    match render(
+        element_id,
+        sensor_type,
+        conditional_image_config,
+        asset_root,
+    ) {
+        Some(image_data) => Ok(Some(image::load_from_memory(&image_data)?.to_rgba8())),
+        None => Ok(None),
     }
 }
 
+/// Returns the folder the images of the element are selected from
+/// If an asset root is given, this is the configured images path relative to it, otherwise the cache dir.
+fn get_images_folder(
+    element_id: &str,
+    conditional_image_config: &ConditionalImageConfig,
+    asset_root: Option<&Path>,
+) -> String {
+    let images_folder = match asset_root {
+        Some(asset_root) => {
+            crate::resolve_asset_path(asset_root, &conditional_image_config.images_path)
+        }
+        None => crate::get_cache_dir(element_id, &ElementType::ConditionalImage),
+    };
+    images_folder.to_string_lossy().into_owned()
+}
+
 /// Renders a given text sensor to an conditional image
 fn render_text_sensor(
     conditional_image_config: &ConditionalImageConfig,
     cache_images_folder: &str,
-) -> Option<Vec<u8>> {
+) -> Option<(Vec<u8>, f64)> {
     // Select image based on sensor value
     let image_path = get_image_based_on_text_sensor_value(
         &conditional_image_config.sensor_value,
@@ -34,16 +125,21 @@ fn render_text_sensor(
     // Read image to memory
     // We heavily assume that this is already png encoded to skip the expensive png decoding
     // So just read the image here
-    image_path.and_then(|image_path| fs::read(image_path).ok())
+    image_path.and_then(|(image_path, distance)| {
+        fs::read(image_path)
+            .ok()
+            .map(|image_data| (image_data, distance))
+    })
 }
 
 /// Renders a given number sensor to an conditional image
 fn render_number_sensor(
     conditional_image_config: &ConditionalImageConfig,
     cache_images_folder: &str,
-) -> Option<Vec<u8>> {
+) -> Option<(Vec<u8>, f64)> {
     // Select image based on sensor value
-    let sensor_value: f64 = conditional_image_config.sensor_value.parse().unwrap();
+    // A number sensor may still emit junk, which results in no image
+    let sensor_value = parse_number_sensor_value(&conditional_image_config.sensor_value)?;
     let image_path = get_image_based_on_numeric_sensor_value(
         conditional_image_config.min_sensor_value,
         conditional_image_config.max_sensor_value,
@@ -54,23 +150,354 @@ fn render_number_sensor(
     // Read image to memory
     // We heavily assume that this is already png encoded to skip the expensive png decoding
     // So just read the image here
-    image_path.and_then(|image_path| fs::read(image_path).ok())
+    image_path.and_then(|(image_path, distance)| {
+        fs::read(image_path)
+            .ok()
+            .map(|image_data| (image_data, distance))
+    })
+}
+
+/// Renders the atlas cell that fits the sensor value best to an conditional image
+/// The cell is cropped from the atlas image, which is decoded once per element, see `get_atlas_image`
+fn render_atlas(
+    element_id: &str,
+    sensor_type: &SensorType,
+    conditional_image_config: &ConditionalImageConfig,
+    atlas_config: &ImageAtlasConfig,
+    cache_images_folder: &str,
+) -> Option<(RgbaImage, f64)> {
+    let sensor_value = &conditional_image_config.sensor_value;
+
+    // Select the cell based on the sensor value, just like the single images
+    let cell = match sensor_type {
+        SensorType::Text => {
+            let cells = atlas_config
+                .cells
+                .iter()
+                .map(|cell| (cell.sensor_value.clone(), cell))
+                .collect();
+            select_by_text_distance(sensor_value, cells)
+        }
+        SensorType::Number => {
+            let mut cells: Vec<_> = atlas_config
+                .cells
+                .iter()
+                .flat_map(|cell| {
+                    let number = cell.sensor_value.replace(',', ".").parse::<f32>();
+                    number.ok().map(|number| (number, cell))
+                })
+                .collect();
+            cells.sort_by(|a, b| a.0.total_cmp(&b.0));
+            select_by_numeric_value(
+                conditional_image_config.min_sensor_value,
+                conditional_image_config.max_sensor_value,
+                parse_number_sensor_value(sensor_value)?,
+                cells,
+            )
+        }
+    };
+    let (cell, distance) = match cell {
+        Some(cell) => cell,
+        None => {
+            error!("No atlas cell found for sensor value {}", sensor_value);
+            return None;
+        }
+    };
+
+    // Crop the cell from the atlas image
+    let atlas_path = Path::new(cache_images_folder).join(&atlas_config.image_name);
+    let atlas_image = get_atlas_image(element_id, &atlas_path)?;
+    let cell_image = image::imageops::crop_imm(
+        atlas_image.as_ref(),
+        cell.x,
+        cell.y,
+        cell.width,
+        cell.height,
+    )
+    .to_image();
+
+    Some((cell_image, distance))
+}
+
+/// Maximum number of decoded atlas images in the atlas cache
+const MAX_CACHED_ATLASES: usize = 16;
+
+/// A decoded atlas image, with the modification time of its file when it was decoded
+struct CachedAtlas {
+    modified: Option<SystemTime>,
+    image: Arc<RgbaImage>,
+}
+
+/// Cache of the decoded atlas images, keyed by element id and atlas path
+static ATLAS_CACHE: OnceLock<Mutex<HashMap<(String, PathBuf), CachedAtlas>>> = OnceLock::new();
+
+#[cfg(test)]
+thread_local! {
+    /// Counts how often an atlas image was decoded, to verify that the atlas cache is used
+    static ATLAS_DECODE_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Returns the decoded atlas image of the element
+/// The atlas is decoded on first use and again if its file was modified since.
+fn get_atlas_image(element_id: &str, atlas_path: &Path) -> Option<Arc<RgbaImage>> {
+    let modified = fs::metadata(atlas_path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let key = (element_id.to_string(), atlas_path.to_path_buf());
+    let mut cache = ATLAS_CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if let Some(atlas) = cache.get(&key).filter(|atlas| atlas.modified == modified) {
+        return Some(atlas.image.clone());
+    }
+
+    #[cfg(test)]
+    ATLAS_DECODE_COUNT.with(|count| count.set(count.get() + 1));
+
+    let atlas_image = match image::open(atlas_path) {
+        Ok(atlas_image) => Arc::new(atlas_image.to_rgba8()),
+        Err(err) => {
+            error!("Failed to load atlas image {:?}: {}", atlas_path, err);
+            return None;
+        }
+    };
+    if cache.len() >= MAX_CACHED_ATLASES && !cache.contains_key(&key) {
+        cache.clear();
+    }
+    cache.insert(
+        key,
+        CachedAtlas {
+            modified,
+            image: atlas_image.clone(),
+        },
+    );
+    Some(atlas_image)
+}
+
+/// Encodes the image to png and returns the encoded bytes
+fn encode_png(image: &RgbaImage) -> Option<Vec<u8>> {
+    let mut writer = BufWriter::new(Cursor::new(Vec::new()));
+    image
+        .write_to(&mut writer, image::ImageOutputFormat::Png)
+        .ok()?;
+    writer.into_inner().ok().map(Cursor::into_inner)
+}
+
+/// Returns the sensor value to request the image of an on-demand conditional image element for
+/// Text values are requested as they are. Numeric values are moved into the min and max sensor value
+/// and rounded to the nearest of the configured image count, so all values selecting the same image
+/// are requested, and cached, as one value.
+pub fn get_on_demand_sensor_value(
+    conditional_image_config: &ConditionalImageConfig,
+    sensor_value: &SensorValue,
+) -> String {
+    let number = match sensor_value.sensor_type {
+        SensorType::Number => sensor_value.value.parse::<f64>().ok(),
+        SensorType::Text => None,
+    };
+    let Some(number) = number.filter(|number| number.is_finite()) else {
+        return sensor_value.value.clone();
+    };
+
+    let sensor_min = conditional_image_config.min_sensor_value;
+    let sensor_max = conditional_image_config.max_sensor_value;
+    let number = number.clamp(sensor_min.min(sensor_max), sensor_min.max(sensor_max));
+    let image_count = conditional_image_config.on_demand_image_count;
+    let number = if image_count == 0 {
+        number
+    } else if image_count == 1 || sensor_min == sensor_max {
+        sensor_min
+    } else {
+        let steps = (image_count - 1) as f64;
+        let step = ((number - sensor_min) / (sensor_max - sensor_min) * steps).round();
+        sensor_min + step / steps * (sensor_max - sensor_min)
+    };
+    number.to_string()
+}
+
+/// Fetches single conditional images on demand, e.g. by sending the request to the host
+/// and awaiting its `ConditionalImageResponse`
+pub trait ConditionalImageFetcher {
+    /// Returns the image data of the element for the sensor value, None if there is no image
+    fn fetch(&self, request: &ConditionalImageRequest) -> Option<Vec<u8>>;
+}
+
+/// Maximum number of images in the on-demand image cache
+const MAX_ON_DEMAND_IMAGES: usize = 256;
+
+/// Caches the conditional images fetched on demand by element id and sensor value,
+/// so each image is only fetched once. Images that can not be fetched are requested again.
+/// Once the cache is full, it is cleared and populated again.
+#[derive(Default)]
+pub struct OnDemandImageCache {
+    images: Mutex<HashMap<(String, String), Vec<u8>>>,
+}
+
+impl OnDemandImageCache {
+    /// Creates a new, empty on-demand image cache
+    pub fn new() -> Self {
+        OnDemandImageCache::default()
+    }
+
+    /// Returns the number of cached images
+    pub fn len(&self) -> usize {
+        self.images
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .len()
+    }
+
+    /// Returns true if no images are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if the image of the element for the sensor value is cached
+    pub fn contains(&self, element_id: &str, sensor_value: &str) -> bool {
+        self.images
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .contains_key(&(element_id.to_string(), sensor_value.to_string()))
+    }
+
+    /// Removes all images from the cache
+    pub fn clear(&self) {
+        self.images
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+    }
+
+    /// Returns the requested image, fetching and caching it on first use
+    pub fn get_or_fetch(
+        &self,
+        request: &ConditionalImageRequest,
+        fetcher: &dyn ConditionalImageFetcher,
+    ) -> Option<Vec<u8>> {
+        let key = (request.element_id.clone(), request.sensor_value.clone());
+        if let Some(image_data) = self
+            .images
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(&key)
+        {
+            return Some(image_data.clone());
+        }
+
+        // The lock is not held while fetching, as fetching may wait for the host
+        let image_data = fetcher.fetch(request)?;
+
+        let mut images = self.images.lock().unwrap_or_else(|err| err.into_inner());
+        if images.len() >= MAX_ON_DEMAND_IMAGES {
+            images.clear();
+        }
+        images.insert(key, image_data.clone());
+        Some(image_data)
+    }
 }
 
-/// Selects the image that fits the sensor value best
+/// Crossfades between the previously and the currently selected image of a conditional image element,
+/// so the image animates to a new selection over time instead of snapping to it
+pub struct ImageTransition {
+    duration_ms: u64,
+    previous_image: Option<RgbaImage>,
+    current_image_data: Vec<u8>,
+    current_image: Option<RgbaImage>,
+    changed_at_ms: u64,
+}
+
+impl ImageTransition {
+    /// Creates a new transition, animating each change of the selection over the given duration
+    pub fn new(duration_ms: u64) -> Self {
+        ImageTransition {
+            duration_ms,
+            previous_image: None,
+            current_image_data: vec![],
+            current_image: None,
+            changed_at_ms: 0,
+        }
+    }
+
+    /// Updates the transition with the currently selected image data at the given timestamp
+    /// If the selection changed, a new transition from the previous selection is started
+    /// Returns the blended image of the previous and the current selection
+    pub fn update(&mut self, image_data: &[u8], timestamp_ms: u64) -> Result<RgbaImage, String> {
+        if self.current_image.is_none() || self.current_image_data != image_data {
+            let image = image::load_from_memory(image_data)
+                .map_err(|err| err.to_string())?
+                .to_rgba8();
+            self.previous_image = self.current_image.replace(image);
+            self.current_image_data = image_data.to_vec();
+            self.changed_at_ms = timestamp_ms;
+        }
+
+        let current_image = self.current_image.as_ref().unwrap();
+        let weight = transition_weight(
+            timestamp_ms.saturating_sub(self.changed_at_ms),
+            self.duration_ms,
+        );
+        match &self.previous_image {
+            Some(previous_image) if weight < 1.0 => {
+                Ok(crossfade(previous_image, current_image, weight))
+            }
+            _ => Ok(current_image.clone()),
+        }
+    }
+}
+
+/// Returns the weight of the new image of a transition, progressing from 0 to 1 over the duration
+/// The progress is eased in and out, so the transition starts and ends smoothly
+pub fn transition_weight(elapsed_ms: u64, duration_ms: u64) -> f32 {
+    if duration_ms == 0 {
+        return 1.0;
+    }
+    let progress = (elapsed_ms as f32 / duration_ms as f32).clamp(0.0, 1.0);
+    progress * progress * (3.0 - 2.0 * progress)
+}
+
+/// Blends the previous and the current image, a weight of 0 shows the previous, 1 the current image
+/// The result has the size of the current image, missing pixels of the previous image are transparent
+pub fn crossfade(previous_image: &RgbaImage, current_image: &RgbaImage, weight: f32) -> RgbaImage {
+    let weight = weight.clamp(0.0, 1.0);
+    RgbaImage::from_fn(current_image.width(), current_image.height(), |x, y| {
+        let previous_pixel = if x < previous_image.width() && y < previous_image.height() {
+            *previous_image.get_pixel(x, y)
+        } else {
+            Rgba([0, 0, 0, 0])
+        };
+        let current_pixel = current_image.get_pixel(x, y);
+        Rgba(std::array::from_fn(|channel| {
+            (previous_pixel[channel] as f32 * (1.0 - weight)
+                + current_pixel[channel] as f32 * weight)
+                .round() as u8
+        }))
+    })
+}
+
+/// Parses the value of a number sensor
+/// Returns None and logs an error if the value is not a number
+fn parse_number_sensor_value(sensor_value: &str) -> Option<f64> {
+    match sensor_value.parse() {
+        Ok(sensor_value) => Some(sensor_value),
+        Err(_) => {
+            error!("Sensor value '{}' is not a number", sensor_value);
+            None
+        }
+    }
+}
+
+/// Selects the image that fits the sensor value best, with its distance to the sensor value
 fn get_image_based_on_text_sensor_value(
     sensor_value: &str,
     images_folder_path: &str,
-) -> Option<String> {
-    let images: Vec<(String, String)> = fs::read_dir(images_folder_path)
-        .unwrap()
-        .flatten()
-        .filter(|dir_entry| dir_entry.file_type().unwrap().is_file())
-        .filter(crate::is_image)
+) -> Option<(String, f64)> {
+    let images: Vec<(String, String)> = read_image_files(images_folder_path)
         .map(|dir_entry| {
             (
                 remove_file_extension(dir_entry.file_name()),
-                dir_entry.path().to_str().unwrap().to_string(),
+                dir_entry.path().to_string_lossy().to_string(),
             )
         })
         .collect();
@@ -81,21 +508,29 @@ fn get_image_based_on_text_sensor_value(
         return None;
     }
 
-    // Select the image based on the lowest levehnstein distance to the sensor value
-    let mut best_image_path = None;
+    select_by_text_distance(sensor_value, images)
+}
+
+/// Selects the candidate based on the lowest levehnstein distance of its name to the sensor value
+/// Returns the candidate with its distance
+fn select_by_text_distance<T>(
+    sensor_value: &str,
+    candidates: Vec<(String, T)>,
+) -> Option<(T, f64)> {
+    let mut best_candidate = None;
     let mut min_distance = usize::MAX;
-    for (image_name, image_path) in images {
-        let distance = levenshtein_distance(sensor_value, &image_name);
+    for (name, candidate) in candidates {
+        let distance = levenshtein_distance(sensor_value, &name);
         if distance < min_distance {
             min_distance = distance;
-            best_image_path = Some(image_path);
+            best_candidate = Some(candidate);
         }
     }
 
-    best_image_path
+    best_candidate.map(|candidate| (candidate, min_distance as f64))
 }
 
-/// Returns the image path that fits the sensor value best
+/// Returns the image path that fits the sensor value best, with its distance
 /// The sensor value is transformed to the image number coordination system
 /// The image number coordination system is the range of all image numbers
 /// # Arguments
@@ -108,7 +543,7 @@ fn get_image_based_on_numeric_sensor_value(
     sensor_max: f64,
     sensor_value: f64,
     images_folder: &str,
-) -> Option<String> {
+) -> Option<(String, f64)> {
     let numbered_images = get_image_numbers_sorted(images_folder);
 
     // If there is none
@@ -117,40 +552,78 @@ fn get_image_based_on_numeric_sensor_value(
         return None;
     }
 
-    // get min and max of images
-    let image_number_min = numbered_images.first().unwrap().0 as f64;
-    let image_number_max = numbered_images.last().unwrap().0 as f64;
+    select_by_numeric_value(sensor_min, sensor_max, sensor_value, numbered_images)
+}
+
+/// Returns the candidate that fits the sensor value best, with its distance
+/// The sensor value is transformed to the number coordination system of the candidates
+/// # Arguments
+/// * `sensor_min` - The minimum value of the sensor
+/// * `sensor_max` - The maximum value of the sensor
+/// * `sensor_value` - The current value of the sensor
+/// * `numbered_candidates` - The candidates with their numbers, sorted by the number
+fn select_by_numeric_value<T>(
+    sensor_min: f64,
+    sensor_max: f64,
+    sensor_value: f64,
+    numbered_candidates: Vec<(f32, T)>,
+) -> Option<(T, f64)> {
+    // get min and max of candidates
+    let image_number_min = numbered_candidates.first()?.0 as f64;
+    let image_number_max = numbered_candidates.last()?.0 as f64;
 
     // Move the sensor value number into the image number coordination system / range
     let transformed_sensor_value = (sensor_value - sensor_min) / (sensor_max - sensor_min)
         * (image_number_max - image_number_min)
         + image_number_min;
 
-    // Get the image that has the lowest distance to the calculated value
-    get_best_fitting_image_path(numbered_images, transformed_sensor_value)
+    // Get the candidate that has the lowest distance to the calculated value
+    get_best_fitting_candidate(numbered_candidates, transformed_sensor_value)
 }
 
-/// Returns the image name that has the lowest distance to the transformed sensor value
-fn get_best_fitting_image_path(
-    numbered_images: Vec<(f32, String)>,
+/// Returns the candidate that has the lowest distance to the transformed sensor value, with its distance
+fn get_best_fitting_candidate<T>(
+    numbered_candidates: Vec<(f32, T)>,
     transformed_sensor_value: f64,
-) -> Option<String> {
-    let mut best_image_path = None;
+) -> Option<(T, f64)> {
+    let mut best_candidate = None;
     let mut min_distance = f64::MAX;
-    for (number, image_path) in numbered_images {
+    for (number, candidate) in numbered_candidates {
         let distance = (transformed_sensor_value - number as f64).abs();
         if distance < min_distance {
             min_distance = distance;
-            best_image_path = Some(image_path);
+            best_candidate = Some(candidate);
         }
     }
-    best_image_path
+    best_candidate.map(|candidate| (candidate, min_distance))
+}
+
+/// Returns the image files in the folder
+/// Logs an error and returns no files if the folder can not be read
+fn read_image_files(images_folder: &str) -> impl Iterator<Item = fs::DirEntry> {
+    let dir_entries = match fs::read_dir(images_folder) {
+        Ok(dir_entries) => Some(dir_entries),
+        Err(err) => {
+            error!("Failed to read image folder {}: {}", images_folder, err);
+            None
+        }
+    };
+
+    dir_entries
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|dir_entry| {
+            dir_entry
+                .file_type()
+                .is_ok_and(|file_type| file_type.is_file())
+        })
+        .filter(crate::is_image)
 }
 
 fn remove_file_extension(file_name: OsString) -> String {
-    let file_name = file_name.to_str().unwrap();
-    let mut file_name = file_name.to_string();
-    let extension = file_name.split('.').last();
+    let mut file_name = file_name.to_string_lossy().to_string();
+    let extension = file_name.split('.').next_back();
     if let Some(extension) = extension {
         file_name = file_name
             .chars()
@@ -166,19 +639,15 @@ fn get_image_numbers_sorted(images_folder: &str) -> Vec<(f32, String)> {
     // Get all image names and parse them to numbers
     // "1.png" -> 1.0
     // "-1,123.png" -> -1.123
-    let mut image_names: Vec<(f32, String)> = fs::read_dir(images_folder)
-        .unwrap()
-        .flatten()
-        .filter(|dir_entry| dir_entry.file_type().unwrap().is_file())
-        .filter(crate::is_image)
+    let mut image_names: Vec<(f32, String)> = read_image_files(images_folder)
         .flat_map(|dir_entry| {
             let number = to_number(dir_entry.file_name());
-            number.map(|num| (num, dir_entry.path().to_str().unwrap().to_string()))
+            number.map(|num| (num, dir_entry.path().to_string_lossy().to_string()))
         })
         .collect();
 
     // Sort by number
-    image_names.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    image_names.sort_by(|a, b| a.0.total_cmp(&b.0));
 
     image_names
 }
@@ -186,7 +655,7 @@ fn get_image_numbers_sorted(images_folder: &str) -> Vec<(f32, String)> {
 /// Converts a OsString to a float number
 fn to_number(string: OsString) -> Option<f32> {
     // Replace "," with "." to make it a parseable number
-    let number_string = string.to_str().unwrap().replace(',', ".");
+    let number_string = string.to_string_lossy().replace(',', ".");
 
     let mut number_string: String = number_string
         .chars()
@@ -252,3 +721,231 @@ fn levenshtein_distance(s1: &str, s2: &str) -> usize {
     }
     column[v1len]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_asset_dir;
+    use crate::ImageAtlasCell;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    fn decode(image_data: &[u8]) -> RgbaImage {
+        image::load_from_memory(image_data).unwrap().to_rgba8()
+    }
+
+    #[test]
+    fn test_atlas_cell_is_selected_by_sensor_value() {
+        let asset_root = temp_asset_dir("atlas");
+        RgbaImage::from_fn(4, 2, |x, _| if x < 2 { RED } else { BLUE })
+            .save(asset_root.join("atlas.png"))
+            .unwrap();
+        let cell = |sensor_value: &str, x: u32| ImageAtlasCell {
+            sensor_value: sensor_value.to_string(),
+            x,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        let config = ConditionalImageConfig {
+            atlas: Some(ImageAtlasConfig {
+                image_name: "atlas.png".to_string(),
+                cells: vec![cell("on", 0), cell("off", 2)],
+            }),
+            ..Default::default()
+        };
+        let render_cell = |sensor_value: &str| {
+            let config = ConditionalImageConfig {
+                sensor_value: sensor_value.to_string(),
+                ..config.clone()
+            };
+            decode(&render("atlas", &SensorType::Text, &config, Some(&asset_root)).unwrap())
+        };
+
+        let on_image = render_cell("on");
+        let off_image = render_cell("off");
+
+        assert_eq!(on_image.dimensions(), (2, 2));
+        assert!(on_image.pixels().all(|pixel| *pixel == RED));
+        assert_eq!(off_image.dimensions(), (2, 2));
+        assert!(off_image.pixels().all(|pixel| *pixel == BLUE));
+        fs::remove_dir_all(asset_root).unwrap();
+    }
+
+    #[test]
+    fn test_atlas_is_decoded_once_and_cells_are_not_encoded() {
+        let asset_root = temp_asset_dir("atlas_cache");
+        RgbaImage::from_fn(4, 2, |x, _| if x < 2 { RED } else { BLUE })
+            .save(asset_root.join("atlas.png"))
+            .unwrap();
+        let cell = |sensor_value: &str, x: u32| ImageAtlasCell {
+            sensor_value: sensor_value.to_string(),
+            x,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        let config = |sensor_value: &str| ConditionalImageConfig {
+            sensor_value: sensor_value.to_string(),
+            atlas: Some(ImageAtlasConfig {
+                image_name: "atlas.png".to_string(),
+                cells: vec![cell("on", 0), cell("off", 2)],
+            }),
+            ..Default::default()
+        };
+        let render_cell = |sensor_value: &str| {
+            render_image(
+                "atlas_cache",
+                &SensorType::Text,
+                &config(sensor_value),
+                Some(&asset_root),
+            )
+            .unwrap()
+            .unwrap()
+        };
+        ATLAS_DECODE_COUNT.with(|count| count.set(0));
+
+        let on_image = render_cell("on");
+        let off_image = render_cell("off");
+        let on_image_again = render_cell("on");
+
+        assert_eq!(ATLAS_DECODE_COUNT.with(|count| count.get()), 1);
+        assert_eq!(on_image.dimensions(), (2, 2));
+        assert!(on_image.pixels().all(|pixel| *pixel == RED));
+        assert!(off_image.pixels().all(|pixel| *pixel == BLUE));
+        assert!(on_image_again == on_image);
+        let encoded_image = render(
+            "atlas_cache",
+            &SensorType::Text,
+            &config("on"),
+            Some(&asset_root),
+        )
+        .unwrap();
+        assert!(decode(&encoded_image) == on_image);
+        fs::remove_dir_all(asset_root).unwrap();
+    }
+
+    #[test]
+    fn test_transition_blends_from_the_old_to_the_new_image() {
+        let encode = |color: Rgba<u8>| {
+            let mut image_data = Cursor::new(vec![]);
+            RgbaImage::from_pixel(1, 1, color)
+                .write_to(&mut image_data, image::ImageOutputFormat::Png)
+                .unwrap();
+            image_data.into_inner()
+        };
+        let (red_image, blue_image) = (encode(RED), encode(BLUE));
+        let mut transition = ImageTransition::new(1000);
+        transition.update(&red_image, 0).unwrap();
+
+        let blended_pixels: Vec<Rgba<u8>> = [1000, 1250, 1500, 1750, 2000]
+            .iter()
+            .map(|timestamp_ms| {
+                *transition
+                    .update(&blue_image, *timestamp_ms)
+                    .unwrap()
+                    .get_pixel(0, 0)
+            })
+            .collect();
+
+        assert_eq!(blended_pixels.first(), Some(&RED));
+        assert_eq!(blended_pixels.last(), Some(&BLUE));
+        assert_eq!(blended_pixels[2], Rgba([128, 0, 128, 255]));
+        assert!(blended_pixels
+            .windows(2)
+            .all(|pair| pair[0][0] > pair[1][0] && pair[0][2] < pair[1][2]));
+        assert_eq!(transition_weight(0, 1000), 0.0);
+        assert_eq!(transition_weight(1000, 1000), 1.0);
+    }
+
+    #[test]
+    fn test_match_distance_is_zero_for_exact_matches() {
+        let asset_root = temp_asset_dir("distance");
+        for image_name in ["on.png", "off.png", "0.png", "100.png"] {
+            RgbaImage::from_pixel(1, 1, RED)
+                .save(asset_root.join(image_name))
+                .unwrap();
+        }
+        let distance = |sensor_type: SensorType, sensor_value: &str| {
+            let config = ConditionalImageConfig {
+                sensor_value: sensor_value.to_string(),
+                min_sensor_value: 0.0,
+                max_sensor_value: 100.0,
+                ..Default::default()
+            };
+            render_with_distance("distance", &sensor_type, &config, Some(&asset_root))
+                .unwrap()
+                .1
+        };
+
+        assert_eq!(distance(SensorType::Text, "on"), 0.0);
+        assert!(distance(SensorType::Text, "something completely different") > 10.0);
+        assert_eq!(distance(SensorType::Number, "100"), 0.0);
+        assert_eq!(distance(SensorType::Number, "50"), 50.0);
+        fs::remove_dir_all(asset_root).unwrap();
+    }
+
+    #[test]
+    fn test_non_numeric_value_of_number_sensor_selects_no_image() {
+        let asset_root = temp_asset_dir("non-numeric");
+        for image_name in ["0.png", "100.png"] {
+            RgbaImage::from_pixel(1, 1, RED)
+                .save(asset_root.join(image_name))
+                .unwrap();
+        }
+        let config = ConditionalImageConfig {
+            sensor_value: "n/a".to_string(),
+            min_sensor_value: 0.0,
+            max_sensor_value: 100.0,
+            ..Default::default()
+        };
+
+        let image_data = render(
+            "non-numeric",
+            &SensorType::Number,
+            &config,
+            Some(&asset_root),
+        );
+
+        assert_eq!(image_data, None);
+        fs::remove_dir_all(asset_root).unwrap();
+    }
+
+    #[test]
+    fn test_on_demand_numeric_values_are_requested_per_image() {
+        let config = |on_demand_image_count: u32| ConditionalImageConfig {
+            min_sensor_value: 0.0,
+            max_sensor_value: 100.0,
+            on_demand: true,
+            on_demand_image_count,
+            ..Default::default()
+        };
+        let sensor_value = |value: &str, sensor_type: SensorType| SensorValue {
+            value: value.to_string(),
+            sensor_type,
+            ..Default::default()
+        };
+        let requested_value = |on_demand_image_count: u32, value: &str| {
+            get_on_demand_sensor_value(
+                &config(on_demand_image_count),
+                &sensor_value(value, SensorType::Number),
+            )
+        };
+
+        for value in ["38", "41.7", "42", "44.9"] {
+            assert_eq!(requested_value(11, value), "40");
+        }
+        assert_eq!(requested_value(11, "46"), "50");
+        assert_eq!(requested_value(11, "150"), "100");
+        assert_eq!(requested_value(11, "-5"), "0");
+        assert_eq!(requested_value(1, "42"), "0");
+        assert_eq!(requested_value(0, "42.5"), "42.5");
+        assert_eq!(requested_value(0, "150"), "100");
+        assert_eq!(requested_value(11, "n/a"), "n/a");
+        assert_eq!(
+            get_on_demand_sensor_value(&config(11), &sensor_value("42", SensorType::Text)),
+            "42"
+        );
+    }
+}
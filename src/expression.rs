@@ -0,0 +1,213 @@
+/// Maximum nesting depth of parentheses and signs in an expression
+/// Deeper expressions are rejected, as the recursive parser would overflow the stack
+pub const MAX_NESTING_DEPTH: usize = 64;
+
+/// Evaluates the given arithmetic expression
+/// Supports numbers, `+`, `-`, `*`, `/`, parentheses and sensor references like `{sensor-id}`
+/// # Returns
+/// The result of the expression, or an error if the expression is malformed
+/// or a referenced sensor could not be resolved
+/// # Arguments
+/// * `expression` - The expression to evaluate, e.g. `{total} - {used}`
+/// * `resolve_sensor` - Resolves the numeric value of a sensor by its id
+pub fn evaluate(
+    expression: &str,
+    resolve_sensor: &dyn Fn(&str) -> Option<f64>,
+) -> Result<f64, String> {
+    let mut parser = Parser::new(expression, resolve_sensor);
+
+    let value = parser.parse_sum()?;
+    parser.skip_whitespace();
+    if parser.position < parser.chars.len() {
+        return Err(format!(
+            "Unexpected character '{}' at position {}",
+            parser.chars[parser.position], parser.position
+        ));
+    }
+
+    Ok(value)
+}
+
+/// Returns true if parentheses or signs are nested deeper than `MAX_NESTING_DEPTH`
+/// The expression is parsed up to the first error, just like it would be evaluated,
+/// so an expression that fails earlier for another reason is not reported.
+pub fn exceeds_nesting_depth(expression: &str) -> bool {
+    let mut parser = Parser::new(expression, &|_| Some(0.0));
+    let _ = parser.parse_sum();
+    parser.depth_exceeded
+}
+
+/// Recursive descent parser, evaluating the expression while parsing
+struct Parser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    resolve_sensor: &'a dyn Fn(&str) -> Option<f64>,
+    /// The current nesting depth of parentheses and signs
+    depth: usize,
+    depth_exceeded: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(expression: &str, resolve_sensor: &'a dyn Fn(&str) -> Option<f64>) -> Self {
+        Parser {
+            chars: expression.chars().collect(),
+            position: 0,
+            resolve_sensor,
+            depth: 0,
+            depth_exceeded: false,
+        }
+    }
+
+    /// sum = product (("+" | "-") product)*
+    fn parse_sum(&mut self) -> Result<f64, String> {
+        let mut value = self.parse_product()?;
+        loop {
+            match self.peek() {
+                Some('+') => {
+                    self.position += 1;
+                    value += self.parse_product()?;
+                }
+                Some('-') => {
+                    self.position += 1;
+                    value -= self.parse_product()?;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    /// product = factor (("*" | "/") factor)*
+    fn parse_product(&mut self) -> Result<f64, String> {
+        let mut value = self.parse_factor()?;
+        loop {
+            match self.peek() {
+                Some('*') => {
+                    self.position += 1;
+                    value *= self.parse_factor()?;
+                }
+                Some('/') => {
+                    self.position += 1;
+                    value /= self.parse_factor()?;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    /// factor = "-" factor | "(" sum ")" | "{" sensor id "}" | number
+    fn parse_factor(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                self.enter_nesting()?;
+                let value = -self.parse_factor()?;
+                self.depth -= 1;
+                Ok(value)
+            }
+            Some('(') => {
+                self.position += 1;
+                self.enter_nesting()?;
+                let value = self.parse_sum()?;
+                self.expect(')')?;
+                self.depth -= 1;
+                Ok(value)
+            }
+            Some('{') => {
+                self.position += 1;
+                let sensor_id = self.take_while(|c| c != '}');
+                self.expect('}')?;
+                (self.resolve_sensor)(sensor_id.trim())
+                    .ok_or(format!("Sensor {} has no numeric value", sensor_id))
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                number
+                    .parse()
+                    .map_err(|_| format!("Invalid number {}", number))
+            }
+            Some(c) => Err(format!(
+                "Unexpected character '{}' at position {}",
+                c, self.position
+            )),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+
+    /// Increases the nesting depth, fails if the maximum nesting depth is exceeded
+    fn enter_nesting(&mut self) -> Result<(), String> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            self.depth_exceeded = true;
+            return Err(format!(
+                "Expression is nested deeper than {} levels",
+                MAX_NESTING_DEPTH
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Returns the next non-whitespace character without consuming it
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let start = self.position;
+        while self.chars.get(self.position).is_some_and(|c| predicate(*c)) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(format!(
+                "Expected '{}' at position {}",
+                expected, self.position
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nesting_within_limit_is_evaluated() {
+        let expression = format!(
+            "{}1{}",
+            "(".repeat(MAX_NESTING_DEPTH),
+            ")".repeat(MAX_NESTING_DEPTH)
+        );
+
+        assert_eq!(evaluate(&expression, &|_| None), Ok(1.0));
+        assert!(!exceeds_nesting_depth(&expression));
+    }
+
+    #[test]
+    fn test_deep_nesting_is_rejected() {
+        let parentheses = format!("{}1{}", "(".repeat(2000), ")".repeat(2000));
+        let signs = format!("{}1", "-".repeat(2000));
+
+        for expression in [parentheses, signs] {
+            assert!(evaluate(&expression, &|_| None).is_err());
+            assert!(exceeds_nesting_depth(&expression));
+        }
+    }
+}
@@ -0,0 +1,846 @@
+use std::borrow::Cow;
+use std::io::Read;
+
+/// Signature of a WOFF font
+const WOFF_SIGNATURE: &[u8; 4] = b"wOFF";
+/// Signature of a WOFF2 font
+const WOFF2_SIGNATURE: &[u8; 4] = b"wOF2";
+/// Flavor of a font collection, which is not supported
+const COLLECTION_FLAVOR: u32 = u32::from_be_bytes(*b"ttcf");
+
+const GLYF_TAG: u32 = u32::from_be_bytes(*b"glyf");
+const LOCA_TAG: u32 = u32::from_be_bytes(*b"loca");
+const HMTX_TAG: u32 = u32::from_be_bytes(*b"hmtx");
+const HHEA_TAG: u32 = u32::from_be_bytes(*b"hhea");
+const HEAD_TAG: u32 = u32::from_be_bytes(*b"head");
+
+/// Known table tags of WOFF2, referenced by their index in the table directory
+const WOFF2_KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+/// Decodes the given font data to a raw TTF/OTF font
+/// WOFF and WOFF2 fonts are decompressed to the underlying sfnt font,
+/// all other font data is returned as it is
+pub fn decode_font(font_data: Cow<'_, [u8]>) -> Result<Cow<'_, [u8]>, String> {
+    if font_data.starts_with(WOFF_SIGNATURE) {
+        decode_woff(&font_data).map(Cow::Owned)
+    } else if font_data.starts_with(WOFF2_SIGNATURE) {
+        decode_woff2(&font_data).map(Cow::Owned)
+    } else {
+        Ok(font_data)
+    }
+}
+
+/// Decodes a WOFF font, each table is either stored raw or zlib compressed
+fn decode_woff(font_data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = Reader::new(font_data);
+    reader.skip(4)?; // signature
+    let flavor = reader.u32()?;
+    reader.skip(4)?; // length
+    let num_tables = reader.u16()?;
+    reader.skip(2 + 4 + 2 + 2 + 5 * 4)?; // reserved, sizes, version, meta and private data
+
+    let mut tables = Vec::with_capacity(num_tables as usize);
+    for _ in 0..num_tables {
+        let tag = reader.u32()?;
+        let offset = reader.u32()? as usize;
+        let compressed_length = reader.u32()? as usize;
+        let original_length = reader.u32()? as usize;
+        reader.skip(4)?; // original checksum
+
+        let table_data = font_data
+            .get(offset..offset + compressed_length)
+            .ok_or("WOFF table exceeds the font data")?;
+        // The output is limited to the original length, so a malicious table can not expand unbounded
+        let table_data = if compressed_length < original_length {
+            miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(table_data, original_length)
+                .map_err(|err| format!("Failed to decompress WOFF table: {:?}", err))?
+        } else {
+            table_data.to_vec()
+        };
+        if table_data.len() != original_length {
+            return Err("WOFF table has an unexpected length".to_string());
+        }
+
+        tables.push((tag, table_data));
+    }
+
+    build_sfnt(flavor, tables)
+}
+
+/// Represents a table directory entry of a WOFF2 font
+struct Woff2Table {
+    tag: u32,
+    transformed: bool,
+    length: usize,
+}
+
+/// Decodes a WOFF2 font, all tables are brotli compressed in a single stream
+/// The glyf, loca and hmtx tables may be transformed and are reconstructed
+fn decode_woff2(font_data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = Reader::new(font_data);
+    reader.skip(4)?; // signature
+    let flavor = reader.u32()?;
+    reader.skip(4)?; // length
+    let num_tables = reader.u16()?;
+    reader.skip(2)?; // reserved
+    let total_sfnt_size = reader.u32()?;
+    let total_compressed_size = reader.u32()? as usize;
+    reader.skip(2 + 2 + 5 * 4)?; // version, meta and private data
+
+    if flavor == COLLECTION_FLAVOR {
+        return Err("WOFF2 font collections are not supported".to_string());
+    }
+
+    // Read the table directory
+    let mut directory = Vec::with_capacity(num_tables as usize);
+    for _ in 0..num_tables {
+        let flags = reader.u8()?;
+        let tag = match flags & 0x3f {
+            63 => reader.u32()?,
+            index => u32::from_be_bytes(*WOFF2_KNOWN_TAGS[index as usize]),
+        };
+        let transform_version = flags >> 6;
+        let original_length = reader.base128()? as usize;
+
+        // For glyf and loca version 0 is the transformed version, for all others version 0 is null
+        let transformed = if tag == GLYF_TAG || tag == LOCA_TAG {
+            transform_version == 0
+        } else {
+            transform_version != 0
+        };
+        let length = if transformed {
+            reader.base128()? as usize
+        } else {
+            original_length
+        };
+
+        directory.push(Woff2Table {
+            tag,
+            transformed,
+            length,
+        });
+    }
+
+    // Decompress all tables at once, the tables are never larger than the whole font,
+    // so the output is limited to the total sfnt size to not expand a malicious stream unbounded
+    let compressed_data = reader.bytes(total_compressed_size)?;
+    let mut decompressed_data = vec![];
+    brotli_decompressor::Decompressor::new(compressed_data, 4096)
+        .take(total_sfnt_size as u64)
+        .read_to_end(&mut decompressed_data)
+        .map_err(|err| format!("Failed to decompress WOFF2 tables: {}", err))?;
+
+    let mut table_reader = Reader::new(&decompressed_data);
+    let mut tables: Vec<(u32, Vec<u8>)> = vec![];
+    let mut transformed_tables: Vec<(u32, &[u8])> = vec![];
+    for table in &directory {
+        let table_data = table_reader.bytes(table.length)?;
+        if table.transformed {
+            transformed_tables.push((table.tag, table_data));
+        } else {
+            tables.push((table.tag, table_data.to_vec()));
+        }
+    }
+
+    // Reconstruct the transformed tables, glyf first, as loca and hmtx depend on it
+    let mut glyph_x_mins = None;
+    if let Some((_, glyf_data)) = transformed_tables.iter().find(|(tag, _)| *tag == GLYF_TAG) {
+        let reconstructed = reconstruct_glyf(glyf_data)?;
+        set_index_to_loc_format(&mut tables, reconstructed.index_format)?;
+        tables.push((GLYF_TAG, reconstructed.glyf));
+        tables.push((LOCA_TAG, reconstructed.loca));
+        glyph_x_mins = Some(reconstructed.x_mins);
+    }
+    if let Some((_, hmtx_data)) = transformed_tables.iter().find(|(tag, _)| *tag == HMTX_TAG) {
+        let glyph_x_mins = glyph_x_mins
+            .as_ref()
+            .ok_or("Transformed hmtx table requires a transformed glyf table")?;
+        let number_of_h_metrics = tables
+            .iter()
+            .find(|(tag, _)| *tag == HHEA_TAG)
+            .and_then(|(_, hhea)| hhea.get(34..36))
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or("Transformed hmtx table requires a hhea table")?;
+        let hmtx = reconstruct_hmtx(hmtx_data, number_of_h_metrics, glyph_x_mins)?;
+        tables.push((HMTX_TAG, hmtx));
+    }
+
+    build_sfnt(flavor, tables)
+}
+
+/// Holds the reconstructed glyf and loca table
+struct ReconstructedGlyf {
+    glyf: Vec<u8>,
+    loca: Vec<u8>,
+    index_format: u16,
+    x_mins: Vec<i16>,
+}
+
+/// Reconstructs the glyf and loca table from the transformed glyf table of a WOFF2 font
+fn reconstruct_glyf(data: &[u8]) -> Result<ReconstructedGlyf, String> {
+    let mut header = Reader::new(data);
+    header.skip(2)?; // reserved
+    let option_flags = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+    let mut stream_sizes = [0usize; 7];
+    for stream_size in stream_sizes.iter_mut() {
+        *stream_size = header.u32()? as usize;
+    }
+
+    // Split the data into the sub streams
+    let mut streams = Vec::with_capacity(7);
+    for stream_size in stream_sizes {
+        streams.push(Reader::new(header.bytes(stream_size)?));
+    }
+    let has_overlap_bitmap = option_flags & 1 != 0;
+    let overlap_bitmap = if has_overlap_bitmap {
+        Some(header.bytes(num_glyphs.div_ceil(8))?)
+    } else {
+        None
+    };
+    let [mut n_contour_stream, mut n_points_stream, mut flag_stream, mut glyph_stream, mut composite_stream, mut bbox_stream, mut instruction_stream]: [Reader; 7] =
+        streams.try_into().map_err(|_| "Invalid glyf streams")?;
+
+    let bbox_bitmap = bbox_stream.bytes(num_glyphs.div_ceil(32) * 4)?;
+    let is_bit_set = |bitmap: &[u8], index: usize| bitmap[index >> 3] & (0x80 >> (index & 7)) != 0;
+
+    let mut glyf = vec![];
+    let mut loca_offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+    for glyph_index in 0..num_glyphs {
+        loca_offsets.push(glyf.len());
+        let number_of_contours = n_contour_stream.i16()?;
+        let has_bbox = is_bit_set(bbox_bitmap, glyph_index);
+
+        if number_of_contours == 0 {
+            // Empty glyph
+            if has_bbox {
+                return Err("Empty glyph must not have a bounding box".to_string());
+            }
+            x_mins.push(0);
+        } else if number_of_contours < 0 {
+            // Composite glyph, the bounding box is always explicit
+            if !has_bbox {
+                return Err("Composite glyph is missing its bounding box".to_string());
+            }
+            let bbox = bbox_stream.bytes(8)?;
+            let (composite_data, has_instructions) = read_composite_glyph(&mut composite_stream)?;
+
+            glyf.extend_from_slice(&number_of_contours.to_be_bytes());
+            glyf.extend_from_slice(bbox);
+            glyf.extend_from_slice(composite_data);
+            if has_instructions {
+                let instruction_length = glyph_stream.u255_16()?;
+                glyf.extend_from_slice(&instruction_length.to_be_bytes());
+                glyf.extend_from_slice(instruction_stream.bytes(instruction_length as usize)?);
+            }
+            x_mins.push(i16::from_be_bytes([bbox[0], bbox[1]]));
+        } else {
+            // Simple glyph
+            let mut end_points = Vec::with_capacity(number_of_contours as usize);
+            let mut total_points = 0usize;
+            for _ in 0..number_of_contours {
+                total_points += n_points_stream.u255_16()? as usize;
+                let end_point = total_points
+                    .checked_sub(1)
+                    .and_then(|end_point| u16::try_from(end_point).ok())
+                    .ok_or("Simple glyph has an invalid number of points")?;
+                end_points.push(end_point);
+            }
+
+            let mut points = Vec::with_capacity(total_points);
+            let (mut x, mut y) = (0i32, 0i32);
+            for _ in 0..total_points {
+                let flag = flag_stream.u8()?;
+                let (dx, dy) = decode_triplet(flag & 0x7f, &mut glyph_stream)?;
+                x += dx;
+                y += dy;
+                points.push((x, y, dx, dy, flag & 0x80 == 0));
+            }
+            let instruction_length = glyph_stream.u255_16()?;
+            let instructions = instruction_stream.bytes(instruction_length as usize)?;
+
+            let bbox = if has_bbox {
+                let bbox = bbox_stream.bytes(8)?;
+                [0, 2, 4, 6].map(|offset| i16::from_be_bytes([bbox[offset], bbox[offset + 1]]))
+            } else {
+                let x_min = points.iter().map(|point| point.0).min().unwrap_or(0);
+                let y_min = points.iter().map(|point| point.1).min().unwrap_or(0);
+                let x_max = points.iter().map(|point| point.0).max().unwrap_or(0);
+                let y_max = points.iter().map(|point| point.1).max().unwrap_or(0);
+                [x_min, y_min, x_max, y_max].map(|value| value as i16)
+            };
+            let overlap_simple =
+                overlap_bitmap.is_some_and(|bitmap| is_bit_set(bitmap, glyph_index));
+
+            glyf.extend_from_slice(&number_of_contours.to_be_bytes());
+            bbox.iter()
+                .for_each(|value| glyf.extend_from_slice(&value.to_be_bytes()));
+            end_points
+                .iter()
+                .for_each(|end_point| glyf.extend_from_slice(&end_point.to_be_bytes()));
+            glyf.extend_from_slice(&instruction_length.to_be_bytes());
+            glyf.extend_from_slice(instructions);
+            encode_simple_glyph_points(&mut glyf, &points, overlap_simple);
+            x_mins.push(bbox[0]);
+        }
+
+        // Each glyph is padded to 4 bytes
+        glyf.resize(glyf.len().next_multiple_of(4), 0);
+    }
+    loca_offsets.push(glyf.len());
+
+    let loca = match index_format {
+        0 => loca_offsets
+            .iter()
+            .flat_map(|offset| ((offset / 2) as u16).to_be_bytes())
+            .collect(),
+        _ => loca_offsets
+            .iter()
+            .flat_map(|offset| (*offset as u32).to_be_bytes())
+            .collect(),
+    };
+
+    Ok(ReconstructedGlyf {
+        glyf,
+        loca,
+        index_format,
+        x_mins,
+    })
+}
+
+/// Reads the components of a composite glyph
+/// Returns the raw component data and whether the glyph has instructions
+fn read_composite_glyph<'a>(composite_stream: &mut Reader<'a>) -> Result<(&'a [u8], bool), String> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+    const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+    let start = composite_stream.position;
+    let mut has_instructions = false;
+    loop {
+        let flags = composite_stream.u16()?;
+        has_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+
+        let mut component_size = 2; // glyph index
+        component_size += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            component_size += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            component_size += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            component_size += 8;
+        }
+        composite_stream.skip(component_size)?;
+
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+
+    Ok((
+        &composite_stream.data[start..composite_stream.position],
+        has_instructions,
+    ))
+}
+
+/// Decodes a point delta of a simple glyph, encoded as triplet of the flag and following bytes
+fn decode_triplet(flag: u8, glyph_stream: &mut Reader) -> Result<(i32, i32), String> {
+    let with_sign = |flag: u8, value: i32| if flag & 1 != 0 { value } else { -value };
+    let flag_value = flag as i32;
+
+    let delta = if flag < 10 {
+        let b0 = glyph_stream.u8()? as i32;
+        (0, with_sign(flag, ((flag_value & 14) << 7) + b0))
+    } else if flag < 20 {
+        let b0 = glyph_stream.u8()? as i32;
+        (with_sign(flag, (((flag_value - 10) & 14) << 7) + b0), 0)
+    } else if flag < 84 {
+        let b0 = flag_value - 20;
+        let b1 = glyph_stream.u8()? as i32;
+        (
+            with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+            with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)),
+        )
+    } else if flag < 120 {
+        let b0 = flag_value - 84;
+        let b1 = glyph_stream.u8()? as i32;
+        let b2 = glyph_stream.u8()? as i32;
+        (
+            with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+            with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+        )
+    } else if flag < 124 {
+        let b1 = glyph_stream.u8()? as i32;
+        let b2 = glyph_stream.u8()? as i32;
+        let b3 = glyph_stream.u8()? as i32;
+        (
+            with_sign(flag, (b1 << 4) + (b2 >> 4)),
+            with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3),
+        )
+    } else {
+        let b1 = glyph_stream.u8()? as i32;
+        let b2 = glyph_stream.u8()? as i32;
+        let b3 = glyph_stream.u8()? as i32;
+        let b4 = glyph_stream.u8()? as i32;
+        (
+            with_sign(flag, (b1 << 8) + b2),
+            with_sign(flag >> 1, (b3 << 8) + b4),
+        )
+    };
+
+    Ok(delta)
+}
+
+/// Encodes the flags and coordinates of the points of a simple glyph
+/// The points hold the absolute coordinates, the deltas and whether the point is on the curve
+fn encode_simple_glyph_points(
+    glyf: &mut Vec<u8>,
+    points: &[(i32, i32, i32, i32, bool)],
+    overlap_simple: bool,
+) {
+    const ON_CURVE_POINT: u8 = 0x01;
+    const X_SHORT_VECTOR: u8 = 0x02;
+    const Y_SHORT_VECTOR: u8 = 0x04;
+    const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+    const OVERLAP_SIMPLE: u8 = 0x40;
+
+    let mut flags = Vec::with_capacity(points.len());
+    let mut x_coordinates = vec![];
+    let mut y_coordinates = vec![];
+    for (index, (_, _, dx, dy, on_curve)) in points.iter().enumerate() {
+        let mut flag = if *on_curve { ON_CURVE_POINT } else { 0 };
+        if index == 0 && overlap_simple {
+            flag |= OVERLAP_SIMPLE;
+        }
+
+        for (delta, coordinates, short_vector, same_or_positive) in [
+            (
+                *dx,
+                &mut x_coordinates,
+                X_SHORT_VECTOR,
+                X_IS_SAME_OR_POSITIVE,
+            ),
+            (
+                *dy,
+                &mut y_coordinates,
+                Y_SHORT_VECTOR,
+                Y_IS_SAME_OR_POSITIVE,
+            ),
+        ] {
+            if delta == 0 {
+                flag |= same_or_positive;
+            } else if delta.abs() < 256 {
+                flag |= short_vector;
+                if delta > 0 {
+                    flag |= same_or_positive;
+                }
+                coordinates.push(delta.unsigned_abs() as u8);
+            } else {
+                coordinates.extend_from_slice(&(delta as i16).to_be_bytes());
+            }
+        }
+
+        flags.push(flag);
+    }
+
+    glyf.extend_from_slice(&flags);
+    glyf.extend_from_slice(&x_coordinates);
+    glyf.extend_from_slice(&y_coordinates);
+}
+
+/// Reconstructs the hmtx table from the transformed hmtx table of a WOFF2 font
+/// Omitted left side bearings are taken from the x min of the glyph bounding boxes
+fn reconstruct_hmtx(
+    data: &[u8],
+    number_of_h_metrics: u16,
+    glyph_x_mins: &[i16],
+) -> Result<Vec<u8>, String> {
+    let mut reader = Reader::new(data);
+    let flags = reader.u8()?;
+    let number_of_h_metrics = number_of_h_metrics as usize;
+    let num_glyphs = glyph_x_mins.len();
+    if number_of_h_metrics > num_glyphs {
+        return Err("hhea has more metrics than glyphs".to_string());
+    }
+
+    let mut advance_widths = Vec::with_capacity(number_of_h_metrics);
+    for _ in 0..number_of_h_metrics {
+        advance_widths.push(reader.u16()?);
+    }
+    let mut left_side_bearings = Vec::with_capacity(num_glyphs);
+    for (glyph_index, x_min) in glyph_x_mins.iter().enumerate() {
+        let lsb_omitted = if glyph_index < number_of_h_metrics {
+            flags & 1 != 0
+        } else {
+            flags & 2 != 0
+        };
+        let left_side_bearing = if lsb_omitted { *x_min } else { reader.i16()? };
+        left_side_bearings.push(left_side_bearing);
+    }
+
+    let mut hmtx = Vec::with_capacity(number_of_h_metrics * 4 + num_glyphs * 2);
+    for (glyph_index, left_side_bearing) in left_side_bearings.iter().enumerate() {
+        if let Some(advance_width) = advance_widths.get(glyph_index) {
+            hmtx.extend_from_slice(&advance_width.to_be_bytes());
+        }
+        hmtx.extend_from_slice(&left_side_bearing.to_be_bytes());
+    }
+
+    Ok(hmtx)
+}
+
+/// Sets the index to loc format in the head table, to match the reconstructed loca table
+fn set_index_to_loc_format(tables: &mut [(u32, Vec<u8>)], index_format: u16) -> Result<(), String> {
+    let head = tables
+        .iter_mut()
+        .find(|(tag, _)| *tag == HEAD_TAG)
+        .map(|(_, head)| head)
+        .ok_or("Font is missing the head table")?;
+    let index_to_loc_format = head
+        .get_mut(50..52)
+        .ok_or("Font has an invalid head table")?;
+    index_to_loc_format.copy_from_slice(&index_format.to_be_bytes());
+    Ok(())
+}
+
+/// Builds a sfnt font from the given tables
+/// The tables are sorted by tag and padded to 4 bytes, as required by the sfnt format
+/// Returns an error if there are too many tables for the table directory
+fn build_sfnt(flavor: u32, mut tables: Vec<(u32, Vec<u8>)>) -> Result<Vec<u8>, String> {
+    tables.sort_by_key(|(tag, _)| *tag);
+
+    let too_many_tables = || format!("Font has too many tables: {}", tables.len());
+    let num_tables = u16::try_from(tables.len()).map_err(|_| too_many_tables())?;
+    let entry_selector = num_tables.max(1).ilog2() as u16;
+    let search_range = (1u16 << entry_selector)
+        .checked_mul(16)
+        .ok_or_else(too_many_tables)?;
+    let range_shift = num_tables
+        .checked_mul(16)
+        .ok_or_else(too_many_tables)?
+        .saturating_sub(search_range);
+
+    let mut font = vec![];
+    font.extend_from_slice(&flavor.to_be_bytes());
+    font.extend_from_slice(&num_tables.to_be_bytes());
+    font.extend_from_slice(&search_range.to_be_bytes());
+    font.extend_from_slice(&entry_selector.to_be_bytes());
+    font.extend_from_slice(&range_shift.to_be_bytes());
+
+    let mut offset = 12 + tables.len() * 16;
+    for (tag, table_data) in &tables {
+        font.extend_from_slice(&tag.to_be_bytes());
+        font.extend_from_slice(&calculate_checksum(table_data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(table_data.len() as u32).to_be_bytes());
+        offset += table_data.len().next_multiple_of(4);
+    }
+
+    for (_, table_data) in &tables {
+        font.extend_from_slice(table_data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+
+    Ok(font)
+}
+
+/// Calculates the sfnt checksum of a table, the sum of all big endian u32 values
+fn calculate_checksum(table_data: &[u8]) -> u32 {
+    table_data.chunks(4).fold(0u32, |checksum, chunk| {
+        let mut value = [0u8; 4];
+        value[..chunk.len()].copy_from_slice(chunk);
+        checksum.wrapping_add(u32::from_be_bytes(value))
+    })
+}
+
+/// Reads big endian values from a byte slice
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, position: 0 }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or("Unexpected end of font data")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, length: usize) -> Result<(), String> {
+        self.bytes(length).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        self.u16().map(|value| value as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a variable length UIntBase128 value of WOFF2
+    fn base128(&mut self) -> Result<u32, String> {
+        let mut value: u32 = 0;
+        for index in 0..5 {
+            let byte = self.u8()?;
+            if index == 0 && byte == 0x80 {
+                return Err("UIntBase128 must not have leading zeros".to_string());
+            }
+            if value & 0xfe00_0000 != 0 {
+                return Err("UIntBase128 overflows".to_string());
+            }
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("UIntBase128 exceeds 5 bytes".to_string())
+    }
+
+    /// Reads a variable length 255UInt16 value of WOFF2
+    fn u255_16(&mut self) -> Result<u16, String> {
+        match self.u8()? {
+            253 => self.u16(),
+            254 => Ok(self.u8()? as u16 + 253 * 2),
+            255 => Ok(self.u8()? as u16 + 253),
+            code => Ok(code as u16),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::load_font_data;
+
+    /// Reads the flavor and the tables of a sfnt font
+    fn read_sfnt(font_data: &[u8]) -> (u32, Vec<(u32, Vec<u8>)>) {
+        let mut reader = Reader::new(font_data);
+        let flavor = reader.u32().unwrap();
+        let num_tables = reader.u16().unwrap();
+        reader.skip(6).unwrap();
+
+        let mut tables = vec![];
+        for _ in 0..num_tables {
+            let tag = reader.u32().unwrap();
+            reader.skip(4).unwrap(); // checksum
+            let offset = reader.u32().unwrap() as usize;
+            let length = reader.u32().unwrap() as usize;
+            tables.push((tag, font_data[offset..offset + length].to_vec()));
+        }
+        tables.sort_by_key(|(tag, _)| *tag);
+        (flavor, tables)
+    }
+
+    /// Encodes a value as variable length UIntBase128
+    fn encode_base128(value: u32) -> Vec<u8> {
+        let mut bytes = vec![(value & 0x7f) as u8];
+        let mut value = value >> 7;
+        while value > 0 {
+            bytes.insert(0, (value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes
+    }
+
+    /// Writes bits least significant bit first, as used by brotli
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bit_count: usize,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, count: usize) {
+            for bit in 0..count {
+                if self.bit_count.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                let last_byte = self.bytes.last_mut().unwrap();
+                *last_byte |= (((value >> bit) & 1) as u8) << (self.bit_count % 8);
+                self.bit_count += 1;
+            }
+        }
+
+        fn write_bytes(&mut self, bytes: &[u8]) {
+            self.bit_count = self.bit_count.next_multiple_of(8);
+            self.bytes.extend_from_slice(bytes);
+            self.bit_count += bytes.len() * 8;
+        }
+    }
+
+    /// Wraps the data in a brotli stream of uncompressed meta-blocks
+    fn encode_uncompressed_brotli(data: &[u8]) -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        writer.write(0, 1); // window size of 16 bits
+        for chunk in data.chunks(65536) {
+            writer.write(0, 1); // not the last meta-block
+            writer.write(0, 2); // 4 nibbles for the length
+            writer.write(chunk.len() as u32 - 1, 16);
+            writer.write(1, 1); // uncompressed
+            writer.write_bytes(chunk);
+        }
+        writer.write(1, 1); // last meta-block
+        writer.write(1, 1); // which is empty
+        writer.bytes
+    }
+
+    /// Encodes the tables as WOFF2 font, with null transforms and uncompressed brotli
+    fn encode_woff2(flavor: u32, tables: &[(u32, Vec<u8>)], total_sfnt_size: u32) -> Vec<u8> {
+        let mut directory = vec![];
+        let mut table_data = vec![];
+        for (tag, data) in tables {
+            let tag_bytes = tag.to_be_bytes();
+            let known_index = WOFF2_KNOWN_TAGS
+                .iter()
+                .position(|known_tag| **known_tag == tag_bytes);
+            // glyf and loca use transform version 3 as null transform
+            let transform_version = if *tag == GLYF_TAG || *tag == LOCA_TAG {
+                3 << 6
+            } else {
+                0
+            };
+            match known_index {
+                Some(index) => directory.push(index as u8 | transform_version),
+                None => {
+                    directory.push(63 | transform_version);
+                    directory.extend_from_slice(&tag_bytes);
+                }
+            }
+            directory.extend_from_slice(&encode_base128(data.len() as u32));
+            table_data.extend_from_slice(data);
+        }
+        let compressed_data = encode_uncompressed_brotli(&table_data);
+
+        let mut font = vec![];
+        font.extend_from_slice(WOFF2_SIGNATURE);
+        font.extend_from_slice(&flavor.to_be_bytes());
+        let length = 48 + directory.len() + compressed_data.len();
+        font.extend_from_slice(&(length as u32).to_be_bytes());
+        font.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        font.extend_from_slice(&[0, 0]); // reserved
+        font.extend_from_slice(&total_sfnt_size.to_be_bytes());
+        font.extend_from_slice(&(compressed_data.len() as u32).to_be_bytes());
+        font.extend_from_slice(&[0; 2 + 2 + 5 * 4]); // version, meta and private data
+        font.extend_from_slice(&directory);
+        font.extend_from_slice(&compressed_data);
+        font
+    }
+
+    /// Encodes a single table as WOFF font with the given compressed data and original length
+    fn encode_woff(tag: u32, compressed_data: &[u8], original_length: u32) -> Vec<u8> {
+        let mut font = vec![];
+        font.extend_from_slice(WOFF_SIGNATURE);
+        font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        font.extend_from_slice(&0u32.to_be_bytes()); // length
+        font.extend_from_slice(&1u16.to_be_bytes());
+        font.extend_from_slice(&[0; 2 + 4 + 2 + 2 + 5 * 4]);
+        font.extend_from_slice(&tag.to_be_bytes());
+        font.extend_from_slice(&(44u32 + 20).to_be_bytes());
+        font.extend_from_slice(&(compressed_data.len() as u32).to_be_bytes());
+        font.extend_from_slice(&original_length.to_be_bytes());
+        font.extend_from_slice(&0u32.to_be_bytes()); // checksum
+        font.extend_from_slice(compressed_data);
+        font
+    }
+
+    fn render_text(font_data: &[u8]) -> Vec<f32> {
+        let font = rusttype::Font::try_from_bytes(font_data).unwrap();
+        let scale = rusttype::Scale::uniform(24.0);
+        let mut coverage = vec![];
+        for glyph in font.layout("Hello WOFF2 123", scale, rusttype::point(0.0, 24.0)) {
+            glyph.draw(|_, _, value| coverage.push(value));
+        }
+        coverage
+    }
+
+    #[test]
+    fn test_woff2_decodes_to_ttf_source() {
+        let ttf_data = load_font_data();
+        let (flavor, tables) = read_sfnt(&ttf_data);
+        let woff2_data = encode_woff2(flavor, &tables, ttf_data.len() as u32);
+
+        let decoded_data = decode_font(Cow::Owned(woff2_data)).unwrap();
+
+        assert_eq!(read_sfnt(&decoded_data), (flavor, tables));
+        let coverage = render_text(&decoded_data);
+        assert!(coverage.iter().any(|value| *value > 0.0));
+        assert_eq!(coverage, render_text(&ttf_data));
+    }
+
+    #[test]
+    fn test_woff2_decompression_is_limited_to_total_sfnt_size() {
+        let tables = vec![(HEAD_TAG, vec![0u8; 4096])];
+
+        let result = decode_woff2(&encode_woff2(0x0001_0000, &tables, 1024));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_woff_decompression_is_limited_to_original_length() {
+        let compressed_data = miniz_oxide::deflate::compress_to_vec_zlib(&[0u8; 1024 * 1024], 6);
+        let original_length = compressed_data.len() as u32 + 1;
+
+        let result = decode_woff(&encode_woff(HEAD_TAG, &compressed_data, original_length));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_glyph_without_points_is_rejected() {
+        let mut data = vec![0, 0, 0, 0]; // reserved, option flags
+        data.extend_from_slice(&1u16.to_be_bytes()); // number of glyphs
+        data.extend_from_slice(&0u16.to_be_bytes()); // index format
+        for stream_size in [2u32, 1, 0, 0, 0, 4, 0] {
+            data.extend_from_slice(&stream_size.to_be_bytes());
+        }
+        data.extend_from_slice(&1i16.to_be_bytes()); // one contour
+        data.push(0); // without points
+        data.extend_from_slice(&[0; 4]); // bounding box bitmap
+
+        assert!(reconstruct_glyf(&data).is_err());
+    }
+
+    #[test]
+    fn test_too_many_tables_are_rejected() {
+        let tables = (0..5000).map(|tag| (tag, vec![])).collect();
+
+        assert!(build_sfnt(0x0001_0000, tables).is_err());
+    }
+}
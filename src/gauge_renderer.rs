@@ -0,0 +1,304 @@
+use image::{Rgba, RgbaImage};
+use imageproc::point::Point;
+
+use crate::{hex_to_rgba_or_default, GaugeConfig, GaugeTick};
+
+/// Maximum length of a polygon edge along the arc in pixels
+const MAX_ARC_SEGMENT_LENGTH: f32 = 2.0;
+
+/// Renders a gauge based on the given config on a transparent image
+/// The track spans the whole arc, the fill spans the part of the arc proportional to the
+/// sensor value within the configured value range. Without a sensor value only the track is drawn.
+/// The tick marks are drawn last, so they stay visible over the fill.
+pub fn render(gauge_config: &GaugeConfig, sensor_value: Option<f64>) -> RgbaImage {
+    let mut image = RgbaImage::new(gauge_config.width, gauge_config.height);
+
+    let sweep_angle = get_sweep_angle(gauge_config);
+    let track_color = hex_to_rgba_or_default(&gauge_config.track_color);
+    let fill_color = hex_to_rgba_or_default(&gauge_config.fill_color);
+
+    draw_arc(&mut image, gauge_config, sweep_angle, track_color);
+
+    let fill_ratio = sensor_value
+        .map(|value| get_fill_ratio(gauge_config, value))
+        .unwrap_or(0.0);
+    if fill_ratio > 0.0 {
+        draw_arc(
+            &mut image,
+            gauge_config,
+            sweep_angle * fill_ratio,
+            fill_color,
+        );
+    }
+
+    for tick in &gauge_config.ticks {
+        draw_tick(&mut image, gauge_config, sweep_angle, tick);
+    }
+
+    image
+}
+
+/// Returns the angle from the start to the end of the arc in degrees
+/// A negative angle runs counter-clockwise, equal start and end angles describe a full circle
+fn get_sweep_angle(gauge_config: &GaugeConfig) -> f32 {
+    let sweep_angle = gauge_config.end_angle - gauge_config.start_angle;
+    if sweep_angle == 0.0 {
+        360.0
+    } else {
+        sweep_angle.clamp(-360.0, 360.0)
+    }
+}
+
+/// Returns the pixel aspect ratio of the gauge, defaults to 1 if not set
+fn get_pixel_aspect_ratio(gauge_config: &GaugeConfig) -> f32 {
+    if gauge_config.pixel_aspect_ratio > 0.0 {
+        gauge_config.pixel_aspect_ratio
+    } else {
+        1.0
+    }
+}
+
+/// Returns the center and the outer radius of the arc, centered in the image and as large as possible
+fn get_arc_bounds(image: &RgbaImage, pixel_aspect_ratio: f32) -> ((f32, f32), f32) {
+    let center = (image.width() as f32 / 2.0, image.height() as f32 / 2.0);
+    let outer_radius = center.1.min(center.0 * pixel_aspect_ratio);
+    (center, outer_radius)
+}
+
+/// Returns the position of the sensor value within the value range, between 0 and 1
+/// If the min value is greater than the max value, the gauge is inverted and fills towards the min value.
+fn get_fill_ratio(gauge_config: &GaugeConfig, sensor_value: f64) -> f32 {
+    let value_range = gauge_config.max_sensor_value - gauge_config.min_sensor_value;
+    if value_range == 0.0 || !value_range.is_finite() || !sensor_value.is_finite() {
+        return 0.0;
+    }
+
+    ((sensor_value - gauge_config.min_sensor_value) / value_range).clamp(0.0, 1.0) as f32
+}
+
+/// Draws the arc from the start angle over the given sweep angle as filled polygon
+/// The arc is centered in the image and as large as possible. Its horizontal radius is narrowed
+/// by the pixel aspect ratio, so the gauge appears round on a panel with non-square pixels.
+fn draw_arc(image: &mut RgbaImage, gauge_config: &GaugeConfig, sweep_angle: f32, color: Rgba<u8>) {
+    let pixel_aspect_ratio = get_pixel_aspect_ratio(gauge_config);
+    let stroke_width = gauge_config.stroke_width.max(1) as f32;
+
+    let (center, outer_radius) = get_arc_bounds(image, pixel_aspect_ratio);
+    let inner_radius = (outer_radius - stroke_width).max(0.0);
+    if outer_radius <= 0.0 {
+        return;
+    }
+
+    let segment_count = (sweep_angle.abs().to_radians() * outer_radius / MAX_ARC_SEGMENT_LENGTH)
+        .ceil()
+        .max(1.0) as usize;
+    let get_arc_point = |radius: f32, segment: usize| {
+        let angle = (gauge_config.start_angle
+            + sweep_angle * segment as f32 / segment_count as f32)
+            .to_radians();
+        Point::new(
+            (center.0 + angle.cos() * radius / pixel_aspect_ratio).round() as i32,
+            (center.1 + angle.sin() * radius).round() as i32,
+        )
+    };
+
+    // The outer edge of the arc from the start to the end, followed by the inner edge back
+    let mut polygon: Vec<Point<i32>> = Vec::with_capacity(2 * (segment_count + 1));
+    let outer_edge = (0..=segment_count).map(|segment| get_arc_point(outer_radius, segment));
+    let inner_edge = (0..=segment_count)
+        .rev()
+        .map(|segment| get_arc_point(inner_radius, segment));
+    for point in outer_edge.chain(inner_edge) {
+        if polygon.last() != Some(&point) {
+            polygon.push(point);
+        }
+    }
+    while polygon.len() > 1 && polygon.first() == polygon.last() {
+        polygon.pop();
+    }
+    if polygon.len() < 3 {
+        return;
+    }
+
+    imageproc::drawing::draw_polygon_mut(image, &polygon, color);
+}
+
+/// Draws a tick mark as radial line at the angle of the tick value, inwards from the outer edge
+/// Ticks outside of the value range are not drawn
+fn draw_tick(
+    image: &mut RgbaImage,
+    gauge_config: &GaugeConfig,
+    sweep_angle: f32,
+    tick: &GaugeTick,
+) {
+    // The min value may be greater than the max value on an inverted gauge
+    let min_sensor_value = gauge_config.min_sensor_value;
+    let max_sensor_value = gauge_config.max_sensor_value;
+    let value_range =
+        min_sensor_value.min(max_sensor_value)..=min_sensor_value.max(max_sensor_value);
+    if !value_range.contains(&tick.value) {
+        return;
+    }
+
+    let pixel_aspect_ratio = get_pixel_aspect_ratio(gauge_config);
+    let (center, outer_radius) = get_arc_bounds(image, pixel_aspect_ratio);
+    if outer_radius <= 0.0 {
+        return;
+    }
+    let length = if tick.length > 0 {
+        tick.length
+    } else {
+        gauge_config.stroke_width.max(1)
+    } as f32;
+    // Keep the outer end of the mark within the image
+    let outer_radius = outer_radius - 0.5;
+    let inner_radius = (outer_radius - length).max(0.0);
+
+    let angle = (gauge_config.start_angle + sweep_angle * get_fill_ratio(gauge_config, tick.value))
+        .to_radians();
+    let get_point = |radius: f32| {
+        (
+            center.0 + angle.cos() * radius / pixel_aspect_ratio,
+            center.1 + angle.sin() * radius,
+        )
+    };
+
+    imageproc::drawing::draw_line_segment_mut(
+        image,
+        get_point(outer_radius),
+        get_point(inner_radius),
+        hex_to_rgba_or_default(&tick.color),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::visible_bounds;
+
+    #[test]
+    fn test_tick_at_max_value_is_drawn_at_end_angle() {
+        let tick_color = Rgba([255, 0, 0, 255]);
+        let gauge_config = GaugeConfig {
+            min_sensor_value: 0.0,
+            max_sensor_value: 100.0,
+            start_angle: 180.0,
+            end_angle: 270.0,
+            width: 101,
+            height: 101,
+            stroke_width: 10,
+            ticks: vec![GaugeTick {
+                value: 100.0,
+                color: "#FF0000".to_string(),
+                length: 10,
+            }],
+            ..Default::default()
+        };
+
+        let image = render(&gauge_config, None);
+
+        // The end angle of 270 degrees points straight up from the center
+        let tick_pixels: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == tick_color)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!tick_pixels.is_empty());
+        assert!(tick_pixels.iter().all(|(x, y)| *x == 50 && *y <= 10));
+    }
+
+    #[test]
+    fn test_ticks_are_drawn_on_an_inverted_gauge() {
+        let tick_color = Rgba([255, 0, 0, 255]);
+        let gauge_config = GaugeConfig {
+            min_sensor_value: 100.0,
+            max_sensor_value: 0.0,
+            start_angle: 180.0,
+            end_angle: 270.0,
+            width: 101,
+            height: 101,
+            stroke_width: 10,
+            ticks: vec![GaugeTick {
+                value: 0.0,
+                color: "#FF0000".to_string(),
+                length: 10,
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(get_fill_ratio(&gauge_config, 75.0), 0.25);
+
+        let image = render(&gauge_config, None);
+
+        // The max value of 0 is at the end angle, which points straight up from the center
+        let tick_pixels: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == tick_color)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!tick_pixels.is_empty());
+        assert!(tick_pixels.iter().all(|(x, y)| *x == 50 && *y <= 10));
+    }
+
+    #[test]
+    fn test_arc_is_compensated_for_non_square_pixels() {
+        let arc_size = |pixel_aspect_ratio: f32| {
+            let gauge_config = GaugeConfig {
+                track_color: "#FFFFFF".to_string(),
+                width: 101,
+                height: 101,
+                stroke_width: 5,
+                pixel_aspect_ratio,
+                ..Default::default()
+            };
+            let (min_x, min_y, max_x, max_y) =
+                visible_bounds(&render(&gauge_config, None)).unwrap();
+            ((max_x - min_x + 1) as f32, (max_y - min_y + 1) as f32)
+        };
+
+        let (square_width, square_height) = arc_size(1.0);
+        let (wide_width, wide_height) = arc_size(2.0);
+
+        assert!((square_width - square_height).abs() <= 1.0);
+        // Each pixel is twice as wide as high, so the arc is half as many pixels wide as high
+        assert_eq!(wide_height, square_height);
+        assert!((wide_width - wide_height / 2.0).abs() <= 1.5);
+    }
+
+    #[test]
+    fn test_fill_is_proportional_to_the_sensor_value() {
+        let track_color = Rgba([64, 64, 64, 255]);
+        let fill_color = Rgba([0, 255, 0, 255]);
+        let gauge_config = GaugeConfig {
+            min_sensor_value: 0.0,
+            max_sensor_value: 100.0,
+            start_angle: 180.0,
+            end_angle: 360.0,
+            track_color: "#404040".to_string(),
+            fill_color: "#00FF00".to_string(),
+            width: 101,
+            height: 101,
+            stroke_width: 10,
+            ..Default::default()
+        };
+        let count_pixels = |image: &RgbaImage, color: Rgba<u8>, columns: std::ops::Range<u32>| {
+            image
+                .enumerate_pixels()
+                .filter(|(x, _, pixel)| columns.contains(x) && **pixel == color)
+                .count()
+        };
+
+        let empty_image = render(&gauge_config, Some(0.0));
+        let half_image = render(&gauge_config, Some(50.0));
+        let full_image = render(&gauge_config, Some(100.0));
+
+        // The arc runs clockwise over the top, from the left to the right
+        assert_eq!(count_pixels(&empty_image, fill_color, 0..101), 0);
+        assert!(count_pixels(&empty_image, track_color, 0..101) > 0);
+        assert!(count_pixels(&half_image, fill_color, 0..49) > 0);
+        assert_eq!(count_pixels(&half_image, fill_color, 52..101), 0);
+        assert!(count_pixels(&half_image, track_color, 52..101) > 0);
+        assert!(count_pixels(&full_image, fill_color, 52..101) > 0);
+        assert_eq!(count_pixels(&full_image, track_color, 0..101), 0);
+    }
+}
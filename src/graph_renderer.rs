@@ -1,148 +1,668 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::io::{BufWriter, Cursor};
+use std::sync::{Arc, Mutex, OnceLock};
 
-use image::{ImageBuffer, Rgba, RgbaImage};
+use image::{ImageBuffer, Pixel, Rgba, RgbaImage};
+use log::error;
 
-use crate::{hex_to_rgba, GraphConfig, GraphType};
+use rusttype::Font;
+
+use crate::{
+    hex_to_rgba_or_default, palette_renderer, text_renderer, AnnotationTarget,
+    GraphAnnotationConfig, GraphConfig, GraphLegendConfig, GraphSeries, GraphType,
+};
 
 /// Renders a graph based on the given config
 /// # Returns
-/// A vector of bytes containing the RGB8 png image
+/// A vector of bytes containing the RGB8 png image, empty if the image can not be encoded,
+/// e.g. because the graph has no width or height
 /// # Arguments
 /// * `graph_config` - The config for the graph
 pub fn render(graph_config: &GraphConfig) -> Vec<u8> {
+    let image = render_image(graph_config);
+
+    // Encode to png and return encoded bytes
+    let mut writer = BufWriter::new(Cursor::new(Vec::new()));
+    if let Err(err) = image.write_to(&mut writer, image::ImageOutputFormat::Png) {
+        error!("Failed to encode graph: {}", err);
+        return vec![];
+    }
+
+    writer.into_inner().unwrap().into_inner()
+}
+
+/// Renders a graph based on the given config, like `render`
+/// # Returns
+/// A vector of bytes containing the palette-indexed png image, see `palette_renderer::render`
+/// # Arguments
+/// * `graph_config` - The config for the graph
+pub fn render_indexed(graph_config: &GraphConfig) -> Result<Vec<u8>, String> {
+    let image = render_image(graph_config);
+    palette_renderer::render(&image)
+}
+
+/// Renders a graph based on the given config to an image
+fn render_image(graph_config: &GraphConfig) -> RgbaImage {
     let width = graph_config.width;
-    let height = graph_config.height;
 
     // Prepare the data for the graph
-    let graph_data = prepare_graph_data(width, &graph_config.sensor_values);
+    let graph_data = match graph_config.time_window_ms {
+        Some(time_window_ms) => prepare_time_window_graph_data(
+            width,
+            &graph_config.sensor_values,
+            &graph_config.sensor_timestamps,
+            time_window_ms,
+        ),
+        None => prepare_graph_data(width, &graph_config.sensor_values),
+    };
 
     // Render the graph
     let mut image = match graph_config.graph_type {
         GraphType::Line => render_line_chart(&graph_data, graph_config),
         GraphType::LineFill => render_line_chart_filled(&graph_data, graph_config),
+        GraphType::Band => render_band_chart(&graph_data, graph_config),
+        GraphType::Bar => render_bar_chart(graph_config),
     };
 
-    // Draw border if border is visible
-    if !graph_config.border_color.ends_with("00") {
-        draw_border(&mut image, &graph_config.border_color, width, height);
+    // Draw the additional series as lines on top of the graph
+    // The upper boundary of a band is only drawn if the boundaries should be stroked
+    let skipped_series = match graph_config.graph_type {
+        GraphType::Band if !graph_config.band_stroke_boundaries => 1,
+        _ => 0,
+    };
+    let value_range = get_value_range(&graph_data, graph_config);
+    for series in graph_config.additional_series.iter().skip(skipped_series) {
+        let series_data = prepare_graph_data(width, &series.sensor_values);
+        draw_line_series(
+            &mut image,
+            &series_data,
+            get_series_value_range(series, value_range),
+            hex_to_rgba_or_default(&series.graph_color),
+            graph_config,
+        );
     }
 
-    // Encode to png and return encoded bytes
-    let mut writer = BufWriter::new(Cursor::new(Vec::new()));
-    image
-        .write_to(&mut writer, image::ImageOutputFormat::Png)
-        .unwrap();
+    // Composite the graph between its cached background and border
+    // so translucent lines and fills blend with a translucent background instead of replacing it
+    // The result keeps the alpha of the background, so it blends with whatever is beneath the graph element
+    let background = get_background(graph_config);
+    let mut composed_image = background.background.clone();
+    draw_grid(&mut composed_image, graph_config, value_range);
+    image::imageops::overlay(&mut composed_image, &image, 0, 0);
+    if let Some(border) = &background.border {
+        image::imageops::overlay(&mut composed_image, border, 0, 0);
+    }
 
-    writer.into_inner().unwrap().into_inner()
+    composed_image
+}
+
+/// Draws the horizontal grid lines and the zero baseline, blended with the background
+/// Grid lines are evenly spaced between the top and the bottom edge, which are left out
+fn draw_grid(image: &mut RgbaImage, config: &GraphConfig, (min_value, max_value): (f64, f64)) {
+    let height = config.height;
+    let grid_color = if config.grid_color.is_empty() {
+        None
+    } else {
+        Some(hex_to_rgba_or_default(&config.grid_color)).filter(|color| color[3] != 0)
+    };
+
+    let mut line_rows = vec![];
+    if let Some(grid_color) = grid_color {
+        let spacing = height as f64 / (config.grid_line_count + 1) as f64;
+        line_rows.extend(
+            (1..=config.grid_line_count)
+                .map(|index| ((index as f64 * spacing).round() as u32, grid_color)),
+        );
+    }
+
+    if config.show_baseline && min_value < 0.0 && max_value > 0.0 {
+        let baseline_color =
+            grid_color.unwrap_or_else(|| hex_to_rgba_or_default(&config.graph_color));
+        let zero_normalized = normalize(0.0, (min_value, max_value));
+        let img_point = zero_normalized * height as f64;
+        let y = if config.invert_y {
+            img_point
+        } else {
+            height as f64 - img_point
+        };
+        line_rows.push((y.round() as u32, baseline_color));
+    }
+
+    for (y, color) in line_rows {
+        if y >= height {
+            continue;
+        }
+        for x in 0..image.width() {
+            image.get_pixel_mut(x, y).blend(&color);
+        }
+    }
+}
+
+/// The static layers of a graph, that only change if the config changes
+struct GraphBackground {
+    background: RgbaImage,
+    border: Option<RgbaImage>,
+}
+
+/// The maximum number of cached graph backgrounds, the cache is cleared if exceeded
+const MAX_CACHED_BACKGROUNDS: usize = 64;
+
+/// Cache of the static graph layers, keyed by the hash of the background-affecting config fields
+static BACKGROUND_CACHE: OnceLock<Mutex<HashMap<u64, Arc<GraphBackground>>>> = OnceLock::new();
+
+/// Returns the static layers of the graph, rendered once per distinct background config
+fn get_background(graph_config: &GraphConfig) -> Arc<GraphBackground> {
+    let key = get_background_hash(graph_config);
+    let mut cache = BACKGROUND_CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if let Some(background) = cache.get(&key) {
+        return background.clone();
+    }
+
+    let background = Arc::new(render_background(graph_config));
+    if cache.len() >= MAX_CACHED_BACKGROUNDS {
+        cache.clear();
+    }
+    cache.insert(key, background.clone());
+    background
+}
+
+/// Hashes all config fields that affect the static layers of the graph
+fn get_background_hash(graph_config: &GraphConfig) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    graph_config.width.hash(&mut hasher);
+    graph_config.height.hash(&mut hasher);
+    graph_config.background_color.hash(&mut hasher);
+    graph_config.border_color.hash(&mut hasher);
+    graph_config.border_width.to_bits().hash(&mut hasher);
+    graph_config.antialias.hash(&mut hasher);
+    get_pixel_aspect_ratio(graph_config)
+        .to_bits()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Renders the static layers of the graph, the background fill and the border if it is visible
+fn render_background(graph_config: &GraphConfig) -> GraphBackground {
+    let width = graph_config.width;
+    let height = graph_config.height;
+
+    let background = RgbaImage::from_pixel(
+        width,
+        height,
+        hex_to_rgba_or_default(&graph_config.background_color),
+    );
+
+    // The border is only drawn if it is visible
+    let border_color = hex_to_rgba_or_default(&graph_config.border_color);
+    let border = if border_color[3] != 0 {
+        let mut border = RgbaImage::new(width, height);
+        draw_border(
+            &mut border,
+            &graph_config.border_color,
+            graph_config.border_width,
+            get_pixel_aspect_ratio(graph_config),
+            graph_config.antialias,
+        );
+        Some(border)
+    } else {
+        None
+    };
+
+    GraphBackground { background, border }
+}
+
+/// Returns the pixel aspect ratio of the graph, 1 if not set
+fn get_pixel_aspect_ratio(graph_config: &GraphConfig) -> f32 {
+    if graph_config.pixel_aspect_ratio > 0.0 {
+        graph_config.pixel_aspect_ratio
+    } else {
+        1.0
+    }
 }
 
 /// Draws a border around the specified image
+/// If anti-aliasing is enabled, the border supports sub-pixel widths and partially covered
+/// pixels are blended with the border color according to their coverage.
+/// The left and right border are narrowed by the pixel aspect ratio,
+/// so all sides appear equally wide on a panel with non-square pixels.
 fn draw_border(
     image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
     border_color: &str,
-    width: u32,
-    height: u32,
+    border_width: f32,
+    pixel_aspect_ratio: f32,
+    antialias: bool,
 ) {
-    let border_color = hex_to_rgba(border_color);
-    let border_x: i32 = 0;
-    let border_y: i32 = 0;
-    let border_width: u32 = width;
-    let border_height: u32 = height;
-    imageproc::drawing::draw_hollow_rect_mut(
-        image,
-        imageproc::rect::Rect::at(border_x, border_y).of_size(border_width, border_height),
-        border_color,
-    );
+    let border_color = hex_to_rgba_or_default(border_color);
+    let border_width = if border_width > 0.0 {
+        border_width
+    } else {
+        1.0
+    };
+    let width = image.width();
+    let height = image.height();
+
+    let horizontal_border_width = border_width / pixel_aspect_ratio;
+
+    if !antialias {
+        // Fill all pixels within the border width from any edge
+        let border_width = border_width.round().max(1.0) as u32;
+        let horizontal_border_width = horizontal_border_width.round().max(1.0) as u32;
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let is_border = x < horizontal_border_width
+                || x >= width.saturating_sub(horizontal_border_width)
+                || y < border_width
+                || y >= height.saturating_sub(border_width);
+            if is_border {
+                *pixel = border_color;
+            }
+        }
+        return;
+    }
+
+    // The border covers the whole image except the inner rect
+    let inner_left = horizontal_border_width;
+    let inner_top = border_width;
+    let inner_right = width as f32 - horizontal_border_width;
+    let inner_bottom = height as f32 - border_width;
+
+    for y in 0..height {
+        for x in 0..width {
+            let overlap_x = get_overlap(x as f32, x as f32 + 1.0, inner_left, inner_right);
+            let overlap_y = get_overlap(y as f32, y as f32 + 1.0, inner_top, inner_bottom);
+            let coverage = 1.0 - overlap_x * overlap_y;
+            if coverage <= 0.0 {
+                continue;
+            }
+
+            let mut color = border_color;
+            color[3] = (border_color[3] as f32 * coverage).round() as u8;
+            image.get_pixel_mut(x, y).blend(&color);
+        }
+    }
+}
+
+/// Returns the length of the overlap between the ranges [start_a, end_a] and [start_b, end_b]
+fn get_overlap(start_a: f32, end_a: f32, start_b: f32, end_b: f32) -> f32 {
+    (end_a.min(end_b) - start_a.max(start_b)).max(0.0)
 }
 
 /// Prepares the plot data for the graph.
 /// Aligns the sensor values to the width of the desired graph width.
-fn prepare_graph_data(width: u32, sensor_values: &Vec<f64>) -> Vec<f64> {
+/// Without sensor values there is nothing to plot, a single sensor value is plotted as flat line.
+fn prepare_graph_data(width: u32, sensor_values: &[f64]) -> Vec<f64> {
+    let width = width as usize;
+    match sensor_values {
+        [] => return vec![],
+        [sensor_value] => return vec![*sensor_value; width],
+        _ => {}
+    }
+
     // Ensure that sensor values does not exceed the width, if so cut them and keep the last values
-    let sensor_values = if sensor_values.len() > width as usize {
-        sensor_values[(sensor_values.len() - width as usize)..].to_vec()
-    } else {
-        sensor_values.to_vec()
-    };
+    let sensor_values = &sensor_values[sensor_values.len().saturating_sub(width)..];
 
     // Create a new vector for the width of the image, initialize with 0
-    let mut plot_data: Vec<f64> = vec![0.0; (width) as usize];
+    let mut plot_data: Vec<f64> = vec![0.0; width];
 
     // Set the gen values to the end of plat data
-    plot_data.splice(
-        (width - sensor_values.len() as u32) as usize..,
-        sensor_values,
-    );
+    // The start index saturates, so this can never underflow even if the values exceed the width
+    let start_index = width.saturating_sub(sensor_values.len());
+    plot_data.splice(start_index.., sensor_values.iter().copied());
+
+    debug_assert_eq!(plot_data.len(), width);
     plot_data
 }
 
-/// Renders a graph based on the given config
+/// Prepares the plot data for a graph in time window mode.
+/// The time window ends with the latest timestamp and is mapped onto the graph width.
+/// Values older than the time window are dropped,
+/// the pixels between two values are linearly interpolated.
+fn prepare_time_window_graph_data(
+    width: u32,
+    sensor_values: &[f64],
+    sensor_timestamps: &[u64],
+    time_window_ms: u64,
+) -> Vec<f64> {
+    let width = width as usize;
+
+    // Without sensor values there is nothing to plot
+    let latest_timestamp = match sensor_timestamps.last() {
+        Some(latest_timestamp) => *latest_timestamp,
+        None => return vec![],
+    };
+
+    // Create a new vector for the width of the image, initialize with 0
+    let mut plot_data: Vec<f64> = vec![0.0; width];
+    if width == 0 {
+        return plot_data;
+    }
+
+    // Map the timestamps of all values within the window to their x position
+    let window_start = latest_timestamp.saturating_sub(time_window_ms);
+    let max_x = (width - 1) as f64;
+    let points: Vec<(f64, f64)> = sensor_timestamps
+        .iter()
+        .zip(sensor_values)
+        .filter(|(timestamp, _)| **timestamp >= window_start)
+        .map(|(timestamp, value)| {
+            let elapsed = (timestamp - window_start) as f64;
+            (elapsed / time_window_ms.max(1) as f64 * max_x, *value)
+        })
+        .collect();
+
+    // Interpolate between the points, pixels left of the first point stay at 0
+    let mut next_point_index = 0;
+    for (x, plot_value) in plot_data.iter_mut().enumerate() {
+        let x = x as f64;
+        while next_point_index < points.len() && points[next_point_index].0 < x {
+            next_point_index += 1;
+        }
+
+        // The previous point lies left of the pixel, the next point on or right of it
+        let previous_point = next_point_index.checked_sub(1).map(|index| points[index]);
+        *plot_value = match (previous_point, points.get(next_point_index)) {
+            (Some((x0, y0)), Some((x1, y1))) => y0 + (y1 - y0) * (x - x0) / (x1 - x0),
+            (None, Some((x1, y1))) if *x1 == x => *y1,
+            (Some((_, y0)), None) => y0,
+            _ => 0.0,
+        };
+    }
+
+    plot_data
+}
+
+/// Renders a graph based on the given config on a transparent image
 fn render_line_chart(numbers: &[f64], config: &GraphConfig) -> RgbaImage {
-    let width = config.width;
-    let height = config.height;
-    let min_value = config.min_sensor_value.unwrap_or(get_min(numbers));
-    let max_value = config.max_sensor_value.unwrap_or(get_max(numbers));
-    let line_width = config.graph_stroke_width;
-    let line_color = hex_to_rgba(&config.graph_color);
-    let background_color = hex_to_rgba(&config.background_color);
+    let value_range = get_value_range(numbers, config);
+    let line_color = hex_to_rgba_or_default(&config.graph_color);
+
+    let mut image = RgbaImage::new(config.width, config.height);
+    draw_line_series(&mut image, numbers, value_range, line_color, config);
+    if config.show_points {
+        draw_points(&mut image, numbers, value_range, line_color, config);
+    }
+
+    image
+}
+
+/// Draws a filled dot at each plotted sensor value, on top of the line
+/// The padding before the first sensor value is skipped
+/// The values are normalized to the image height like the line
+fn draw_points(
+    image: &mut RgbaImage,
+    numbers: &[f64],
+    (min_value, max_value): (f64, f64),
+    point_color: Rgba<u8>,
+    config: &GraphConfig,
+) {
+    let height = config.height as f64;
+    let first_value_index = numbers.len().saturating_sub(config.sensor_values.len());
+
+    for (x, value) in numbers.iter().enumerate().skip(first_value_index) {
+        let value_normalized = normalize(*value, (min_value, max_value));
+        let img_point = value_normalized * height;
+        let y = if config.invert_y {
+            img_point
+        } else {
+            height - img_point
+        };
 
-    let mut image = RgbaImage::from_pixel(width, height, background_color);
-    let half_line_width = (line_width / 2) as f32;
+        // The horizontal radius is narrowed by the pixel aspect ratio, so the dots appear round
+        let radius = config.point_radius.max(0);
+        let horizontal_radius = (radius as f32 / get_pixel_aspect_ratio(config)).round() as i32;
+        imageproc::drawing::draw_filled_ellipse_mut(
+            image,
+            (x as i32, y.round() as i32),
+            horizontal_radius,
+            radius,
+            point_color,
+        );
+    }
+}
+
+/// Draws the given values as line on the image
+/// The values are normalized to the image height using the given min and max value
+fn draw_line_series(
+    image: &mut RgbaImage,
+    numbers: &[f64],
+    (min_value, max_value): (f64, f64),
+    line_color: Rgba<u8>,
+    config: &GraphConfig,
+) {
+    let height = config.height;
 
-    for i in 0..numbers.len() - 1 {
+    for i in 0..numbers.len().saturating_sub(1) {
         let current_value = numbers[i];
         let next_value = numbers[i + 1];
 
         // First move value between 0 and 1, where min_value is the lower bound and max_value the upper bound
-        let current_value_normalized = (current_value - min_value) / (max_value - min_value);
-        let next_value_normalized = (next_value - min_value) / (max_value - min_value);
+        let current_value_normalized = normalize(current_value, (min_value, max_value));
+        let next_value_normalized = normalize(next_value, (min_value, max_value));
 
         // Then move the value between 0 and height
         let img_line_start = current_value_normalized * height as f64;
         let img_line_end = next_value_normalized * height as f64;
 
         // Render line on image
+        // If the y-axis is inverted, the values grow from the top to the bottom
         let x0 = i;
-        let y0 = height as f64 - img_line_start;
+        let y0 = if config.invert_y {
+            img_line_start
+        } else {
+            height as f64 - img_line_start
+        };
 
         let x1 = i + 1;
-        let y1 = height as f64 - img_line_end;
+        let y1 = if config.invert_y {
+            img_line_end
+        } else {
+            height as f64 - img_line_end
+        };
 
         // Draw graph line
-        for offset in -half_line_width as i32..=half_line_width as i32 {
-            imageproc::drawing::draw_line_segment_mut(
-                &mut image,
-                ((x0 as f32) + offset as f32, y0 as f32),
-                ((x1 as f32) + offset as f32, y1 as f32),
-                line_color,
+        draw_stroke(
+            image,
+            (x0 as f32, y0 as f32),
+            (x1 as f32, y1 as f32),
+            config.graph_stroke_width,
+            line_color,
+            config.antialias,
+        );
+    }
+}
+
+/// Draws a line segment with the given stroke width
+/// The stroke is thickened perpendicular to the segment direction, by drawing parallel lines,
+/// so steep segments are as thick as flat ones. If anti-aliasing is enabled,
+/// each line is drawn anti-aliased and blended with the image according to its coverage.
+fn draw_stroke(
+    image: &mut RgbaImage,
+    start: (f32, f32),
+    end: (f32, f32),
+    stroke_width: i32,
+    color: Rgba<u8>,
+    antialias: bool,
+) {
+    let half_stroke_width = stroke_width / 2;
+    let (delta_x, delta_y) = (end.0 - start.0, end.1 - start.1);
+    let length = delta_x.hypot(delta_y);
+    let normal = if length > 0.0 {
+        (-delta_y / length, delta_x / length)
+    } else {
+        (1.0, 0.0)
+    };
+
+    for offset in -half_stroke_width..=half_stroke_width {
+        let (offset_x, offset_y) = (normal.0 * offset as f32, normal.1 * offset as f32);
+        let line_start = (start.0 + offset_x, start.1 + offset_y);
+        let line_end = (end.0 + offset_x, end.1 + offset_y);
+
+        if antialias {
+            imageproc::drawing::draw_antialiased_line_segment_mut(
+                image,
+                (line_start.0.round() as i32, line_start.1.round() as i32),
+                (line_end.0.round() as i32, line_end.1.round() as i32),
+                color,
+                blend_coverage,
             );
+        } else {
+            imageproc::drawing::draw_line_segment_mut(image, line_start, line_end, color);
+        }
+    }
+}
+
+/// Blends the color onto the pixel, weighted by the coverage of the pixel
+fn blend_coverage(color: Rgba<u8>, pixel: Rgba<u8>, coverage: f32) -> Rgba<u8> {
+    let mut color = color;
+    color[3] = (color[3] as f32 * coverage).round().clamp(0.0, 255.0) as u8;
+    let mut pixel = pixel;
+    pixel.blend(&color);
+    pixel
+}
+
+/// Renders a bar graph based on the given config on a transparent image
+/// Each sensor value is drawn as a filled bar, the bars share the width evenly,
+/// separated by the configured gap. If there are more values than pixels, the latest values are kept.
+fn render_bar_chart(config: &GraphConfig) -> RgbaImage {
+    let width = config.width;
+    let height = config.height;
+    let bar_color = hex_to_rgba_or_default(&config.graph_color);
+
+    let mut image = RgbaImage::new(width, height);
+    let numbers =
+        &config.sensor_values[config.sensor_values.len().saturating_sub(width as usize)..];
+    if numbers.is_empty() {
+        return image;
+    }
+    let (min_value, max_value) = get_value_range(numbers, config);
+
+    let bar_count = numbers.len() as f64;
+    for (i, value) in numbers.iter().enumerate() {
+        // First move value between 0 and 1, where min_value is the lower bound and max_value the upper bound
+        let value_normalized = normalize(*value, (min_value, max_value));
+
+        // Then move the value between 0 and height
+        let bar_height = (value_normalized * height as f64)
+            .round()
+            .clamp(0.0, height as f64) as u32;
+
+        // Each bar gets an even share of the width, minus the gap to the next bar
+        let bar_start = (i as f64 * width as f64 / bar_count).round() as u32;
+        let bar_end = ((i + 1) as f64 * width as f64 / bar_count).round() as u32;
+        let bar_width = bar_end
+            .saturating_sub(bar_start)
+            .saturating_sub(config.bar_gap);
+        if bar_width == 0 || bar_height == 0 {
+            continue;
         }
+
+        // If the y-axis is inverted, the bars grow from the top to the bottom
+        let bar_top = if config.invert_y {
+            0
+        } else {
+            height - bar_height
+        };
+        imageproc::drawing::draw_filled_rect_mut(
+            &mut image,
+            imageproc::rect::Rect::at(bar_start as i32, bar_top as i32)
+                .of_size(bar_width, bar_height),
+            bar_color,
+        );
     }
 
     image
 }
 
-/// Renders a graph based on the given config
+/// Renders a band graph based on the given config on a transparent image
+/// The area between the sensor series and the first additional series is filled,
+/// the padding before the shorter of both series is left empty
+fn render_band_chart(numbers: &[f64], config: &GraphConfig) -> RgbaImage {
+    let height = config.height as f64;
+    let value_range = get_value_range(numbers, config);
+    let line_color = hex_to_rgba_or_default(&config.graph_color);
+    let fill_color = if config.band_fill_color.is_empty() {
+        let mut fill_color = line_color;
+        fill_color[3] /= 2;
+        fill_color
+    } else {
+        hex_to_rgba_or_default(&config.band_fill_color)
+    };
+
+    let mut image = RgbaImage::new(config.width, config.height);
+    let bound_series = config.additional_series.first();
+    let bound_values = bound_series
+        .map(|series| series.sensor_values.as_slice())
+        .unwrap_or_default();
+    let bound_value_range = bound_series
+        .map(|series| get_series_value_range(series, value_range))
+        .unwrap_or(value_range);
+    let bound_numbers = prepare_graph_data(config.width, bound_values);
+
+    // Moves the value between 0 and height, if the y-axis is inverted the values grow from the top to the bottom
+    let to_img_y = |value: f64, value_range: (f64, f64)| {
+        let img_point = normalize(value, value_range) * height;
+        let y = if config.invert_y {
+            img_point
+        } else {
+            height - img_point
+        };
+        y.round().clamp(0.0, height) as u32
+    };
+
+    // A single sensor value is plotted across the whole width
+    let plotted_len = |len: usize| if len == 1 { numbers.len() } else { len };
+    let first_value_index = numbers.len().saturating_sub(
+        plotted_len(config.sensor_values.len())
+            .min(plotted_len(bound_values.len()))
+            .min(numbers.len()),
+    );
+    for (x, (value, bound_value)) in numbers
+        .iter()
+        .zip(&bound_numbers)
+        .enumerate()
+        .skip(first_value_index)
+    {
+        let y0 = to_img_y(*value, value_range);
+        let y1 = to_img_y(*bound_value, bound_value_range);
+        for y in y0.min(y1)..y0.max(y1) {
+            image.put_pixel(x as u32, y, fill_color);
+        }
+    }
+
+    if config.band_stroke_boundaries {
+        draw_line_series(&mut image, numbers, value_range, line_color, config);
+    }
+
+    image
+}
+
+/// Renders a filled graph based on the given config on a transparent image
 fn render_line_chart_filled(numbers: &[f64], config: &GraphConfig) -> RgbaImage {
     let width = config.width;
     let height = config.height;
-    let min_value = config.min_sensor_value.unwrap_or(get_min(numbers));
-    let max_value = config.max_sensor_value.unwrap_or(get_max(numbers));
+    let (min_value, max_value) = get_value_range(numbers, config);
     let line_width = config.graph_stroke_width;
-    let line_color = hex_to_rgba(&config.graph_color);
-    let fill_color = line_color;
-    let background_color = hex_to_rgba(&config.background_color);
+    let line_color = hex_to_rgba_or_default(&config.graph_color);
+    let fill_color = if config.fill_color.is_empty() {
+        line_color
+    } else {
+        hex_to_rgba_or_default(&config.fill_color)
+    };
 
-    let mut image = RgbaImage::from_pixel(width, height, background_color);
-    let half_line_width = (line_width / 2) as f32;
+    let mut image = RgbaImage::new(width, height);
 
-    for i in 0..numbers.len() - 1 {
+    for i in 0..numbers.len().saturating_sub(1) {
         let current_value = numbers[i];
         let next_value = numbers[i + 1];
 
         // First move value between 0 and 1, where min_value is the lower bound and max_value the upper bound
-        let current_value_normalized = (current_value - min_value) / (max_value - min_value);
-        let next_value_normalized = (next_value - min_value) / (max_value - min_value);
+        let current_value_normalized = normalize(current_value, (min_value, max_value));
+        let next_value_normalized = normalize(next_value, (min_value, max_value));
 
         // Then move the value between 0 and height
         let img_line_start = current_value_normalized * height as f64;
@@ -150,51 +670,800 @@ fn render_line_chart_filled(numbers: &[f64], config: &GraphConfig) -> RgbaImage
 
         // Render line on image
         // Avoid attempt to subtract with overflow by using saturating_sub
+        // If the y-axis is inverted, the values grow from the top to the bottom
         let x0 = i;
-        let y0 = height.saturating_sub(img_line_start as u32);
+        let y0 = if config.invert_y {
+            (img_line_start as u32).min(height)
+        } else {
+            height.saturating_sub(img_line_start as u32)
+        };
 
         let x1 = i + 1;
-        let y1 = height.saturating_sub(img_line_end as u32);
+        let y1 = if config.invert_y {
+            (img_line_end as u32).min(height)
+        } else {
+            height.saturating_sub(img_line_end as u32)
+        };
 
-        // Fill the area under the line until image bottom, respect the line width
-        let mut y = y0;
-        while y < height {
-            image.put_pixel(x0 as u32, y, fill_color);
-            y += 1;
+        // Fill the area under the line until image bottom, for every column of the segment
+        // The top edge of the fill is interpolated along the line, so the fill follows the curve
+        // If the y-axis is inverted, fill the area above the line until image top
+        // The last segment also fills its end column, all others leave it to the next segment
+        let last_column = if i + 2 == numbers.len() { x1 } else { x1 - 1 };
+        for x in x0..=last_column.min(width as usize - 1) {
+            let progress = (x - x0) as f64 / (x1 - x0) as f64;
+            let line_y = (y0 as f64 + (y1 as f64 - y0 as f64) * progress).round() as u32;
+            let fill_range = if config.invert_y {
+                0..line_y
+            } else {
+                line_y..height
+            };
+            for y in fill_range {
+                image.put_pixel(x as u32, y, fill_color);
+            }
         }
 
         // Draw graph line
-        for offset in -half_line_width as i32..=half_line_width as i32 {
-            imageproc::drawing::draw_line_segment_mut(
-                &mut image,
-                ((x0 as f32) + offset as f32, y0 as f32),
-                ((x1 as f32) + offset as f32, y1 as f32),
-                line_color,
-            );
+        draw_stroke(
+            &mut image,
+            (x0 as f32, y0 as f32),
+            (x1 as f32, y1 as f32),
+            line_width,
+            line_color,
+            config.antialias,
+        );
+    }
+
+    image
+}
+
+/// Returns the min and max value used to normalize the graph values
+/// Configured min and max values take precedence over the min and max of the values.
+/// If all values are equal, the range is widened around them, so the values are rendered as a
+/// centered flat line instead of dividing by zero. An equal configured min and max is kept,
+/// all values are then rendered at mid-height, see `normalize`.
+fn get_value_range(numbers: &[f64], config: &GraphConfig) -> (f64, f64) {
+    // Respect the values of the additional series as well, unless they are scaled on their own
+    let series_values = config
+        .additional_series
+        .iter()
+        .filter(|series| !has_own_value_range(series))
+        .flat_map(|series| series.sensor_values.iter().copied());
+    let min_value = config
+        .min_sensor_value
+        .unwrap_or_else(|| series_values.clone().fold(get_min(numbers), f64::min));
+    let max_value = config
+        .max_sensor_value
+        .unwrap_or_else(|| series_values.fold(get_max(numbers), f64::max));
+    let is_configured = config.min_sensor_value.is_some() && config.max_sensor_value.is_some();
+    if min_value == max_value && !is_configured {
+        return (min_value - 1.0, max_value + 1.0);
+    }
+    (min_value, max_value)
+}
+
+/// Moves the value between 0 and 1, where min_value is the lower bound and max_value the upper bound
+/// If the min and max are equal, every value is placed in the middle
+fn normalize(value: f64, (min_value, max_value): (f64, f64)) -> f64 {
+    if min_value == max_value {
+        return 0.5;
+    }
+    (value - min_value) / (max_value - min_value)
+}
+
+/// Returns true if the series is normalized against its own value range
+fn has_own_value_range(series: &GraphSeries) -> bool {
+    series.min_sensor_value.is_some() || series.max_sensor_value.is_some()
+}
+
+/// Returns the min and max value used to normalize the values of the additional series
+/// A series with its own min or max value is normalized independently, like on a secondary axis,
+/// an unset bound is taken from the values of the series. Otherwise the shared value range is used.
+fn get_series_value_range(series: &GraphSeries, shared_value_range: (f64, f64)) -> (f64, f64) {
+    if !has_own_value_range(series) {
+        return shared_value_range;
+    }
+
+    let min_value = series
+        .min_sensor_value
+        .unwrap_or_else(|| get_min(&series.sensor_values));
+    let max_value = series
+        .max_sensor_value
+        .unwrap_or_else(|| get_max(&series.sensor_values));
+    let is_configured = series.min_sensor_value.is_some() && series.max_sensor_value.is_some();
+    if min_value == max_value && !is_configured {
+        return (min_value - 1.0, max_value + 1.0);
+    }
+    (min_value, max_value)
+}
+
+/// Renders the legend of the graph, listing a color swatch and the label of each series
+pub fn render_legend(
+    graph_config: &GraphConfig,
+    legend_config: &GraphLegendConfig,
+    font: &Font,
+) -> RgbaImage {
+    let padding = 2;
+    let font_size = legend_config.font_size.max(1);
+    let font_scale = rusttype::Scale::uniform(font_size as f32);
+    let font_color = hex_to_rgba_or_default(&legend_config.font_color);
+    let background_color = hex_to_rgba_or_default(&legend_config.background_color);
+
+    // Collect the color and label of all series, labels fall back to the sensor id
+    let label_or_id = |label: &str, sensor_id: &str| {
+        if label.is_empty() {
+            sensor_id.to_string()
+        } else {
+            label.to_string()
         }
+    };
+    let mut entries = vec![(
+        hex_to_rgba_or_default(&graph_config.graph_color),
+        label_or_id(&graph_config.label, &graph_config.sensor_id),
+    )];
+    entries.extend(graph_config.additional_series.iter().map(|series| {
+        (
+            hex_to_rgba_or_default(&series.graph_color),
+            label_or_id(&series.label, &series.sensor_id),
+        )
+    }));
+
+    // Each row consists of the swatch, followed by the label
+    let max_label_width = entries
+        .iter()
+        .map(|(_, label)| text_renderer::get_text_width(font, font_scale, label))
+        .max()
+        .unwrap_or(0);
+    let row_height = font_size + padding;
+    let legend_width = padding + font_size + padding + max_label_width + padding;
+    let legend_height = padding + entries.len() as u32 * row_height;
+
+    let mut image = RgbaImage::from_pixel(legend_width, legend_height, background_color);
+    for (index, (color, label)) in entries.iter().enumerate() {
+        let row_y = padding + index as u32 * row_height;
+        imageproc::drawing::draw_filled_rect_mut(
+            &mut image,
+            imageproc::rect::Rect::at(padding as i32, row_y as i32).of_size(font_size, font_size),
+            *color,
+        );
+        text_renderer::draw_text_mut(
+            &mut image,
+            font_color,
+            (padding + font_size + padding) as i32,
+            row_y as i32,
+            font_scale,
+            font,
+            label,
+        );
     }
 
     image
 }
 
-/// Returns the minimum value of the given vector
-fn get_min(values: &[f64]) -> f64 {
-    let mut min = values[0];
-    for value in values {
-        if *value < min {
-            min = *value;
+/// Length of the connector between an annotated data point and its label in pixels
+const ANNOTATION_CONNECTOR_LENGTH: f32 = 4.0;
+
+/// Renders the value labels of the annotated data points on a transparent image of the graph size
+/// Labels of the latest and the maximum value are placed above their data point, labels of the
+/// minimum value below it. If there is no room on that side, the label is placed on the other side.
+/// Each label is connected to its data point by a short line and kept within the graph horizontally.
+pub fn render_annotations(
+    graph_config: &GraphConfig,
+    annotation_config: &GraphAnnotationConfig,
+    font: &Font,
+) -> RgbaImage {
+    let mut image = RgbaImage::new(graph_config.width, graph_config.height);
+    let font_scale = rusttype::Scale::uniform(annotation_config.font_size.max(1) as f32);
+    let font_color = hex_to_rgba_or_default(&annotation_config.font_color);
+
+    let points = get_plotted_points(graph_config);
+    let (min_point, max_point, latest_point) = match (
+        points
+            .iter()
+            .rev()
+            .copied()
+            .reduce(|a, b| if b.2 < a.2 { b } else { a }),
+        points
+            .iter()
+            .rev()
+            .copied()
+            .reduce(|a, b| if b.2 > a.2 { b } else { a }),
+        points.last(),
+    ) {
+        (Some(min_point), Some(max_point), Some(latest_point)) => {
+            (min_point, max_point, *latest_point)
         }
+        _ => return image,
+    };
+
+    // Pair each annotated point with whether its label prefers to be above it
+    let annotations = match annotation_config.target {
+        AnnotationTarget::Latest => vec![(latest_point, true)],
+        AnnotationTarget::Extrema if min_point == max_point => vec![(max_point, true)],
+        AnnotationTarget::Extrema => vec![(max_point, true), (min_point, false)],
+    };
+
+    let height = graph_config.height as f32;
+    for ((x, y, value), prefer_above) in annotations {
+        let label = format!(
+            "{:.*}{}",
+            annotation_config.decimal_places as usize, value, annotation_config.suffix
+        );
+        let (label_width, label_height) = text_renderer::measure_text(font, font_scale, &label);
+        let label_height = label_height as f32;
+
+        let fits_above = y - ANNOTATION_CONNECTOR_LENGTH - label_height >= 0.0;
+        let fits_below = y + ANNOTATION_CONNECTOR_LENGTH + label_height <= height;
+        let is_above = if prefer_above {
+            fits_above || !fits_below
+        } else {
+            !fits_below && fits_above
+        };
+        let (connector_end, label_y) = if is_above {
+            let connector_end = y - ANNOTATION_CONNECTOR_LENGTH;
+            (connector_end, connector_end - label_height)
+        } else {
+            let connector_end = y + ANNOTATION_CONNECTOR_LENGTH;
+            (connector_end, connector_end)
+        };
+
+        // Center the label over the data point, but keep it within the graph
+        let max_label_x = graph_config.width.saturating_sub(label_width) as f32;
+        let label_x = (x - label_width as f32 / 2.0).clamp(0.0, max_label_x);
+
+        draw_stroke(
+            &mut image,
+            (x, y),
+            (x, connector_end),
+            1,
+            font_color,
+            graph_config.antialias,
+        );
+        text_renderer::draw_text_mut(
+            &mut image,
+            font_color,
+            label_x.round() as i32,
+            label_y.round() as i32,
+            font_scale,
+            font,
+            &label,
+        );
+    }
+
+    image
+}
+
+/// Returns the position and the value of each plotted sensor value, from left to right
+/// The position is the center of the top of the bar for bar graphs,
+/// otherwise the data point of the line. The padding before the first sensor value is skipped.
+fn get_plotted_points(config: &GraphConfig) -> Vec<(f32, f32, f64)> {
+    let width = config.width;
+    let height = config.height as f64;
+    let to_y = |value: f64, value_range: (f64, f64)| {
+        let img_point = normalize(value, value_range) * height;
+        let y = if config.invert_y {
+            img_point
+        } else {
+            height - img_point
+        };
+        y.clamp(0.0, height) as f32
+    };
+
+    if config.graph_type == GraphType::Bar {
+        let numbers =
+            &config.sensor_values[config.sensor_values.len().saturating_sub(width as usize)..];
+        let value_range = get_value_range(numbers, config);
+        let bar_width = width as f64 / numbers.len() as f64;
+        return numbers
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let x = (i as f64 + 0.5) * bar_width - config.bar_gap as f64 / 2.0;
+                (x as f32, to_y(*value, value_range), *value)
+            })
+            .collect();
     }
-    min
+
+    let numbers = match config.time_window_ms {
+        Some(time_window_ms) => prepare_time_window_graph_data(
+            width,
+            &config.sensor_values,
+            &config.sensor_timestamps,
+            time_window_ms,
+        ),
+        None => prepare_graph_data(width, &config.sensor_values),
+    };
+    let value_range = get_value_range(&numbers, config);
+    let first_value_index = numbers.len().saturating_sub(config.sensor_values.len());
+    numbers
+        .iter()
+        .enumerate()
+        .skip(first_value_index)
+        .map(|(x, value)| (x as f32, to_y(*value, value_range), *value))
+        .collect()
 }
 
-/// Returns the maximum value of the given vector
+/// Returns the minimum value of the given vector, or 0 if it is empty
+fn get_min(values: &[f64]) -> f64 {
+    values.iter().copied().reduce(f64::min).unwrap_or(0.0)
+}
+
+/// Returns the maximum value of the given vector, or 0 if it is empty
 fn get_max(values: &[f64]) -> f64 {
-    let mut max = values[0];
-    for value in values {
-        if *value > max {
-            max = *value;
+    values.iter().copied().reduce(f64::max).unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_font, visible_bounds};
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    fn line_config(sensor_values: Vec<f64>) -> GraphConfig {
+        GraphConfig {
+            width: sensor_values.len() as u32,
+            height: 10,
+            sensor_values,
+            graph_type: GraphType::Line,
+            graph_color: "#FF0000".to_string(),
+            graph_stroke_width: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the rows of the pixels of the column, that have the given color
+    fn colored_rows(image: &RgbaImage, x: u32, color: Rgba<u8>) -> Vec<u32> {
+        (0..image.height())
+            .filter(|y| *image.get_pixel(x, *y) == color)
+            .collect()
+    }
+
+    #[test]
+    fn test_inverted_y_plots_the_max_value_at_the_bottom() {
+        let config = line_config((0..10).map(f64::from).collect());
+        let inverted_config = GraphConfig {
+            invert_y: true,
+            ..config.clone()
+        };
+
+        let max_point = *get_plotted_points(&config).last().unwrap();
+        let inverted_max_point = *get_plotted_points(&inverted_config).last().unwrap();
+        let image = render_image(&config);
+        let inverted_image = render_image(&inverted_config);
+
+        assert_eq!((max_point.1, max_point.2), (0.0, 9.0));
+        assert_eq!((inverted_max_point.1, inverted_max_point.2), (10.0, 9.0));
+        assert_eq!(colored_rows(&image, 9, RED), [0]);
+        assert_eq!(colored_rows(&inverted_image, 9, RED), [9]);
+    }
+
+    #[test]
+    fn test_antialiased_border_blends_its_edges() {
+        let draw = |antialias: bool| {
+            let mut image = RgbaImage::new(10, 10);
+            draw_border(&mut image, "#FF0000", 1.5, 1.0, antialias);
+            image
+        };
+        let is_intermediate = |pixel: &Rgba<u8>| pixel[3] > 0 && pixel[3] < 255;
+
+        let aliased = draw(false);
+        let antialiased = draw(true);
+
+        assert!(!aliased.pixels().any(is_intermediate));
+        for (x, y) in [(1, 5), (8, 5), (5, 1), (5, 8)] {
+            assert!(
+                is_intermediate(antialiased.get_pixel(x, y)),
+                "({}, {})",
+                x,
+                y
+            );
+        }
+        assert_eq!(*antialiased.get_pixel(0, 5), RED);
+        assert_eq!(antialiased.get_pixel(5, 5)[3], 0);
+    }
+
+    #[test]
+    fn test_graph_data_is_aligned_to_the_width() {
+        assert_eq!(prepare_graph_data(3, &[1.0, 2.0, 3.0]), [1.0, 2.0, 3.0]);
+        assert_eq!(
+            prepare_graph_data(3, &[1.0, 2.0, 3.0, 4.0, 5.0]),
+            [3.0, 4.0, 5.0]
+        );
+        assert_eq!(prepare_graph_data(4, &[1.0, 2.0]), [0.0, 0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_time_window_drops_old_values_and_maps_time_to_x() {
+        let plot_data = prepare_time_window_graph_data(
+            11,
+            &[99.0, 10.0, 20.0, 30.0],
+            &[0, 1000, 1500, 2000],
+            1000,
+        );
+
+        assert_eq!(plot_data.len(), 11);
+        assert!(!plot_data.contains(&99.0));
+        assert_eq!(plot_data[0], 10.0);
+        assert_eq!(plot_data[2], 14.0);
+        assert_eq!(plot_data[5], 20.0);
+        assert_eq!(plot_data[10], 30.0);
+    }
+
+    #[test]
+    fn test_equal_configured_min_and_max_render_a_centered_line() {
+        let config = GraphConfig {
+            min_sensor_value: Some(5.0),
+            max_sensor_value: Some(5.0),
+            ..line_config(vec![5.0; 4])
+        };
+
+        let points = get_plotted_points(&config);
+        let image = render_image(&config);
+
+        assert_eq!(get_value_range(&config.sensor_values, &config), (5.0, 5.0));
+        assert!(points
+            .iter()
+            .all(|(x, y, _)| x.is_finite() && y.is_finite()));
+        assert_eq!(points[0].1, 5.0);
+        for x in 0..4 {
+            assert_eq!(colored_rows(&image, x, RED), [5]);
+        }
+    }
+
+    #[test]
+    fn test_equal_configured_min_and_max_render_all_values_centered() {
+        let config = GraphConfig {
+            min_sensor_value: Some(5.0),
+            max_sensor_value: Some(5.0),
+            ..line_config(vec![5.0, 9.0, 1.0, 5.0])
+        };
+
+        let points = get_plotted_points(&config);
+        let image = render_image(&config);
+
+        assert!(points.iter().all(|(_, y, _)| *y == 5.0), "{:?}", points);
+        for x in 0..4 {
+            assert_eq!(colored_rows(&image, x, RED), [5]);
+        }
+    }
+
+    #[test]
+    fn test_legend_lists_a_swatch_and_label_per_series() {
+        let font = load_font();
+        let config = GraphConfig {
+            label: "CPU".to_string(),
+            additional_series: vec![GraphSeries {
+                sensor_id: "gpu".to_string(),
+                graph_color: "#0000FF".to_string(),
+                ..Default::default()
+            }],
+            ..line_config(vec![1.0, 2.0])
+        };
+        let legend_config = GraphLegendConfig {
+            font_size: 12,
+            font_color: "#FFFFFF".to_string(),
+            ..Default::default()
+        };
+
+        let image = render_legend(&config, &legend_config, &font);
+
+        // Each row is the padding, followed by the swatch and its label
+        assert_eq!(*image.get_pixel(8, 8), RED);
+        assert_eq!(*image.get_pixel(8, 22), Rgba([0, 0, 255, 255]));
+        let label_has_text = |row_y: u32| {
+            (16..image.width())
+                .flat_map(|x| (row_y..row_y + 12).map(move |y| (x, y)))
+                .any(|(x, y)| image.get_pixel(x, y)[3] > 0)
+        };
+        assert!(label_has_text(2));
+        assert!(label_has_text(16));
+    }
+
+    #[test]
+    fn test_points_are_drawn_on_top_of_the_line() {
+        let mut config = line_config(vec![5.0; 10]);
+        config.height = 20;
+        config.min_sensor_value = Some(0.0);
+        config.max_sensor_value = Some(10.0);
+        let points_config = GraphConfig {
+            show_points: true,
+            point_radius: 2,
+            ..config.clone()
+        };
+
+        let line_image = render_image(&config);
+        let points_image = render_image(&points_config);
+
+        // Every line pixel is still present, and each vertex is surrounded by a dot
+        assert!(line_image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == RED)
+            .all(|(x, y, _)| *points_image.get_pixel(x, y) == RED));
+        assert_eq!(colored_rows(&line_image, 5, RED), [10]);
+        assert_eq!(colored_rows(&points_image, 5, RED), [8, 9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn test_config_change_invalidates_the_cached_background() {
+        let config = GraphConfig {
+            min_sensor_value: Some(0.0),
+            max_sensor_value: Some(10.0),
+            background_color: "#0000FF".to_string(),
+            ..line_config(vec![0.0; 10])
+        };
+        let changed_config = GraphConfig {
+            background_color: "#00FF00".to_string(),
+            ..config.clone()
+        };
+
+        let image = render_image(&config);
+        let cached_image = render_image(&config);
+        let changed_image = render_image(&changed_config);
+
+        assert_ne!(
+            get_background_hash(&config),
+            get_background_hash(&changed_config)
+        );
+        assert!(image == cached_image);
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(*changed_image.get_pixel(0, 0), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_band_fills_only_between_the_series() {
+        let blue = Rgba([0, 0, 255, 255]);
+        let config = GraphConfig {
+            graph_type: GraphType::Band,
+            min_sensor_value: Some(0.0),
+            max_sensor_value: Some(10.0),
+            band_fill_color: "#0000FF".to_string(),
+            additional_series: vec![GraphSeries {
+                sensor_values: vec![3.0; 10],
+                ..Default::default()
+            }],
+            ..line_config(vec![7.0; 10])
+        };
+
+        let image = render_image(&config);
+
+        for x in 0..10 {
+            assert_eq!(colored_rows(&image, x, blue), [3, 4, 5, 6]);
+        }
+        assert_eq!(image.pixels().filter(|pixel| pixel[3] > 0).count(), 10 * 4);
+    }
+
+    #[test]
+    fn test_bars_reach_the_height_of_their_values() {
+        let config = GraphConfig {
+            graph_type: GraphType::Bar,
+            width: 40,
+            min_sensor_value: Some(0.0),
+            max_sensor_value: Some(10.0),
+            bar_gap: 1,
+            ..line_config((1..=10).map(f64::from).collect())
+        };
+
+        let image = render_image(&config);
+
+        assert_eq!(image.dimensions(), (40, 10));
+        for bar in 0..10 {
+            let bar_rows: Vec<u32> = (10 - (bar + 1)..10).collect();
+            assert_eq!(colored_rows(&image, bar * 4, RED), bar_rows);
+            assert_eq!(colored_rows(&image, bar * 4 + 2, RED), bar_rows);
+            // The gap to the next bar stays empty
+            assert!(colored_rows(&image, bar * 4 + 3, RED).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_filled_line_fills_every_column_under_the_line() {
+        let blue = Rgba([0, 0, 255, 255]);
+        let config = GraphConfig {
+            graph_type: GraphType::LineFill,
+            height: 20,
+            min_sensor_value: Some(0.0),
+            max_sensor_value: Some(20.0),
+            fill_color: "#0000FF".to_string(),
+            ..line_config((0..20).map(f64::from).collect())
+        };
+
+        let image = render_image(&config);
+
+        // Each sampled column is filled from below its line point down to the bottom
+        for x in [1, 5, 10, 15, 19] {
+            let line_y = 20 - x;
+            assert!((line_y + 1..20).all(|y| *image.get_pixel(x, y) == blue));
+            assert!((0..line_y - 1).all(|y| image.get_pixel(x, y)[3] == 0));
+        }
+    }
+
+    #[test]
+    fn test_graphs_with_zero_one_and_two_values() {
+        let graph_types = [
+            GraphType::Line,
+            GraphType::LineFill,
+            GraphType::Band,
+            GraphType::Bar,
+        ];
+        for graph_type in graph_types {
+            let render_values = |sensor_values: Vec<f64>| {
+                // The band is filled down to a second series, below the sensor values
+                let band_series = GraphSeries {
+                    sensor_values: sensor_values.iter().map(|value| value - 2.0).collect(),
+                    ..Default::default()
+                };
+                let config = GraphConfig {
+                    graph_type: graph_type.clone(),
+                    additional_series: match graph_type {
+                        GraphType::Band => vec![band_series],
+                        _ => vec![],
+                    },
+                    width: 10,
+                    min_sensor_value: Some(0.0),
+                    max_sensor_value: Some(10.0),
+                    ..line_config(sensor_values)
+                };
+                assert!(!render(&config).is_empty());
+                render_image(&config)
+            };
+
+            let empty_image = render_values(vec![]);
+            let single_value_image = render_values(vec![5.0]);
+            let two_values_image = render_values(vec![2.0, 8.0]);
+
+            assert!(empty_image.pixels().all(|pixel| pixel[3] == 0));
+            assert!(single_value_image.pixels().any(|pixel| pixel[3] > 0));
+            assert!(two_values_image.pixels().any(|pixel| pixel[3] > 0));
+        }
+
+        // A single value of a line graph is plotted as flat line across the whole width
+        let single_value_line = render_image(&GraphConfig {
+            width: 10,
+            min_sensor_value: Some(0.0),
+            max_sensor_value: Some(10.0),
+            ..line_config(vec![5.0])
+        });
+        for x in 0..10 {
+            assert_eq!(colored_rows(&single_value_line, x, RED), [5]);
+        }
+    }
+
+    #[test]
+    fn test_equal_values_render_a_centered_line() {
+        let config = line_config(vec![50.0, 50.0, 50.0]);
+        let filled_config = GraphConfig {
+            graph_type: GraphType::LineFill,
+            ..config.clone()
+        };
+
+        let image = render_image(&config);
+        let filled_image = render_image(&filled_config);
+
+        assert_eq!(
+            get_value_range(&config.sensor_values, &config),
+            (49.0, 51.0)
+        );
+        for x in 0..3 {
+            assert_eq!(colored_rows(&image, x, RED), [5]);
+            assert_eq!(colored_rows(&filled_image, x, RED), [5, 6, 7, 8, 9]);
         }
     }
-    max
+
+    #[test]
+    fn test_grid_lines_are_drawn_at_evenly_spaced_rows() {
+        let gray = Rgba([128, 128, 128, 255]);
+        let config = GraphConfig {
+            width: 10,
+            height: 20,
+            grid_color: "#808080".to_string(),
+            grid_line_count: 3,
+            ..line_config(vec![])
+        };
+        let baseline_config = GraphConfig {
+            width: 10,
+            height: 20,
+            min_sensor_value: Some(-10.0),
+            max_sensor_value: Some(10.0),
+            show_baseline: true,
+            ..line_config(vec![])
+        };
+
+        let image = render_image(&config);
+        let baseline_image = render_image(&baseline_config);
+
+        for x in 0..10 {
+            assert_eq!(colored_rows(&image, x, gray), [5, 10, 15]);
+            assert_eq!(colored_rows(&baseline_image, x, RED), [10]);
+        }
+    }
+
+    #[test]
+    fn test_antialiased_graph_matches_the_reference_image() {
+        let config = GraphConfig {
+            width: 64,
+            height: 32,
+            min_sensor_value: Some(-1.0),
+            max_sensor_value: Some(1.0),
+            graph_stroke_width: 3,
+            antialias: true,
+            background_color: "#101010".to_string(),
+            border_color: "#808080".to_string(),
+            ..line_config((0..64).map(|x| (x as f64 / 8.0).sin()).collect())
+        };
+
+        let image = render_image(&config);
+
+        let reference_image =
+            image::load_from_memory(include_bytes!("../tests/data/antialiased_line_graph.png"))
+                .unwrap()
+                .to_rgba8();
+        assert_eq!(image.dimensions(), reference_image.dimensions());
+        // Small differences are tolerated, e.g. from rounding of the anti-aliasing coverage
+        let max_difference = image
+            .as_raw()
+            .iter()
+            .zip(reference_image.as_raw())
+            .map(|(channel, reference_channel)| channel.abs_diff(*reference_channel))
+            .max()
+            .unwrap();
+        assert!(max_difference <= 2, "max difference {}", max_difference);
+    }
+
+    #[test]
+    fn test_annotation_is_drawn_near_the_latest_data_point() {
+        let mut sensor_values = vec![2.0; 99];
+        sensor_values.push(5.0);
+        let config = GraphConfig {
+            height: 40,
+            min_sensor_value: Some(0.0),
+            max_sensor_value: Some(10.0),
+            ..line_config(sensor_values)
+        };
+        let annotation_config = GraphAnnotationConfig {
+            font_size: 12,
+            font_color: "#FFFFFF".to_string(),
+            ..Default::default()
+        };
+
+        let image = render_annotations(&config, &annotation_config, &load_font());
+
+        // The latest value is plotted at (99, 20), the label is above it at the right edge
+        let (min_x, min_y, max_x, max_y) = visible_bounds(&image).unwrap();
+        assert!(min_x >= 80 && max_x <= 99);
+        assert!(min_y < 20 - 4 && max_y <= 20);
+    }
+
+    #[test]
+    fn test_series_with_own_value_range_is_normalized_independently() {
+        let blue = Rgba([0, 0, 255, 255]);
+        let config = GraphConfig {
+            min_sensor_value: Some(0.0),
+            max_sensor_value: Some(10.0),
+            additional_series: vec![GraphSeries {
+                sensor_values: (0..=10).map(|value| value as f64 * 1000.0).collect(),
+                graph_color: "#0000FF".to_string(),
+                min_sensor_value: Some(0.0),
+                max_sensor_value: Some(10000.0),
+                ..Default::default()
+            }],
+            ..line_config((0..=10).rev().map(f64::from).collect())
+        };
+
+        let image = render_image(&config);
+
+        // Both series span the full height, instead of one being flattened by the range of the other
+        let distinct_rows = |color: Rgba<u8>| {
+            let mut rows: Vec<u32> = (0..11)
+                .flat_map(|x| colored_rows(&image, x, color))
+                .collect();
+            rows.sort();
+            rows.dedup();
+            rows.len()
+        };
+        assert!(distinct_rows(RED) >= 8);
+        assert!(distinct_rows(blue) >= 8);
+        assert!(colored_rows(&image, 1, RED).contains(&1));
+        assert!(colored_rows(&image, 9, blue).contains(&1));
+    }
 }
@@ -1,17 +1,33 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs;
 use std::fs::DirEntry;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use image::{ImageBuffer, ImageFormat, Rgba};
-use log::{debug, error};
+use bincode::Options;
+use image::{ImageBuffer, ImageEncoder, ImageFormat, Rgba};
+use log::{debug, error, warn};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+pub mod clock_renderer;
 pub mod conditional_image_renderer;
+pub mod expression;
+pub mod font_decoder;
+pub mod gauge_renderer;
 pub mod graph_renderer;
+pub mod mono_renderer;
+pub mod palette_renderer;
+pub mod progress_bar_renderer;
+pub mod segment_bar_renderer;
+pub mod sensor_history;
 pub mod text_renderer;
 
+#[cfg(test)]
+mod test_utils;
+
 /// Indicates the current type of message to be sent to the display.
 /// Either a message to prepares static assets, by sending them to the display, and then be stored on the fs.
 /// Or the actual render loop, where the prev. stored asses will be used to render the image.
@@ -23,6 +39,90 @@ pub struct TransportMessage {
     pub data: Vec<u8>,
 }
 
+/// The size of a serialized transport message without its data,
+/// the transport type variant index (u32) and the length prefix of the data (u64)
+const TRANSPORT_MESSAGE_HEADER_SIZE: usize = 4 + 8;
+
+impl TransportMessage {
+    /// Returns the size of the serialized transport message in bytes, without serializing it
+    pub fn serialized_size(&self) -> usize {
+        TRANSPORT_MESSAGE_HEADER_SIZE + self.data.len()
+    }
+
+    /// Checks that the data can be deserialized to the struct implied by the transport type,
+    /// so a mistagged message is rejected before it is processed
+    /// The data has to be consumed completely. Payloads of the same shape can not be told apart,
+    /// e.g. the data of PrepareText and PrepareStaticImage are both maps of bytes by element id.
+    pub fn validate_payload(&self) -> Result<(), TransportError> {
+        let result = match self.transport_type {
+            TransportType::PrepareText => validate_payload::<PrepareTextData>(&self.data),
+            TransportType::PrepareStaticImage => {
+                validate_payload::<PrepareStaticImageData>(&self.data)
+            }
+            TransportType::PrepareConditionalImage => {
+                validate_payload::<PrepareConditionalImageData>(&self.data)
+            }
+            TransportType::RenderImage => validate_payload::<RenderData>(&self.data),
+            TransportType::DisplayFrame => validate_payload::<DisplayFrame>(&self.data),
+            TransportType::RequestConditionalImage => {
+                validate_payload::<ConditionalImageRequest>(&self.data)
+            }
+            TransportType::ConditionalImageResponse => {
+                validate_payload::<ConditionalImageResponse>(&self.data)
+            }
+        };
+
+        result.map_err(|err| TransportError::PayloadMismatch {
+            transport_type: self.transport_type.clone(),
+            message: err.to_string(),
+        })
+    }
+}
+
+/// Deserializes the data to the given type, with the encoding of `bincode::serialize`,
+/// but rejecting trailing bytes
+fn validate_payload<T: DeserializeOwned>(data: &[u8]) -> Result<(), bincode::Error> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize::<T>(data)
+        .map(|_| ())
+}
+
+/// Represents an error of a transport message.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum TransportError {
+    /// The data can not be deserialized to the struct implied by the transport type
+    PayloadMismatch {
+        transport_type: TransportType,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::PayloadMismatch {
+                transport_type,
+                message,
+            } => write!(
+                f,
+                "Data does not match the transport type {:?}: {}",
+                transport_type, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// Estimates the size of the serialized transport message wrapping the given payload,
+/// e.g. the render data or a display frame, without allocating the serialized payload
+pub fn estimate_transport_message_size<T: Serialize>(payload: &T) -> Result<usize, bincode::Error> {
+    let payload_size = bincode::serialized_size(payload)? as usize;
+    Ok(TRANSPORT_MESSAGE_HEADER_SIZE + payload_size)
+}
+
 /// Represents the type of the message to be sent to the display.
 /// Either a message to prepares static assets, by sending them to the display, and then be stored on the fs.
 /// Or the actual render loop, where the prev. stored asses will be used to render the image.
@@ -37,6 +137,82 @@ pub enum TransportType {
     PrepareConditionalImage,
     /// De/Serialize to RenderData
     RenderImage,
+    /// De/Serialize to DisplayFrame
+    DisplayFrame,
+    /// De/Serialize to ConditionalImageRequest
+    RequestConditionalImage,
+    /// De/Serialize to ConditionalImageResponse
+    ConditionalImageResponse,
+}
+
+/// Represents the pixel format of the data of a display frame.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum FrameFormat {
+    /// Raw, uncompressed RGBA8 pixels, row by row
+    #[default]
+    #[serde(rename = "rgba8")]
+    Rgba8,
+    /// PNG encoded image
+    #[serde(rename = "png")]
+    Png,
+    /// Palette-indexed PNG encoded image with at most 256 colors, see `palette_renderer::render`
+    #[serde(rename = "indexed-png")]
+    IndexedPng,
+}
+
+/// Represents a rendered frame, to be sent to or stored by the display.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct DisplayFrame {
+    pub width: u32,
+    pub height: u32,
+    pub format: FrameFormat,
+    pub data: Vec<u8>,
+}
+
+impl DisplayFrame {
+    /// Creates a new display frame from the raw pixels of the given rendered image
+    pub fn from_image(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> DisplayFrame {
+        DisplayFrame {
+            width: image.width(),
+            height: image.height(),
+            format: FrameFormat::Rgba8,
+            data: image.as_raw().clone(),
+        }
+    }
+
+    /// Converts the display frame back to an image
+    /// Returns None if the data does not match the format or the dimensions
+    pub fn to_image(&self) -> Option<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let image = match self.format {
+            FrameFormat::Rgba8 => {
+                ImageBuffer::from_raw(self.width, self.height, self.data.clone())?
+            }
+            FrameFormat::Png | FrameFormat::IndexedPng => {
+                image::load_from_memory_with_format(&self.data, ImageFormat::Png)
+                    .ok()?
+                    .to_rgba8()
+            }
+        };
+
+        if image.width() != self.width || image.height() != self.height {
+            return None;
+        }
+
+        Some(image)
+    }
+
+    /// Estimates the size of the serialized transport message of the display frame
+    pub fn transport_message_size(&self) -> Result<usize, bincode::Error> {
+        estimate_transport_message_size(self)
+    }
+
+    /// Serializes the display frame and wraps it in a transport message
+    pub fn to_transport_message(&self) -> Result<TransportMessage, bincode::Error> {
+        Ok(TransportMessage {
+            transport_type: TransportType::DisplayFrame,
+            data: bincode::serialize(self)?,
+        })
+    }
 }
 
 /// Represents the data to be rendered on a display.
@@ -47,6 +223,53 @@ pub struct RenderData {
     pub sensor_values: Vec<SensorValue>,
 }
 
+impl RenderData {
+    /// Normalizes all sensor values once on ingestion,
+    /// so all elements referencing a sensor see the same value
+    pub fn normalize_sensor_values(&mut self) {
+        self.sensor_values
+            .iter_mut()
+            .for_each(SensorValue::normalize);
+    }
+
+    /// Evaluates the virtual sensors of the display config once
+    /// and injects them into the sensor values, so elements can reference them by id.
+    /// Virtual sensors may reference virtual sensors defined before them.
+    /// The render functions do not evaluate virtual sensors, so this has to be called once per frame,
+    /// before the sensor values are added to the sensor value history.
+    pub fn apply_virtual_sensors(&mut self) {
+        for virtual_sensor in &self.display_config.virtual_sensors {
+            let sensor_values = &self.sensor_values;
+            let resolve_sensor = |sensor_id: &str| {
+                sensor_values
+                    .iter()
+                    .find(|sensor_value| sensor_value.id == sensor_id)
+                    .and_then(|sensor_value| sensor_value.value.parse().ok())
+            };
+
+            let value = match expression::evaluate(&virtual_sensor.expression, &resolve_sensor) {
+                Ok(value) => value,
+                Err(err) => {
+                    error!(
+                        "Failed to evaluate virtual sensor {}: {}",
+                        virtual_sensor.id, err
+                    );
+                    continue;
+                }
+            };
+
+            self.sensor_values.push(SensorValue {
+                id: virtual_sensor.id.clone(),
+                value: value.to_string(),
+                unit: virtual_sensor.unit.clone(),
+                label: virtual_sensor.label.clone(),
+                sensor_type: SensorType::Number,
+                ..Default::default()
+            });
+        }
+    }
+}
+
 /// Represents the preparation data for the render process.
 /// It holds all static assets to be rendered.
 /// This is done once before the loop starts.
@@ -76,6 +299,7 @@ pub struct PrepareStaticImageData {
 /// This is done once before the loop starts.
 /// Each asset will be stored on the display locally, and load during the render process by its
 /// asset id / element id
+/// Elements that load their images on demand are not prepared, see `ConditionalImageRequest`
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct PrepareConditionalImageData {
     /// Key is the element id
@@ -83,6 +307,78 @@ pub struct PrepareConditionalImageData {
     pub images_data: HashMap<String, HashMap<String, Vec<u8>>>,
 }
 
+/// Represents the request of a display for the image of a conditional image element,
+/// that loads its images on demand instead of preparing all of them.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ConditionalImageRequest {
+    pub element_id: String,
+    pub sensor_value: String,
+    pub sensor_type: SensorType,
+}
+
+impl ConditionalImageRequest {
+    /// Serializes the request and wraps it in a transport message
+    pub fn to_transport_message(&self) -> Result<TransportMessage, bincode::Error> {
+        Ok(TransportMessage {
+            transport_type: TransportType::RequestConditionalImage,
+            data: bincode::serialize(self)?,
+        })
+    }
+}
+
+/// Represents the response to a conditional image request.
+/// It holds the image that fits the requested sensor value best.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ConditionalImageResponse {
+    pub element_id: String,
+    pub sensor_value: String,
+    /// The image data, empty if no image matches the sensor value
+    pub image_data: Vec<u8>,
+}
+
+impl ConditionalImageResponse {
+    /// Selects the image of the requested element that fits the requested sensor value best,
+    /// from the configured images path of the element, like a prepared conditional image.
+    /// A relative images path is resolved against the asset root, if the display config has one,
+    /// otherwise the images are taken from the cache dir of the element.
+    pub fn for_request(
+        display_config: &DisplayConfig,
+        request: &ConditionalImageRequest,
+    ) -> ConditionalImageResponse {
+        let conditional_image_config = display_config
+            .elements
+            .iter()
+            .find(|element| element.id == request.element_id)
+            .and_then(|element| element.conditional_image_config.as_ref());
+        let image_data = conditional_image_config.and_then(|conditional_image_config| {
+            let config = ConditionalImageConfig {
+                sensor_value: request.sensor_value.clone(),
+                ..conditional_image_config.clone()
+            };
+            conditional_image_renderer::render(
+                &request.element_id,
+                &request.sensor_type,
+                &config,
+                display_config.asset_root.as_deref().map(Path::new),
+            )
+        });
+
+        ConditionalImageResponse {
+            element_id: request.element_id.clone(),
+            sensor_value: request.sensor_value.clone(),
+            image_data: image_data.unwrap_or_default(),
+        }
+    }
+
+    /// Serializes the response and wraps it in a transport message
+    pub fn to_transport_message(&self) -> Result<TransportMessage, bincode::Error> {
+        Ok(TransportMessage {
+            transport_type: TransportType::ConditionalImageResponse,
+            data: bincode::serialize(self)?,
+        })
+    }
+}
+
 /// Represents the display config.
 /// It holds the resolution and the elements to be rendered.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
@@ -93,6 +389,639 @@ pub struct DisplayConfig {
     pub resolution_width: u32,
     #[serde(default)]
     pub elements: Vec<ElementConfig>,
+    /// Sensors computed from other sensors, injected into the sensor values before rendering
+    /// by `RenderData::apply_virtual_sensors`
+    #[serde(default)]
+    pub virtual_sensors: Vec<VirtualSensorConfig>,
+    /// Optional root folder for image assets.
+    /// If set, relative image paths of the elements are resolved against this folder,
+    /// instead of loading the prepared assets from the cache dir.
+    #[serde(default)]
+    pub asset_root: Option<String>,
+    /// Pixel density of the display, sizes in density-independent units are multiplied by it.
+    /// Defaults to 1 if not set
+    #[serde(default)]
+    pub scale_factor: f32,
+    /// Colors of discrete sensor values, shared by all elements showing the sensor
+    /// Maps a sensor id to a map of sensor values to colors, e.g. "power-state" -> "high" -> "#FF0000"
+    #[serde(default)]
+    pub value_color_map: HashMap<String, HashMap<String, String>>,
+    /// Width divided by height of a physical pixel of the display, for panels with non-square pixels
+    /// Geometric shapes are compensated, so they look correct on the panel. Defaults to 1 if not set
+    #[serde(default)]
+    pub pixel_aspect_ratio: f32,
+    /// Trades render quality for render speed, by toggling anti-aliasing and supersampling for all elements
+    #[serde(default)]
+    pub render_quality: RenderQuality,
+    /// Rotation of the rendered frame, for displays that are mounted sideways or upside down
+    /// Elements are positioned in the unrotated resolution, the frame is rotated after all elements are drawn.
+    #[serde(default)]
+    pub rotation: Rotation,
+    /// Color the whole display is filled with, before the elements are drawn
+    /// If empty, the regions not covered by elements are transparent
+    #[serde(default)]
+    pub background_color: String,
+    /// Optional image drawn over the background color, before the elements are drawn
+    /// Loaded like a static image, with `BACKGROUND_IMAGE_ID` as element id
+    #[serde(default)]
+    pub background_image_path: Option<String>,
+}
+
+/// Id of the background image of the display, used as element id to load and prepare the image
+pub const BACKGROUND_IMAGE_ID: &str = "display-background";
+
+/// Represents the clockwise rotation of the rendered frame of a display.
+/// Rotating by 90 or 270 degrees swaps the width and height of the frame.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum Rotation {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "rotate-90")]
+    Rotate90,
+    #[serde(rename = "rotate-180")]
+    Rotate180,
+    #[serde(rename = "rotate-270")]
+    Rotate270,
+}
+
+impl Rotation {
+    /// Returns the image rotated clockwise by the rotation
+    pub fn rotate<P: image::Pixel + 'static>(
+        &self,
+        image: ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        match self {
+            Rotation::None => image,
+            Rotation::Rotate90 => image::imageops::rotate90(&image),
+            Rotation::Rotate180 => image::imageops::rotate180(&image),
+            Rotation::Rotate270 => image::imageops::rotate270(&image),
+        }
+    }
+
+    /// Returns the rectangle of an unrotated image of the given size, at its position in the rotated image
+    fn rotate_rect(
+        &self,
+        rect: imageproc::rect::Rect,
+        image_width: u32,
+        image_height: u32,
+    ) -> imageproc::rect::Rect {
+        let right = rect.left() + rect.width() as i32;
+        let bottom = rect.top() + rect.height() as i32;
+        match self {
+            Rotation::None => rect,
+            Rotation::Rotate90 => {
+                imageproc::rect::Rect::at(image_height as i32 - bottom, rect.left())
+                    .of_size(rect.height(), rect.width())
+            }
+            Rotation::Rotate180 => {
+                imageproc::rect::Rect::at(image_width as i32 - right, image_height as i32 - bottom)
+                    .of_size(rect.width(), rect.height())
+            }
+            Rotation::Rotate270 => {
+                imageproc::rect::Rect::at(rect.top(), image_width as i32 - right)
+                    .of_size(rect.height(), rect.width())
+            }
+        }
+    }
+}
+
+/// Represents the render quality of a display.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum RenderQuality {
+    /// Text, graph lines and borders are drawn without anti-aliasing
+    #[serde(rename = "fast")]
+    Fast,
+    /// Text is drawn anti-aliased, graph lines and borders are anti-aliased if set for the graph
+    #[default]
+    #[serde(rename = "balanced")]
+    Balanced,
+    /// Text, graph lines and borders are drawn anti-aliased,
+    /// and the frame is rendered at a multiple of the display resolution, before it is downscaled
+    /// If the multiplied resolution would exceed the maximum resolution, the frame is not supersampled
+    #[serde(rename = "high")]
+    High,
+}
+
+impl RenderQuality {
+    /// Returns the factor the display resolution is multiplied by for rendering
+    pub fn supersampling_factor(&self) -> u32 {
+        match self {
+            RenderQuality::Fast | RenderQuality::Balanced => 1,
+            RenderQuality::High => 2,
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Composes a base config and an overlay config
+    /// Elements of the overlay replace base elements with the same id, all others are appended.
+    /// Virtual sensors are merged the same way.
+    /// The resolution, asset root, scale factor, pixel aspect ratio and background of the overlay
+    /// take precedence, if it specifies them. So do the render quality and the rotation,
+    /// if they differ from the default.
+    /// The value colors of the overlay are added to the value colors of the base, replacing equal values.
+    pub fn merge(base: DisplayConfig, overlay: DisplayConfig) -> DisplayConfig {
+        let mut merged = base;
+
+        if overlay.resolution_width > 0 && overlay.resolution_height > 0 {
+            merged.resolution_width = overlay.resolution_width;
+            merged.resolution_height = overlay.resolution_height;
+        }
+        if overlay.asset_root.is_some() {
+            merged.asset_root = overlay.asset_root;
+        }
+        if overlay.scale_factor > 0.0 {
+            merged.scale_factor = overlay.scale_factor;
+        }
+        if overlay.pixel_aspect_ratio > 0.0 {
+            merged.pixel_aspect_ratio = overlay.pixel_aspect_ratio;
+        }
+        if overlay.render_quality != RenderQuality::default() {
+            merged.render_quality = overlay.render_quality;
+        }
+        if overlay.rotation != Rotation::default() {
+            merged.rotation = overlay.rotation;
+        }
+        if !overlay.background_color.is_empty() {
+            merged.background_color = overlay.background_color;
+        }
+        if overlay.background_image_path.is_some() {
+            merged.background_image_path = overlay.background_image_path;
+        }
+        for (sensor_id, value_colors) in overlay.value_color_map {
+            merged
+                .value_color_map
+                .entry(sensor_id)
+                .or_default()
+                .extend(value_colors);
+        }
+
+        for element in overlay.elements {
+            match merged
+                .elements
+                .iter_mut()
+                .find(|base_element| base_element.id == element.id)
+            {
+                Some(base_element) => *base_element = element,
+                None => merged.elements.push(element),
+            }
+        }
+
+        for virtual_sensor in overlay.virtual_sensors {
+            match merged
+                .virtual_sensors
+                .iter_mut()
+                .find(|base_sensor| base_sensor.id == virtual_sensor.id)
+            {
+                Some(base_sensor) => *base_sensor = virtual_sensor,
+                None => merged.virtual_sensors.push(virtual_sensor),
+            }
+        }
+
+        merged
+    }
+
+    /// Validates the config, checking the colors and the layout of all elements
+    pub fn validate(&self) -> ValidationReport {
+        ValidationReport {
+            color_issues: self.validate_colors(),
+            collisions: self.find_collisions(),
+        }
+    }
+
+    /// Finds all pairs of elements whose rectangles overlap, so the later element paints over the earlier
+    /// Elements without a size are ignored
+    pub fn find_collisions(&self) -> Vec<ElementCollision> {
+        let rects: Vec<(&str, imageproc::rect::Rect)> = self
+            .elements
+            .iter()
+            .filter_map(|element| {
+                let (width, height) = get_element_size(element);
+                if width == 0 || height == 0 {
+                    return None;
+                }
+                let rect = imageproc::rect::Rect::at(element.x, element.y).of_size(width, height);
+                Some((element.id.as_str(), rect))
+            })
+            .collect();
+
+        let mut collisions = vec![];
+        for (index, (element_id, rect)) in rects.iter().enumerate() {
+            for (other_element_id, other_rect) in &rects[index + 1..] {
+                if rect.intersect(*other_rect).is_some() {
+                    collisions.push(ElementCollision {
+                        element_id: element_id.to_string(),
+                        other_element_id: other_element_id.to_string(),
+                    });
+                }
+            }
+        }
+        collisions
+    }
+
+    /// Checks all color fields of all elements at once
+    /// Returns an issue for every color that can not be parsed, with the element id and field name
+    /// An invalid background color of the display is reported with `BACKGROUND_IMAGE_ID` as element id
+    pub fn validate_colors(&self) -> Vec<ColorIssue> {
+        let background_color = Some(self.background_color.as_str())
+            .filter(|color| !color.is_empty())
+            .map(|color| (BACKGROUND_IMAGE_ID, "background_color", color));
+        background_color
+            .into_iter()
+            .chain(self.elements.iter().flat_map(|element| {
+                get_color_fields(element)
+                    .into_iter()
+                    .map(|(field, value)| (element.id.as_str(), field, value))
+            }))
+            .filter(|(_, _, value)| normalize_hex_color(value).is_none())
+            .map(|(element_id, field, value)| ColorIssue {
+                element_id: element_id.to_string(),
+                field: field.to_string(),
+                value: value.to_string(),
+            })
+            .collect()
+    }
+
+    /// Parses a display config from untrusted JSON, e.g. uploaded by a user
+    /// The limits are enforced while and after deserializing, so a malicious config
+    /// results in an error instead of exhausting the memory or panicking the renderer.
+    /// Nesting is limited by the recursion limit of the JSON parser and, for the expressions of
+    /// virtual sensors, by the maximum nesting depth of the expression parser.
+    pub fn parse_untrusted(json: &str, limits: &RenderLimits) -> Result<DisplayConfig, ParseError> {
+        if json.len() > limits.max_json_length {
+            return Err(ParseError::InputTooLarge {
+                length: json.len(),
+                max_length: limits.max_json_length,
+            });
+        }
+
+        // Check all strings before they are deserialized into the config
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|err| ParseError::InvalidJson(err.to_string()))?;
+        check_string_lengths(&value, "", limits.max_string_length)?;
+        let display_config: DisplayConfig = serde_json::from_value(value)
+            .map_err(|err| ParseError::InvalidJson(err.to_string()))?;
+
+        let (width, height) = (
+            display_config.resolution_width,
+            display_config.resolution_height,
+        );
+        if width > limits.max_resolution || height > limits.max_resolution {
+            return Err(ParseError::ResolutionTooLarge {
+                width,
+                height,
+                max_resolution: limits.max_resolution,
+            });
+        }
+
+        if display_config.elements.len() > limits.max_elements {
+            return Err(ParseError::TooManyElements {
+                count: display_config.elements.len(),
+                max_elements: limits.max_elements,
+            });
+        }
+
+        for element in &display_config.elements {
+            // Elements allocate their own image buffers, so they are limited like the display
+            let (width, height) = get_element_size(element);
+            if width > limits.max_resolution || height > limits.max_resolution {
+                return Err(ParseError::ElementTooLarge {
+                    element_id: element.id.clone(),
+                    width,
+                    height,
+                    max_resolution: limits.max_resolution,
+                });
+            }
+
+            let font_sizes = [
+                element.text_config.as_ref().map(|config| config.font_size),
+                element.table_config.as_ref().map(|config| config.font_size),
+                element.clock_config.as_ref().map(|config| config.font_size),
+                element
+                    .graph_config
+                    .as_ref()
+                    .and_then(|config| config.legend.as_ref())
+                    .map(|legend| legend.font_size),
+                element
+                    .graph_config
+                    .as_ref()
+                    .and_then(|config| config.annotation.as_ref())
+                    .map(|annotation| annotation.font_size),
+            ];
+            if let Some(font_size) = font_sizes
+                .into_iter()
+                .flatten()
+                .find(|font_size| *font_size > limits.max_font_size)
+            {
+                return Err(ParseError::FontSizeTooLarge {
+                    element_id: element.id.clone(),
+                    font_size,
+                    max_font_size: limits.max_font_size,
+                });
+            }
+        }
+
+        // The expressions are evaluated recursively, so deep nesting could overflow the stack
+        if let Some(virtual_sensor) = display_config
+            .virtual_sensors
+            .iter()
+            .find(|virtual_sensor| expression::exceeds_nesting_depth(&virtual_sensor.expression))
+        {
+            return Err(ParseError::ExpressionTooDeep {
+                sensor_id: virtual_sensor.id.clone(),
+                max_depth: expression::MAX_NESTING_DEPTH,
+            });
+        }
+
+        Ok(display_config)
+    }
+}
+
+/// Returns the name and value of all color fields of the element
+/// Empty colors are left out, as they are drawn transparent
+fn get_color_fields(element: &ElementConfig) -> Vec<(&'static str, &str)> {
+    let mut color_fields: Vec<(&'static str, &str)> = vec![];
+    if let Some(text_config) = &element.text_config {
+        color_fields.push(("text_config.font_color", &text_config.font_color));
+        color_fields.push((
+            "text_config.background_color",
+            &text_config.background_color,
+        ));
+    }
+    if let Some(graph_config) = &element.graph_config {
+        color_fields.push(("graph_config.graph_color", &graph_config.graph_color));
+        color_fields.push((
+            "graph_config.background_color",
+            &graph_config.background_color,
+        ));
+        color_fields.push(("graph_config.border_color", &graph_config.border_color));
+        color_fields.push(("graph_config.grid_color", &graph_config.grid_color));
+        color_fields.push(("graph_config.fill_color", &graph_config.fill_color));
+        color_fields.push((
+            "graph_config.band_fill_color",
+            &graph_config.band_fill_color,
+        ));
+        for series in &graph_config.additional_series {
+            color_fields.push((
+                "graph_config.additional_series.graph_color",
+                &series.graph_color,
+            ));
+        }
+        if let Some(legend) = &graph_config.legend {
+            color_fields.push(("graph_config.legend.font_color", &legend.font_color));
+            color_fields.push((
+                "graph_config.legend.background_color",
+                &legend.background_color,
+            ));
+        }
+        if let Some(annotation) = &graph_config.annotation {
+            color_fields.push(("graph_config.annotation.font_color", &annotation.font_color));
+        }
+    }
+    if let Some(table_config) = &element.table_config {
+        color_fields.push(("table_config.font_color", &table_config.font_color));
+    }
+    if let Some(gauge_config) = &element.gauge_config {
+        color_fields.push(("gauge_config.track_color", &gauge_config.track_color));
+        color_fields.push(("gauge_config.fill_color", &gauge_config.fill_color));
+        for tick in &gauge_config.ticks {
+            color_fields.push(("gauge_config.ticks.color", &tick.color));
+        }
+    }
+    if let Some(progress_bar_config) = &element.progress_bar_config {
+        color_fields.push((
+            "progress_bar_config.fill_color",
+            &progress_bar_config.fill_color,
+        ));
+        color_fields.push((
+            "progress_bar_config.background_color",
+            &progress_bar_config.background_color,
+        ));
+        if let Some(border_color) = &progress_bar_config.border_color {
+            color_fields.push(("progress_bar_config.border_color", border_color));
+        }
+    }
+    if let Some(clock_config) = &element.clock_config {
+        color_fields.push(("clock_config.font_color", &clock_config.font_color));
+    }
+    if let Some(segment_bar_config) = &element.segment_bar_config {
+        color_fields.push(("segment_bar_config.on_color", &segment_bar_config.on_color));
+        color_fields.push((
+            "segment_bar_config.off_color",
+            &segment_bar_config.off_color,
+        ));
+    }
+    color_fields.retain(|(_, value)| !value.trim().is_empty());
+    color_fields
+}
+
+/// Checks that no string in the JSON value exceeds the maximum length
+/// The path of the value is reported in the error, e.g. `elements[0].id`
+fn check_string_lengths(
+    value: &serde_json::Value,
+    path: &str,
+    max_length: usize,
+) -> Result<(), ParseError> {
+    match value {
+        serde_json::Value::String(string) if string.len() > max_length => {
+            Err(ParseError::StringTooLong {
+                field: path.to_string(),
+                length: string.len(),
+                max_length,
+            })
+        }
+        serde_json::Value::Array(values) => {
+            values.iter().enumerate().try_for_each(|(index, value)| {
+                check_string_lengths(value, &format!("{}[{}]", path, index), max_length)
+            })
+        }
+        serde_json::Value::Object(fields) => fields.iter().try_for_each(|(key, value)| {
+            if key.len() > max_length {
+                return Err(ParseError::StringTooLong {
+                    field: path.to_string(),
+                    length: key.len(),
+                    max_length,
+                });
+            }
+            let field_path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", path, key)
+            };
+            check_string_lengths(value, &field_path, max_length)
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Represents the limits an untrusted display config has to stay within.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct RenderLimits {
+    /// The maximum length of the JSON input in bytes
+    pub max_json_length: usize,
+    /// The maximum width and height of the display and of each element in pixels
+    pub max_resolution: u32,
+    /// The maximum number of elements
+    pub max_elements: usize,
+    /// The maximum font size of all text
+    pub max_font_size: u32,
+    /// The maximum length of every string in bytes, e.g. ids, formats and paths
+    pub max_string_length: usize,
+}
+
+impl Default for RenderLimits {
+    fn default() -> Self {
+        RenderLimits {
+            max_json_length: 1024 * 1024,
+            max_resolution: DEFAULT_MAX_RESOLUTION,
+            max_elements: 256,
+            max_font_size: 512,
+            max_string_length: 4096,
+        }
+    }
+}
+
+/// Represents an error that occurred while parsing an untrusted display config.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ParseError {
+    /// The JSON input exceeds the maximum length
+    InputTooLarge { length: usize, max_length: usize },
+    /// The input is not a valid display config
+    InvalidJson(String),
+    /// The resolution of the display exceeds the maximum resolution
+    ResolutionTooLarge {
+        width: u32,
+        height: u32,
+        max_resolution: u32,
+    },
+    /// The config has more elements than allowed
+    TooManyElements { count: usize, max_elements: usize },
+    /// The size of an element exceeds the maximum resolution
+    ElementTooLarge {
+        element_id: String,
+        width: u32,
+        height: u32,
+        max_resolution: u32,
+    },
+    /// The font size of an element exceeds the maximum font size
+    FontSizeTooLarge {
+        element_id: String,
+        font_size: u32,
+        max_font_size: u32,
+    },
+    /// A string exceeds the maximum length
+    StringTooLong {
+        field: String,
+        length: usize,
+        max_length: usize,
+    },
+    /// The expression of a virtual sensor is nested deeper than the parser allows
+    ExpressionTooDeep { sensor_id: String, max_depth: usize },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::InputTooLarge { length, max_length } => write!(
+                f,
+                "Input of {} bytes exceeds the maximum of {} bytes",
+                length, max_length
+            ),
+            ParseError::InvalidJson(message) => write!(f, "Invalid display config: {}", message),
+            ParseError::ResolutionTooLarge {
+                width,
+                height,
+                max_resolution,
+            } => write!(
+                f,
+                "Resolution {}x{} exceeds the maximum of {}x{}",
+                width, height, max_resolution, max_resolution
+            ),
+            ParseError::TooManyElements {
+                count,
+                max_elements,
+            } => write!(
+                f,
+                "{} elements exceed the maximum of {} elements",
+                count, max_elements
+            ),
+            ParseError::ElementTooLarge {
+                element_id,
+                width,
+                height,
+                max_resolution,
+            } => write!(
+                f,
+                "Size {}x{} of element {} exceeds the maximum of {}x{}",
+                width, height, element_id, max_resolution, max_resolution
+            ),
+            ParseError::FontSizeTooLarge {
+                element_id,
+                font_size,
+                max_font_size,
+            } => write!(
+                f,
+                "Font size {} of element {} exceeds the maximum of {}",
+                font_size, element_id, max_font_size
+            ),
+            ParseError::StringTooLong {
+                field,
+                length,
+                max_length,
+            } => write!(
+                f,
+                "String of {} bytes in {} exceeds the maximum of {} bytes",
+                length, field, max_length
+            ),
+            ParseError::ExpressionTooDeep {
+                sensor_id,
+                max_depth,
+            } => write!(
+                f,
+                "Expression of virtual sensor {} is nested deeper than {} levels",
+                sensor_id, max_depth
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Represents the result of validating a display config.
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct ValidationReport {
+    pub color_issues: Vec<ColorIssue>,
+    pub collisions: Vec<ElementCollision>,
+}
+
+/// Represents two elements whose rectangles overlap, the other element is drawn on top.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ElementCollision {
+    pub element_id: String,
+    pub other_element_id: String,
+}
+
+/// Represents a color field of an element that can not be parsed.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ColorIssue {
+    pub element_id: String,
+    pub field: String,
+    pub value: String,
+}
+
+/// Represents a virtual sensor, computed from other sensors.
+/// The expression references other sensors by their id, e.g. `{total} - {used}`
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct VirtualSensorConfig {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub expression: String,
+    #[serde(default)]
+    pub unit: String,
+    #[serde(default)]
+    pub label: String,
 }
 
 /// Represents a single element to be rendered on a display.
@@ -116,6 +1045,61 @@ pub struct ElementConfig {
     pub graph_config: Option<GraphConfig>,
     #[serde(default)]
     pub conditional_image_config: Option<ConditionalImageConfig>,
+    #[serde(default)]
+    pub table_config: Option<TableConfig>,
+    #[serde(default)]
+    pub gauge_config: Option<GaugeConfig>,
+    #[serde(default)]
+    pub progress_bar_config: Option<ProgressBarConfig>,
+    #[serde(default)]
+    pub clock_config: Option<ClockConfig>,
+    #[serde(default)]
+    pub segment_bar_config: Option<SegmentBarConfig>,
+    #[serde(default)]
+    pub padding: ElementPadding,
+    /// If set, the element is only re-rendered after this interval in the incremental render mode,
+    /// in between the previously rendered element is reused
+    #[serde(default)]
+    pub refresh_interval_ms: Option<u32>,
+    /// How the element is blended with the elements beneath it
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+    /// Opacity of the whole element from 0 to 1, the alpha of each pixel is scaled by it
+    /// Values outside of the range are clamped, the element is fully opaque if not set
+    #[serde(default)]
+    pub opacity: Option<f32>,
+}
+
+/// Represents how the colors of an element are combined with the colors beneath it.
+/// The alpha of the element is applied after blending, as with normal blending.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum BlendMode {
+    /// The element is drawn over the elements beneath it
+    #[default]
+    #[serde(rename = "normal")]
+    Normal,
+    /// The colors are added, which brightens the elements beneath
+    #[serde(rename = "additive")]
+    Additive,
+    /// The colors are multiplied, which darkens the elements beneath
+    #[serde(rename = "multiply")]
+    Multiply,
+    /// The inverted colors are multiplied and inverted again, which brightens the elements beneath
+    #[serde(rename = "screen")]
+    Screen,
+}
+
+/// Represents the inset of the content of an element from the edges of its box in pixels.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub struct ElementPadding {
+    #[serde(default)]
+    pub top: u32,
+    #[serde(default)]
+    pub right: u32,
+    #[serde(default)]
+    pub bottom: u32,
+    #[serde(default)]
+    pub left: u32,
 }
 
 /// Represents a text element on a display.
@@ -125,6 +1109,9 @@ pub struct TextConfig {
     pub sensor_id: String,
     #[serde(default)]
     pub value_modifier: SensorValueModifier,
+    /// Text to show, with placeholders like `{value}`, `{value-avg}`, `{unit}` or `{label}`
+    /// Numeric placeholders take an optional precision spec `{name:.N}` with N decimals, e.g. `{value:.1}`,
+    /// values of non-numeric sensors are shown unchanged
     #[serde(default)]
     pub format: String,
     #[serde(default)]
@@ -133,38 +1120,172 @@ pub struct TextConfig {
     pub font_size: u32,
     #[serde(default)]
     pub font_color: String,
+    /// Color of the plate filling the element behind the text, an empty color disables it
+    /// A semi-transparent color is blended with what is already drawn on the frame at the element
+    #[serde(default)]
+    pub background_color: String,
     #[serde(default)]
     pub width: u32,
     #[serde(default)]
     pub height: u32,
     #[serde(default)]
     pub alignment: TextAlign,
+    /// Vertical placement of the text within the element, across the text direction if oriented vertically
+    #[serde(default)]
+    pub vertical_alignment: VerticalAlign,
+    #[serde(default)]
+    pub orientation: TextOrientation,
+    /// If set, the unit is rendered as a separate run with its own size and baseline
+    #[serde(default)]
+    pub unit_style: Option<UnitStyle>,
+    /// If true, the font color is replaced by black or white,
+    /// depending on what contrasts best with the background behind the element
+    #[serde(default)]
+    pub auto_contrast: bool,
+    /// If set, the format and font color are selected by the range the sensor value is in
+    #[serde(default)]
+    pub conditional_text: Option<ConditionalTextConfig>,
+    /// If true, numbers are always shown with two decimals,
+    /// otherwise integer values are shown without decimals.
+    /// Applies to the value and the aggregates of numeric sensors.
+    #[serde(default)]
+    pub force_decimals: bool,
+    /// Rounding of numbers to the shown decimals
+    #[serde(default)]
+    pub rounding_mode: RoundingMode,
+    /// If true, the text is broken into lines that fit the element width, at spaces and at explicit line breaks
+    /// Lines are aligned according to the text alignment. A styled unit is wrapped like the rest of the text.
+    #[serde(default)]
+    pub wrap: bool,
+    /// If true, the text is drawn without anti-aliasing, each pixel is either covered or not
+    /// Set for all text elements by the render quality of the display
+    #[serde(default)]
+    pub aliased: bool,
+    /// If set, the decimal point of the first number in the text is placed at this horizontal position
+    /// within the element in pixels, instead of aligning the text. Integers are aligned at their end.
+    /// So numbers with a different amount of integer digits line up in a column. Not applied to wrapped text.
+    #[serde(default)]
+    pub decimal_point_x: Option<u32>,
+    /// Converts numeric sensor values and their unit before they are shown, e.g. from °C to °F
+    #[serde(default)]
+    pub unit_conversion: UnitConversion,
 }
 
-/// Represents the text alignment of a text element.
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
-pub enum TextAlign {
-    #[default]
-    #[serde(rename = "left")]
-    Left,
-    #[serde(rename = "center")]
-    Center,
-    #[serde(rename = "right")]
-    Right,
-}
-
-/// Represents a static image element on a display.
+/// Represents the aggregates of the numeric values of a sensor over the sensor value history
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
-pub struct ImageConfig {
+pub struct HistoryAggregates {
     #[serde(default)]
-    pub width: u32,
+    pub min: f64,
     #[serde(default)]
-    pub height: u32,
+    pub max: f64,
     #[serde(default)]
-    pub image_path: String,
+    pub avg: f64,
 }
 
-/// Represents the type of a graph element on a display.
+/// Represents how numbers are rounded to the shown decimals.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum RoundingMode {
+    /// Halfway values are rounded away from zero, e.g. 2.5 to 3
+    #[serde(rename = "half-up")]
+    HalfUp,
+    /// Halfway values are rounded to the nearest even number, e.g. 2.5 to 2
+    #[default]
+    #[serde(rename = "half-even")]
+    HalfEven,
+    /// Values are rounded towards negative infinity
+    #[serde(rename = "floor")]
+    Floor,
+    /// Values are rounded towards positive infinity
+    #[serde(rename = "ceil")]
+    Ceil,
+}
+
+/// Represents the texts of a text element per numeric sensor value range.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct ConditionalTextConfig {
+    /// The first range containing the sensor value is used
+    #[serde(default)]
+    pub ranges: Vec<ConditionalTextRange>,
+}
+
+/// Represents the text of a numeric sensor value range.
+/// The minimum value is inclusive, the maximum value exclusive, unset bounds are open.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct ConditionalTextRange {
+    #[serde(default)]
+    pub min_value: Option<f64>,
+    #[serde(default)]
+    pub max_value: Option<f64>,
+    /// Replaces the format of the text element, supports the same placeholders
+    #[serde(default)]
+    pub text: String,
+    /// Replaces the font color of the text element, if set
+    #[serde(default)]
+    pub font_color: String,
+}
+
+/// Represents the text alignment of a text element.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum TextAlign {
+    #[default]
+    #[serde(rename = "left")]
+    Left,
+    #[serde(rename = "center")]
+    Center,
+    #[serde(rename = "right")]
+    Right,
+}
+
+/// Represents the vertical text alignment of a text element.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum VerticalAlign {
+    #[serde(rename = "top")]
+    Top,
+    #[default]
+    #[serde(rename = "middle")]
+    Middle,
+    #[serde(rename = "bottom")]
+    Bottom,
+}
+
+/// Represents the style of the unit of a text element, e.g. for a superscript-style unit.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct UnitStyle {
+    /// Font size of the unit relative to the font size of the text, 0 keeps the font size
+    #[serde(default)]
+    pub font_scale: f32,
+    /// Shift of the unit baseline relative to the font size of the text, positive values raise it
+    #[serde(default)]
+    pub baseline_offset: f32,
+}
+
+/// Represents the orientation of a text element.
+/// Vertical text runs from bottom to top, only the text is rotated, not the element box.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum TextOrientation {
+    #[default]
+    #[serde(rename = "horizontal")]
+    Horizontal,
+    #[serde(rename = "vertical")]
+    Vertical,
+}
+
+/// Represents a static image element on a display.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct ImageConfig {
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    #[serde(default)]
+    pub image_path: String,
+    /// If true, the image colors are premultiplied with the alpha channel
+    /// and will be converted to straight alpha before compositing
+    #[serde(default)]
+    pub premultiplied_alpha: bool,
+}
+
+/// Represents the type of a graph element on a display.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
 pub enum GraphType {
     #[default]
@@ -172,6 +1293,12 @@ pub enum GraphType {
     Line,
     #[serde(rename = "line-fill")]
     LineFill,
+    /// Shades the area between the sensor series and the first additional series
+    #[serde(rename = "band")]
+    Band,
+    /// Draws one vertical bar per sensor value, spread across the width
+    #[serde(rename = "bar")]
+    Bar,
 }
 
 /// Represents a graph element on a display.
@@ -181,6 +1308,13 @@ pub struct GraphConfig {
     pub sensor_id: String,
     #[serde(default)]
     pub sensor_values: Vec<f64>,
+    /// Unix timestamps in milliseconds of the sensor values, only used in time window mode
+    #[serde(default)]
+    pub sensor_timestamps: Vec<u64>,
+    /// If set, the graph plots the sensor values of the last milliseconds against time,
+    /// instead of one value per pixel. Older values scroll out to the left.
+    #[serde(default)]
+    pub time_window_ms: Option<u64>,
     #[serde(default)]
     pub min_sensor_value: Option<f64>,
     #[serde(default)]
@@ -193,12 +1327,163 @@ pub struct GraphConfig {
     pub graph_type: GraphType,
     #[serde(default)]
     pub graph_color: String,
+    /// Color of the area under the line of a filled line graph
+    /// Defaults to the graph color if not set
+    #[serde(default)]
+    pub fill_color: String,
+    /// Label of the sensor series, shown in the legend. Defaults to the sensor id.
+    #[serde(default)]
+    pub label: String,
+    /// Further sensor series drawn as lines on top of the graph
+    #[serde(default)]
+    pub additional_series: Vec<GraphSeries>,
+    /// If set, a legend listing all series is drawn in a corner of the graph
+    #[serde(default)]
+    pub legend: Option<GraphLegendConfig>,
+    /// If set, the latest or the extreme values are labeled next to their data point
+    #[serde(default)]
+    pub annotation: Option<GraphAnnotationConfig>,
     #[serde(default)]
     pub graph_stroke_width: i32,
+    /// Unit of the stroke width and the border width
+    #[serde(default)]
+    pub stroke_unit: SizeUnit,
+    /// If true, a filled dot is drawn at each data point of a line graph
+    #[serde(default)]
+    pub show_points: bool,
+    /// Radius of the data point dots in pixels
+    #[serde(default)]
+    pub point_radius: i32,
+    /// Fill color of the area between the two series of a band graph
+    /// Defaults to the graph color at half opacity if not set
+    #[serde(default)]
+    pub band_fill_color: String,
+    /// If true, both boundaries of a band graph are stroked with the color of their series
+    #[serde(default)]
+    pub band_stroke_boundaries: bool,
+    /// Gap between two bars of a bar graph in pixels
+    #[serde(default)]
+    pub bar_gap: u32,
+    /// Color of the horizontal grid lines behind the series, an empty or transparent color disables them
+    #[serde(default)]
+    pub grid_color: String,
+    /// Number of evenly spaced horizontal grid lines
+    #[serde(default)]
+    pub grid_line_count: u32,
+    /// If true, a baseline is drawn at the value 0, if the value range crosses it
+    /// The baseline has the grid color, or the graph color if there is no grid color
+    #[serde(default)]
+    pub show_baseline: bool,
     #[serde(default)]
     pub background_color: String,
     #[serde(default)]
     pub border_color: String,
+    /// Width of the border in pixels, supports sub-pixel widths if anti-aliasing is enabled
+    /// Defaults to 1 pixel if not set
+    #[serde(default)]
+    pub border_width: f32,
+    /// If true, the graph lines and the border are drawn anti-aliased
+    #[serde(default)]
+    pub antialias: bool,
+    /// Width divided by height of a physical pixel, used to keep points round and borders even
+    /// Taken from the display config if not set, defaults to 1
+    #[serde(default)]
+    pub pixel_aspect_ratio: f32,
+    /// If true, higher values are plotted lower, so the graph grows downwards
+    #[serde(default)]
+    pub invert_y: bool,
+}
+
+/// Represents the unit of a size.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum SizeUnit {
+    /// Physical pixels of the display
+    #[default]
+    #[serde(rename = "px")]
+    Pixel,
+    /// Density-independent pixels, scaled by the scale factor of the display
+    #[serde(rename = "dp")]
+    DensityIndependentPixel,
+}
+
+/// Represents an additional sensor series of a graph element.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct GraphSeries {
+    #[serde(default)]
+    pub sensor_id: String,
+    #[serde(default)]
+    pub sensor_values: Vec<f64>,
+    #[serde(default)]
+    pub graph_color: String,
+    /// Label of the sensor series, shown in the legend. Defaults to the sensor id.
+    #[serde(default)]
+    pub label: String,
+    /// If set, the series is normalized against its own value range instead of the range of the graph,
+    /// e.g. to show a sensor with another unit on a secondary axis. An unset bound is taken from the values.
+    #[serde(default)]
+    pub min_sensor_value: Option<f64>,
+    #[serde(default)]
+    pub max_sensor_value: Option<f64>,
+}
+
+/// Represents the legend of a graph element.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct GraphLegendConfig {
+    #[serde(default)]
+    pub position: LegendPosition,
+    #[serde(default)]
+    pub font_family: String,
+    #[serde(default)]
+    pub font_size: u32,
+    #[serde(default)]
+    pub font_color: String,
+    #[serde(default)]
+    pub background_color: String,
+}
+
+/// Represents the value labels of a graph element, drawn next to the annotated data points.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct GraphAnnotationConfig {
+    #[serde(default)]
+    pub target: AnnotationTarget,
+    #[serde(default)]
+    pub font_family: String,
+    #[serde(default)]
+    pub font_size: u32,
+    #[serde(default)]
+    pub font_color: String,
+    /// Number of decimals of the labeled values
+    #[serde(default)]
+    pub decimal_places: u32,
+    /// Text appended to the labeled values, e.g. the unit
+    #[serde(default)]
+    pub suffix: String,
+}
+
+/// Represents the data points of a graph element, that are labeled with their value.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum AnnotationTarget {
+    /// The latest, rightmost data point
+    #[default]
+    #[serde(rename = "latest")]
+    Latest,
+    /// The data points with the minimum and the maximum value
+    #[serde(rename = "extrema")]
+    Extrema,
+}
+
+/// Represents the corner of a graph element, the legend is drawn in.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum LegendPosition {
+    #[default]
+    #[serde(rename = "top-left")]
+    TopLeft,
+    #[serde(rename = "top-right")]
+    TopRight,
+    #[serde(rename = "bottom-left")]
+    BottomLeft,
+    #[serde(rename = "bottom-right")]
+    BottomRight,
 }
 
 /// Represents a conditional image element on a display.
@@ -210,6 +1495,13 @@ pub struct ConditionalImageConfig {
     pub sensor_value: String,
     #[serde(default)]
     pub images_path: String,
+    /// If set, the images are cropped from a single atlas image instead of one file per value
+    #[serde(default)]
+    pub atlas: Option<ImageAtlasConfig>,
+    /// If true, the image colors are premultiplied with the alpha channel
+    /// and will be converted to straight alpha before compositing
+    #[serde(default)]
+    pub premultiplied_alpha: bool,
     #[serde(default)]
     pub min_sensor_value: f64,
     #[serde(default)]
@@ -218,6 +1510,225 @@ pub struct ConditionalImageConfig {
     pub width: u32,
     #[serde(default)]
     pub height: u32,
+    /// If true, the images are not prepared up front, instead the image of the current sensor value
+    /// is requested from the host when it is shown, see `render_lcd_image_with_on_demand_images`
+    #[serde(default)]
+    pub on_demand: bool,
+    /// Number of images of a numeric sensor, evenly spaced between the min and max sensor value,
+    /// if the images are loaded on demand. The sensor value is rounded to the nearest image before
+    /// it is requested, so values showing the same image share one request. If 0, only values
+    /// outside of the min and max are moved to the nearest bound.
+    #[serde(default)]
+    pub on_demand_image_count: u32,
+    /// The image data of the current sensor value, if the images are loaded on demand
+    /// Set when the image is fetched before rendering
+    #[serde(default)]
+    pub on_demand_image_data: Vec<u8>,
+}
+
+/// Represents an atlas / sprite sheet image, holding all images of a conditional image element.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct ImageAtlasConfig {
+    /// File name of the atlas image within the images folder
+    #[serde(default)]
+    pub image_name: String,
+    #[serde(default)]
+    pub cells: Vec<ImageAtlasCell>,
+}
+
+/// Represents a single image within an atlas image.
+/// The sensor value is matched like the file name of a single image.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct ImageAtlasCell {
+    #[serde(default)]
+    pub sensor_value: String,
+    #[serde(default)]
+    pub x: u32,
+    #[serde(default)]
+    pub y: u32,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+}
+
+/// Represents a table element on a display.
+/// Each row shows a label on the left and the sensor value on the right.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct TableConfig {
+    #[serde(default)]
+    pub rows: Vec<TableRow>,
+    /// Format of the values, e.g. "{value} {unit}", supports the same placeholders as text elements
+    #[serde(default)]
+    pub value_format: String,
+    #[serde(default)]
+    pub font_family: String,
+    #[serde(default)]
+    pub font_size: u32,
+    #[serde(default)]
+    pub font_color: String,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    /// If true, the text is drawn without anti-aliasing, each pixel is either covered or not
+    /// Set for all table elements by the render quality of the display
+    #[serde(default)]
+    pub aliased: bool,
+}
+
+/// Represents a single row of a table element.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct TableRow {
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub sensor_id: String,
+}
+
+/// Represents a gauge element on a display.
+/// The current sensor value is shown as an arc, filled proportionally within the value range.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct GaugeConfig {
+    #[serde(default)]
+    pub sensor_id: String,
+    #[serde(default)]
+    pub min_sensor_value: f64,
+    #[serde(default)]
+    pub max_sensor_value: f64,
+    /// Angle in degrees the arc starts at, 0 points to the right and angles increase clockwise
+    #[serde(default)]
+    pub start_angle: f32,
+    /// Angle in degrees the arc ends at, the arc is filled from the start towards the end angle
+    /// If it is smaller than the start angle, the arc runs counter-clockwise.
+    /// If it equals the start angle, the arc is a full circle.
+    #[serde(default)]
+    pub end_angle: f32,
+    /// Color of the whole arc, behind the filled part
+    #[serde(default)]
+    pub track_color: String,
+    #[serde(default)]
+    pub fill_color: String,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    /// Width of the arc in pixels, defaults to 1 pixel if not set
+    #[serde(default)]
+    pub stroke_width: u32,
+    /// Width divided by height of a physical pixel, used to keep the arc round
+    /// Taken from the display config if not set, defaults to 1
+    #[serde(default)]
+    pub pixel_aspect_ratio: f32,
+    /// Radial marks at specific sensor values, e.g. a redline, drawn over the arc
+    #[serde(default)]
+    pub ticks: Vec<GaugeTick>,
+}
+
+/// Represents a radial tick mark at a specific sensor value on a gauge.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct GaugeTick {
+    /// Sensor value the mark points at, marks outside of the value range are not drawn
+    #[serde(default)]
+    pub value: f64,
+    #[serde(default)]
+    pub color: String,
+    /// Length of the mark in pixels, from the outer edge of the arc towards the center
+    /// Defaults to the stroke width of the arc if not set
+    #[serde(default)]
+    pub length: u32,
+}
+
+/// Represents a progress bar element on a display.
+/// The bar is filled proportionally to the current sensor value within the value range.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct ProgressBarConfig {
+    #[serde(default)]
+    pub sensor_id: String,
+    #[serde(default)]
+    pub min_sensor_value: f64,
+    #[serde(default)]
+    pub max_sensor_value: f64,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    #[serde(default)]
+    pub orientation: ProgressBarOrientation,
+    #[serde(default)]
+    pub fill_color: String,
+    /// Color of the unfilled part of the bar
+    #[serde(default)]
+    pub background_color: String,
+    /// If set, a border of one pixel is drawn around the bar and the bar is filled within it
+    #[serde(default)]
+    pub border_color: Option<String>,
+}
+
+/// Represents the direction a progress bar or a segment bar is filled in.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum ProgressBarOrientation {
+    /// Filled from the left to the right
+    #[default]
+    #[serde(rename = "horizontal")]
+    Horizontal,
+    /// Filled from the bottom to the top
+    #[serde(rename = "vertical")]
+    Vertical,
+}
+
+/// Represents a segment bar element on a display, a row of LED-style segments like a VU meter.
+/// The segments light up proportionally to the current sensor value within the value range.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct SegmentBarConfig {
+    #[serde(default)]
+    pub sensor_id: String,
+    #[serde(default)]
+    pub min_sensor_value: f64,
+    #[serde(default)]
+    pub max_sensor_value: f64,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    /// Number of segments, defaults to 10 if not set
+    #[serde(default)]
+    pub segment_count: u32,
+    /// Color of the lit segments
+    #[serde(default)]
+    pub on_color: String,
+    /// Color of the segments that are not lit
+    #[serde(default)]
+    pub off_color: String,
+    /// Gap between two segments in pixels
+    #[serde(default)]
+    pub spacing: u32,
+    #[serde(default)]
+    pub orientation: ProgressBarOrientation,
+}
+
+/// Represents a clock element on a display, showing the current time.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct ClockConfig {
+    /// Format of the time as strftime string, e.g. "%H:%M:%S", defaults to "%H:%M"
+    #[serde(default)]
+    pub format: String,
+    #[serde(default)]
+    pub font_family: String,
+    #[serde(default)]
+    pub font_size: u32,
+    #[serde(default)]
+    pub font_color: String,
+    /// IANA name of the time zone the time is shown in, e.g. "Europe/Berlin"
+    /// Defaults to the local time zone
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    #[serde(default)]
+    pub alignment: TextAlign,
 }
 
 /// Represents the type of an element on a display.
@@ -232,6 +1743,16 @@ pub enum ElementType {
     Graph,
     #[serde(rename = "conditional-image")]
     ConditionalImage,
+    #[serde(rename = "table")]
+    Table,
+    #[serde(rename = "gauge")]
+    Gauge,
+    #[serde(rename = "progress-bar")]
+    ProgressBar,
+    #[serde(rename = "clock")]
+    Clock,
+    #[serde(rename = "segment-bar")]
+    SegmentBar,
 }
 
 /// Provides a single SensorValue
@@ -247,6 +1768,31 @@ pub struct SensorValue {
     pub label: String,
     #[serde(default)]
     pub sensor_type: SensorType,
+    /// Unix timestamp in milliseconds, when the value was sampled
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// If set, number values are rounded to this amount of decimals when they are ingested
+    #[serde(default)]
+    pub decimals: Option<u32>,
+}
+
+impl SensorValue {
+    /// Rounds the value to the configured amount of decimals
+    /// Only applies to number values, all other values are left untouched
+    pub fn normalize(&mut self) {
+        let decimals = match self.decimals {
+            Some(decimals) => decimals as usize,
+            None => return,
+        };
+
+        if self.sensor_type != SensorType::Number {
+            return;
+        }
+
+        if let Ok(value) = self.value.parse::<f64>() {
+            self.value = format!("{:.*}", decimals, value);
+        }
+    }
 }
 
 /// Represents the modifier of a sensor value.
@@ -265,6 +1811,54 @@ pub enum SensorValueModifier {
     Avg,
 }
 
+/// Represents a conversion of numeric sensor values to another unit.
+/// Decimal prefixes are used, a megabyte are 1 000 000 bytes.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
+pub enum UnitConversion {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "celsius-to-fahrenheit")]
+    CelsiusToFahrenheit,
+    #[serde(rename = "bytes-to-megabytes")]
+    BytesToMegabytes,
+    #[serde(rename = "bytes-to-megabits")]
+    BytesToMegabits,
+    #[serde(rename = "bits-to-megabits")]
+    BitsToMegabits,
+}
+
+impl UnitConversion {
+    /// Converts the value to the target unit
+    pub fn convert_value(&self, value: f64) -> f64 {
+        match self {
+            UnitConversion::None => value,
+            UnitConversion::CelsiusToFahrenheit => value * 9.0 / 5.0 + 32.0,
+            UnitConversion::BytesToMegabytes => value / 1_000_000.0,
+            UnitConversion::BytesToMegabits => value * 8.0 / 1_000_000.0,
+            UnitConversion::BitsToMegabits => value / 1_000_000.0,
+        }
+    }
+
+    /// Returns the target unit, rates keep their per second suffix
+    /// The unit is kept unchanged if there is no conversion
+    pub fn convert_unit(&self, unit: &str) -> String {
+        let rate_suffix = if unit.trim_end().ends_with("/s") {
+            "/s"
+        } else {
+            ""
+        };
+        match self {
+            UnitConversion::None => unit.to_string(),
+            UnitConversion::CelsiusToFahrenheit => "°F".to_string(),
+            UnitConversion::BytesToMegabytes => format!("MB{}", rate_suffix),
+            UnitConversion::BytesToMegabits | UnitConversion::BitsToMegabits => {
+                format!("Mbit{}", rate_suffix)
+            }
+        }
+    }
+}
+
 /// Represents the type of a sensor value.
 /// This is used to determine how to render the value.
 /// For example a text value will be rendered as text, while a number value can be rendered as a graph.
@@ -277,254 +1871,5026 @@ pub enum SensorType {
     Number,
 }
 
+/// The default maximum width and height of a rendered image in pixels
+pub const DEFAULT_MAX_RESOLUTION: u32 = 8192;
+
+/// Represents an error that occurred while rendering an image.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum RenderError {
+    /// The configured resolution has a zero width or height
+    ZeroResolution { width: u32, height: u32 },
+    /// The configured resolution exceeds the maximum resolution
+    ResolutionTooLarge {
+        width: u32,
+        height: u32,
+        max_resolution: u32,
+    },
+    /// The config has more elements than allowed by the render options
+    TooManyElements { count: usize, max_elements: usize },
+    /// The type specific config of an element is missing
+    MissingConfig { config: String },
+    /// No font data is available for the font family
+    MissingFont { font_family: String },
+    /// The font data of the font family can not be parsed
+    InvalidFont { font_family: String },
+    /// An image can not be decoded
+    ImageDecode { message: String },
+    /// No conditional image matches the sensor value
+    NoMatchingImage { sensor_value: String },
+    /// The time zone of a clock is unknown
+    InvalidTimezone { timezone: String },
+    /// The time format of a clock has invalid specifiers
+    InvalidTimeFormat { format: String },
+    /// The sensor value history is empty, so the element shows no sensor values
+    EmptyHistory,
+    /// A color field can not be parsed
+    ColorParse {
+        field: String,
+        error: ColorParseError,
+    },
+    /// A file can not be read
+    Io { path: String, message: String },
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::ZeroResolution { width, height } => {
+                write!(f, "Resolution {}x{} must not be zero", width, height)
+            }
+            RenderError::ResolutionTooLarge {
+                width,
+                height,
+                max_resolution,
+            } => write!(
+                f,
+                "Resolution {}x{} exceeds the maximum of {}x{}",
+                width, height, max_resolution, max_resolution
+            ),
+            RenderError::TooManyElements {
+                count,
+                max_elements,
+            } => write!(
+                f,
+                "{} elements exceed the maximum of {} elements",
+                count, max_elements
+            ),
+            RenderError::MissingConfig { config } => write!(f, "{} is missing", config),
+            RenderError::MissingFont { font_family } => {
+                write!(f, "Font data for font family {} not found", font_family)
+            }
+            RenderError::InvalidFont { font_family } => {
+                write!(f, "Font data for font family {} is invalid", font_family)
+            }
+            RenderError::ImageDecode { message } => {
+                write!(f, "Failed to decode image: {}", message)
+            }
+            RenderError::NoMatchingImage { sensor_value } => {
+                write!(f, "No image found for sensor value {}", sensor_value)
+            }
+            RenderError::InvalidTimezone { timezone } => {
+                write!(f, "Unknown time zone {}", timezone)
+            }
+            RenderError::InvalidTimeFormat { format } => {
+                write!(f, "Invalid time format {}", format)
+            }
+            RenderError::EmptyHistory => write!(f, "The sensor value history is empty"),
+            RenderError::ColorParse { field, error } => {
+                write!(f, "Invalid color in {}: {}", field, error)
+            }
+            RenderError::Io { path, message } => {
+                write!(f, "Failed to read {}: {}", path, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// Represents an error of a single element, the element is skipped or drawn as good as possible
+/// while the rest of the frame is still rendered.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ElementError {
+    pub element_id: String,
+    pub error: RenderError,
+}
+
 /// Render the image
 /// The image will be a RGB8 png image
+/// If the resolution is invalid, the error is logged and an empty image is returned
 pub fn render_lcd_image(
     display_config: DisplayConfig,
     sensor_value_history: &[Vec<SensorValue>],
     fonts_data: &HashMap<String, Vec<u8>>,
 ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    let start_time = Instant::now();
+    match try_render_lcd_image(
+        display_config,
+        sensor_value_history,
+        fonts_data,
+        &RenderOptions::default(),
+    ) {
+        Ok(image) => image,
+        Err(err) => {
+            error!("Failed to render image: {}", err);
+            ImageBuffer::new(0, 0)
+        }
+    }
+}
 
-    // Get the resolution from the lcd config
-    let image_width = display_config.resolution_width;
-    let image_height = display_config.resolution_height;
+/// Represents the options of the render process.
+#[derive(PartialEq, Debug, Clone)]
+pub struct RenderOptions {
+    /// The maximum width and height of the rendered image in pixels
+    pub max_resolution: u32,
+    /// If true, the bounds and the id of each element are drawn on top of the rendered image
+    pub debug_overlay: bool,
+    /// If set, only the latest entries of the sensor value history are taken into account,
+    /// by all elements, aggregations and graphs
+    pub max_history: Option<usize>,
+    /// Defines what happens if an element fails to render
+    pub on_error: OnError,
+    /// Fraction of the display resolution the frame is rendered at, before it is upscaled
+    /// to the display resolution. Trades sharpness for render speed, 1 renders at full resolution.
+    pub render_scale: f32,
+    /// If true, a warning is logged for each pair of overlapping elements
+    pub warn_on_collisions: bool,
+    /// If set, configs with more elements are rejected before anything is rendered
+    pub max_elements: Option<usize>,
+}
 
-    // Create a new ImageBuffer with the specified resolution
-    let mut image = ImageBuffer::new(image_width, image_height);
+/// Represents the behavior if an element fails to render.
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub enum OnError {
+    /// The element is skipped, leaving a gap in the rendered image
+    #[default]
+    Skip,
+    /// A gray placeholder box with the element id is drawn instead of the element
+    Placeholder,
+}
 
-    // Iterate over lcd elements and draw them on the image
-    for lcd_element in display_config.elements {
-        draw_element(&mut image, lcd_element, sensor_value_history, fonts_data);
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            max_resolution: DEFAULT_MAX_RESOLUTION,
+            debug_overlay: false,
+            max_history: None,
+            on_error: OnError::Skip,
+            render_scale: 1.0,
+            warn_on_collisions: false,
+            max_elements: None,
+        }
     }
+}
 
-    debug!(" = Total frame render duration: {:?}", start_time.elapsed());
+/// Supplies the font data by font family, e.g. lazily from a database
+/// Implemented for the fonts data map and for closures
+pub trait FontProvider {
+    /// Returns the font data of the given font family
+    fn get_font_data(&self, font_family: &str) -> Option<Cow<'_, [u8]>>;
 
-    image
-}
+    /// Returns the font data of any font, used to draw diagnostic labels
+    fn get_fallback_font_data(&self) -> Option<Cow<'_, [u8]>> {
+        None
+    }
 
-/// Draws a single element on the image.
-/// The element will be drawn on the given image buffer.
-/// Distinguishes between the different element types and calls the corresponding draw function.
-fn draw_element(
-    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    lcd_element: ElementConfig,
-    sensor_value_history: &[Vec<SensorValue>],
-    fonts_data: &HashMap<String, Vec<u8>>,
-) {
-    let x = lcd_element.x;
-    let y = lcd_element.y;
-    let element_id = lcd_element.id.as_str();
+    /// Returns the parsed font of the given font family
+    /// By default the font data is parsed on every call
+    fn get_font(&self, font_family: &str) -> Result<rusttype::Font<'_>, RenderError> {
+        let font_data =
+            self.get_font_data(font_family)
+                .ok_or_else(|| RenderError::MissingFont {
+                    font_family: font_family.to_string(),
+                })?;
+        parse_font(font_data).ok_or_else(|| RenderError::InvalidFont {
+            font_family: font_family.to_string(),
+        })
+    }
 
-    // diff between type
-    match lcd_element.element_type {
-        ElementType::Text => {
-            let text_config = lcd_element.text_config.unwrap();
-            draw_text(
-                image,
-                &lcd_element.id,
-                text_config,
-                x,
-                y,
-                sensor_value_history,
-                fonts_data,
-            );
-        }
-        ElementType::StaticImage => {
-            draw_static_image(image, &lcd_element.id, x, y);
-        }
-        ElementType::Graph => {
-            let mut graph_config = lcd_element.graph_config.unwrap();
-            graph_config.sensor_values =
-                extract_value_sequence(sensor_value_history, &graph_config.sensor_id);
+    /// Returns the cache of the rasterized glyphs, if the glyphs should be cached
+    fn get_glyph_cache(&self) -> Option<&text_renderer::GlyphCache> {
+        None
+    }
 
-            draw_graph(image, x, y, graph_config);
-        }
-        ElementType::ConditionalImage => {
-            let conditional_image_config = lcd_element.conditional_image_config.unwrap();
-            let sensor_value = sensor_value_history[0]
-                .iter()
-                .find(|&s| s.id == conditional_image_config.sensor_id);
-            draw_conditional_image(
-                image,
-                x,
-                y,
-                element_id,
-                conditional_image_config,
-                sensor_value,
-            )
-        }
+    /// Returns the cache of the rendered text elements, if unchanged texts should be reused
+    fn get_text_render_cache(&self) -> Option<&text_renderer::TextRenderCache> {
+        None
+    }
+
+    /// Returns the aggregates of the numeric values of the sensor over the rendered history,
+    /// if they are maintained outside of the history, see `render_lcd_image_with_history`
+    fn get_history_aggregates(&self, _sensor_id: &str) -> Option<HistoryAggregates> {
+        None
     }
 }
 
-/// Draws a static image on the image buffer.
-fn draw_static_image(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, element_id: &str, x: i32, y: i32) {
-    let start_time = Instant::now();
+impl FontProvider for HashMap<String, Vec<u8>> {
+    /// The font family is looked up ignoring surrounding whitespace and casing,
+    /// an exact match takes precedence
+    fn get_font_data(&self, font_family: &str) -> Option<Cow<'_, [u8]>> {
+        let normalized_font_family = font_family.trim().to_lowercase();
+        self.get(font_family)
+            .or_else(|| {
+                self.iter()
+                    .find(|(key, _)| key.trim().to_lowercase() == normalized_font_family)
+                    .map(|(_, font_data)| font_data)
+            })
+            .map(|font_data| Cow::Borrowed(font_data.as_slice()))
+    }
 
-    let cache_dir = get_cache_dir(element_id, &ElementType::StaticImage).join(element_id);
-    let file_path = cache_dir.to_str().unwrap();
+    fn get_fallback_font_data(&self) -> Option<Cow<'_, [u8]>> {
+        self.keys()
+            .min()
+            .map(|font_family| Cow::Borrowed(self[font_family].as_slice()))
+    }
+}
 
-    if !Path::new(&file_path).exists() {
-        error!("File {} does not exist", file_path);
-        return;
+impl<F: Fn(&str) -> Option<Vec<u8>>> FontProvider for F {
+    fn get_font_data(&self, font_family: &str) -> Option<Cow<'_, [u8]>> {
+        self(font_family).map(Cow::Owned)
     }
+}
 
-    // Read image into memory
-    // We heavily assume that this is already png encoded to skip the expensive png decoding
-    let img_data = fs::read(file_path).unwrap();
-    let overlay_image = image::load_from_memory(&img_data).unwrap();
+/// Caches the parsed fonts and the rasterized glyphs by font family, so they are not parsed
+/// and rasterized again on every frame
+/// The cache is populated lazily and has to be cleared if the font data of a font family changes.
+#[derive(Default)]
+pub struct FontCache {
+    fonts: Mutex<HashMap<String, rusttype::Font<'static>>>,
+    glyph_cache: text_renderer::GlyphCache,
+    text_render_cache: text_renderer::TextRenderCache,
+}
 
-    image::imageops::overlay(image, &overlay_image, x as i64, y as i64);
+impl FontCache {
+    /// Creates a new, empty font cache
+    pub fn new() -> Self {
+        FontCache::default()
+    }
 
-    debug!("    - Image render duration: {:?}", start_time.elapsed());
-}
+    /// Returns the parsed font of the given font family
+    /// On first use the font data is taken from the font provider and parsed
+    pub fn get_font(
+        &self,
+        font_provider: &dyn FontProvider,
+        font_family: &str,
+    ) -> Result<rusttype::Font<'static>, RenderError> {
+        let mut fonts = self.fonts.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(font) = fonts.get(font_family) {
+            return Ok(font.clone());
+        }
 
-fn draw_graph(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, x: i32, y: i32, config: GraphConfig) {
-    let start_time = Instant::now();
+        let font_data =
+            font_provider
+                .get_font_data(font_family)
+                .ok_or_else(|| RenderError::MissingFont {
+                    font_family: font_family.to_string(),
+                })?;
+        let font = parse_font(Cow::Owned(font_data.into_owned())).ok_or_else(|| {
+            RenderError::InvalidFont {
+                font_family: font_family.to_string(),
+            }
+        })?;
+        fonts.insert(font_family.to_string(), font.clone());
+        Ok(font)
+    }
 
-    let img_data = graph_renderer::render(&config);
-    let graph_image = image::load_from_memory(&img_data).unwrap();
+    /// Returns the cache of the rasterized glyphs
+    pub fn glyph_cache(&self) -> &text_renderer::GlyphCache {
+        &self.glyph_cache
+    }
 
-    image::imageops::overlay(image, &graph_image, x as i64, y as i64);
+    /// Returns the cache of the rendered text elements
+    pub fn text_render_cache(&self) -> &text_renderer::TextRenderCache {
+        &self.text_render_cache
+    }
 
-    debug!("    - Graph render duration: {:?}", start_time.elapsed());
+    /// Removes all parsed fonts, rasterized glyphs and rendered texts from the cache
+    pub fn clear(&self) {
+        self.fonts
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+        self.glyph_cache.clear();
+        self.text_render_cache.clear();
+    }
 }
 
-/// Draws a conditional image on the image buffer.
-fn draw_conditional_image(
-    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    x: i32,
-    y: i32,
-    element_id: &str,
-    mut config: ConditionalImageConfig,
-    sensor_value: Option<&SensorValue>,
-) {
-    let start_time = Instant::now();
+/// Supplies the fonts of the font provider, parsed fonts are taken from the font cache
+struct CachedFontProvider<'a> {
+    font_provider: &'a dyn FontProvider,
+    font_cache: &'a FontCache,
+}
 
-    let sensor_value = match sensor_value {
-        None => {
-            return;
-        }
-        Some(sensor_value) => sensor_value,
-    };
+impl FontProvider for CachedFontProvider<'_> {
+    fn get_font_data(&self, font_family: &str) -> Option<Cow<'_, [u8]>> {
+        self.font_provider.get_font_data(font_family)
+    }
 
-    config.sensor_value = sensor_value.value.clone();
-    let img_data =
-        conditional_image_renderer::render(element_id, &sensor_value.sensor_type, &config);
+    fn get_fallback_font_data(&self) -> Option<Cow<'_, [u8]>> {
+        self.font_provider.get_fallback_font_data()
+    }
 
-    if let Some(img_data) = img_data {
-        let conditional_image = image::load_from_memory(&img_data).unwrap();
-        image::imageops::overlay(image, &conditional_image, x as i64, y as i64);
+    fn get_font(&self, font_family: &str) -> Result<rusttype::Font<'_>, RenderError> {
+        self.font_cache.get_font(self.font_provider, font_family)
     }
 
-    debug!(
-        "    - Conditional image render duration: {:?}",
-        start_time.elapsed()
-    );
+    fn get_glyph_cache(&self) -> Option<&text_renderer::GlyphCache> {
+        Some(&self.font_cache.glyph_cache)
+    }
+
+    fn get_text_render_cache(&self) -> Option<&text_renderer::TextRenderCache> {
+        Some(&self.font_cache.text_render_cache)
+    }
 }
 
-/// Draws a text element on the image buffer.
-fn draw_text(
-    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    _element_id: &str,
-    text_config: TextConfig,
-    x: i32,
-    y: i32,
+/// Render the image
+/// The image will be a RGB8 png image
+/// Returns an error if the resolution is zero or exceeds the maximum resolution of the options,
+/// before any image buffer is allocated
+pub fn try_render_lcd_image(
+    display_config: DisplayConfig,
     sensor_value_history: &[Vec<SensorValue>],
     fonts_data: &HashMap<String, Vec<u8>>,
-) {
-    let start_time = Instant::now();
+    options: &RenderOptions,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, RenderError> {
+    render_lcd_image_with_font_provider(display_config, sensor_value_history, fonts_data, options)
+}
 
-    let font_data = match fonts_data.get(&text_config.font_family) {
-        Some(font_data) => font_data,
-        None => {
-            error!(
-                "Font data for font family {} not found",
-                text_config.font_family
-            );
-            return;
-        }
+/// Render the image, the fonts are supplied lazily by the font provider
+/// Only the fonts of font families that are actually referenced are requested
+/// The image will be a RGB8 png image
+/// Returns an error if the resolution is zero or exceeds the maximum resolution of the options,
+/// before any image buffer is allocated
+pub fn render_lcd_image_with_font_provider(
+    display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    font_provider: &dyn FontProvider,
+    options: &RenderOptions,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, RenderError> {
+    render_frame(
+        display_config,
+        sensor_value_history,
+        font_provider,
+        options,
+        None,
+        None,
+    )
+    .map(|(image, _)| image)
+}
+
+/// Render the image, like `try_render_lcd_image`
+/// The fonts are parsed and the glyphs of text elements are rasterized once,
+/// and reused from the font cache across frames
+pub fn render_lcd_image_with_font_cache(
+    display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+    options: &RenderOptions,
+    font_cache: &FontCache,
+) -> Result<image::RgbaImage, RenderError> {
+    let font_provider = CachedFontProvider {
+        font_provider: fonts_data,
+        font_cache,
     };
-    let font = rusttype::Font::try_from_bytes(font_data).unwrap();
+    render_lcd_image_with_font_provider(
+        display_config,
+        sensor_value_history,
+        &font_provider,
+        options,
+    )
+}
 
-    let text_image = text_renderer::render(
-        image.width(),
-        image.height(),
-        &text_config,
+/// Render the image, like `try_render_lcd_image`
+/// The images of conditional image elements, that load their images on demand, are taken from the
+/// image cache. Images that are not cached yet are fetched for the current sensor value first.
+pub fn render_lcd_image_with_on_demand_images(
+    mut display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+    options: &RenderOptions,
+    fetcher: &dyn conditional_image_renderer::ConditionalImageFetcher,
+    image_cache: &conditional_image_renderer::OnDemandImageCache,
+) -> Result<image::RgbaImage, RenderError> {
+    apply_on_demand_images(
+        &mut display_config,
         sensor_value_history,
-        &font,
+        fetcher,
+        image_cache,
     );
-    image::imageops::overlay(image, &text_image, x as i64, y as i64);
-
-    debug!("    - Text render duration: {:?}", start_time.elapsed());
+    render_lcd_image_with_font_provider(display_config, sensor_value_history, fonts_data, options)
 }
 
-/// Converts a hex string to a Rgba<u8>
-/// The hex string must be in the format #RRGGBBAA
-/// Example: #FF0000CC
-/// Returns a Rgba<u8> struct
-pub fn hex_to_rgba(hex_string: &str) -> Rgba<u8> {
-    let hex_string = hex_string.trim_start_matches('#');
-    let hex = u32::from_str_radix(hex_string, 16).unwrap();
-    let r = ((hex >> 24) & 0xff) as u8;
-    let g = ((hex >> 16) & 0xff) as u8;
-    let b = ((hex >> 8) & 0xff) as u8;
-    let a = (hex & 0xff) as u8;
-    Rgba([r, g, b, a])
+/// Render the image, like `try_render_lcd_image`, of the history kept by the history builder
+/// The min, max and average placeholders of text elements are taken from the aggregates
+/// maintained by the history builder, instead of a pass over the history per frame.
+/// If the options cap the history below the length of the kept history, the aggregates do not
+/// match the rendered history, so the placeholders are computed from the capped history instead.
+pub fn render_lcd_image_with_history(
+    display_config: DisplayConfig,
+    history_builder: &mut sensor_history::SensorHistoryBuilder,
+    fonts_data: &HashMap<String, Vec<u8>>,
+    options: &RenderOptions,
+) -> Result<image::RgbaImage, RenderError> {
+    let history_aggregates = match options.max_history {
+        Some(max_history) if max_history < history_builder.len() => HashMap::new(),
+        _ => get_history_aggregates(&display_config, history_builder),
+    };
+    let font_provider = HistoryAggregatesFontProvider {
+        font_provider: fonts_data,
+        history_aggregates,
+    };
+    render_lcd_image_with_font_provider(
+        display_config,
+        history_builder.history(),
+        &font_provider,
+        options,
+    )
 }
 
-/// Extracts the historical values from the sensor_value_history and reverses the order
-pub fn extract_value_sequence(
-    sensor_value_history: &[Vec<SensorValue>],
-    sensor_id: &str,
-) -> Vec<f64> {
-    let mut sensor_values: Vec<f64> = sensor_value_history
+/// Returns the aggregates of the sensors of the text elements by sensor id
+/// Sensors without numeric values in the history have no aggregates
+fn get_history_aggregates(
+    display_config: &DisplayConfig,
+    history_builder: &sensor_history::SensorHistoryBuilder,
+) -> HashMap<String, HistoryAggregates> {
+    display_config
+        .elements
         .iter()
-        .flat_map(|history_entry| {
-            history_entry.iter().find_map(|entry| {
-                if entry.id.eq(sensor_id) {
-                    return entry.value.parse().ok();
-                }
-                None
-            })
+        .filter_map(|element| element.text_config.as_ref())
+        .filter_map(|text_config| {
+            let aggregates = history_builder.aggregates(&text_config.sensor_id)?;
+            let history_aggregates = HistoryAggregates {
+                min: aggregates.min()?,
+                max: aggregates.max()?,
+                avg: aggregates.avg()?,
+            };
+            Some((text_config.sensor_id.clone(), history_aggregates))
         })
-        .collect();
-    sensor_values.reverse();
-    sensor_values
+        .collect()
 }
 
-/// Checks if the given DirEntry is an image
-pub fn is_image(dir_entry: &DirEntry) -> bool {
-    let entry_path = dir_entry.path();
-    let extension_string = entry_path.extension().map(|ext| ext.to_str().unwrap());
-    let image_format = extension_string.and_then(ImageFormat::from_extension);
-    image_format.map(|x| x.can_read()).unwrap_or(false)
+/// Supplies the aggregates maintained by a history builder along with the fonts of the font provider
+struct HistoryAggregatesFontProvider<'a> {
+    font_provider: &'a dyn FontProvider,
+    history_aggregates: HashMap<String, HistoryAggregates>,
 }
 
-/// Get the cache directory for the given element
-pub fn get_cache_dir(element_id: &str, element_type: &ElementType) -> PathBuf {
-    let element_type_folder_name = match element_type {
-        ElementType::Text => "text",
-        ElementType::StaticImage => "static-image",
-        ElementType::Graph => "graph",
-        ElementType::ConditionalImage => "conditional-image",
-    };
+impl FontProvider for HistoryAggregatesFontProvider<'_> {
+    fn get_font_data(&self, font_family: &str) -> Option<Cow<'_, [u8]>> {
+        self.font_provider.get_font_data(font_family)
+    }
 
-    get_cache_base_dir()
-        .join(element_type_folder_name)
-        .join(element_id)
+    fn get_fallback_font_data(&self) -> Option<Cow<'_, [u8]>> {
+        self.font_provider.get_fallback_font_data()
+    }
+
+    fn get_font(&self, font_family: &str) -> Result<rusttype::Font<'_>, RenderError> {
+        self.font_provider.get_font(font_family)
+    }
+
+    fn get_glyph_cache(&self) -> Option<&text_renderer::GlyphCache> {
+        self.font_provider.get_glyph_cache()
+    }
+
+    fn get_text_render_cache(&self) -> Option<&text_renderer::TextRenderCache> {
+        self.font_provider.get_text_render_cache()
+    }
+
+    fn get_history_aggregates(&self, sensor_id: &str) -> Option<HistoryAggregates> {
+        self.history_aggregates.get(sensor_id).cloned()
+    }
 }
 
-/// Get the base cache directory
-pub fn get_cache_base_dir() -> PathBuf {
-    dirs::cache_dir()
-        .unwrap()
-        .join(std::env::var("SENSOR_BRIDGE_APP_NAME").unwrap())
+/// Sets the image data of the conditional image elements, that load their images on demand,
+/// to the image of the latest sensor value. Elements without a sensor value are left empty.
+/// Numeric sensor values are requested as the value of the image they select, see
+/// `conditional_image_renderer::get_on_demand_sensor_value`
+fn apply_on_demand_images(
+    display_config: &mut DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fetcher: &dyn conditional_image_renderer::ConditionalImageFetcher,
+    image_cache: &conditional_image_renderer::OnDemandImageCache,
+) {
+    let latest_sensor_values = sensor_value_history.first();
+    for element in display_config.elements.iter_mut() {
+        let Some(config) = element.conditional_image_config.as_mut() else {
+            continue;
+        };
+        let sensor_value = latest_sensor_values.and_then(|sensor_values| {
+            sensor_values
+                .iter()
+                .find(|sensor_value| sensor_value.id == config.sensor_id)
+        });
+        let (true, Some(sensor_value)) = (config.on_demand, sensor_value) else {
+            continue;
+        };
+
+        let request = ConditionalImageRequest {
+            element_id: element.id.clone(),
+            sensor_value: conditional_image_renderer::get_on_demand_sensor_value(
+                config,
+                sensor_value,
+            ),
+            sensor_type: sensor_value.sensor_type.clone(),
+        };
+        config.on_demand_image_data = image_cache
+            .get_or_fetch(&request, fetcher)
+            .unwrap_or_default();
+    }
 }
 
-/// Get the application config dir
-pub fn get_config_dir() -> PathBuf {
-    dirs::config_dir()
-        .unwrap()
-        .join(std::env::var("SENSOR_BRIDGE_APP_NAME").unwrap())
+/// Render the image, like `try_render_lcd_image`
+/// Additionally returns the errors of the individual elements, e.g. a missing font,
+/// an image that can not be decoded or a color that can not be parsed.
+/// Failing elements do not abort the frame, only an invalid resolution does.
+pub fn render_lcd_image_with_errors(
+    display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+    options: &RenderOptions,
+) -> Result<(image::RgbaImage, Vec<ElementError>), RenderError> {
+    render_frame(
+        display_config,
+        sensor_value_history,
+        fonts_data,
+        options,
+        None,
+        None,
+    )
+}
+
+/// Render the image, like `try_render_lcd_image`
+/// Additionally returns a render tree, describing the decisions of the renderer for each element
+pub fn render_lcd_image_with_render_tree(
+    display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+    options: &RenderOptions,
+) -> Result<(image::RgbaImage, RenderTree), RenderError> {
+    let mut render_tree = RenderTree::default();
+    let (image, _) = render_frame(
+        display_config,
+        sensor_value_history,
+        fonts_data,
+        options,
+        Some(&mut render_tree),
+        None,
+    )?;
+    Ok((image, render_tree))
+}
+
+/// The frame buffer the elements of a frame are composited on
+/// The elements are always drawn in rgba, the frame decides how they are composited.
+trait Frame: Sized {
+    /// Creates the frame filled with the background color, or empty without one
+    fn new_frame(width: u32, height: u32, background_color: &str) -> Self;
+
+    /// Draws on the whole frame with the drawing function
+    fn draw<R>(&mut self, draw: impl FnOnce(&mut image::RgbaImage) -> R) -> R;
+
+    /// Draws the element on the frame with the drawing function
+    fn draw_element(
+        &mut self,
+        lcd_element: ElementConfig,
+        draw: impl FnOnce(&mut image::RgbaImage, ElementConfig) -> Result<(), RenderError>,
+    ) -> Result<(), RenderError>;
+
+    /// Returns the frame scaled to the given size
+    fn resize(&self, width: u32, height: u32) -> Self;
+
+    /// Returns the frame rotated clockwise by the rotation
+    fn rotate(self, rotation: &Rotation) -> Self;
+}
+
+/// The full color frame, the elements are drawn on it directly
+impl Frame for image::RgbaImage {
+    fn new_frame(width: u32, height: u32, background_color: &str) -> Self {
+        create_background(width, height, background_color)
+    }
+
+    fn draw<R>(&mut self, draw: impl FnOnce(&mut image::RgbaImage) -> R) -> R {
+        draw(self)
+    }
+
+    fn draw_element(
+        &mut self,
+        lcd_element: ElementConfig,
+        draw: impl FnOnce(&mut image::RgbaImage, ElementConfig) -> Result<(), RenderError>,
+    ) -> Result<(), RenderError> {
+        draw(self, lcd_element)
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Self {
+        image::imageops::resize(self, width, height, image::imageops::FilterType::Triangle)
+    }
+
+    fn rotate(self, rotation: &Rotation) -> Self {
+        rotation.rotate(self)
+    }
+}
+
+/// The luminance frame of monochrome displays, without a background color it is black
+/// Each element is drawn on a transparent layer of its bounds within the frame,
+/// and its luminance is blended with the frame by the blend mode of the element.
+impl Frame for image::GrayImage {
+    fn new_frame(width: u32, height: u32, background_color: &str) -> Self {
+        let background = if background_color.is_empty() {
+            0
+        } else {
+            let color = hex_to_rgba_or_default(background_color);
+            (mono_renderer::luminance(&color) as u32 * color[3] as u32 / 255) as u8
+        };
+        ImageBuffer::from_pixel(width, height, image::Luma([background]))
+    }
+
+    fn draw<R>(&mut self, draw: impl FnOnce(&mut image::RgbaImage) -> R) -> R {
+        let mut layer = ImageBuffer::new(self.width(), self.height());
+        let result = draw(&mut layer);
+        mono_renderer::composite(self, &layer, 0, 0, &BlendMode::Normal);
+        result
+    }
+
+    fn draw_element(
+        &mut self,
+        mut lcd_element: ElementConfig,
+        draw: impl FnOnce(&mut image::RgbaImage, ElementConfig) -> Result<(), RenderError>,
+    ) -> Result<(), RenderError> {
+        // Elements without a configured size span the whole frame
+        let (frame_width, frame_height) = (self.width() as i32, self.height() as i32);
+        let (width, height) = get_element_size(&lcd_element);
+        let (x, y, layer_width, layer_height) = if width == 0 || height == 0 {
+            (0, 0, frame_width, frame_height)
+        } else {
+            let x = lcd_element.x.clamp(0, frame_width);
+            let y = lcd_element.y.clamp(0, frame_height);
+            let right = lcd_element
+                .x
+                .saturating_add(width as i32)
+                .clamp(x, frame_width);
+            let bottom = lcd_element
+                .y
+                .saturating_add(height as i32)
+                .clamp(y, frame_height);
+            (x, y, right - x, bottom - y)
+        };
+
+        // The element is drawn relative to its layer and blended with the frame afterwards
+        let blend_mode = std::mem::take(&mut lcd_element.blend_mode);
+        lcd_element.x -= x;
+        lcd_element.y -= y;
+        let mut layer = ImageBuffer::new(layer_width.max(1) as u32, layer_height.max(1) as u32);
+        let result = draw(&mut layer, lcd_element);
+        mono_renderer::composite(self, &layer, x, y, &blend_mode);
+        result
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Self {
+        image::imageops::resize(self, width, height, image::imageops::FilterType::Triangle)
+    }
+
+    fn rotate(self, rotation: &Rotation) -> Self {
+        rotation.rotate(self)
+    }
+}
+
+/// Renders the frame, if a render tree is given each element and its draw status is added to it
+/// If a layer cache is given, elements with a refresh interval are drawn from their cached layers
+/// Returns the frame and the errors of the individual elements
+fn render_frame<F: Frame>(
+    mut display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    font_provider: &dyn FontProvider,
+    options: &RenderOptions,
+    mut render_tree: Option<&mut RenderTree>,
+    mut layer_cache: Option<LayerCache>,
+) -> Result<(F, Vec<ElementError>), RenderError> {
+    let start_time = Instant::now();
+
+    // Get the resolution from the lcd config
+    let image_width = display_config.resolution_width;
+    let image_height = display_config.resolution_height;
+    validate_resolution(image_width, image_height, options.max_resolution)?;
+    if let Some(max_elements) = options.max_elements {
+        validate_element_count(display_config.elements.len(), max_elements)?;
+    }
+
+    // Cap the history once, the latest entries are at the beginning
+    let sensor_value_history = match options.max_history {
+        Some(max_history) => &sensor_value_history[..sensor_value_history.len().min(max_history)],
+        None => sensor_value_history,
+    };
+    prepare_display_config(&mut display_config, sensor_value_history);
+
+    if options.warn_on_collisions {
+        for collision in display_config.find_collisions() {
+            warn!(
+                "Element {} is drawn over element {}",
+                collision.other_element_id, collision.element_id
+            );
+        }
+    }
+
+    // Remember the element bounds for the debug overlay and the render tree,
+    // before the elements are scaled and consumed
+    let element_bounds: Vec<(String, i32, i32, u32, u32)> = display_config
+        .elements
+        .iter()
+        .map(|element| {
+            let (width, height) = get_element_size(element);
+            (element.id.clone(), element.x, element.y, width, height)
+        })
+        .collect();
+
+    // Render at a reduced internal resolution if configured, and at an increased one for supersampling
+    let render_scale = if options.render_scale > 0.0 && options.render_scale < 1.0 {
+        options.render_scale
+    } else {
+        1.0
+    };
+    // The supersampled frame is subject to the maximum resolution too, so it is skipped if too large
+    let supersampling_factor = display_config.render_quality.supersampling_factor();
+    let (supersampled_width, supersampled_height) = (
+        image_width.saturating_mul(supersampling_factor),
+        image_height.saturating_mul(supersampling_factor),
+    );
+    let supersampling_factor = if supersampled_width > options.max_resolution
+        || supersampled_height > options.max_resolution
+    {
+        debug!(
+            "Supersampling skipped, {}x{} exceeds the maximum resolution of {}",
+            supersampled_width, supersampled_height, options.max_resolution
+        );
+        1
+    } else {
+        supersampling_factor
+    };
+    let render_scale = render_scale * supersampling_factor as f32;
+    let is_scaled = render_scale != 1.0;
+    if is_scaled {
+        scale_display_config(&mut display_config, render_scale);
+    }
+
+    // Create a new ImageBuffer with the specified resolution, filled with the background
+    let mut image = F::new_frame(
+        display_config.resolution_width,
+        display_config.resolution_height,
+        &display_config.background_color,
+    );
+    let asset_root = display_config.asset_root.map(PathBuf::from);
+    let rotation = display_config.rotation;
+    let mut element_errors = vec![];
+
+    if let Some(background_image_path) = &display_config.background_image_path {
+        if let Err(err) = image.draw(|image| {
+            draw_background_image(image, background_image_path, asset_root.as_deref())
+        }) {
+            error!("Failed to render the background image: {}", err);
+            element_errors.push(ElementError {
+                element_id: BACKGROUND_IMAGE_ID.to_string(),
+                error: err,
+            });
+        }
+    }
+
+    if let Some(layer_cache) = layer_cache.as_mut() {
+        layer_cache.retain(&display_config.elements);
+    }
+
+    // Iterate over lcd elements and draw them on the image
+    for (lcd_element, element_bounds) in display_config.elements.into_iter().zip(&element_bounds) {
+        let element_id = lcd_element.id.clone();
+        let (x, y) = (lcd_element.x, lcd_element.y);
+        let (width, height) = get_element_size(&lcd_element);
+        let element_type = lcd_element.element_type.clone();
+        let sensor_id = get_element_sensor_id(&lcd_element).map(str::to_string);
+
+        // Report problems that do not prevent the element from being drawn
+        element_errors.extend(
+            get_element_warnings(&lcd_element, sensor_value_history)
+                .into_iter()
+                .map(|error| ElementError {
+                    element_id: element_id.clone(),
+                    error,
+                }),
+        );
+
+        let result = image.draw_element(lcd_element, |image, lcd_element| {
+            match layer_cache.as_mut() {
+                Some(layer_cache) => layer_cache.draw_element(
+                    image,
+                    lcd_element,
+                    sensor_value_history,
+                    font_provider,
+                    asset_root.as_deref(),
+                ),
+                None => draw_element(
+                    image,
+                    lcd_element,
+                    sensor_value_history,
+                    font_provider,
+                    asset_root.as_deref(),
+                ),
+            }
+        });
+
+        if let Err(err) = &result {
+            error!("Failed to render element {}: {}", element_id, err);
+            if options.on_error == OnError::Placeholder {
+                image.draw(|image| {
+                    draw_placeholder(image, &element_id, x, y, width, height, font_provider)
+                });
+            }
+            element_errors.push(ElementError {
+                element_id: element_id.clone(),
+                error: err.clone(),
+            });
+        }
+
+        if let Some(render_tree) = render_tree.as_deref_mut() {
+            let sensor_value = sensor_id.as_ref().and_then(|sensor_id| {
+                sensor_value_history
+                    .first()?
+                    .iter()
+                    .find(|sensor_value| &sensor_value.id == sensor_id)
+                    .map(|sensor_value| sensor_value.value.clone())
+            });
+            let status = match result {
+                Err(error) => DrawStatus::Failed {
+                    error: error.to_string(),
+                },
+                // Conditional images without a sensor value are not drawn
+                Ok(())
+                    if element_type == ElementType::ConditionalImage && sensor_value.is_none() =>
+                {
+                    DrawStatus::Skipped
+                }
+                Ok(()) => DrawStatus::Drawn,
+            };
+            let (_, x, y, width, height) = element_bounds.clone();
+            render_tree.elements.push(RenderTreeElement {
+                id: element_id,
+                element_type,
+                x,
+                y,
+                width,
+                height,
+                sensor_id,
+                sensor_value,
+                status,
+            });
+        }
+    }
+
+    // Scale the internal resolution back to the display resolution
+    if is_scaled {
+        image = image.resize(image_width, image_height);
+    }
+
+    if options.debug_overlay {
+        image.draw(|image| draw_debug_overlay(image, &element_bounds, font_provider));
+    }
+
+    let image = image.rotate(&rotation);
+
+    debug!(" = Total frame render duration: {:?}", start_time.elapsed());
+
+    Ok((image, element_errors))
+}
+
+/// Returns the problems of the element that do not prevent it from being drawn,
+/// colors that can not be parsed are drawn black, missing sensor values are shown as N/A
+fn get_element_warnings(
+    element: &ElementConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+) -> Vec<RenderError> {
+    let mut warnings: Vec<RenderError> = get_color_fields(element)
+        .into_iter()
+        .filter_map(|(field, value)| {
+            hex_to_rgba(value.trim())
+                .err()
+                .map(|error| RenderError::ColorParse {
+                    field: field.to_string(),
+                    error,
+                })
+        })
+        .collect();
+
+    let references_sensor = get_element_sensor_ids(element)
+        .iter()
+        .any(|sensor_id| !sensor_id.is_empty());
+    if references_sensor && sensor_value_history.is_empty() {
+        warnings.push(RenderError::EmptyHistory);
+    }
+
+    warnings
+}
+
+/// Returns the ids of the elements whose referenced sensor values changed between two snapshots
+/// A sensor value changed if it differs or is only present in one of the snapshots.
+/// This allows to decide what to redraw without rendering.
+pub fn changed_element_ids(
+    prev_values: &[SensorValue],
+    next_values: &[SensorValue],
+    display_config: &DisplayConfig,
+) -> Vec<String> {
+    let find_value = |sensor_values: &[SensorValue], sensor_id: &str| {
+        sensor_values
+            .iter()
+            .find(|sensor_value| sensor_value.id == sensor_id)
+            .cloned()
+    };
+    let is_changed =
+        |sensor_id: &&str| find_value(prev_values, sensor_id) != find_value(next_values, sensor_id);
+
+    display_config
+        .elements
+        .iter()
+        .filter(|element| get_element_sensor_ids(element).iter().any(is_changed))
+        .map(|element| element.id.clone())
+        .collect()
+}
+
+/// Returns the ids of all sensors the given element references
+fn get_element_sensor_ids(element: &ElementConfig) -> Vec<&str> {
+    let mut sensor_ids = vec![];
+    if let Some(config) = &element.text_config {
+        sensor_ids.push(config.sensor_id.as_str());
+    }
+    if let Some(config) = &element.graph_config {
+        sensor_ids.push(config.sensor_id.as_str());
+        sensor_ids.extend(
+            config
+                .additional_series
+                .iter()
+                .map(|series| series.sensor_id.as_str()),
+        );
+    }
+    if let Some(config) = &element.conditional_image_config {
+        sensor_ids.push(config.sensor_id.as_str());
+    }
+    if let Some(config) = &element.table_config {
+        sensor_ids.extend(config.rows.iter().map(|row| row.sensor_id.as_str()));
+    }
+    if let Some(config) = &element.gauge_config {
+        sensor_ids.push(config.sensor_id.as_str());
+    }
+    if let Some(config) = &element.progress_bar_config {
+        sensor_ids.push(config.sensor_id.as_str());
+    }
+    if let Some(config) = &element.segment_bar_config {
+        sensor_ids.push(config.sensor_id.as_str());
+    }
+    sensor_ids
+}
+
+/// Returns the id of the sensor the given element displays, if any
+fn get_element_sensor_id(element: &ElementConfig) -> Option<&str> {
+    match element.element_type {
+        ElementType::Text => element
+            .text_config
+            .as_ref()
+            .map(|config| config.sensor_id.as_str()),
+        ElementType::Graph => element
+            .graph_config
+            .as_ref()
+            .map(|config| config.sensor_id.as_str()),
+        ElementType::ConditionalImage => element
+            .conditional_image_config
+            .as_ref()
+            .map(|config| config.sensor_id.as_str()),
+        ElementType::Gauge => element
+            .gauge_config
+            .as_ref()
+            .map(|config| config.sensor_id.as_str()),
+        ElementType::ProgressBar => element
+            .progress_bar_config
+            .as_ref()
+            .map(|config| config.sensor_id.as_str()),
+        ElementType::SegmentBar => element
+            .segment_bar_config
+            .as_ref()
+            .map(|config| config.sensor_id.as_str()),
+        ElementType::StaticImage | ElementType::Table | ElementType::Clock => None,
+    }
+}
+
+/// Returns the configured width and height of the given element
+fn get_element_size(element: &ElementConfig) -> (u32, u32) {
+    match element.element_type {
+        ElementType::Text => element
+            .text_config
+            .as_ref()
+            .map(|config| (config.width, config.height)),
+        ElementType::StaticImage => element
+            .image_config
+            .as_ref()
+            .map(|config| (config.width, config.height)),
+        ElementType::Graph => element
+            .graph_config
+            .as_ref()
+            .map(|config| (config.width, config.height)),
+        ElementType::ConditionalImage => element
+            .conditional_image_config
+            .as_ref()
+            .map(|config| (config.width, config.height)),
+        ElementType::Table => element
+            .table_config
+            .as_ref()
+            .map(|config| (config.width, config.height)),
+        ElementType::Gauge => element
+            .gauge_config
+            .as_ref()
+            .map(|config| (config.width, config.height)),
+        ElementType::ProgressBar => element
+            .progress_bar_config
+            .as_ref()
+            .map(|config| (config.width, config.height)),
+        ElementType::Clock => element
+            .clock_config
+            .as_ref()
+            .map(|config| (config.width, config.height)),
+        ElementType::SegmentBar => element
+            .segment_bar_config
+            .as_ref()
+            .map(|config| (config.width, config.height)),
+    }
+    .unwrap_or((0, 0))
+}
+
+/// Draws a gray placeholder box with the element id at the bounds of the element
+fn draw_placeholder(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    element_id: &str,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    font_provider: &dyn FontProvider,
+) {
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    imageproc::drawing::draw_filled_rect_mut(
+        image,
+        imageproc::rect::Rect::at(x, y).of_size(width, height),
+        Rgba([128, 128, 128, 255]),
+    );
+
+    if let Some(font) = get_fallback_font(font_provider) {
+        text_renderer::draw_text_mut(
+            image,
+            Rgba([255, 255, 255, 255]),
+            x + 2,
+            y + 2,
+            rusttype::Scale::uniform(10.0),
+            &font,
+            element_id,
+        );
+    }
+}
+
+/// Draws the outline and the id of each element on top of the image
+/// The id is drawn with the first available font, if there is no font only the outline is drawn
+fn draw_debug_overlay(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    element_bounds: &[(String, i32, i32, u32, u32)],
+    font_provider: &dyn FontProvider,
+) {
+    let overlay_color = Rgba([255, 0, 255, 255]);
+    let font = get_fallback_font(font_provider);
+
+    for (element_id, x, y, width, height) in element_bounds {
+        if *width > 0 && *height > 0 {
+            imageproc::drawing::draw_hollow_rect_mut(
+                image,
+                imageproc::rect::Rect::at(*x, *y).of_size(*width, *height),
+                overlay_color,
+            );
+        }
+
+        if let Some(font) = &font {
+            text_renderer::draw_text_mut(
+                image,
+                overlay_color,
+                *x + 2,
+                *y + 2,
+                rusttype::Scale::uniform(10.0),
+                font,
+                element_id,
+            );
+        }
+    }
+}
+
+/// Represents the output format for monochrome displays.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub enum MonoFormat {
+    /// One byte of luminance per pixel
+    #[serde(rename = "gray8")]
+    Gray8,
+    /// One bit per pixel, set if the luminance is greater or equal to the threshold
+    #[serde(rename = "mono1")]
+    Mono1 { threshold: u8 },
+}
+
+/// Render the image for a monochrome display, like `try_render_lcd_image`
+/// The frame is composited in a single luminance channel, each element is drawn on a transparent
+/// rgba layer of its own bounds and only its luminance is blended with the frame.
+/// Text with auto contrast contrasts with black, as it can not see the elements beneath it.
+/// The image will be a packed grayscale or 1-bit buffer, see `mono_renderer::render`
+pub fn render_lcd_image_mono(
+    display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+    options: &RenderOptions,
+    format: &MonoFormat,
+) -> Result<Vec<u8>, RenderError> {
+    let (image, _) = render_frame::<image::GrayImage>(
+        display_config,
+        sensor_value_history,
+        fonts_data,
+        options,
+        None,
+        None,
+    )?;
+    Ok(mono_renderer::render(image, format))
+}
+
+/// Render the image and encode it in the given format straight into the writer
+/// This avoids an intermediate buffer of the encoded frame, e.g. when piping frames to a subprocess
+pub fn render_and_encode<W: std::io::Write>(
+    display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+    format: &FrameFormat,
+    writer: &mut W,
+) -> image::ImageResult<()> {
+    let image = render_lcd_image(display_config, sensor_value_history, fonts_data);
+
+    match format {
+        FrameFormat::Rgba8 => writer
+            .write_all(image.as_raw())
+            .map_err(image::ImageError::IoError),
+        FrameFormat::Png => image::codecs::png::PngEncoder::new(writer).write_image(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            image::ColorType::Rgba8,
+        ),
+        FrameFormat::IndexedPng => palette_renderer::render_to_writer(&image, writer)
+            .map_err(|err| image::ImageError::IoError(std::io::Error::other(err))),
+    }
+}
+
+/// Holds the rendered elements between the frames of the incremental render mode.
+#[derive(Default)]
+pub struct IncrementalRenderState {
+    cached_elements: HashMap<String, CachedElement>,
+}
+
+/// Represents a rendered element, cropped to its bounds
+struct CachedElement {
+    config: ElementConfig,
+    rendered_at_ms: u64,
+    x: i32,
+    y: i32,
+    layer: ImageBuffer<Rgba<u8>, Vec<u8>>,
+}
+
+/// Render the image incrementally, like `try_render_lcd_image`
+/// Elements with a refresh interval are only re-rendered if the interval elapsed since their last render
+/// at the given frame timestamp, or if their config changed. Otherwise their cached rendering is reused.
+/// Elements without a refresh interval are rendered every frame.
+pub fn render_lcd_image_incremental(
+    display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+    options: &RenderOptions,
+    timestamp_ms: u64,
+    state: &mut IncrementalRenderState,
+) -> Result<image::RgbaImage, RenderError> {
+    render_frame(
+        display_config,
+        sensor_value_history,
+        fonts_data,
+        options,
+        None,
+        Some(LayerCache {
+            state,
+            timestamp_ms,
+        }),
+    )
+    .map(|(image, _)| image)
+}
+
+/// Reuses the rendered layers of the elements with a refresh interval between frames,
+/// see `render_lcd_image_incremental`
+struct LayerCache<'a> {
+    state: &'a mut IncrementalRenderState,
+    timestamp_ms: u64,
+}
+
+impl LayerCache<'_> {
+    /// Forgets the elements that are no longer part of the config
+    fn retain(&mut self, elements: &[ElementConfig]) {
+        self.state
+            .cached_elements
+            .retain(|element_id, _| elements.iter().any(|element| &element.id == element_id));
+    }
+
+    /// Draws the element from its cached layer, the layer is re-rendered if the refresh interval
+    /// of the element elapsed or its config changed. Elements without a refresh interval are drawn directly.
+    fn draw_element(
+        &mut self,
+        image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+        lcd_element: ElementConfig,
+        sensor_value_history: &[Vec<SensorValue>],
+        font_provider: &dyn FontProvider,
+        asset_root: Option<&Path>,
+    ) -> Result<(), RenderError> {
+        let Some(refresh_interval_ms) = lcd_element.refresh_interval_ms else {
+            return draw_element(
+                image,
+                lcd_element,
+                sensor_value_history,
+                font_provider,
+                asset_root,
+            );
+        };
+
+        let element_id = lcd_element.id.clone();
+        let is_due = match self.state.cached_elements.get(&element_id) {
+            Some(cached_element) => {
+                cached_element.config != lcd_element
+                    || self
+                        .timestamp_ms
+                        .saturating_sub(cached_element.rendered_at_ms)
+                        >= refresh_interval_ms as u64
+            }
+            None => true,
+        };
+
+        if is_due {
+            let result = render_element_layer(
+                image.width(),
+                image.height(),
+                lcd_element,
+                sensor_value_history,
+                font_provider,
+                asset_root,
+                self.timestamp_ms,
+            );
+            match result {
+                Ok(cached_element) => {
+                    self.state
+                        .cached_elements
+                        .insert(element_id.clone(), cached_element);
+                }
+                Err(err) => {
+                    self.state.cached_elements.remove(&element_id);
+                    return Err(err);
+                }
+            }
+        }
+
+        if let Some(cached_element) = self.state.cached_elements.get(&element_id) {
+            composite(
+                image,
+                &cached_element.layer,
+                cached_element.x,
+                cached_element.y,
+                &cached_element.config.blend_mode,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Holds the previous frame between the frames of the changed region render mode.
+#[derive(Default)]
+pub struct ChangedRegionState {
+    previous_image: Option<image::RgbaImage>,
+    previous_bounds: Vec<imageproc::rect::Rect>,
+}
+
+/// Render the image, like `try_render_lcd_image`, but only return the region that changed since the last frame
+/// The region is the bounding rectangle of all elements whose pixels changed, at their previous
+/// or their current bounds, clipped to the display. Elements without a configured size span the whole display.
+/// The returned image is cropped to the region and has to be drawn at the offset of the region.
+/// The first frame and frames with a different resolution cover the whole display.
+/// Returns None if nothing changed since the last frame.
+pub fn render_lcd_image_changed_region(
+    display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+    options: &RenderOptions,
+    state: &mut ChangedRegionState,
+) -> Result<Option<(image::RgbaImage, imageproc::rect::Rect)>, RenderError> {
+    let rotation = display_config.rotation.clone();
+    let (unrotated_width, unrotated_height) = (
+        display_config.resolution_width,
+        display_config.resolution_height,
+    );
+    let (image, render_tree) = render_lcd_image_with_render_tree(
+        display_config,
+        sensor_value_history,
+        fonts_data,
+        options,
+    )?;
+    let display_rect = imageproc::rect::Rect::at(0, 0).of_size(image.width(), image.height());
+    let unrotated_display_rect =
+        imageproc::rect::Rect::at(0, 0).of_size(unrotated_width, unrotated_height);
+
+    // The element bounds are positioned in the unrotated frame
+    let bounds: Vec<imageproc::rect::Rect> = render_tree
+        .elements
+        .iter()
+        .filter_map(|element| {
+            if element.width == 0 || element.height == 0 {
+                return Some(display_rect);
+            }
+            imageproc::rect::Rect::at(element.x, element.y)
+                .of_size(element.width, element.height)
+                .intersect(unrotated_display_rect)
+                .map(|rect| rotation.rotate_rect(rect, unrotated_width, unrotated_height))
+        })
+        .collect();
+
+    let changed_region = match &state.previous_image {
+        Some(previous_image) if previous_image.dimensions() == image.dimensions() => bounds
+            .iter()
+            .chain(&state.previous_bounds)
+            .filter(|rect| is_region_changed(previous_image, &image, rect))
+            .copied()
+            .reduce(get_union),
+        _ => Some(display_rect),
+    };
+
+    let changed_image = changed_region.map(|region| {
+        let region_image = image::imageops::crop_imm(
+            &image,
+            region.left() as u32,
+            region.top() as u32,
+            region.width(),
+            region.height(),
+        )
+        .to_image();
+        (region_image, region)
+    });
+
+    state.previous_image = Some(image);
+    state.previous_bounds = bounds;
+
+    Ok(changed_image)
+}
+
+/// Returns true if any pixel within the region differs between both images of equal size
+fn is_region_changed(
+    previous_image: &image::RgbaImage,
+    image: &image::RgbaImage,
+    region: &imageproc::rect::Rect,
+) -> bool {
+    let row_start = region.left() as usize * 4;
+    let row_end = row_start + region.width() as usize * 4;
+    let row_length = image.width() as usize * 4;
+
+    (region.top()..=region.bottom()).any(|y| {
+        let offset = y as usize * row_length;
+        previous_image.as_raw()[offset + row_start..offset + row_end]
+            != image.as_raw()[offset + row_start..offset + row_end]
+    })
+}
+
+/// Returns the smallest rectangle containing both rectangles
+fn get_union(a: imageproc::rect::Rect, b: imageproc::rect::Rect) -> imageproc::rect::Rect {
+    let left = a.left().min(b.left());
+    let top = a.top().min(b.top());
+    let right = a.right().max(b.right());
+    let bottom = a.bottom().max(b.bottom());
+    imageproc::rect::Rect::at(left, top)
+        .of_size((right - left + 1) as u32, (bottom - top + 1) as u32)
+}
+
+/// Represents a single rendered element, to be composited by the caller.
+#[derive(PartialEq, Debug, Clone)]
+pub struct RenderedLayer {
+    pub id: String,
+    /// The rendered element, cropped to its bounds
+    pub image: image::RgbaImage,
+    /// Position of the layer on the display
+    pub x: i32,
+    pub y: i32,
+    /// Stacking order of the layer, layers with a higher z-index are drawn on top
+    pub z_index: usize,
+    /// How the layer is blended with the layers beneath it
+    pub blend_mode: BlendMode,
+}
+
+/// Renders each element onto its own transparent layer, without compositing them
+/// The z-index of a layer is the position of its element in the config,
+/// the layers are meant to be composited in z-order with their blend mode.
+/// The layers only hold the elements: the background and the rotation of the display are not applied,
+/// and text with auto contrast contrasts with black, as it can not see the elements beneath it.
+/// So the composited layers only match `render_lcd_image` for configs that use none of these.
+/// Elements that fail to render are logged and left out.
+/// If the resolution is invalid, the error is logged and no layers are returned
+pub fn render_layers(
+    mut display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+) -> Vec<RenderedLayer> {
+    let image_width = display_config.resolution_width;
+    let image_height = display_config.resolution_height;
+    if let Err(err) = validate_resolution(image_width, image_height, DEFAULT_MAX_RESOLUTION) {
+        error!("Failed to render layers: {}", err);
+        return vec![];
+    }
+    prepare_display_config(&mut display_config, sensor_value_history);
+    let asset_root = display_config.asset_root.map(PathBuf::from);
+
+    display_config
+        .elements
+        .into_iter()
+        .enumerate()
+        .filter_map(|(z_index, lcd_element)| {
+            let element_id = lcd_element.id.clone();
+            let blend_mode = lcd_element.blend_mode.clone();
+            match render_element_layer(
+                image_width,
+                image_height,
+                lcd_element,
+                sensor_value_history,
+                fonts_data,
+                asset_root.as_deref(),
+                0,
+            ) {
+                Ok(cached_element) => Some(RenderedLayer {
+                    id: element_id,
+                    image: cached_element.layer,
+                    x: cached_element.x,
+                    y: cached_element.y,
+                    z_index,
+                    blend_mode,
+                }),
+                Err(err) => {
+                    error!("Failed to render element {}: {}", element_id, err);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Renders the element on a transparent layer and crops it to the bounds of the element
+/// Elements without a configured size keep the whole layer
+fn render_element_layer(
+    image_width: u32,
+    image_height: u32,
+    lcd_element: ElementConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    font_provider: &dyn FontProvider,
+    asset_root: Option<&Path>,
+    timestamp_ms: u64,
+) -> Result<CachedElement, RenderError> {
+    let mut layer = ImageBuffer::new(image_width, image_height);
+    draw_element(
+        &mut layer,
+        lcd_element.clone(),
+        sensor_value_history,
+        font_provider,
+        asset_root,
+    )?;
+
+    let (width, height) = get_element_size(&lcd_element);
+    let (x, y, layer) = if width == 0 || height == 0 {
+        (0, 0, layer)
+    } else {
+        let x = lcd_element.x.clamp(0, image_width as i32) as u32;
+        let y = lcd_element.y.clamp(0, image_height as i32) as u32;
+        let layer = image::imageops::crop_imm(&layer, x, y, width, height).to_image();
+        (x as i32, y as i32, layer)
+    };
+
+    Ok(CachedElement {
+        config: lcd_element,
+        rendered_at_ms: timestamp_ms,
+        x,
+        y,
+        layer,
+    })
+}
+
+/// Represents the declared and the effective rendered size of an element.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ElementDimensions {
+    pub element_id: String,
+    /// The size as configured in the element config
+    pub declared_width: u32,
+    pub declared_height: u32,
+    /// The size of the visible content as actually rendered, after layout and auto-sizing
+    pub effective_width: u32,
+    pub effective_height: u32,
+}
+
+/// Renders each element on its own and collects its declared and effective rendered size
+/// The effective size is the size of the visible, non-transparent content of the element.
+/// Elements that fail to render or render nothing visible have an effective size of zero.
+pub fn measure_elements(
+    display_config: &DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+) -> Result<Vec<ElementDimensions>, RenderError> {
+    let image_width = display_config.resolution_width;
+    let image_height = display_config.resolution_height;
+    validate_resolution(image_width, image_height, DEFAULT_MAX_RESOLUTION)?;
+
+    let mut display_config = display_config.clone();
+    prepare_display_config(&mut display_config, sensor_value_history);
+    let asset_root = display_config.asset_root.map(PathBuf::from);
+
+    let element_dimensions = display_config
+        .elements
+        .into_iter()
+        .map(|lcd_element| {
+            let element_id = lcd_element.id.clone();
+            let (declared_width, declared_height) = get_element_size(&lcd_element);
+
+            let mut layer = ImageBuffer::new(image_width, image_height);
+            let (effective_width, effective_height) = match draw_element(
+                &mut layer,
+                lcd_element,
+                sensor_value_history,
+                fonts_data,
+                asset_root.as_deref(),
+            ) {
+                Ok(()) => get_content_size(&layer),
+                Err(err) => {
+                    error!("Failed to render element {}: {}", element_id, err);
+                    (0, 0)
+                }
+            };
+
+            ElementDimensions {
+                element_id,
+                declared_width,
+                declared_height,
+                effective_width,
+                effective_height,
+            }
+        })
+        .collect();
+
+    Ok(element_dimensions)
+}
+
+/// Returns the size of the bounding box of all non-transparent pixels of the image
+fn get_content_size(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> (u32, u32) {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        bounds = Some(match bounds {
+            Some((min_x, min_y, max_x, max_y)) => {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            }
+            None => (x, y, x, y),
+        });
+    }
+
+    bounds
+        .map(|(min_x, min_y, max_x, max_y)| (max_x - min_x + 1, max_y - min_y + 1))
+        .unwrap_or((0, 0))
+}
+
+/// Describes the decisions of the renderer for a rendered frame, for tooling and troubleshooting.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct RenderTree {
+    pub elements: Vec<RenderTreeElement>,
+}
+
+/// Describes a single element of a rendered frame.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct RenderTreeElement {
+    pub id: String,
+    pub element_type: ElementType,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// The sensor displayed by the element, if any
+    pub sensor_id: Option<String>,
+    /// The latest value of the sensor, used to render the element
+    pub sensor_value: Option<String>,
+    pub status: DrawStatus,
+}
+
+/// Represents whether an element was drawn.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub enum DrawStatus {
+    #[default]
+    #[serde(rename = "drawn")]
+    Drawn,
+    /// The element was intentionally not drawn, e.g. as there is no sensor value yet
+    #[serde(rename = "skipped")]
+    Skipped,
+    /// The element failed to render
+    #[serde(rename = "failed")]
+    Failed { error: String },
+}
+
+/// Represents the timing of a rendered frame.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct FrameTiming {
+    /// The duration of the render process
+    pub duration: Duration,
+    /// If true, the render process took longer than the target interval
+    pub over_budget: bool,
+}
+
+/// Render the image and measure the render duration against the target frame interval
+/// This allows render loops with a fixed frame rate to react on an exceeded frame budget,
+/// e.g. by dropping elements or lowering the quality
+pub fn render_lcd_image_timed(
+    display_config: DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    fonts_data: &HashMap<String, Vec<u8>>,
+    target_interval: Duration,
+) -> (ImageBuffer<Rgba<u8>, Vec<u8>>, FrameTiming) {
+    let start_time = Instant::now();
+
+    let image = render_lcd_image(display_config, sensor_value_history, fonts_data);
+
+    let duration = start_time.elapsed();
+    let frame_timing = FrameTiming {
+        duration,
+        over_budget: duration > target_interval,
+    };
+    debug!(" = Frame timing: {:?}", frame_timing);
+
+    (image, frame_timing)
+}
+
+/// Scales the resolution and all positions and sizes of the elements by the given factor
+/// Used to render at a reduced or, for supersampling, at an increased internal resolution
+fn scale_display_config(display_config: &mut DisplayConfig, scale: f32) {
+    let scale_size = |size: &mut u32| *size = (*size as f32 * scale).round() as u32;
+    let scale_font_size = |font_size: &mut u32| {
+        *font_size = ((*font_size as f32 * scale).round() as u32).max(1);
+    };
+
+    scale_size(&mut display_config.resolution_width);
+    scale_size(&mut display_config.resolution_height);
+    display_config.resolution_width = display_config.resolution_width.max(1);
+    display_config.resolution_height = display_config.resolution_height.max(1);
+
+    for element in display_config.elements.iter_mut() {
+        element.x = (element.x as f32 * scale).round() as i32;
+        element.y = (element.y as f32 * scale).round() as i32;
+        scale_size(&mut element.padding.top);
+        scale_size(&mut element.padding.right);
+        scale_size(&mut element.padding.bottom);
+        scale_size(&mut element.padding.left);
+
+        if let Some(config) = element.text_config.as_mut() {
+            scale_size(&mut config.width);
+            scale_size(&mut config.height);
+            scale_font_size(&mut config.font_size);
+        }
+        if let Some(config) = element.image_config.as_mut() {
+            scale_size(&mut config.width);
+            scale_size(&mut config.height);
+        }
+        if let Some(config) = element.graph_config.as_mut() {
+            scale_size(&mut config.width);
+            scale_size(&mut config.height);
+            if config.graph_stroke_width > 0 {
+                config.graph_stroke_width =
+                    ((config.graph_stroke_width as f32 * scale).round() as i32).max(1);
+            }
+            if config.border_width > 0.0 {
+                config.border_width *= scale;
+            }
+            config.point_radius = (config.point_radius as f32 * scale).round() as i32;
+            scale_size(&mut config.bar_gap);
+            if let Some(legend) = config.legend.as_mut() {
+                scale_font_size(&mut legend.font_size);
+            }
+            if let Some(annotation) = config.annotation.as_mut() {
+                scale_font_size(&mut annotation.font_size);
+            }
+        }
+        if let Some(config) = element.conditional_image_config.as_mut() {
+            scale_size(&mut config.width);
+            scale_size(&mut config.height);
+        }
+        if let Some(config) = element.table_config.as_mut() {
+            scale_size(&mut config.width);
+            scale_size(&mut config.height);
+            scale_font_size(&mut config.font_size);
+        }
+        if let Some(config) = element.gauge_config.as_mut() {
+            scale_size(&mut config.width);
+            scale_size(&mut config.height);
+            scale_size(&mut config.stroke_width);
+            for tick in config.ticks.iter_mut() {
+                scale_size(&mut tick.length);
+            }
+        }
+        if let Some(config) = element.progress_bar_config.as_mut() {
+            scale_size(&mut config.width);
+            scale_size(&mut config.height);
+        }
+        if let Some(config) = element.clock_config.as_mut() {
+            scale_size(&mut config.width);
+            scale_size(&mut config.height);
+            scale_font_size(&mut config.font_size);
+        }
+        if let Some(config) = element.segment_bar_config.as_mut() {
+            scale_size(&mut config.width);
+            scale_size(&mut config.height);
+            scale_size(&mut config.spacing);
+        }
+    }
+}
+
+/// Applies the display wide settings to the elements, before they are drawn
+fn prepare_display_config(
+    display_config: &mut DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+) {
+    resolve_density_independent_sizes(display_config);
+    apply_pixel_aspect_ratio(display_config);
+    apply_render_quality(display_config);
+    apply_value_color_map(display_config, sensor_value_history);
+}
+
+/// Converts all sizes in density-independent units to pixels, using the scale factor of the display
+fn resolve_density_independent_sizes(display_config: &mut DisplayConfig) {
+    let scale_factor = if display_config.scale_factor > 0.0 {
+        display_config.scale_factor
+    } else {
+        1.0
+    };
+
+    for element in display_config.elements.iter_mut() {
+        if let Some(graph_config) = element.graph_config.as_mut() {
+            if graph_config.stroke_unit == SizeUnit::DensityIndependentPixel {
+                graph_config.graph_stroke_width =
+                    (graph_config.graph_stroke_width as f32 * scale_factor).round() as i32;
+                // An unset border width defaults to 1, which has to be scaled as well
+                let border_width = if graph_config.border_width > 0.0 {
+                    graph_config.border_width
+                } else {
+                    1.0
+                };
+                graph_config.border_width = border_width * scale_factor;
+                graph_config.stroke_unit = SizeUnit::Pixel;
+            }
+        }
+    }
+}
+
+/// Passes the pixel aspect ratio of the display to the elements drawing geometric shapes,
+/// unless they configure their own
+fn apply_pixel_aspect_ratio(display_config: &mut DisplayConfig) {
+    if display_config.pixel_aspect_ratio <= 0.0 {
+        return;
+    }
+
+    for element in display_config.elements.iter_mut() {
+        if let Some(graph_config) = element.graph_config.as_mut() {
+            if graph_config.pixel_aspect_ratio <= 0.0 {
+                graph_config.pixel_aspect_ratio = display_config.pixel_aspect_ratio;
+            }
+        }
+        if let Some(gauge_config) = element.gauge_config.as_mut() {
+            if gauge_config.pixel_aspect_ratio <= 0.0 {
+                gauge_config.pixel_aspect_ratio = display_config.pixel_aspect_ratio;
+            }
+        }
+    }
+}
+
+/// Applies the render quality of the display to the anti-aliasing of all elements
+/// In the balanced quality the anti-aliasing of the graphs is kept as configured
+fn apply_render_quality(display_config: &mut DisplayConfig) {
+    let antialias = match display_config.render_quality {
+        RenderQuality::Fast => false,
+        RenderQuality::Balanced => return,
+        RenderQuality::High => true,
+    };
+
+    for element in display_config.elements.iter_mut() {
+        if let Some(text_config) = element.text_config.as_mut() {
+            text_config.aliased = !antialias;
+        }
+        if let Some(table_config) = element.table_config.as_mut() {
+            table_config.aliased = !antialias;
+        }
+        if let Some(graph_config) = element.graph_config.as_mut() {
+            graph_config.antialias = antialias;
+        }
+    }
+}
+
+/// Colors the text elements by the shared value color map of the display
+/// The font color is replaced if there is a color for the latest value of the sensor of the element
+fn apply_value_color_map(
+    display_config: &mut DisplayConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+) {
+    if display_config.value_color_map.is_empty() {
+        return;
+    }
+    let latest_sensor_values = match sensor_value_history.first() {
+        Some(latest_sensor_values) => latest_sensor_values,
+        None => return,
+    };
+
+    for element in display_config.elements.iter_mut() {
+        let text_config = match element.text_config.as_mut() {
+            Some(text_config) => text_config,
+            None => continue,
+        };
+        let color = display_config
+            .value_color_map
+            .get(&text_config.sensor_id)
+            .and_then(|value_colors| {
+                let sensor_value = latest_sensor_values
+                    .iter()
+                    .find(|sensor_value| sensor_value.id == text_config.sensor_id)?;
+                value_colors.get(sensor_value.value.trim())
+            });
+        if let Some(color) = color {
+            text_config.font_color = color.clone();
+        }
+    }
+}
+
+/// Validates that the resolution is not zero and does not exceed the maximum resolution
+fn validate_resolution(width: u32, height: u32, max_resolution: u32) -> Result<(), RenderError> {
+    if width == 0 || height == 0 {
+        return Err(RenderError::ZeroResolution { width, height });
+    }
+
+    if width > max_resolution || height > max_resolution {
+        return Err(RenderError::ResolutionTooLarge {
+            width,
+            height,
+            max_resolution,
+        });
+    }
+
+    Ok(())
+}
+
+/// Validates that the element count does not exceed the maximum element count
+fn validate_element_count(count: usize, max_elements: usize) -> Result<(), RenderError> {
+    if count > max_elements {
+        return Err(RenderError::TooManyElements {
+            count,
+            max_elements,
+        });
+    }
+
+    Ok(())
+}
+
+/// Draws a single element on the image.
+/// The element will be drawn on the given image buffer.
+/// Distinguishes between the different element types and calls the corresponding draw function.
+fn draw_element(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    mut lcd_element: ElementConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    font_provider: &dyn FontProvider,
+    asset_root: Option<&Path>,
+) -> Result<(), RenderError> {
+    // Elements that are not blended normally or are translucent are drawn on a transparent layer first,
+    // which is then faded and blended with the image
+    let opacity = get_opacity(&lcd_element);
+    if lcd_element.blend_mode != BlendMode::Normal || opacity < 1.0 {
+        let blend_mode = std::mem::take(&mut lcd_element.blend_mode);
+        lcd_element.opacity = None;
+        let mut layer = ImageBuffer::new(image.width(), image.height());
+        draw_element(
+            &mut layer,
+            lcd_element,
+            sensor_value_history,
+            font_provider,
+            asset_root,
+        )?;
+        if opacity < 1.0 {
+            apply_opacity(&mut layer, opacity);
+        }
+        composite(image, &layer, 0, 0, &blend_mode);
+        return Ok(());
+    }
+
+    apply_padding(&mut lcd_element);
+
+    let x = lcd_element.x;
+    let y = lcd_element.y;
+    let element_id = lcd_element.id.as_str();
+
+    // diff between type
+    match lcd_element.element_type {
+        ElementType::Text => {
+            let text_config = lcd_element.text_config.ok_or(RenderError::MissingConfig {
+                config: "text_config".to_string(),
+            })?;
+            draw_text(
+                image,
+                &lcd_element.id,
+                text_config,
+                x,
+                y,
+                sensor_value_history,
+                font_provider,
+            )
+        }
+        ElementType::StaticImage => {
+            let image_config = lcd_element.image_config.unwrap_or_default();
+            draw_static_image(image, &lcd_element.id, &image_config, x, y, asset_root)
+        }
+        ElementType::Graph => {
+            let mut graph_config = lcd_element.graph_config.ok_or(RenderError::MissingConfig {
+                config: "graph_config".to_string(),
+            })?;
+            if graph_config.time_window_ms.is_some() {
+                (graph_config.sensor_timestamps, graph_config.sensor_values) =
+                    extract_timed_value_sequence(sensor_value_history, &graph_config.sensor_id)
+                        .into_iter()
+                        .unzip();
+            } else {
+                graph_config.sensor_values =
+                    extract_value_sequence(sensor_value_history, &graph_config.sensor_id);
+            }
+            for series in graph_config.additional_series.iter_mut() {
+                series.sensor_values =
+                    extract_value_sequence(sensor_value_history, &series.sensor_id);
+            }
+
+            draw_graph(image, x, y, graph_config, font_provider)
+        }
+        ElementType::ConditionalImage => {
+            let conditional_image_config =
+                lcd_element
+                    .conditional_image_config
+                    .ok_or(RenderError::MissingConfig {
+                        config: "conditional_image_config".to_string(),
+                    })?;
+            let sensor_value = sensor_value_history.first().and_then(|sensor_values| {
+                sensor_values
+                    .iter()
+                    .find(|&s| s.id == conditional_image_config.sensor_id)
+            });
+            draw_conditional_image(
+                image,
+                x,
+                y,
+                element_id,
+                conditional_image_config,
+                sensor_value,
+                asset_root,
+            )
+        }
+        ElementType::Table => {
+            let table_config = lcd_element.table_config.ok_or(RenderError::MissingConfig {
+                config: "table_config".to_string(),
+            })?;
+            draw_table(
+                image,
+                element_id,
+                &table_config,
+                x,
+                y,
+                sensor_value_history,
+                font_provider,
+            )
+        }
+        ElementType::Gauge => {
+            let gauge_config = lcd_element.gauge_config.ok_or(RenderError::MissingConfig {
+                config: "gauge_config".to_string(),
+            })?;
+            let sensor_value = sensor_value_history.first().and_then(|sensor_values| {
+                sensor_values
+                    .iter()
+                    .find(|&s| s.id == gauge_config.sensor_id)
+            });
+            draw_gauge(image, x, y, &gauge_config, sensor_value);
+            Ok(())
+        }
+        ElementType::ProgressBar => {
+            let progress_bar_config =
+                lcd_element
+                    .progress_bar_config
+                    .ok_or(RenderError::MissingConfig {
+                        config: "progress_bar_config".to_string(),
+                    })?;
+            let sensor_value = sensor_value_history.first().and_then(|sensor_values| {
+                sensor_values
+                    .iter()
+                    .find(|&s| s.id == progress_bar_config.sensor_id)
+            });
+            draw_progress_bar(image, x, y, &progress_bar_config, sensor_value);
+            Ok(())
+        }
+        ElementType::Clock => {
+            let clock_config = lcd_element.clock_config.ok_or(RenderError::MissingConfig {
+                config: "clock_config".to_string(),
+            })?;
+            draw_clock(image, x, y, &clock_config, font_provider)
+        }
+        ElementType::SegmentBar => {
+            let segment_bar_config =
+                lcd_element
+                    .segment_bar_config
+                    .ok_or(RenderError::MissingConfig {
+                        config: "segment_bar_config".to_string(),
+                    })?;
+            let sensor_value = sensor_value_history.first().and_then(|sensor_values| {
+                sensor_values
+                    .iter()
+                    .find(|&s| s.id == segment_bar_config.sensor_id)
+            });
+            draw_segment_bar(image, x, y, &segment_bar_config, sensor_value);
+            Ok(())
+        }
+    }
+}
+
+/// Returns the opacity of the element clamped between 0 and 1, fully opaque if not set
+fn get_opacity(element: &ElementConfig) -> f32 {
+    match element.opacity {
+        Some(opacity) if !opacity.is_nan() => opacity.clamp(0.0, 1.0),
+        _ => 1.0,
+    }
+}
+
+/// Scales the alpha of each pixel of the image by the opacity
+fn apply_opacity(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, opacity: f32) {
+    for pixel in image.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+    }
+}
+
+/// Insets the content of the element by its padding
+/// The position is moved by the left and top padding and the content size is reduced accordingly,
+/// so the content is aligned within the padded area
+fn apply_padding(element: &mut ElementConfig) {
+    let padding = element.padding.clone();
+    if padding == ElementPadding::default() {
+        return;
+    }
+
+    element.x += padding.left as i32;
+    element.y += padding.top as i32;
+
+    let inset = |width: &mut u32, height: &mut u32| {
+        *width = width.saturating_sub(padding.left + padding.right);
+        *height = height.saturating_sub(padding.top + padding.bottom);
+    };
+    if let Some(config) = element.text_config.as_mut() {
+        inset(&mut config.width, &mut config.height);
+    }
+    if let Some(config) = element.image_config.as_mut() {
+        inset(&mut config.width, &mut config.height);
+    }
+    if let Some(config) = element.graph_config.as_mut() {
+        inset(&mut config.width, &mut config.height);
+    }
+    if let Some(config) = element.conditional_image_config.as_mut() {
+        inset(&mut config.width, &mut config.height);
+    }
+    if let Some(config) = element.table_config.as_mut() {
+        inset(&mut config.width, &mut config.height);
+    }
+    if let Some(config) = element.gauge_config.as_mut() {
+        inset(&mut config.width, &mut config.height);
+    }
+    if let Some(config) = element.progress_bar_config.as_mut() {
+        inset(&mut config.width, &mut config.height);
+    }
+    if let Some(config) = element.clock_config.as_mut() {
+        inset(&mut config.width, &mut config.height);
+    }
+    if let Some(config) = element.segment_bar_config.as_mut() {
+        inset(&mut config.width, &mut config.height);
+    }
+}
+
+/// Creates the image the elements are drawn on, filled with the background color, or transparent without one
+fn create_background(width: u32, height: u32, background_color: &str) -> image::RgbaImage {
+    if background_color.is_empty() {
+        ImageBuffer::new(width, height)
+    } else {
+        ImageBuffer::from_pixel(width, height, hex_to_rgba_or_default(background_color))
+    }
+}
+
+/// Draws the background image of the display over the whole image, like a static image
+/// Without an asset root, the image is loaded from the cache dir of `BACKGROUND_IMAGE_ID`
+fn draw_background_image(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    background_image_path: &str,
+    asset_root: Option<&Path>,
+) -> Result<(), RenderError> {
+    let image_config = ImageConfig {
+        width: image.width(),
+        height: image.height(),
+        image_path: background_image_path.to_string(),
+        premultiplied_alpha: false,
+    };
+    draw_static_image(image, BACKGROUND_IMAGE_ID, &image_config, 0, 0, asset_root)
+}
+
+/// Draws a static image on the image buffer.
+/// If an asset root is given, the image is loaded from the configured image path relative to it.
+/// Otherwise the prepared image is loaded from the cache dir.
+fn draw_static_image(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    element_id: &str,
+    image_config: &ImageConfig,
+    x: i32,
+    y: i32,
+    asset_root: Option<&Path>,
+) -> Result<(), RenderError> {
+    let start_time = Instant::now();
+
+    let file_path = match asset_root {
+        Some(asset_root) => resolve_asset_path(asset_root, &image_config.image_path),
+        None => get_cache_dir(element_id, &ElementType::StaticImage).join(element_id),
+    };
+
+    // Read image into memory
+    // We heavily assume that this is already png encoded to skip the expensive png decoding
+    let img_data = fs::read(&file_path).map_err(|err| RenderError::Io {
+        path: file_path.to_string_lossy().to_string(),
+        message: err.to_string(),
+    })?;
+    let mut overlay_image = image::load_from_memory(&img_data).map_err(to_image_decode_error)?;
+
+    // Assets from the asset root are not prepared, so scale them to the element size
+    let needs_resize = image_config.width > 0
+        && image_config.height > 0
+        && (overlay_image.width() != image_config.width
+            || overlay_image.height() != image_config.height);
+    if asset_root.is_some() && needs_resize {
+        overlay_image = overlay_image.resize_exact(
+            image_config.width,
+            image_config.height,
+            image::imageops::FilterType::Lanczos3,
+        );
+    }
+
+    if image_config.premultiplied_alpha {
+        let mut straight_image = overlay_image.to_rgba8();
+        unpremultiply_alpha(&mut straight_image);
+        image::imageops::overlay(image, &straight_image, x as i64, y as i64);
+    } else {
+        image::imageops::overlay(image, &overlay_image, x as i64, y as i64);
+    }
+
+    debug!("    - Image render duration: {:?}", start_time.elapsed());
+
+    Ok(())
+}
+
+fn draw_graph(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    config: GraphConfig,
+    font_provider: &dyn FontProvider,
+) -> Result<(), RenderError> {
+    let start_time = Instant::now();
+
+    let img_data = graph_renderer::render(&config);
+    let graph_image = image::load_from_memory(&img_data).map_err(to_image_decode_error)?;
+
+    image::imageops::overlay(image, &graph_image, x as i64, y as i64);
+
+    if let Some(legend_config) = &config.legend {
+        draw_graph_legend(image, x, y, &config, legend_config, font_provider)?;
+    }
+
+    if let Some(annotation_config) = &config.annotation {
+        let font = font_provider.get_font(&annotation_config.font_family)?;
+        let annotation_image =
+            graph_renderer::render_annotations(&config, annotation_config, &font);
+        image::imageops::overlay(image, &annotation_image, x as i64, y as i64);
+    }
+
+    debug!("    - Graph render duration: {:?}", start_time.elapsed());
+
+    Ok(())
+}
+
+/// Draws the legend of a graph in the configured corner of the graph
+fn draw_graph_legend(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    config: &GraphConfig,
+    legend_config: &GraphLegendConfig,
+    font_provider: &dyn FontProvider,
+) -> Result<(), RenderError> {
+    let font = font_provider.get_font(&legend_config.font_family)?;
+
+    let legend_image = graph_renderer::render_legend(config, legend_config, &font);
+    let free_width = config.width.saturating_sub(legend_image.width()) as i32;
+    let free_height = config.height.saturating_sub(legend_image.height()) as i32;
+    let (legend_x, legend_y) = match legend_config.position {
+        LegendPosition::TopLeft => (x, y),
+        LegendPosition::TopRight => (x + free_width, y),
+        LegendPosition::BottomLeft => (x, y + free_height),
+        LegendPosition::BottomRight => (x + free_width, y + free_height),
+    };
+
+    image::imageops::overlay(image, &legend_image, legend_x as i64, legend_y as i64);
+
+    Ok(())
+}
+
+/// Draws a gauge on the image buffer.
+/// Non-numeric sensor values are treated like a missing value, only the track of the gauge is drawn.
+fn draw_gauge(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    config: &GaugeConfig,
+    sensor_value: Option<&SensorValue>,
+) {
+    let start_time = Instant::now();
+
+    let sensor_value = sensor_value.and_then(|sensor_value| sensor_value.value.parse::<f64>().ok());
+    let gauge_image = gauge_renderer::render(config, sensor_value);
+    image::imageops::overlay(image, &gauge_image, x as i64, y as i64);
+
+    debug!("    - Gauge render duration: {:?}", start_time.elapsed());
+}
+
+/// Draws a progress bar on the image buffer.
+/// Non-numeric sensor values are treated like a missing value, the bar is drawn empty.
+fn draw_progress_bar(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    config: &ProgressBarConfig,
+    sensor_value: Option<&SensorValue>,
+) {
+    let start_time = Instant::now();
+
+    let sensor_value = sensor_value.and_then(|sensor_value| sensor_value.value.parse::<f64>().ok());
+    let progress_bar_image = progress_bar_renderer::render(config, sensor_value);
+    image::imageops::overlay(image, &progress_bar_image, x as i64, y as i64);
+
+    debug!(
+        "    - Progress bar render duration: {:?}",
+        start_time.elapsed()
+    );
+}
+
+/// Draws a segment bar on the image buffer.
+/// Non-numeric sensor values are treated like a missing value, all segments are off.
+fn draw_segment_bar(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    config: &SegmentBarConfig,
+    sensor_value: Option<&SensorValue>,
+) {
+    let start_time = Instant::now();
+
+    let sensor_value = sensor_value.and_then(|sensor_value| sensor_value.value.parse::<f64>().ok());
+    let segment_bar_image = segment_bar_renderer::render(config, sensor_value);
+    image::imageops::overlay(image, &segment_bar_image, x as i64, y as i64);
+
+    debug!(
+        "    - Segment bar render duration: {:?}",
+        start_time.elapsed()
+    );
+}
+
+/// Draws a clock showing the current time on the image buffer.
+fn draw_clock(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    config: &ClockConfig,
+    font_provider: &dyn FontProvider,
+) -> Result<(), RenderError> {
+    let start_time = Instant::now();
+
+    let font = font_provider.get_font(&config.font_family)?;
+    let clock_image = clock_renderer::render(
+        image.width(),
+        image.height(),
+        config,
+        chrono::Utc::now(),
+        &font,
+    )?;
+    image::imageops::overlay(image, &clock_image, x as i64, y as i64);
+
+    debug!("    - Clock render duration: {:?}", start_time.elapsed());
+
+    Ok(())
+}
+
+/// Draws a conditional image on the image buffer.
+fn draw_conditional_image(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    element_id: &str,
+    mut config: ConditionalImageConfig,
+    sensor_value: Option<&SensorValue>,
+    asset_root: Option<&Path>,
+) -> Result<(), RenderError> {
+    let start_time = Instant::now();
+
+    // Nothing to draw, if there is no value for the sensor yet
+    let sensor_value = match sensor_value {
+        None => {
+            return Ok(());
+        }
+        Some(sensor_value) => sensor_value,
+    };
+
+    config.sensor_value = sensor_value.value.clone();
+    let conditional_image = if config.on_demand {
+        let img_data = std::mem::take(&mut config.on_demand_image_data);
+        if img_data.is_empty() {
+            None
+        } else {
+            Some(
+                image::load_from_memory(&img_data)
+                    .map_err(to_image_decode_error)?
+                    .to_rgba8(),
+            )
+        }
+    } else {
+        conditional_image_renderer::render_image(
+            element_id,
+            &sensor_value.sensor_type,
+            &config,
+            asset_root,
+        )
+        .map_err(to_image_decode_error)?
+    };
+
+    let mut conditional_image = conditional_image.ok_or(RenderError::NoMatchingImage {
+        sensor_value: config.sensor_value.clone(),
+    })?;
+    if config.premultiplied_alpha {
+        unpremultiply_alpha(&mut conditional_image);
+    }
+    image::imageops::overlay(image, &conditional_image, x as i64, y as i64);
+
+    debug!(
+        "    - Conditional image render duration: {:?}",
+        start_time.elapsed()
+    );
+
+    Ok(())
+}
+
+/// Draws a text element on the image buffer.
+fn draw_text(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    element_id: &str,
+    mut text_config: TextConfig,
+    x: i32,
+    y: i32,
+    sensor_value_history: &[Vec<SensorValue>],
+    font_provider: &dyn FontProvider,
+) -> Result<(), RenderError> {
+    let start_time = Instant::now();
+
+    let font = font_provider.get_font(&text_config.font_family)?;
+
+    // Replace the format and font color by the range the sensor value is in
+    if let Some(conditional_text) = &text_config.conditional_text {
+        if let Some(range) = text_renderer::select_conditional_text(
+            conditional_text,
+            &text_config.sensor_id,
+            sensor_value_history,
+        ) {
+            text_config.format = range.text.clone();
+            if !range.font_color.is_empty() {
+                text_config.font_color = range.font_color.clone();
+            }
+        }
+    }
+
+    if !text_config.background_color.is_empty() {
+        draw_text_background(image, &text_config, x, y);
+    }
+
+    // Pick a font color that contrasts with the already drawn background behind the element
+    if text_config.auto_contrast {
+        // Transparent parts of the background are shown as black on the display
+        let element_rect = imageproc::rect::Rect::at(x, y)
+            .of_size(text_config.width.max(1), text_config.height.max(1));
+        let background_color = average_color(image, Some(element_rect));
+        let alpha = background_color[3] as u32;
+        let background_color = Rgba(
+            background_color
+                .0
+                .map(|channel| (channel as u32 * alpha / 255) as u8),
+        );
+        text_config.font_color = rgba_to_hex(contrasting_color(background_color));
+    }
+
+    let history_aggregates = font_provider.get_history_aggregates(&text_config.sensor_id);
+    let text_image = match font_provider.get_text_render_cache() {
+        Some(text_render_cache) => text_renderer::render_with_cache(
+            element_id,
+            (image.width(), image.height()),
+            &text_config,
+            sensor_value_history,
+            history_aggregates.as_ref(),
+            &font,
+            font_provider.get_glyph_cache(),
+            text_render_cache,
+        ),
+        None => text_renderer::render_with_history_aggregates(
+            image.width(),
+            image.height(),
+            &text_config,
+            sensor_value_history,
+            history_aggregates.as_ref(),
+            &font,
+            font_provider.get_glyph_cache(),
+        ),
+    };
+    image::imageops::overlay(image, &text_image, x as i64, y as i64);
+
+    debug!("    - Text render duration: {:?}", start_time.elapsed());
+
+    Ok(())
+}
+
+/// Fills the bounds of the text element with its background color
+/// The background is composited over the frame, so a semi-transparent color tints what is behind it
+fn draw_text_background(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    text_config: &TextConfig,
+    x: i32,
+    y: i32,
+) {
+    if text_config.width == 0 || text_config.height == 0 {
+        return;
+    }
+
+    let background_color = hex_to_rgba_or_default(&text_config.background_color);
+    let background =
+        image::RgbaImage::from_pixel(text_config.width, text_config.height, background_color);
+    image::imageops::overlay(image, &background, x as i64, y as i64);
+}
+
+/// Draws a table element on the image buffer.
+/// The element height is split evenly across the rows,
+/// each row is drawn as a left aligned label and a right aligned value text.
+fn draw_table(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    element_id: &str,
+    table_config: &TableConfig,
+    x: i32,
+    y: i32,
+    sensor_value_history: &[Vec<SensorValue>],
+    font_provider: &dyn FontProvider,
+) -> Result<(), RenderError> {
+    let start_time = Instant::now();
+
+    if table_config.rows.is_empty() {
+        return Ok(());
+    }
+    let row_height = table_config.height / table_config.rows.len() as u32;
+
+    for (row_index, row) in table_config.rows.iter().enumerate() {
+        let row_y = y + (row_index as u32 * row_height) as i32;
+        let row_text_config = TextConfig {
+            font_family: table_config.font_family.clone(),
+            font_size: table_config.font_size,
+            font_color: table_config.font_color.clone(),
+            width: table_config.width,
+            height: row_height,
+            aliased: table_config.aliased,
+            ..Default::default()
+        };
+
+        let label_config = TextConfig {
+            format: row.label.clone(),
+            alignment: TextAlign::Left,
+            ..row_text_config.clone()
+        };
+        draw_text(
+            image,
+            &format!("{}/{}/label", element_id, row_index),
+            label_config,
+            x,
+            row_y,
+            sensor_value_history,
+            font_provider,
+        )?;
+
+        let value_config = TextConfig {
+            sensor_id: row.sensor_id.clone(),
+            format: table_config.value_format.clone(),
+            alignment: TextAlign::Right,
+            ..row_text_config
+        };
+        draw_text(
+            image,
+            &format!("{}/{}/value", element_id, row_index),
+            value_config,
+            x,
+            row_y,
+            sensor_value_history,
+            font_provider,
+        )?;
+    }
+
+    debug!("    - Table render duration: {:?}", start_time.elapsed());
+
+    Ok(())
+}
+
+/// Converts an image decoding error to a render error
+fn to_image_decode_error(err: image::ImageError) -> RenderError {
+    RenderError::ImageDecode {
+        message: err.to_string(),
+    }
+}
+
+/// Returns the fallback font of the font provider, used to draw diagnostic labels
+fn get_fallback_font(font_provider: &dyn FontProvider) -> Option<rusttype::Font<'_>> {
+    font_provider.get_fallback_font_data().and_then(parse_font)
+}
+
+/// Parses the font data, borrowing or taking ownership of it
+/// WOFF and WOFF2 fonts are decompressed before parsing
+fn parse_font(font_data: Cow<'_, [u8]>) -> Option<rusttype::Font<'_>> {
+    let font_data = match font_decoder::decode_font(font_data) {
+        Ok(font_data) => font_data,
+        Err(err) => {
+            error!("Failed to decode font: {}", err);
+            return None;
+        }
+    };
+    match font_data {
+        Cow::Borrowed(font_data) => rusttype::Font::try_from_bytes(font_data),
+        Cow::Owned(font_data) => rusttype::Font::try_from_vec(font_data),
+    }
+}
+
+/// Normalizes a hex color string to the format #RRGGBBAA, with upper case digits
+/// Surrounding whitespace and a missing leading # are tolerated
+/// Returns None if the string is not a valid hex color
+pub fn normalize_hex_color(hex_string: &str) -> Option<String> {
+    hex_to_rgba(hex_string.trim()).ok().map(rgba_to_hex)
+}
+
+/// Represents an error that occurred while parsing a hex color string.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ColorParseError {
+    /// The hex string does not have the expected amount of hex digits
+    InvalidLength { hex_string: String, length: usize },
+    /// The hex string contains a character that is not a hex digit
+    InvalidHexDigit { hex_string: String, digit: char },
+    /// The color has no leading # and is not a known color name
+    UnknownColorName { name: String },
+}
+
+impl std::fmt::Display for ColorParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorParseError::InvalidLength { hex_string, length } => write!(
+                f,
+                "Color '{}' has {} hex digits, expected #RRGGBBAA, #RRGGBB, #RGBA or #RGB",
+                hex_string, length
+            ),
+            ColorParseError::InvalidHexDigit { hex_string, digit } => write!(
+                f,
+                "Color '{}' contains the invalid hex digit '{}'",
+                hex_string, digit
+            ),
+            ColorParseError::UnknownColorName { name } => write!(
+                f,
+                "Color '{}' is not a known color name, hex colors have to start with #",
+                name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ColorParseError {}
+
+/// The standard CSS named colors with their RGB value, sorted by name
+const CSS_NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xF0F8FF),
+    ("antiquewhite", 0xFAEBD7),
+    ("aqua", 0x00FFFF),
+    ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF),
+    ("beige", 0xF5F5DC),
+    ("bisque", 0xFFE4C4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD),
+    ("blue", 0x0000FF),
+    ("blueviolet", 0x8A2BE2),
+    ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887),
+    ("cadetblue", 0x5F9EA0),
+    ("chartreuse", 0x7FFF00),
+    ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50),
+    ("cornflowerblue", 0x6495ED),
+    ("cornsilk", 0xFFF8DC),
+    ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF),
+    ("darkblue", 0x00008B),
+    ("darkcyan", 0x008B8B),
+    ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xA9A9A9),
+    ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B),
+    ("darkolivegreen", 0x556B2F),
+    ("darkorange", 0xFF8C00),
+    ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000),
+    ("darksalmon", 0xE9967A),
+    ("darkseagreen", 0x8FBC8F),
+    ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F),
+    ("darkslategrey", 0x2F4F4F),
+    ("darkturquoise", 0x00CED1),
+    ("darkviolet", 0x9400D3),
+    ("deeppink", 0xFF1493),
+    ("deepskyblue", 0x00BFFF),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1E90FF),
+    ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0),
+    ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF),
+    ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF),
+    ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xADFF2F),
+    ("grey", 0x808080),
+    ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4),
+    ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082),
+    ("ivory", 0xFFFFF0),
+    ("khaki", 0xF0E68C),
+    ("lavender", 0xE6E6FA),
+    ("lavenderblush", 0xFFF0F5),
+    ("lawngreen", 0x7CFC00),
+    ("lemonchiffon", 0xFFFACD),
+    ("lightblue", 0xADD8E6),
+    ("lightcoral", 0xF08080),
+    ("lightcyan", 0xE0FFFF),
+    ("lightgoldenrodyellow", 0xFAFAD2),
+    ("lightgray", 0xD3D3D3),
+    ("lightgreen", 0x90EE90),
+    ("lightgrey", 0xD3D3D3),
+    ("lightpink", 0xFFB6C1),
+    ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA),
+    ("lightskyblue", 0x87CEFA),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE),
+    ("lightyellow", 0xFFFFE0),
+    ("lime", 0x00FF00),
+    ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6),
+    ("magenta", 0xFF00FF),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD),
+    ("mediumorchid", 0xBA55D3),
+    ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371),
+    ("mediumslateblue", 0x7B68EE),
+    ("mediumspringgreen", 0x00FA9A),
+    ("mediumturquoise", 0x48D1CC),
+    ("mediumvioletred", 0xC71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA),
+    ("mistyrose", 0xFFE4E1),
+    ("moccasin", 0xFFE4B5),
+    ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080),
+    ("oldlace", 0xFDF5E6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6B8E23),
+    ("orange", 0xFFA500),
+    ("orangered", 0xFF4500),
+    ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA),
+    ("palegreen", 0x98FB98),
+    ("paleturquoise", 0xAFEEEE),
+    ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5),
+    ("peachpuff", 0xFFDAB9),
+    ("peru", 0xCD853F),
+    ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD),
+    ("powderblue", 0xB0E0E6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xFF0000),
+    ("rosybrown", 0xBC8F8F),
+    ("royalblue", 0x4169E1),
+    ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072),
+    ("sandybrown", 0xF4A460),
+    ("seagreen", 0x2E8B57),
+    ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D),
+    ("silver", 0xC0C0C0),
+    ("skyblue", 0x87CEEB),
+    ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xFFFAFA),
+    ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4),
+    ("tan", 0xD2B48C),
+    ("teal", 0x008080),
+    ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347),
+    ("turquoise", 0x40E0D0),
+    ("violet", 0xEE82EE),
+    ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF),
+    ("whitesmoke", 0xF5F5F5),
+    ("yellow", 0xFFFF00),
+    ("yellowgreen", 0x9ACD32),
+];
+
+/// Returns the color of a standard CSS color name, e.g. "cornflowerblue"
+/// The name is matched case-insensitively, "transparent" is fully transparent black
+pub fn named_color(name: &str) -> Option<Rgba<u8>> {
+    let name = name.trim().to_lowercase();
+    if name == "transparent" {
+        return Some(Rgba([0, 0, 0, 0]));
+    }
+
+    CSS_NAMED_COLORS
+        .binary_search_by(|(color_name, _)| color_name.cmp(&name.as_str()))
+        .ok()
+        .map(|index| {
+            let rgb = CSS_NAMED_COLORS[index].1;
+            Rgba([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 0xff])
+        })
+}
+
+/// Converts a hex string to a Rgba<u8>
+/// The hex string must be in the format #RRGGBBAA or #RRGGBB, the latter is fully opaque
+/// The shorthand formats #RGBA and #RGB are expanded by duplicating each digit
+/// Without a leading # only standard CSS color names are accepted, see `named_color`,
+/// so a misspelled name made of hex digits like "bad" is not silently taken as hex color
+/// Example: #FF0000CC
+/// Returns a Rgba<u8> struct, or an error describing why the hex string is malformed
+pub fn hex_to_rgba(hex_string: &str) -> Result<Rgba<u8>, ColorParseError> {
+    let Some(hex_digits) = hex_string.strip_prefix('#') else {
+        return named_color(hex_string).ok_or_else(|| ColorParseError::UnknownColorName {
+            name: hex_string.to_string(),
+        });
+    };
+
+    if let Some(digit) = hex_digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(ColorParseError::InvalidHexDigit {
+            hex_string: hex_string.to_string(),
+            digit,
+        });
+    }
+    // Expand the shorthand formats, e.g. F00 -> FF0000
+    let expanded_hex_digits: String;
+    let hex_digits = match hex_digits.len() {
+        3 | 4 => {
+            expanded_hex_digits = hex_digits.chars().flat_map(|c| [c, c]).collect();
+            expanded_hex_digits.as_str()
+        }
+        _ => hex_digits,
+    };
+
+    let hex_digits = match hex_digits.len() {
+        8 => hex_digits.to_string(),
+        6 => format!("{}FF", hex_digits),
+        length => {
+            return Err(ColorParseError::InvalidLength {
+                hex_string: hex_string.to_string(),
+                length,
+            })
+        }
+    };
+
+    let hex = u32::from_str_radix(&hex_digits, 16).map_err(|_| ColorParseError::InvalidLength {
+        hex_string: hex_string.to_string(),
+        length: hex_digits.len(),
+    })?;
+    let r = ((hex >> 24) & 0xff) as u8;
+    let g = ((hex >> 16) & 0xff) as u8;
+    let b = ((hex >> 8) & 0xff) as u8;
+    let a = (hex & 0xff) as u8;
+    Ok(Rgba([r, g, b, a]))
+}
+
+/// Converts a hex string to a Rgba<u8>, like `hex_to_rgba`
+/// An empty string is fully transparent.
+/// If the hex string is malformed, the error is logged and opaque black is returned
+pub fn hex_to_rgba_or_default(hex_string: &str) -> Rgba<u8> {
+    if hex_string.trim().is_empty() {
+        return Rgba([0, 0, 0, 0]);
+    }
+    hex_to_rgba(hex_string).unwrap_or_else(|err| {
+        error!("Invalid color: {}", err);
+        Rgba([0, 0, 0, 255])
+    })
+}
+
+/// Converts an image with premultiplied alpha to straight alpha in place
+/// Fully transparent pixels are left untouched
+pub fn unpremultiply_alpha(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        if alpha == 0 || alpha == 255 {
+            continue;
+        }
+
+        for channel in pixel.0.iter_mut().take(3) {
+            *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+        }
+    }
+}
+
+/// Composites the layer onto the image at the given position using the given blend mode
+/// The blended color is weighted by the alpha of the image, so blending onto transparent pixels
+/// draws the layer unchanged. The result is then drawn over the image using the alpha of the layer.
+pub fn composite(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    layer: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    blend_mode: &BlendMode,
+) {
+    if *blend_mode == BlendMode::Normal {
+        image::imageops::overlay(image, layer, x as i64, y as i64);
+        return;
+    }
+
+    let blend: fn(f32, f32) -> f32 = match blend_mode {
+        BlendMode::Normal => |_, source| source,
+        BlendMode::Additive => |base, source| (base + source).min(1.0),
+        BlendMode::Multiply => |base, source| base * source,
+        BlendMode::Screen => |base, source| base + source - base * source,
+    };
+
+    for (layer_x, layer_y, source) in layer.enumerate_pixels() {
+        let source_alpha = source[3] as f32 / 255.0;
+        if source_alpha == 0.0 {
+            continue;
+        }
+        let image_x = x as i64 + layer_x as i64;
+        let image_y = y as i64 + layer_y as i64;
+        if image_x < 0
+            || image_y < 0
+            || image_x >= image.width() as i64
+            || image_y >= image.height() as i64
+        {
+            continue;
+        }
+
+        let base = image.get_pixel_mut(image_x as u32, image_y as u32);
+        let base_alpha = base[3] as f32 / 255.0;
+        let alpha = source_alpha + base_alpha * (1.0 - source_alpha);
+        for channel in 0..3 {
+            let base_color = base[channel] as f32 / 255.0;
+            let source_color = source[channel] as f32 / 255.0;
+            let blended_color =
+                (1.0 - base_alpha) * source_color + base_alpha * blend(base_color, source_color);
+            let color = (source_alpha * blended_color
+                + base_alpha * base_color * (1.0 - source_alpha))
+                / alpha;
+            base[channel] = (color * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        base[3] = (alpha * 255.0).round() as u8;
+    }
+}
+
+/// Converts a Rgba<u8> to a hex string
+/// The hex string will be in the format #RRGGBBAA
+/// Example: #FF0000CC
+pub fn rgba_to_hex(color: Rgba<u8>) -> String {
+    format!(
+        "#{:02X}{:02X}{:02X}{:02X}",
+        color[0], color[1], color[2], color[3]
+    )
+}
+
+/// Returns black or white, depending on which contrasts best with the given background color
+/// The decision is based on the perceived luminance of the background color
+pub fn contrasting_color(background_color: Rgba<u8>) -> Rgba<u8> {
+    let luminance = 0.299 * background_color[0] as f32
+        + 0.587 * background_color[1] as f32
+        + 0.114 * background_color[2] as f32;
+
+    if luminance > 127.5 {
+        Rgba([0, 0, 0, 255])
+    } else {
+        Rgba([255, 255, 255, 255])
+    }
+}
+
+/// Returns the alpha-weighted average color of the image or of the given region of the image
+/// The region is clipped to the image bounds.
+/// The color channels are weighted by the alpha of each pixel, the alpha is the mean alpha.
+/// An empty or fully transparent region results in a transparent color.
+pub fn average_color(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    rect: Option<imageproc::rect::Rect>,
+) -> Rgba<u8> {
+    let rect =
+        rect.unwrap_or(imageproc::rect::Rect::at(0, 0).of_size(image.width(), image.height()));
+    let x_start = rect.left().max(0) as u32;
+    let y_start = rect.top().max(0) as u32;
+    let x_end = (rect.right() + 1).clamp(0, image.width() as i32) as u32;
+    let y_end = (rect.bottom() + 1).clamp(0, image.height() as i32) as u32;
+
+    let mut color_sum = [0u64; 3];
+    let mut alpha_sum = 0u64;
+    let mut count = 0u64;
+    for pixel_y in y_start..y_end {
+        for pixel_x in x_start..x_end {
+            let [r, g, b, a] = image.get_pixel(pixel_x, pixel_y).0;
+            for (channel_sum, channel) in color_sum.iter_mut().zip([r, g, b]) {
+                *channel_sum += channel as u64 * a as u64;
+            }
+            alpha_sum += a as u64;
+            count += 1;
+        }
+    }
+
+    if alpha_sum == 0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let [r, g, b] = color_sum.map(|channel_sum| (channel_sum / alpha_sum) as u8);
+    Rgba([r, g, b, (alpha_sum / count) as u8])
+}
+
+/// Extracts the historical values from the sensor_value_history and reverses the order
+pub fn extract_value_sequence(
+    sensor_value_history: &[Vec<SensorValue>],
+    sensor_id: &str,
+) -> Vec<f64> {
+    let mut sensor_values: Vec<f64> = sensor_value_history
+        .iter()
+        .flat_map(|history_entry| {
+            history_entry.iter().find_map(|entry| {
+                if entry.id.eq(sensor_id) {
+                    return entry.value.parse().ok();
+                }
+                None
+            })
+        })
+        .collect();
+    sensor_values.reverse();
+    sensor_values
+}
+
+/// Extracts the historical values with their timestamps from the sensor_value_history
+/// and reverses the order. Values without a timestamp are skipped.
+pub fn extract_timed_value_sequence(
+    sensor_value_history: &[Vec<SensorValue>],
+    sensor_id: &str,
+) -> Vec<(u64, f64)> {
+    let mut sensor_values: Vec<(u64, f64)> = sensor_value_history
+        .iter()
+        .flat_map(|history_entry| {
+            history_entry.iter().find_map(|entry| {
+                if entry.id.eq(sensor_id) {
+                    let value = entry.value.parse().ok()?;
+                    return entry.timestamp.map(|timestamp| (timestamp, value));
+                }
+                None
+            })
+        })
+        .collect();
+    sensor_values.reverse();
+    sensor_values
+}
+
+/// Checks if the given DirEntry is an image
+pub fn is_image(dir_entry: &DirEntry) -> bool {
+    let entry_path = dir_entry.path();
+    let image_format = entry_path.extension().and_then(ImageFormat::from_extension);
+    image_format.map(|x| x.can_read()).unwrap_or(false)
+}
+
+/// Resolves the given asset path against the asset root
+/// Absolute paths are returned as they are, relative paths are joined to the asset root
+pub fn resolve_asset_path(asset_root: &Path, asset_path: &str) -> PathBuf {
+    let asset_path = Path::new(asset_path);
+    if asset_path.is_absolute() {
+        asset_path.to_path_buf()
+    } else {
+        asset_root.join(asset_path)
+    }
+}
+
+/// Get the cache directory for the given element
+pub fn get_cache_dir(element_id: &str, element_type: &ElementType) -> PathBuf {
+    let element_type_folder_name = match element_type {
+        ElementType::Text => "text",
+        ElementType::StaticImage => "static-image",
+        ElementType::Graph => "graph",
+        ElementType::ConditionalImage => "conditional-image",
+        ElementType::Table => "table",
+        ElementType::Gauge => "gauge",
+        ElementType::ProgressBar => "progress-bar",
+        ElementType::Clock => "clock",
+        ElementType::SegmentBar => "segment-bar",
+    };
+
+    get_cache_base_dir()
+        .join(element_type_folder_name)
+        .join(element_id)
+}
+
+/// Get the base cache directory
+pub fn get_cache_base_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap()
+        .join(std::env::var("SENSOR_BRIDGE_APP_NAME").unwrap())
+}
+
+/// Get the application config dir
+pub fn get_config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap()
+        .join(std::env::var("SENSOR_BRIDGE_APP_NAME").unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        fonts_data, has_visible_pixels, temp_asset_dir, visible_bounds, TEST_FONT_FAMILY,
+    };
+
+    fn number_sensor(id: &str, value: &str) -> SensorValue {
+        SensorValue {
+            id: id.to_string(),
+            value: value.to_string(),
+            sensor_type: SensorType::Number,
+            ..Default::default()
+        }
+    }
+
+    fn text_element(id: &str, sensor_id: &str, format: &str) -> ElementConfig {
+        ElementConfig {
+            id: id.to_string(),
+            element_type: ElementType::Text,
+            text_config: Some(TextConfig {
+                sensor_id: sensor_id.to_string(),
+                format: format.to_string(),
+                font_family: TEST_FONT_FAMILY.to_string(),
+                font_size: 20,
+                font_color: "#FFFFFF".to_string(),
+                width: 100,
+                height: 30,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn display_config(elements: Vec<ElementConfig>) -> DisplayConfig {
+        DisplayConfig {
+            resolution_width: 100,
+            resolution_height: 30,
+            elements,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_relative_image_path_is_resolved_against_asset_root() {
+        let asset_root = temp_asset_dir("asset-root");
+        std::fs::create_dir_all(asset_root.join("images")).unwrap();
+        image::RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]))
+            .save(asset_root.join("images/red.png"))
+            .unwrap();
+        let config = DisplayConfig {
+            asset_root: Some(asset_root.to_string_lossy().to_string()),
+            ..display_config(vec![ElementConfig {
+                id: "image".to_string(),
+                element_type: ElementType::StaticImage,
+                x: 5,
+                y: 5,
+                image_config: Some(ImageConfig {
+                    width: 10,
+                    height: 10,
+                    image_path: "images/red.png".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }])
+        };
+
+        let (image, errors) =
+            render_lcd_image_with_errors(config, &[], &HashMap::new(), &RenderOptions::default())
+                .unwrap();
+
+        assert!(errors.is_empty());
+        assert_eq!(*image.get_pixel(10, 10), Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(20, 20), Rgba([0, 0, 0, 0]));
+        std::fs::remove_dir_all(asset_root).unwrap();
+    }
+
+    #[test]
+    fn test_supersampling_is_skipped_beyond_the_maximum_resolution() {
+        let config = display_config(vec![text_element("text", "cpu", "42")]);
+        let fonts_data = fonts_data();
+        let options = RenderOptions {
+            max_resolution: 100,
+            ..Default::default()
+        };
+
+        let image = render_lcd_image_with_font_provider(
+            DisplayConfig {
+                render_quality: RenderQuality::High,
+                ..config.clone()
+            },
+            &[],
+            &fonts_data,
+            &options,
+        )
+        .unwrap();
+        let expected =
+            render_lcd_image_with_font_provider(config, &[], &fonts_data, &options).unwrap();
+
+        assert_eq!(image.dimensions(), (100, 30));
+        assert!(has_visible_pixels(&image));
+        assert_eq!(image, expected);
+    }
+
+    #[test]
+    fn test_history_aggregates_render_like_the_history() {
+        let mut history_builder = sensor_history::SensorHistoryBuilder::new(3);
+        for value in ["10", "90", "40", "20"] {
+            history_builder.push(vec![number_sensor("cpu", value)]);
+        }
+        let config = display_config(vec![text_element("max", "cpu", "{value-min} {value-max}")]);
+        let fonts_data = fonts_data();
+        let options = RenderOptions::default();
+
+        let expected = render_lcd_image_with_font_provider(
+            config.clone(),
+            history_builder.history(),
+            &fonts_data,
+            &options,
+        )
+        .unwrap();
+        let history_aggregates = get_history_aggregates(&config, &history_builder);
+        let image =
+            render_lcd_image_with_history(config, &mut history_builder, &fonts_data, &options)
+                .unwrap();
+
+        assert_eq!(
+            history_aggregates,
+            HashMap::from([(
+                "cpu".to_string(),
+                HistoryAggregates {
+                    min: 20.0,
+                    max: 90.0,
+                    avg: 50.0,
+                }
+            )])
+        );
+        assert!(has_visible_pixels(&image));
+        assert_eq!(image, expected);
+    }
+
+    #[test]
+    fn test_virtual_sensor_renders_sum_of_sensors() {
+        let fonts_data = fonts_data();
+        let mut config = display_config(vec![text_element("free", "free", "{value}")]);
+        config.virtual_sensors = vec![VirtualSensorConfig {
+            id: "free".to_string(),
+            expression: "{total} + {used}".to_string(),
+            ..Default::default()
+        }];
+        let mut render_data = RenderData {
+            display_config: config.clone(),
+            sensor_values: vec![number_sensor("total", "2"), number_sensor("used", "3")],
+        };
+
+        render_data.apply_virtual_sensors();
+        let image = render_lcd_image(config.clone(), &[render_data.sensor_values], &fonts_data);
+
+        let expected_image =
+            render_lcd_image(config, &[vec![number_sensor("free", "5")]], &fonts_data);
+        assert!(has_visible_pixels(&image));
+        assert_eq!(image, expected_image);
+    }
+
+    #[test]
+    fn test_parse_untrusted_rejects_deeply_nested_expression() {
+        let expression = format!("{}1{}", "(".repeat(2000), ")".repeat(2000));
+        let json = format!(
+            r#"{{"virtual_sensors": [{{"id": "deep", "expression": "{}"}}]}}"#,
+            expression
+        );
+
+        let result = DisplayConfig::parse_untrusted(&json, &RenderLimits::default());
+
+        assert_eq!(
+            result,
+            Err(ParseError::ExpressionTooDeep {
+                sensor_id: "deep".to_string(),
+                max_depth: expression::MAX_NESTING_DEPTH,
+            })
+        );
+    }
+
+    #[test]
+    fn test_empty_colors_are_not_reported() {
+        let element = ElementConfig {
+            id: "graph".to_string(),
+            element_type: ElementType::Graph,
+            graph_config: Some(GraphConfig::default()),
+            ..Default::default()
+        };
+        let config = display_config(vec![element.clone(), text_element("text", "cpu", "")]);
+
+        assert_eq!(config.validate_colors(), vec![]);
+        assert_eq!(get_element_warnings(&element, &[]), vec![]);
+        assert_eq!(hex_to_rgba_or_default(""), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_named_colors_are_accepted_without_hash() {
+        assert_eq!(hex_to_rgba("red"), Ok(Rgba([255, 0, 0, 255])));
+        assert_eq!(
+            hex_to_rgba("CornflowerBlue"),
+            Ok(Rgba([100, 149, 237, 255]))
+        );
+        assert_eq!(hex_to_rgba("transparent"), Ok(Rgba([0, 0, 0, 0])));
+    }
+
+    #[test]
+    fn test_hex_digits_without_hash_are_rejected() {
+        for color in ["bad", "fed", "beef", "FF0000", "FF0000FF"] {
+            assert_eq!(
+                hex_to_rgba(color),
+                Err(ColorParseError::UnknownColorName {
+                    name: color.to_string()
+                })
+            );
+        }
+        assert_eq!(hex_to_rgba("#fed"), Ok(Rgba([255, 238, 221, 255])));
+    }
+
+    #[test]
+    fn test_dark_background_gets_white_text() {
+        assert_eq!(
+            contrasting_color(Rgba([0, 0, 0, 255])),
+            Rgba([255, 255, 255, 255])
+        );
+        assert_eq!(
+            contrasting_color(Rgba([0, 0, 139, 255])),
+            Rgba([255, 255, 255, 255])
+        );
+    }
+
+    #[test]
+    fn test_light_background_gets_black_text() {
+        assert_eq!(
+            contrasting_color(Rgba([255, 255, 255, 255])),
+            Rgba([0, 0, 0, 255])
+        );
+        assert_eq!(
+            contrasting_color(Rgba([255, 255, 0, 255])),
+            Rgba([0, 0, 0, 255])
+        );
+    }
+
+    #[test]
+    fn test_zero_resolution_is_rejected() {
+        let config = DisplayConfig {
+            resolution_width: 0,
+            ..display_config(vec![])
+        };
+
+        let result = try_render_lcd_image(config, &[], &HashMap::new(), &RenderOptions::default());
+
+        assert_eq!(
+            result,
+            Err(RenderError::ZeroResolution {
+                width: 0,
+                height: 30
+            })
+        );
+    }
+
+    #[test]
+    fn test_resolution_over_the_limit_is_rejected() {
+        let config = DisplayConfig {
+            resolution_width: 100_000,
+            resolution_height: 100_000,
+            ..display_config(vec![])
+        };
+
+        let result = try_render_lcd_image(config, &[], &HashMap::new(), &RenderOptions::default());
+
+        assert_eq!(
+            result,
+            Err(RenderError::ResolutionTooLarge {
+                width: 100_000,
+                height: 100_000,
+                max_resolution: DEFAULT_MAX_RESOLUTION,
+            })
+        );
+    }
+
+    #[test]
+    fn test_ingestion_rounded_value_is_shown_by_all_elements() {
+        let mut render_data = RenderData {
+            display_config: DisplayConfig {
+                resolution_height: 60,
+                ..display_config(vec![
+                    text_element("first", "cpu", "{value}"),
+                    ElementConfig {
+                        y: 30,
+                        ..text_element("second", "cpu", "{value}")
+                    },
+                ])
+            },
+            sensor_values: vec![SensorValue {
+                decimals: Some(1),
+                ..number_sensor("cpu", "21.456")
+            }],
+        };
+        let fonts_data = fonts_data();
+        let options = RenderOptions::default();
+
+        render_data.normalize_sensor_values();
+        let image = try_render_lcd_image(
+            render_data.display_config,
+            &[render_data.sensor_values.clone()],
+            &fonts_data,
+            &options,
+        )
+        .unwrap();
+        let expected = try_render_lcd_image(
+            // Numbers with decimals are shown with two decimals
+            display_config(vec![text_element("first", "cpu", "21.50")]),
+            &[],
+            &fonts_data,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(render_data.sensor_values[0].value, "21.5");
+        let first = image::imageops::crop_imm(&image, 0, 0, 100, 30).to_image();
+        let second = image::imageops::crop_imm(&image, 0, 30, 100, 30).to_image();
+        assert!(first == expected);
+        assert!(second == expected);
+    }
+
+    #[test]
+    fn test_display_frame_transport_message_round_trip() {
+        let mut image = image::RgbaImage::new(3, 2);
+        image.put_pixel(1, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(2, 1, Rgba([0, 0, 255, 128]));
+        let frame = DisplayFrame::from_image(&image);
+
+        let message = frame.to_transport_message().unwrap();
+        let serialized_message = bincode::serialize(&message).unwrap();
+        let deserialized_message: TransportMessage =
+            bincode::deserialize(&serialized_message).unwrap();
+        let deserialized_frame: DisplayFrame =
+            bincode::deserialize(&deserialized_message.data).unwrap();
+
+        assert_eq!(
+            deserialized_message.transport_type,
+            TransportType::DisplayFrame
+        );
+        assert_eq!(deserialized_frame, frame);
+        assert_eq!(deserialized_frame.to_image(), Some(image));
+    }
+
+    #[test]
+    fn test_premultiplied_asset_is_composited_with_straight_alpha() {
+        let asset_root = temp_asset_dir("premultiplied");
+        let straight_asset =
+            image::RgbaImage::from_fn(2, 1, |x, _| Rgba([255, 0, 0, [255, 128][x as usize]]));
+        let premultiplied_asset = image::RgbaImage::from_fn(2, 1, |x, _| {
+            Rgba([[255, 128][x as usize], 0, 0, [255, 128][x as usize]])
+        });
+        premultiplied_asset
+            .save(asset_root.join("edge.png"))
+            .unwrap();
+        let image_config = ImageConfig {
+            width: 2,
+            height: 1,
+            image_path: "edge.png".to_string(),
+            premultiplied_alpha: true,
+        };
+        let white = Rgba([255, 255, 255, 255]);
+
+        let mut image = image::RgbaImage::from_pixel(2, 1, white);
+        draw_static_image(&mut image, "edge", &image_config, 0, 0, Some(&asset_root)).unwrap();
+        let mut expected = image::RgbaImage::from_pixel(2, 1, white);
+        image::imageops::overlay(&mut expected, &straight_asset, 0, 0);
+
+        assert_eq!(image, expected);
+        assert_eq!(image.get_pixel(1, 0).0[..3], [255, 127, 127]);
+        std::fs::remove_dir_all(asset_root).unwrap();
+    }
+
+    #[test]
+    fn test_mono_output_is_the_thresholded_bitmap() {
+        let config = DisplayConfig {
+            resolution_width: 10,
+            resolution_height: 2,
+            background_color: "#202020".to_string(),
+            elements: vec![ElementConfig {
+                id: "plate".to_string(),
+                element_type: ElementType::Text,
+                x: 2,
+                y: 1,
+                text_config: Some(TextConfig {
+                    font_family: TEST_FONT_FAMILY.to_string(),
+                    background_color: "#C0C0C0".to_string(),
+                    width: 8,
+                    height: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let fonts_data = fonts_data();
+
+        let render = |format: MonoFormat| {
+            render_lcd_image_mono(
+                config.clone(),
+                &[],
+                &fonts_data,
+                &RenderOptions::default(),
+                &format,
+            )
+            .unwrap()
+        };
+        let gray = render(MonoFormat::Gray8);
+        let packed = render(MonoFormat::Mono1 { threshold: 128 });
+
+        assert_eq!(&gray[..10], &[0x20; 10]);
+        assert_eq!(
+            &gray[10..],
+            &[0x20, 0x20, 0xC0, 0xC0, 0xC0, 0xC0, 0xC0, 0xC0, 0xC0, 0xC0]
+        );
+        // Each row is padded to two bytes, the leftmost pixel is the most significant bit
+        assert_eq!(packed, [0b0000_0000, 0b0000_0000, 0b0011_1111, 0b1100_0000]);
+    }
+
+    #[test]
+    fn test_debug_overlay_outlines_each_element() {
+        let magenta = Rgba([255, 0, 255, 255]);
+        let config = DisplayConfig {
+            resolution_width: 200,
+            resolution_height: 100,
+            ..display_config(vec![
+                text_element("first", "cpu", ""),
+                ElementConfig {
+                    x: 60,
+                    y: 50,
+                    ..text_element("second", "cpu", "")
+                },
+            ])
+        };
+        let options = RenderOptions {
+            debug_overlay: true,
+            ..Default::default()
+        };
+
+        let image = try_render_lcd_image(config, &[], &fonts_data(), &options).unwrap();
+
+        // The outline covers the corners and edges of the 100x30 elements
+        for (x, y) in [
+            (0, 0),
+            (99, 29),
+            (50, 0),
+            (0, 15),
+            (60, 50),
+            (159, 79),
+            (110, 79),
+        ] {
+            assert_eq!(*image.get_pixel(x, y), magenta, "({}, {})", x, y);
+        }
+        assert_ne!(*image.get_pixel(180, 20), magenta);
+    }
+
+    #[test]
+    fn test_broken_element_is_replaced_by_a_placeholder() {
+        let gray = Rgba([128, 128, 128, 255]);
+        let mut broken_element = ElementConfig {
+            x: 10,
+            y: 5,
+            ..text_element("broken", "cpu", "{value}")
+        };
+        broken_element.text_config.as_mut().unwrap().font_family = "Missing Font".to_string();
+        let config = DisplayConfig {
+            resolution_width: 200,
+            resolution_height: 50,
+            ..display_config(vec![broken_element])
+        };
+        let render = |on_error: OnError| {
+            let options = RenderOptions {
+                on_error,
+                ..Default::default()
+            };
+            render_lcd_image_with_errors(config.clone(), &[], &fonts_data(), &options).unwrap()
+        };
+
+        let (image, errors) = render(OnError::Placeholder);
+        let (skipped_image, _) = render(OnError::Skip);
+
+        assert!(errors.contains(&ElementError {
+            element_id: "broken".to_string(),
+            error: RenderError::MissingFont {
+                font_family: "Missing Font".to_string()
+            },
+        }));
+        for (x, y) in [(10, 5), (109, 34), (100, 30)] {
+            assert_eq!(*image.get_pixel(x, y), gray, "({}, {})", x, y);
+        }
+        assert_eq!(image.get_pixel(9, 5)[3], 0);
+        assert_eq!(image.get_pixel(110, 35)[3], 0);
+        assert!(!has_visible_pixels(&skipped_image));
+    }
+
+    #[test]
+    fn test_average_color_of_a_solid_image_is_that_color() {
+        let color = Rgba([12, 34, 56, 255]);
+        let image = image::RgbaImage::from_pixel(4, 4, color);
+
+        assert_eq!(average_color(&image, None), color);
+    }
+
+    #[test]
+    fn test_average_color_of_a_half_red_half_blue_image_is_the_blend() {
+        let image = image::RgbaImage::from_fn(4, 4, |x, _| {
+            if x < 2 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+
+        assert_eq!(average_color(&image, None), Rgba([127, 0, 127, 255]));
+        let red_half = imageproc::rect::Rect::at(0, 0).of_size(2, 4);
+        assert_eq!(
+            average_color(&image, Some(red_half)),
+            Rgba([255, 0, 0, 255])
+        );
+    }
+
+    #[test]
+    fn test_font_family_is_matched_ignoring_case() {
+        let mut element = text_element("text", "cpu", "42");
+        element.text_config.as_mut().unwrap().font_family =
+            format!(" {} ", TEST_FONT_FAMILY.to_uppercase());
+
+        let (image, errors) = render_lcd_image_with_errors(
+            display_config(vec![element]),
+            &[],
+            &fonts_data(),
+            &RenderOptions::default(),
+        )
+        .unwrap();
+
+        assert!(!errors
+            .iter()
+            .any(|error| matches!(error.error, RenderError::MissingFont { .. })));
+        assert!(has_visible_pixels(&image));
+    }
+
+    #[test]
+    fn test_closure_font_provider_is_asked_for_referenced_families_only() {
+        let font_data = crate::test_utils::load_font_data();
+        let requested_families = std::cell::RefCell::new(vec![]);
+        let font_provider = |font_family: &str| {
+            requested_families
+                .borrow_mut()
+                .push(font_family.to_string());
+            Some(font_data.clone())
+        };
+
+        let image = render_lcd_image_with_font_provider(
+            display_config(vec![text_element("text", "cpu", "42")]),
+            &[],
+            &font_provider,
+            &RenderOptions::default(),
+        )
+        .unwrap();
+
+        assert!(has_visible_pixels(&image));
+        assert!(!requested_families.borrow().is_empty());
+        assert!(requested_families
+            .borrow()
+            .iter()
+            .all(|font_family| font_family == TEST_FONT_FAMILY));
+    }
+
+    #[test]
+    fn test_frame_over_a_tiny_budget_is_reported() {
+        // A large text element takes way longer to render than the budget
+        let mut slow_element = text_element("slow", "cpu", &"888 ".repeat(10));
+        if let Some(text_config) = slow_element.text_config.as_mut() {
+            text_config.font_size = 60;
+            text_config.width = 400;
+            text_config.height = 400;
+            text_config.wrap = true;
+        }
+        let config = DisplayConfig {
+            resolution_width: 400,
+            resolution_height: 400,
+            ..display_config(vec![slow_element])
+        };
+        let fonts_data = fonts_data();
+
+        let (_, tiny_budget_timing) =
+            render_lcd_image_timed(config.clone(), &[], &fonts_data, Duration::from_nanos(1));
+        let (image, large_budget_timing) =
+            render_lcd_image_timed(config, &[], &fonts_data, Duration::from_secs(3600));
+
+        assert!(tiny_budget_timing.over_budget);
+        assert!(!large_budget_timing.over_budget);
+        assert!(has_visible_pixels(&image));
+    }
+
+    #[test]
+    fn test_text_stays_within_the_padding() {
+        let mut element = ElementConfig {
+            padding: ElementPadding {
+                top: 10,
+                right: 10,
+                bottom: 10,
+                left: 10,
+            },
+            ..text_element("padded", "cpu", "42")
+        };
+        if let Some(text_config) = element.text_config.as_mut() {
+            text_config.height = 50;
+            text_config.alignment = TextAlign::Right;
+            text_config.vertical_alignment = VerticalAlign::Bottom;
+        }
+        let config = DisplayConfig {
+            resolution_height: 50,
+            ..display_config(vec![element])
+        };
+
+        let image =
+            try_render_lcd_image(config, &[], &fonts_data(), &RenderOptions::default()).unwrap();
+
+        let (min_x, min_y, max_x, max_y) = visible_bounds(&image).unwrap();
+        assert!(min_x >= 10 && min_y >= 10, "{:?}", (min_x, min_y));
+        assert!(max_x <= 89 && max_y <= 39, "{:?}", (max_x, max_y));
+        assert!(max_x >= 85 && max_y >= 35, "{:?}", (max_x, max_y));
+    }
+
+    #[test]
+    fn test_table_rows_show_label_left_and_value_right() {
+        let row = |label: &str, sensor_id: &str| TableRow {
+            label: label.to_string(),
+            sensor_id: sensor_id.to_string(),
+        };
+        let config = DisplayConfig {
+            resolution_width: 200,
+            resolution_height: 60,
+            ..display_config(vec![ElementConfig {
+                id: "table".to_string(),
+                element_type: ElementType::Table,
+                table_config: Some(TableConfig {
+                    rows: vec![row("CPU", "cpu"), row("GPU", "gpu")],
+                    value_format: "{value}".to_string(),
+                    font_family: TEST_FONT_FAMILY.to_string(),
+                    font_size: 20,
+                    font_color: "#FFFFFF".to_string(),
+                    width: 200,
+                    height: 60,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }])
+        };
+        let history = [vec![number_sensor("cpu", "1"), number_sensor("gpu", "2")]];
+
+        let image =
+            try_render_lcd_image(config, &history, &fonts_data(), &RenderOptions::default())
+                .unwrap();
+
+        let is_visible = |x_range: std::ops::Range<u32>, y_range: std::ops::Range<u32>| {
+            x_range
+                .flat_map(|x| y_range.clone().map(move |y| (x, y)))
+                .any(|(x, y)| image.get_pixel(x, y)[3] > 0)
+        };
+        for row_y in [0, 30] {
+            let row_range = row_y..row_y + 30;
+            assert!(
+                is_visible(0..60, row_range.clone()),
+                "label of row {}",
+                row_y
+            );
+            assert!(
+                !is_visible(60..170, row_range.clone()),
+                "gap of row {}",
+                row_y
+            );
+            assert!(is_visible(170..200, row_range), "value of row {}", row_y);
+        }
+        let first_row = image::imageops::crop_imm(&image, 0, 0, 200, 30).to_image();
+        let second_row = image::imageops::crop_imm(&image, 0, 30, 200, 30).to_image();
+        assert!(first_row != second_row);
+    }
+
+    #[test]
+    fn test_translucent_graph_background_shows_the_base() {
+        let config = DisplayConfig {
+            background_color: "#0000FF".to_string(),
+            ..display_config(vec![ElementConfig {
+                id: "graph".to_string(),
+                element_type: ElementType::Graph,
+                graph_config: Some(GraphConfig {
+                    width: 50,
+                    height: 30,
+                    background_color: "#FF000080".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }])
+        };
+
+        let image =
+            try_render_lcd_image(config, &[], &HashMap::new(), &RenderOptions::default()).unwrap();
+
+        // Half of the red background is blended with the blue base
+        let [r, g, b, a] = image.get_pixel(25, 15).0;
+        assert!(
+            r.abs_diff(128) <= 1 && b.abs_diff(127) <= 1,
+            "{:?}",
+            (r, g, b)
+        );
+        assert_eq!(g, 0);
+        assert!(a >= 254);
+        assert_eq!(*image.get_pixel(75, 15), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_element_is_only_rerendered_when_its_interval_is_due() {
+        let config = display_config(vec![ElementConfig {
+            refresh_interval_ms: Some(1000),
+            ..text_element("slow", "cpu", "{value}")
+        }]);
+        let fonts_data = fonts_data();
+        let history = |value: &str| [vec![number_sensor("cpu", value)]];
+        let mut state = IncrementalRenderState::default();
+        let mut render_at = |value: &str, timestamp_ms: u64| {
+            render_lcd_image_incremental(
+                config.clone(),
+                &history(value),
+                &fonts_data,
+                &RenderOptions::default(),
+                timestamp_ms,
+                &mut state,
+            )
+            .unwrap()
+        };
+
+        let first_frame = render_at("1", 0);
+        let before_due_frame = render_at("2", 999);
+        let due_frame = render_at("2", 1000);
+
+        let first_value = render_lcd_image(config.clone(), &history("1"), &fonts_data);
+        let second_value = render_lcd_image(config.clone(), &history("2"), &fonts_data);
+        assert!(first_value != second_value);
+        assert!(first_frame == first_value);
+        assert!(before_due_frame == first_value);
+        assert!(due_frame == second_value);
+    }
+
+    #[test]
+    fn test_density_independent_stroke_scales_with_the_display() {
+        let stroke_thickness = |scale_factor: f32| {
+            let config = DisplayConfig {
+                scale_factor,
+                ..display_config(vec![ElementConfig {
+                    id: "graph".to_string(),
+                    element_type: ElementType::Graph,
+                    graph_config: Some(GraphConfig {
+                        sensor_id: "cpu".to_string(),
+                        min_sensor_value: Some(0.0),
+                        max_sensor_value: Some(10.0),
+                        width: 100,
+                        height: 30,
+                        graph_color: "#FF0000".to_string(),
+                        graph_stroke_width: 4,
+                        stroke_unit: SizeUnit::DensityIndependentPixel,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }])
+            };
+            let history = vec![vec![number_sensor("cpu", "5")]; 100];
+            let image =
+                try_render_lcd_image(config, &history, &HashMap::new(), &RenderOptions::default())
+                    .unwrap();
+            (0..image.height())
+                .filter(|y| *image.get_pixel(50, *y) == Rgba([255, 0, 0, 255]))
+                .count()
+        };
+
+        let thickness = stroke_thickness(1.0);
+        let doubled_thickness = stroke_thickness(2.0);
+
+        // The stroke is centered on the line, so it is always an odd number of pixels thick
+        assert_eq!(thickness, 5);
+        assert_eq!(doubled_thickness, 2 * thickness - 1);
+    }
+
+    #[test]
+    fn test_invalid_graph_color_is_reported_with_its_location() {
+        let config = display_config(vec![
+            text_element("text", "cpu", "{value}"),
+            ElementConfig {
+                id: "graph".to_string(),
+                element_type: ElementType::Graph,
+                graph_config: Some(GraphConfig {
+                    graph_color: "#FF0000".to_string(),
+                    border_color: "#GG0000".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ]);
+
+        assert_eq!(
+            config.validate_colors(),
+            vec![ColorIssue {
+                element_id: "graph".to_string(),
+                field: "graph_config.border_color".to_string(),
+                value: "#GG0000".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_reduced_render_scale_keeps_the_display_resolution() {
+        let config = DisplayConfig {
+            resolution_width: 320,
+            resolution_height: 170,
+            ..display_config(vec![text_element("text", "cpu", "{value}")])
+        };
+        let options = RenderOptions {
+            render_scale: 0.5,
+            ..Default::default()
+        };
+
+        let image = try_render_lcd_image(
+            config,
+            &[vec![number_sensor("cpu", "42")]],
+            &fonts_data(),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(image.dimensions(), (320, 170));
+        assert!(has_visible_pixels(&image));
+    }
+
+    #[test]
+    fn test_merge_replaces_adds_and_inherits_elements() {
+        let base = display_config(vec![
+            text_element("cpu", "cpu", "{value}"),
+            text_element("gpu", "gpu", "{value}"),
+        ]);
+        let overlay = DisplayConfig {
+            resolution_width: 0,
+            resolution_height: 0,
+            ..display_config(vec![
+                text_element("gpu", "gpu", "GPU {value}"),
+                text_element("ram", "ram", "{value}"),
+            ])
+        };
+
+        let merged = DisplayConfig::merge(base, overlay);
+
+        let element_formats: Vec<(&str, &str)> = merged
+            .elements
+            .iter()
+            .map(|element| {
+                let text_config = element.text_config.as_ref().unwrap();
+                (element.id.as_str(), text_config.format.as_str())
+            })
+            .collect();
+        assert_eq!(
+            element_formats,
+            vec![
+                ("cpu", "{value}"),
+                ("gpu", "GPU {value}"),
+                ("ram", "{value}")
+            ]
+        );
+        assert_eq!(
+            (merged.resolution_width, merged.resolution_height),
+            (100, 30)
+        );
+    }
+
+    #[test]
+    fn test_conditional_text_renders_the_range_of_the_value() {
+        let range = |min_value, max_value, text: &str, font_color: &str| ConditionalTextRange {
+            min_value,
+            max_value,
+            text: text.to_string(),
+            font_color: font_color.to_string(),
+        };
+        let mut element = text_element("text", "cpu", "{value}");
+        element.text_config.as_mut().unwrap().conditional_text = Some(ConditionalTextConfig {
+            ranges: vec![
+                range(None, Some(30.0), "LOW", "#00FF00"),
+                range(Some(30.0), Some(70.0), "MID", "#FFFF00"),
+                range(Some(70.0), None, "HIGH", "#FF0000"),
+            ],
+        });
+        let render = |element: ElementConfig, value: &str| {
+            try_render_lcd_image(
+                display_config(vec![element]),
+                &[vec![number_sensor("cpu", value)]],
+                &fonts_data(),
+                &RenderOptions::default(),
+            )
+            .unwrap()
+        };
+
+        for (value, text, font_color) in [
+            ("10", "LOW", "#00FF00"),
+            ("50", "MID", "#FFFF00"),
+            ("90", "HIGH", "#FF0000"),
+        ] {
+            let mut expected_element = text_element("text", "cpu", text);
+            expected_element.text_config.as_mut().unwrap().font_color = font_color.to_string();
+
+            let image = render(element.clone(), value);
+
+            assert!(has_visible_pixels(&image));
+            assert!(image == render(expected_element, value), "value {}", value);
+        }
+    }
+
+    #[test]
+    fn test_effective_dimensions_differ_from_declared_for_small_text() {
+        let config = display_config(vec![text_element("text", "cpu", "{value}")]);
+
+        let dimensions =
+            measure_elements(&config, &[vec![number_sensor("cpu", "7")]], &fonts_data()).unwrap();
+
+        let text_dimensions = &dimensions[0];
+        assert_eq!(text_dimensions.element_id, "text");
+        assert_eq!(
+            (
+                text_dimensions.declared_width,
+                text_dimensions.declared_height
+            ),
+            (100, 30)
+        );
+        assert!(text_dimensions.effective_width > 0 && text_dimensions.effective_width < 100);
+        assert!(text_dimensions.effective_height > 0 && text_dimensions.effective_height < 30);
+    }
+
+    #[test]
+    fn test_render_tree_lists_drawn_and_skipped_elements() {
+        let config = display_config(vec![
+            text_element("text", "cpu", "{value}"),
+            ElementConfig {
+                id: "image".to_string(),
+                element_type: ElementType::ConditionalImage,
+                conditional_image_config: Some(ConditionalImageConfig {
+                    sensor_id: "fan".to_string(),
+                    width: 10,
+                    height: 10,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ]);
+
+        let (_, render_tree) = render_lcd_image_with_render_tree(
+            config,
+            &[vec![number_sensor("cpu", "42")]],
+            &fonts_data(),
+            &RenderOptions::default(),
+        )
+        .unwrap();
+
+        let elements: Vec<(&str, Option<&str>, Option<&str>, &DrawStatus)> = render_tree
+            .elements
+            .iter()
+            .map(|element| {
+                (
+                    element.id.as_str(),
+                    element.sensor_id.as_deref(),
+                    element.sensor_value.as_deref(),
+                    &element.status,
+                )
+            })
+            .collect();
+        assert_eq!(
+            elements,
+            vec![
+                ("text", Some("cpu"), Some("42"), &DrawStatus::Drawn),
+                ("image", Some("fan"), None, &DrawStatus::Skipped),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_six_digit_colors_are_opaque() {
+        assert_eq!(hex_to_rgba("#FF0000"), Ok(Rgba([255, 0, 0, 255])));
+        assert_eq!(hex_to_rgba("#ff0000"), Ok(Rgba([255, 0, 0, 255])));
+        assert_eq!(hex_to_rgba("#FF000080"), Ok(Rgba([255, 0, 0, 128])));
+    }
+
+    #[test]
+    fn test_short_hex_colors_are_expanded() {
+        assert_eq!(hex_to_rgba("#F00"), hex_to_rgba("#FF0000FF"));
+        assert_eq!(hex_to_rgba("#1234"), hex_to_rgba("#11223344"));
+        assert_eq!(hex_to_rgba("#1234"), Ok(Rgba([0x11, 0x22, 0x33, 0x44])));
+    }
+
+    #[test]
+    fn test_only_elements_referencing_a_changed_sensor_are_returned() {
+        let graph_element = |id: &str, sensor_id: &str, additional_sensor_id: &str| ElementConfig {
+            id: id.to_string(),
+            element_type: ElementType::Graph,
+            graph_config: Some(GraphConfig {
+                sensor_id: sensor_id.to_string(),
+                additional_series: vec![GraphSeries {
+                    sensor_id: additional_sensor_id.to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let config = display_config(vec![
+            text_element("cpu-text", "cpu", "{value}"),
+            text_element("cpu-avg", "cpu", "{value-avg} / {value-max}"),
+            text_element("gpu-text", "gpu", "{value}"),
+            graph_element("cpu-graph", "cpu", "gpu"),
+            graph_element("ram-graph", "ram", "cpu"),
+            graph_element("gpu-graph", "gpu", "ram"),
+        ]);
+        let prev_values = vec![
+            number_sensor("cpu", "10"),
+            number_sensor("gpu", "20"),
+            number_sensor("ram", "30"),
+        ];
+        let next_values = vec![
+            number_sensor("cpu", "11"),
+            number_sensor("gpu", "20"),
+            number_sensor("ram", "30"),
+        ];
+
+        assert_eq!(
+            changed_element_ids(&prev_values, &next_values, &config),
+            vec!["cpu-text", "cpu-avg", "cpu-graph", "ram-graph"]
+        );
+        assert!(changed_element_ids(&prev_values, &prev_values, &config).is_empty());
+    }
+
+    #[test]
+    fn test_estimated_message_size_matches_the_serialized_size() {
+        let render_data = RenderData {
+            display_config: display_config(vec![
+                text_element("cpu", "cpu", "{value} %"),
+                text_element("gpu", "gpu", "{value-avg}"),
+            ]),
+            sensor_values: vec![number_sensor("cpu", "42"), number_sensor("gpu", "17.5")],
+        };
+
+        let estimated_size = estimate_transport_message_size(&render_data).unwrap();
+
+        let transport_message = TransportMessage {
+            transport_type: TransportType::RenderImage,
+            data: bincode::serialize(&render_data).unwrap(),
+        };
+        let serialized_size = bincode::serialize(&transport_message).unwrap().len();
+        assert_eq!(estimated_size, serialized_size);
+        assert_eq!(transport_message.serialized_size(), serialized_size);
+    }
+
+    #[test]
+    fn test_value_color_map_colors_text_by_sensor_state() {
+        let text_sensor = |value: &str| SensorValue {
+            id: "power".to_string(),
+            value: value.to_string(),
+            sensor_type: SensorType::Text,
+            ..Default::default()
+        };
+        let render_state = |state: &str| {
+            let config = DisplayConfig {
+                value_color_map: HashMap::from([(
+                    "power".to_string(),
+                    HashMap::from([
+                        ("high".to_string(), "#FF0000".to_string()),
+                        ("low".to_string(), "#00FF00".to_string()),
+                    ]),
+                )]),
+                ..display_config(vec![text_element("text", "power", "{value}")])
+            };
+            let image = try_render_lcd_image(
+                config,
+                &[vec![text_sensor(state)]],
+                &fonts_data(),
+                &RenderOptions::default(),
+            )
+            .unwrap();
+            // The fully covered pixels of the glyphs have the font color
+            image
+                .pixels()
+                .filter(|pixel| pixel[3] == 255)
+                .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect::<std::collections::HashSet<_>>()
+        };
+
+        assert_eq!(render_state("high"), [[255, 0, 0]].into());
+        assert_eq!(render_state("low"), [[0, 255, 0]].into());
+        // States without a color keep the font color of the element
+        assert_eq!(render_state("off"), [[255, 255, 255]].into());
+    }
+
+    #[test]
+    fn test_parse_untrusted_rejects_configs_exceeding_each_limit() {
+        let limits = RenderLimits {
+            max_json_length: 64 * 1024,
+            max_resolution: 200,
+            max_elements: 2,
+            max_font_size: 50,
+            max_string_length: 32,
+        };
+        let parse = |display_config: DisplayConfig| {
+            let json = serde_json::to_string(&display_config).unwrap();
+            DisplayConfig::parse_untrusted(&json, &limits)
+        };
+        let with_text_config = |update: &dyn Fn(&mut TextConfig)| {
+            let mut element = text_element("text", "cpu", "{value}");
+            update(element.text_config.as_mut().unwrap());
+            display_config(vec![element])
+        };
+
+        assert!(parse(display_config(vec![text_element("text", "cpu", "{value}")])).is_ok());
+        assert_eq!(
+            DisplayConfig::parse_untrusted(&" ".repeat(64 * 1024 + 1), &limits),
+            Err(ParseError::InputTooLarge {
+                length: 64 * 1024 + 1,
+                max_length: 64 * 1024,
+            })
+        );
+        assert!(matches!(
+            DisplayConfig::parse_untrusted("{\"elements\": [", &limits),
+            Err(ParseError::InvalidJson(_))
+        ));
+        assert_eq!(
+            parse(DisplayConfig {
+                resolution_width: 201,
+                ..display_config(vec![])
+            }),
+            Err(ParseError::ResolutionTooLarge {
+                width: 201,
+                height: 30,
+                max_resolution: 200,
+            })
+        );
+        assert_eq!(
+            parse(display_config(vec![
+                text_element("text", "cpu", "{value}");
+                3
+            ])),
+            Err(ParseError::TooManyElements {
+                count: 3,
+                max_elements: 2,
+            })
+        );
+        assert_eq!(
+            parse(with_text_config(&|text_config| text_config.width = 201)),
+            Err(ParseError::ElementTooLarge {
+                element_id: "text".to_string(),
+                width: 201,
+                height: 30,
+                max_resolution: 200,
+            })
+        );
+        assert_eq!(
+            parse(with_text_config(&|text_config| text_config.font_size = 51)),
+            Err(ParseError::FontSizeTooLarge {
+                element_id: "text".to_string(),
+                font_size: 51,
+                max_font_size: 50,
+            })
+        );
+        assert_eq!(
+            parse(with_text_config(&|text_config| {
+                text_config.format = "x".repeat(33)
+            })),
+            Err(ParseError::StringTooLong {
+                field: "elements[0].text_config.format".to_string(),
+                length: 33,
+                max_length: 32,
+            })
+        );
+    }
+
+    #[test]
+    fn test_layers_composited_in_z_order_match_the_rendered_image() {
+        let mut overlapping_element = text_element("gpu", "gpu", "{value}");
+        overlapping_element.x = 10;
+        overlapping_element.blend_mode = BlendMode::Additive;
+        overlapping_element.text_config.as_mut().unwrap().font_color = "#FF0000".to_string();
+        let config = display_config(vec![
+            text_element("cpu", "cpu", "{value}"),
+            overlapping_element,
+        ]);
+        let history = [vec![number_sensor("cpu", "88"), number_sensor("gpu", "88")]];
+        let fonts_data = fonts_data();
+
+        let mut layers = render_layers(config.clone(), &history, &fonts_data);
+        let image = render_lcd_image(config, &history, &fonts_data);
+
+        layers.sort_by_key(|layer| layer.z_index);
+        let mut composited_image = ImageBuffer::new(100, 30);
+        for layer in &layers {
+            composite(
+                &mut composited_image,
+                &layer.image,
+                layer.x,
+                layer.y,
+                &layer.blend_mode,
+            );
+        }
+        assert_eq!(layers.len(), 2);
+        assert!(has_visible_pixels(&composited_image));
+        assert!(composited_image == image);
+    }
+
+    #[test]
+    fn test_overlapping_text_elements_are_reported_as_collision() {
+        let mut overlapping_element = text_element("gpu", "gpu", "{value}");
+        overlapping_element.x = 50;
+        let mut separate_element = text_element("ram", "ram", "{value}");
+        separate_element.y = 30;
+        let config = display_config(vec![
+            text_element("cpu", "cpu", "{value}"),
+            overlapping_element,
+            separate_element,
+        ]);
+
+        assert_eq!(
+            config.validate().collisions,
+            vec![ElementCollision {
+                element_id: "cpu".to_string(),
+                other_element_id: "gpu".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_vertical_align_serializes_with_lowercase_names() {
+        for (vertical_align, name) in [
+            (VerticalAlign::Top, "\"top\""),
+            (VerticalAlign::Middle, "\"middle\""),
+            (VerticalAlign::Bottom, "\"bottom\""),
+        ] {
+            assert_eq!(serde_json::to_string(&vertical_align).unwrap(), name);
+            assert_eq!(
+                serde_json::from_str::<VerticalAlign>(name).unwrap(),
+                vertical_align
+            );
+        }
+        assert_eq!(VerticalAlign::default(), VerticalAlign::Middle);
+    }
+
+    #[test]
+    fn test_blend_modes_over_mid_gray() {
+        let blend_over_gray = |blend_mode: BlendMode| {
+            let mut image = ImageBuffer::from_pixel(1, 1, Rgba([128, 128, 128, 255]));
+            let layer = ImageBuffer::from_pixel(1, 1, Rgba([100, 100, 100, 255]));
+            composite(&mut image, &layer, 0, 0, &blend_mode);
+            *image.get_pixel(0, 0)
+        };
+
+        // 128 + 100
+        assert_eq!(
+            blend_over_gray(BlendMode::Additive),
+            Rgba([228, 228, 228, 255])
+        );
+        // 128 * 100 / 255
+        assert_eq!(
+            blend_over_gray(BlendMode::Multiply),
+            Rgba([50, 50, 50, 255])
+        );
+        assert_eq!(
+            blend_over_gray(BlendMode::Normal),
+            Rgba([100, 100, 100, 255])
+        );
+    }
+
+    #[test]
+    fn test_changed_region_spans_the_changed_elements() {
+        let narrow_text_element = |id: &str, x: i32| {
+            let mut element = text_element(id, id, "{value}");
+            element.x = x;
+            element.text_config.as_mut().unwrap().width = 40;
+            element
+        };
+        let config = DisplayConfig {
+            resolution_width: 200,
+            ..display_config(vec![
+                narrow_text_element("cpu", 0),
+                narrow_text_element("gpu", 80),
+                narrow_text_element("ram", 150),
+            ])
+        };
+        let history = |cpu: &str, ram: &str| {
+            vec![vec![
+                number_sensor("cpu", cpu),
+                number_sensor("gpu", "5"),
+                number_sensor("ram", ram),
+            ]]
+        };
+        let fonts_data = fonts_data();
+        let options = RenderOptions::default();
+        let mut state = ChangedRegionState::default();
+        let mut render_changed_region = |history: &[Vec<SensorValue>]| {
+            render_lcd_image_changed_region(
+                config.clone(),
+                history,
+                &fonts_data,
+                &options,
+                &mut state,
+            )
+            .unwrap()
+        };
+
+        let (_, first_region) = render_changed_region(&history("1", "1")).unwrap();
+        let unchanged = render_changed_region(&history("1", "1"));
+        let (changed_image, changed_region) = render_changed_region(&history("2", "2")).unwrap();
+
+        assert_eq!(
+            first_region,
+            imageproc::rect::Rect::at(0, 0).of_size(200, 30)
+        );
+        assert_eq!(unchanged, None);
+        assert_eq!(
+            changed_region,
+            imageproc::rect::Rect::at(0, 0).of_size(190, 30)
+        );
+        let full_image = render_lcd_image(config, &history("2", "2"), &fonts_data);
+        let expected_image = image::imageops::crop_imm(&full_image, 0, 0, 190, 30).to_image();
+        assert!(changed_image == expected_image);
+    }
+
+    #[test]
+    fn test_high_quality_has_more_smoothed_edge_pixels_than_fast() {
+        let config = display_config(vec![
+            text_element("text", "cpu", "42 %"),
+            ElementConfig {
+                id: "graph".to_string(),
+                element_type: ElementType::Graph,
+                graph_config: Some(GraphConfig {
+                    sensor_id: "cpu".to_string(),
+                    width: 100,
+                    height: 30,
+                    graph_color: "#FF0000".to_string(),
+                    graph_stroke_width: 2,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ]);
+        let history: Vec<Vec<SensorValue>> = (0..100)
+            .map(|value| vec![number_sensor("cpu", &(value % 37).to_string())])
+            .collect();
+        let count_edge_pixels = |render_quality: RenderQuality| {
+            let config = DisplayConfig {
+                render_quality,
+                ..config.clone()
+            };
+            let image =
+                try_render_lcd_image(config, &history, &fonts_data(), &RenderOptions::default())
+                    .unwrap();
+            image
+                .pixels()
+                .filter(|pixel| pixel[3] > 0 && pixel[3] < 255)
+                .count()
+        };
+
+        let fast_edge_pixels = count_edge_pixels(RenderQuality::Fast);
+        let high_edge_pixels = count_edge_pixels(RenderQuality::High);
+
+        assert!(
+            high_edge_pixels > fast_edge_pixels,
+            "high {} fast {}",
+            high_edge_pixels,
+            fast_edge_pixels
+        );
+    }
+
+    #[test]
+    fn test_label_placeholder_renders_the_sensor_label() {
+        let history = [vec![SensorValue {
+            label: "CPU".to_string(),
+            ..number_sensor("cpu", "42")
+        }]];
+        let render = |format: &str| {
+            try_render_lcd_image(
+                display_config(vec![text_element("text", "cpu", format)]),
+                &history,
+                &fonts_data(),
+                &RenderOptions::default(),
+            )
+            .unwrap()
+        };
+
+        let image = render("{label}: {value}");
+
+        assert!(has_visible_pixels(&image));
+        assert!(image == render("CPU: 42"));
+    }
+
+    #[test]
+    fn test_configs_exceeding_the_maximum_element_count_are_rejected() {
+        let config = display_config(vec![text_element("text", "cpu", "{value}"); 3]);
+        let render_with_max_elements = |max_elements: usize| {
+            try_render_lcd_image(
+                config.clone(),
+                &[vec![number_sensor("cpu", "1")]],
+                &fonts_data(),
+                &RenderOptions {
+                    max_elements: Some(max_elements),
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert_eq!(
+            render_with_max_elements(2).unwrap_err(),
+            RenderError::TooManyElements {
+                count: 3,
+                max_elements: 2,
+            }
+        );
+        assert!(render_with_max_elements(3).is_ok());
+    }
+
+    #[test]
+    fn test_translucent_text_background_darkens_the_frame() {
+        let mut element = text_element("text", "cpu", "{value}");
+        element.text_config.as_mut().unwrap().background_color = "#00000066".to_string();
+        let config = DisplayConfig {
+            background_color: "#C86432".to_string(),
+            ..display_config(vec![element])
+        };
+
+        let image = try_render_lcd_image(
+            config,
+            &[vec![number_sensor("cpu", "1")]],
+            &fonts_data(),
+            &RenderOptions::default(),
+        )
+        .unwrap();
+
+        // A plate of 40 % black keeps 60 % of the base color, away from the glyphs
+        let pixel = image.get_pixel(99, 29);
+        let expected = [200.0 * 0.6, 100.0 * 0.6, 50.0 * 0.6];
+        assert!((0..3).all(|channel| (pixel[channel] as f32 - expected[channel]).abs() <= 1.0));
+        assert_eq!(pixel[3], 255);
+    }
+
+    #[test]
+    fn test_validate_payload_rejects_mistagged_messages() {
+        let render_data = RenderData {
+            display_config: display_config(vec![text_element("cpu", "cpu", "{value} %")]),
+            sensor_values: vec![number_sensor("cpu", "42")],
+        };
+        let render_message = TransportMessage {
+            transport_type: TransportType::RenderImage,
+            data: bincode::serialize(&render_data).unwrap(),
+        };
+        let request_message = ConditionalImageRequest {
+            element_id: "cpu".to_string(),
+            sensor_value: "42".to_string(),
+            sensor_type: SensorType::Number,
+        }
+        .to_transport_message()
+        .unwrap();
+
+        assert_eq!(render_message.validate_payload(), Ok(()));
+        assert_eq!(request_message.validate_payload(), Ok(()));
+
+        let mistagged_message = TransportMessage {
+            transport_type: TransportType::PrepareText,
+            ..render_message
+        };
+        assert!(matches!(
+            mistagged_message.validate_payload(),
+            Err(TransportError::PayloadMismatch {
+                transport_type: TransportType::PrepareText,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_rotation_swaps_dimensions_and_moves_the_top_left_corner() {
+        // A red plate in the top left corner of the 100x30 display as landmark
+        let mut marker = text_element("marker", "cpu", "");
+        marker.text_config = Some(TextConfig {
+            width: 10,
+            height: 5,
+            background_color: "#FF0000".to_string(),
+            ..marker.text_config.unwrap()
+        });
+        let render = |rotation: Rotation| {
+            let config = DisplayConfig {
+                rotation,
+                ..display_config(vec![marker.clone()])
+            };
+            render_lcd_image(config, &[vec![number_sensor("cpu", "42")]], &fonts_data())
+        };
+        let is_red = |image: &image::RgbaImage, x: u32, y: u32| image.get_pixel(x, y)[0] > 200;
+
+        let image = render(Rotation::None);
+        assert_eq!(image.dimensions(), (100, 30));
+        assert!(is_red(&image, 0, 0) && !is_red(&image, 99, 29));
+
+        // Clockwise, the top left corner moves to the top right
+        let image = render(Rotation::Rotate90);
+        assert_eq!(image.dimensions(), (30, 100));
+        assert!(is_red(&image, 29, 0) && is_red(&image, 25, 9) && !is_red(&image, 0, 0));
+
+        let image = render(Rotation::Rotate180);
+        assert_eq!(image.dimensions(), (100, 30));
+        assert!(is_red(&image, 99, 29) && is_red(&image, 90, 25) && !is_red(&image, 0, 0));
+
+        let image = render(Rotation::Rotate270);
+        assert_eq!(image.dimensions(), (30, 100));
+        assert!(is_red(&image, 0, 99) && is_red(&image, 4, 90) && !is_red(&image, 29, 0));
+    }
+
+    #[test]
+    fn test_opacity_blends_an_opaque_element_with_the_background() {
+        let render = |opacity: Option<f32>| {
+            // A fully opaque red square on a blue display
+            let mut square = text_element("square", "cpu", "");
+            square.text_config = Some(TextConfig {
+                width: 10,
+                height: 10,
+                background_color: "#FF0000".to_string(),
+                ..square.text_config.unwrap()
+            });
+            square.opacity = opacity;
+            let config = DisplayConfig {
+                background_color: "#0000FF".to_string(),
+                ..display_config(vec![square])
+            };
+            render_lcd_image(config, &[vec![number_sensor("cpu", "42")]], &fonts_data())
+        };
+        let is_close = |pixel: &Rgba<u8>, expected: [u8; 4]| {
+            (0..4).all(|channel| (pixel[channel] as i32 - expected[channel] as i32).abs() <= 1)
+        };
+
+        let image = render(Some(0.5));
+        assert!(is_close(image.get_pixel(5, 5), [128, 0, 128, 255]));
+        // Outside of the square only the background is visible
+        assert!(is_close(image.get_pixel(50, 20), [0, 0, 255, 255]));
+
+        // Out of range values are clamped
+        assert!(is_close(
+            render(Some(2.0)).get_pixel(5, 5),
+            [255, 0, 0, 255]
+        ));
+        assert!(is_close(
+            render(Some(-1.0)).get_pixel(5, 5),
+            [0, 0, 255, 255]
+        ));
+        assert!(is_close(render(None).get_pixel(5, 5), [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_on_demand_images_are_fetched_and_cached_only_for_the_current_value() {
+        /// Records the requests and returns a green image for each of them
+        struct RecordingFetcher {
+            requests: std::sync::Mutex<Vec<ConditionalImageRequest>>,
+        }
+
+        impl conditional_image_renderer::ConditionalImageFetcher for RecordingFetcher {
+            fn fetch(&self, request: &ConditionalImageRequest) -> Option<Vec<u8>> {
+                self.requests.lock().unwrap().push(request.clone());
+                let mut image_data = std::io::Cursor::new(Vec::new());
+                image::RgbaImage::from_pixel(10, 10, Rgba([0, 255, 0, 255]))
+                    .write_to(&mut image_data, image::ImageOutputFormat::Png)
+                    .unwrap();
+                Some(image_data.into_inner())
+            }
+        }
+
+        let config = display_config(vec![ElementConfig {
+            id: "fan".to_string(),
+            element_type: ElementType::ConditionalImage,
+            conditional_image_config: Some(ConditionalImageConfig {
+                sensor_id: "fan".to_string(),
+                min_sensor_value: 0.0,
+                max_sensor_value: 100.0,
+                width: 10,
+                height: 10,
+                on_demand: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }]);
+        let fetcher = RecordingFetcher {
+            requests: std::sync::Mutex::new(Vec::new()),
+        };
+        let image_cache = conditional_image_renderer::OnDemandImageCache::new();
+        let render = |sensor_value: &str| {
+            render_lcd_image_with_on_demand_images(
+                config.clone(),
+                &[vec![number_sensor("fan", sensor_value)]],
+                &fonts_data(),
+                &RenderOptions::default(),
+                &fetcher,
+                &image_cache,
+            )
+            .unwrap()
+        };
+
+        // Nothing is prepared, the image of the current value is requested when it is shown
+        let image = render("42");
+        assert_eq!(image.get_pixel(5, 5), &Rgba([0, 255, 0, 255]));
+        assert_eq!(
+            *fetcher.requests.lock().unwrap(),
+            vec![ConditionalImageRequest {
+                element_id: "fan".to_string(),
+                sensor_value: "42".to_string(),
+                sensor_type: SensorType::Number,
+            }]
+        );
+        assert_eq!(image_cache.len(), 1);
+        assert!(image_cache.contains("fan", "42"));
+
+        // The cached image is reused
+        render("42");
+        assert_eq!(fetcher.requests.lock().unwrap().len(), 1);
+
+        render("43");
+        assert_eq!(fetcher.requests.lock().unwrap().len(), 2);
+        assert_eq!(image_cache.len(), 2);
+        assert!(image_cache.contains("fan", "43"));
+    }
+
+    #[test]
+    fn test_background_color_fills_the_regions_not_covered_by_elements() {
+        let background = Rgba([10, 20, 30, 255]);
+        let with_background = |elements: Vec<ElementConfig>| DisplayConfig {
+            background_color: "#0A141E".to_string(),
+            ..display_config(elements)
+        };
+
+        let image = render_lcd_image(with_background(vec![]), &[], &fonts_data());
+        assert!(image.pixels().all(|pixel| *pixel == background));
+
+        // Without a background color the image stays transparent
+        let image = render_lcd_image(display_config(vec![]), &[], &fonts_data());
+        assert!(image.pixels().all(|pixel| pixel[3] == 0));
+
+        // Elements are drawn over the background
+        let mut plate = text_element("plate", "cpu", "");
+        plate.x = 10;
+        plate.y = 10;
+        plate.text_config = Some(TextConfig {
+            width: 10,
+            height: 10,
+            background_color: "#FF0000".to_string(),
+            ..plate.text_config.unwrap()
+        });
+        let image = render_lcd_image(
+            with_background(vec![plate]),
+            &[vec![number_sensor("cpu", "88")]],
+            &fonts_data(),
+        );
+        assert_eq!(*image.get_pixel(15, 15), Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(5, 5), background);
+        assert_eq!(*image.get_pixel(25, 25), background);
+    }
+
+    #[test]
+    fn test_background_image_covers_the_background_color() {
+        let asset_root = temp_asset_dir("background-image");
+        image::RgbaImage::from_pixel(100, 30, Rgba([0, 0, 255, 255]))
+            .save(asset_root.join("background.png"))
+            .unwrap();
+        let config = DisplayConfig {
+            asset_root: Some(asset_root.to_string_lossy().to_string()),
+            background_color: "#FF0000".to_string(),
+            background_image_path: Some("background.png".to_string()),
+            ..display_config(vec![])
+        };
+
+        let (image, errors) =
+            render_lcd_image_with_errors(config, &[], &HashMap::new(), &RenderOptions::default())
+                .unwrap();
+
+        assert!(errors.is_empty());
+        // The background image covers the background color
+        assert_eq!(*image.get_pixel(50, 20), Rgba([0, 0, 255, 255]));
+        std::fs::remove_dir_all(asset_root).unwrap();
+    }
+
+    #[test]
+    fn test_render_and_encode_writes_the_rendered_frame() {
+        let mut plate = text_element("plate", "cpu", "");
+        plate.text_config = Some(TextConfig {
+            width: 10,
+            height: 10,
+            background_color: "#FF0000".to_string(),
+            ..plate.text_config.unwrap()
+        });
+        let config = DisplayConfig {
+            background_color: "#0A141E".to_string(),
+            ..display_config(vec![plate])
+        };
+        let sensor_value_history = [vec![number_sensor("cpu", "42")]];
+        let expected = render_lcd_image(config.clone(), &sensor_value_history, &fonts_data());
+        let encode = |format: FrameFormat| {
+            let mut writer = Vec::new();
+            render_and_encode(
+                config.clone(),
+                &sensor_value_history,
+                &fonts_data(),
+                &format,
+                &mut writer,
+            )
+            .unwrap();
+            writer
+        };
+
+        assert_eq!(encode(FrameFormat::Rgba8), expected.as_raw().clone());
+
+        let png_image = image::load_from_memory(&encode(FrameFormat::Png)).unwrap();
+        assert!(png_image.to_rgba8() == expected);
+
+        let indexed_png_image = image::load_from_memory(&encode(FrameFormat::IndexedPng)).unwrap();
+        assert!(indexed_png_image.to_rgba8() == expected);
+    }
+
+    #[test]
+    fn test_incremental_render_applies_the_render_options_and_quality() {
+        let config = DisplayConfig {
+            render_quality: RenderQuality::High,
+            ..display_config(vec![ElementConfig {
+                refresh_interval_ms: Some(1000),
+                ..text_element("slow", "cpu", "{value}")
+            }])
+        };
+        let history = [vec![number_sensor("cpu", "42")]];
+        let mut state = IncrementalRenderState::default();
+
+        let image = render_lcd_image_incremental(
+            config.clone(),
+            &history,
+            &fonts_data(),
+            &RenderOptions::default(),
+            0,
+            &mut state,
+        )
+        .unwrap();
+        let expected = try_render_lcd_image(
+            config.clone(),
+            &history,
+            &fonts_data(),
+            &RenderOptions::default(),
+        )
+        .unwrap();
+        assert!(image == expected);
+
+        let options = RenderOptions {
+            max_elements: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            render_lcd_image_incremental(config, &history, &fonts_data(), &options, 0, &mut state),
+            Err(RenderError::TooManyElements { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mono_frame_matches_the_luminance_of_the_color_frame() {
+        let mut plate = text_element("plate", "cpu", "");
+        plate.x = 80;
+        plate.y = -5;
+        plate.text_config = Some(TextConfig {
+            width: 40,
+            height: 20,
+            background_color: "#3080C0".to_string(),
+            ..plate.text_config.unwrap()
+        });
+        let shade = ElementConfig {
+            blend_mode: BlendMode::Multiply,
+            opacity: Some(0.5),
+            ..text_element("shade", "cpu", "{value}")
+        };
+        let config = DisplayConfig {
+            background_color: "#404040".to_string(),
+            ..display_config(vec![text_element("text", "cpu", "{value} %"), plate, shade])
+        };
+        let history = [vec![number_sensor("cpu", "42")]];
+
+        let gray = render_lcd_image_mono(
+            config.clone(),
+            &history,
+            &fonts_data(),
+            &RenderOptions::default(),
+            &MonoFormat::Gray8,
+        )
+        .unwrap();
+        let expected =
+            mono_renderer::to_luminance(&render_lcd_image(config, &history, &fonts_data()));
+
+        assert_eq!(gray.len(), 100 * 30);
+        assert!(gray
+            .iter()
+            .zip(expected.as_raw())
+            .all(|(gray, expected)| gray.abs_diff(*expected) <= 2));
+    }
+
+    #[test]
+    fn test_history_aggregates_respect_the_history_cap() {
+        let mut history_builder = sensor_history::SensorHistoryBuilder::new(4);
+        for value in ["10", "90", "40", "20"] {
+            history_builder.push(vec![number_sensor("cpu", value)]);
+        }
+        let config = display_config(vec![text_element("max", "cpu", "{value-max}")]);
+        let fonts_data = fonts_data();
+        let options = RenderOptions {
+            max_history: Some(2),
+            ..Default::default()
+        };
+
+        let image = render_lcd_image_with_history(
+            config.clone(),
+            &mut history_builder,
+            &fonts_data,
+            &options,
+        )
+        .unwrap();
+
+        // Only the latest two values are rendered, so the max is 40 instead of 90
+        let expected = render_lcd_image(
+            display_config(vec![text_element("max", "cpu", "40")]),
+            &[],
+            &fonts_data,
+        );
+        assert!(image == expected);
+    }
+
+    #[test]
+    fn test_table_rows_are_cached_separately() {
+        let row = |label: &str, sensor_id: &str| TableRow {
+            label: label.to_string(),
+            sensor_id: sensor_id.to_string(),
+        };
+        let config = DisplayConfig {
+            resolution_width: 200,
+            resolution_height: 60,
+            ..display_config(vec![ElementConfig {
+                id: "table".to_string(),
+                element_type: ElementType::Table,
+                table_config: Some(TableConfig {
+                    rows: vec![row("CPU", "cpu"), row("GPU", "gpu")],
+                    value_format: "{value}".to_string(),
+                    font_family: TEST_FONT_FAMILY.to_string(),
+                    font_size: 20,
+                    font_color: "#FFFFFF".to_string(),
+                    width: 200,
+                    height: 60,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }])
+        };
+        let history = [vec![number_sensor("cpu", "1"), number_sensor("gpu", "2")]];
+        let font_cache = FontCache::new();
+        let render = || {
+            render_lcd_image_with_font_cache(
+                config.clone(),
+                &history,
+                &fonts_data(),
+                &RenderOptions::default(),
+                &font_cache,
+            )
+            .unwrap()
+        };
+
+        let image = render();
+        let text_render_cache = font_cache.text_render_cache();
+        // The label and the value of each row are cached as separate texts
+        assert_eq!(text_render_cache.len(), 4);
+        assert_eq!(text_render_cache.render_count(), 4);
+
+        let cached_image = render();
+        assert_eq!(text_render_cache.render_count(), 4);
+        assert!(cached_image == image);
+    }
+
+    #[test]
+    fn test_noisy_on_demand_values_share_the_image_request() {
+        /// Counts the requests and returns a green image for each of them
+        struct CountingFetcher {
+            fetch_count: std::sync::atomic::AtomicUsize,
+        }
+
+        impl conditional_image_renderer::ConditionalImageFetcher for CountingFetcher {
+            fn fetch(&self, _request: &ConditionalImageRequest) -> Option<Vec<u8>> {
+                self.fetch_count
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let mut image_data = std::io::Cursor::new(Vec::new());
+                image::RgbaImage::from_pixel(10, 10, Rgba([0, 255, 0, 255]))
+                    .write_to(&mut image_data, image::ImageOutputFormat::Png)
+                    .unwrap();
+                Some(image_data.into_inner())
+            }
+        }
+
+        let config = display_config(vec![ElementConfig {
+            id: "fan".to_string(),
+            element_type: ElementType::ConditionalImage,
+            conditional_image_config: Some(ConditionalImageConfig {
+                sensor_id: "fan".to_string(),
+                min_sensor_value: 0.0,
+                max_sensor_value: 100.0,
+                width: 10,
+                height: 10,
+                on_demand: true,
+                on_demand_image_count: 11,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }]);
+        let fetcher = CountingFetcher {
+            fetch_count: std::sync::atomic::AtomicUsize::new(0),
+        };
+        let image_cache = conditional_image_renderer::OnDemandImageCache::new();
+
+        for sensor_value in ["41.2", "39.8", "42.3", "40.1", "43.9"] {
+            let image = render_lcd_image_with_on_demand_images(
+                config.clone(),
+                &[vec![number_sensor("fan", sensor_value)]],
+                &fonts_data(),
+                &RenderOptions::default(),
+                &fetcher,
+                &image_cache,
+            )
+            .unwrap();
+            assert_eq!(image.get_pixel(5, 5), &Rgba([0, 255, 0, 255]));
+        }
+
+        assert_eq!(
+            fetcher
+                .fetch_count
+                .load(std::sync::atomic::Ordering::Relaxed),
+            1
+        );
+        assert_eq!(image_cache.len(), 1);
+        assert!(image_cache.contains("fan", "40"));
+    }
 }
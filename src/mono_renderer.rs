@@ -0,0 +1,92 @@
+use image::{GrayImage, ImageBuffer, Luma, Rgba};
+
+use crate::{BlendMode, MonoFormat};
+
+/// Converts the luminance frame to the given monochrome format
+/// # Returns
+/// A vector of bytes containing the pixels row by row.
+/// For `Gray8` each pixel is one byte, for `Mono1` each row is packed into bytes, MSB first,
+/// and padded to a full byte.
+/// # Arguments
+/// * `image` - The rendered luminance frame
+/// * `format` - The monochrome output format
+pub fn render(image: GrayImage, format: &MonoFormat) -> Vec<u8> {
+    match format {
+        MonoFormat::Gray8 => image.into_raw(),
+        MonoFormat::Mono1 { threshold } => pack_1bit(&image, *threshold),
+    }
+}
+
+/// Returns the luminance of the color, ignoring its alpha
+pub fn luminance(color: &Rgba<u8>) -> u8 {
+    let [r, g, b, _] = color.0;
+    ((299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000) as u8
+}
+
+/// Converts the rgba image to a single luminance channel
+/// The pixels are composited on a black background, so transparent pixels become black
+pub fn to_luminance(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> GrayImage {
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let pixel = image.get_pixel(x, y);
+        Luma([(luminance(pixel) as u32 * pixel[3] as u32 / 255) as u8])
+    })
+}
+
+/// Composites the luminance of the rgba layer onto the luminance frame at the given position,
+/// weighted by the alpha of the layer and blended with the blend mode
+/// Pixels of the layer outside of the frame are skipped
+pub fn composite(
+    image: &mut GrayImage,
+    layer: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    blend_mode: &BlendMode,
+) {
+    let blend: fn(f32, f32) -> f32 = match blend_mode {
+        BlendMode::Normal => |_, source| source,
+        BlendMode::Additive => |base, source| (base + source).min(1.0),
+        BlendMode::Multiply => |base, source| base * source,
+        BlendMode::Screen => |base, source| base + source - base * source,
+    };
+
+    for (layer_x, layer_y, source) in layer.enumerate_pixels() {
+        let source_alpha = source[3] as f32 / 255.0;
+        if source_alpha == 0.0 {
+            continue;
+        }
+        let image_x = x as i64 + layer_x as i64;
+        let image_y = y as i64 + layer_y as i64;
+        if image_x < 0
+            || image_y < 0
+            || image_x >= image.width() as i64
+            || image_y >= image.height() as i64
+        {
+            continue;
+        }
+
+        // The frame is opaque, so the layer is blended with it by its own alpha only
+        let base = image.get_pixel_mut(image_x as u32, image_y as u32);
+        let base_luminance = base[0] as f32 / 255.0;
+        let source_luminance = luminance(source) as f32 / 255.0;
+        let blended = blend(base_luminance, source_luminance);
+        let luminance = source_alpha * blended + (1.0 - source_alpha) * base_luminance;
+        base[0] = (luminance * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+/// Packs the gray image into a 1-bit bitmap
+/// Pixels with a luminance greater or equal to the threshold are set
+/// Each row starts at a new byte, the most significant bit is the leftmost pixel
+pub fn pack_1bit(image: &GrayImage, threshold: u8) -> Vec<u8> {
+    let bytes_per_row = (image.width() as usize).div_ceil(8);
+    let mut data = vec![0u8; bytes_per_row * image.height() as usize];
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[0] >= threshold {
+            let index = y as usize * bytes_per_row + x as usize / 8;
+            data[index] |= 0x80 >> (x % 8);
+        }
+    }
+
+    data
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use image::RgbaImage;
+
+/// The maximum number of colors of a palette with 8 bit per pixel
+const MAX_PALETTE_SIZE: usize = 256;
+
+/// Encodes the image as palette-indexed png with 8 bit per pixel, for tiny transfers
+/// The palette is chosen from the actual colors of the image.
+/// If the image has more colors than fit in the palette, the colors are quantized.
+pub fn render(image: &RgbaImage) -> Result<Vec<u8>, String> {
+    let mut png_data = vec![];
+    render_to_writer(image, &mut png_data)?;
+    Ok(png_data)
+}
+
+/// Encodes the image as palette-indexed png like `render`, straight into the writer
+pub fn render_to_writer<W: Write>(image: &RgbaImage, writer: W) -> Result<(), String> {
+    let (palette, indices) = match get_exact_palette(image) {
+        Some(exact_palette) => exact_palette,
+        None => quantize(image),
+    };
+
+    encode_indexed_png(writer, image.width(), image.height(), &palette, &indices)
+}
+
+/// Collects the colors of the image as palette and maps each pixel to its palette index
+/// Returns None if the image has more colors than fit in the palette
+fn get_exact_palette(image: &RgbaImage) -> Option<(Vec<[u8; 4]>, Vec<u8>)> {
+    let mut palette: Vec<[u8; 4]> = vec![];
+    let mut palette_indices: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity(image.as_raw().len() / 4);
+
+    for pixel in image.pixels() {
+        let index = match palette_indices.get(&pixel.0) {
+            Some(index) => *index,
+            None => {
+                if palette.len() == MAX_PALETTE_SIZE {
+                    return None;
+                }
+                let index = palette.len() as u8;
+                palette.push(pixel.0);
+                palette_indices.insert(pixel.0, index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+
+    Some((palette, indices))
+}
+
+/// Quantizes the colors of the image to a palette and maps each pixel to its nearest palette index
+fn quantize(image: &RgbaImage) -> (Vec<[u8; 4]>, Vec<u8>) {
+    let neu_quant = color_quant::NeuQuant::new(10, MAX_PALETTE_SIZE, image.as_raw());
+
+    let palette = neu_quant
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|color| [color[0], color[1], color[2], color[3]])
+        .collect();
+    let indices = image
+        .pixels()
+        .map(|pixel| neu_quant.index_of(&pixel.0) as u8)
+        .collect();
+
+    (palette, indices)
+}
+
+/// Encodes the palette indices as indexed png into the writer
+/// The alpha of the palette colors is only written if any color is not opaque
+fn encode_indexed_png<W: Write>(
+    writer: W,
+    width: u32,
+    height: u32,
+    palette: &[[u8; 4]],
+    indices: &[u8],
+) -> Result<(), String> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        palette
+            .iter()
+            .flat_map(|color| [color[0], color[1], color[2]])
+            .collect::<Vec<u8>>(),
+    );
+    if palette.iter().any(|color| color[3] != 255) {
+        encoder.set_trns(palette.iter().map(|color| color[3]).collect::<Vec<u8>>());
+    }
+
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    writer
+        .write_image_data(indices)
+        .map_err(|err| err.to_string())?;
+    writer.finish().map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(png_data: &[u8]) -> RgbaImage {
+        let decoder = png::Decoder::new(png_data);
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().color_type, png::ColorType::Indexed);
+        image::load_from_memory(png_data).unwrap().to_rgba8()
+    }
+
+    #[test]
+    fn test_indexed_png_decodes_to_equivalent_colors() {
+        let few_colors_image = RgbaImage::from_fn(8, 8, |x, _| {
+            image::Rgba(if x < 4 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 0, 0]
+            })
+        });
+        // A gradient with more colors than fit in the palette, so the colors are quantized
+        let gradient_image = RgbaImage::from_fn(64, 64, |x, y| {
+            image::Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255])
+        });
+
+        let few_colors_decoded = decode(&render(&few_colors_image).unwrap());
+        let gradient_decoded = decode(&render(&gradient_image).unwrap());
+
+        assert!(few_colors_decoded == few_colors_image);
+        assert_eq!(gradient_decoded.dimensions(), gradient_image.dimensions());
+        let total_difference: u64 = gradient_image
+            .as_raw()
+            .iter()
+            .zip(gradient_decoded.as_raw())
+            .map(|(channel, decoded_channel)| channel.abs_diff(*decoded_channel) as u64)
+            .sum();
+        let mean_difference = total_difference as f64 / gradient_image.as_raw().len() as f64;
+        assert!(mean_difference < 8.0, "mean difference {}", mean_difference);
+    }
+}
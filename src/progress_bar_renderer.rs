@@ -0,0 +1,105 @@
+use image::RgbaImage;
+use imageproc::rect::Rect;
+
+use crate::{hex_to_rgba_or_default, ProgressBarConfig, ProgressBarOrientation};
+
+/// Renders a progress bar based on the given config
+/// The filled part is proportional to the sensor value within the configured value range.
+/// Values outside of the range are clamped, so the bar is empty or full but never overflows.
+/// Without a sensor value only the background and the border are drawn.
+pub fn render(progress_bar_config: &ProgressBarConfig, sensor_value: Option<f64>) -> RgbaImage {
+    let width = progress_bar_config.width;
+    let height = progress_bar_config.height;
+    let mut image = RgbaImage::from_pixel(
+        width,
+        height,
+        hex_to_rgba_or_default(&progress_bar_config.background_color),
+    );
+    if width == 0 || height == 0 {
+        return image;
+    }
+
+    // The bar is filled within the border, so the border does not hide part of the fill
+    let border_width = if progress_bar_config.border_color.is_some() {
+        1
+    } else {
+        0
+    };
+    let inner_width = width.saturating_sub(2 * border_width);
+    let inner_height = height.saturating_sub(2 * border_width);
+
+    let fill_ratio = sensor_value
+        .map(|value| get_fill_ratio(progress_bar_config, value))
+        .unwrap_or(0.0);
+    let fill_color = hex_to_rgba_or_default(&progress_bar_config.fill_color);
+
+    // Horizontal bars fill from the left, vertical bars from the bottom
+    let (fill_width, fill_height) = match progress_bar_config.orientation {
+        ProgressBarOrientation::Horizontal => (
+            (inner_width as f64 * fill_ratio).round() as u32,
+            inner_height,
+        ),
+        ProgressBarOrientation::Vertical => (
+            inner_width,
+            (inner_height as f64 * fill_ratio).round() as u32,
+        ),
+    };
+    if fill_width > 0 && fill_height > 0 {
+        let fill_y = border_width + inner_height - fill_height;
+        imageproc::drawing::draw_filled_rect_mut(
+            &mut image,
+            Rect::at(border_width as i32, fill_y as i32).of_size(fill_width, fill_height),
+            fill_color,
+        );
+    }
+
+    if let Some(border_color) = &progress_bar_config.border_color {
+        imageproc::drawing::draw_hollow_rect_mut(
+            &mut image,
+            Rect::at(0, 0).of_size(width, height),
+            hex_to_rgba_or_default(border_color),
+        );
+    }
+
+    image
+}
+
+/// Returns the position of the sensor value within the value range, clamped between 0 and 1
+fn get_fill_ratio(progress_bar_config: &ProgressBarConfig, sensor_value: f64) -> f64 {
+    let value_range = progress_bar_config.max_sensor_value - progress_bar_config.min_sensor_value;
+    if value_range <= 0.0 || sensor_value.is_nan() {
+        return 0.0;
+    }
+
+    ((sensor_value - progress_bar_config.min_sensor_value) / value_range).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_values_outside_of_the_range_are_clamped() {
+        let fill_color = Rgba([0, 255, 0, 255]);
+        let background_color = Rgba([0, 0, 0, 255]);
+        let progress_bar_config = ProgressBarConfig {
+            min_sensor_value: 0.0,
+            max_sensor_value: 100.0,
+            width: 20,
+            height: 4,
+            fill_color: "#00FF00".to_string(),
+            background_color: "#000000".to_string(),
+            ..Default::default()
+        };
+
+        let below_min_image = render(&progress_bar_config, Some(-50.0));
+        let above_max_image = render(&progress_bar_config, Some(250.0));
+
+        assert!(below_min_image
+            .pixels()
+            .all(|pixel| *pixel == background_color));
+        assert!(above_max_image.pixels().all(|pixel| *pixel == fill_color));
+        assert_eq!(above_max_image.dimensions(), (20, 4));
+    }
+}
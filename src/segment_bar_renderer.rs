@@ -0,0 +1,156 @@
+use image::RgbaImage;
+use imageproc::rect::Rect;
+
+use crate::{hex_to_rgba_or_default, ProgressBarOrientation, SegmentBarConfig};
+
+/// Number of segments, if the segment bar has no segment count configured
+const DEFAULT_SEGMENT_COUNT: u32 = 10;
+
+/// Renders a segment bar based on the given config on a transparent image
+/// The segments share the length of the bar evenly, separated by the spacing. The number of lit
+/// segments is proportional to the sensor value within the value range, values outside are clamped.
+/// Without a sensor value all segments are off.
+pub fn render(segment_bar_config: &SegmentBarConfig, sensor_value: Option<f64>) -> RgbaImage {
+    let width = segment_bar_config.width;
+    let height = segment_bar_config.height;
+    let mut image = RgbaImage::new(width, height);
+
+    let segment_count = get_segment_count(segment_bar_config);
+    let lit_segment_count = sensor_value
+        .map(|value| get_lit_segment_count(segment_bar_config, value))
+        .unwrap_or(0);
+    let on_color = hex_to_rgba_or_default(&segment_bar_config.on_color);
+    let off_color = hex_to_rgba_or_default(&segment_bar_config.off_color);
+
+    // Horizontal bars light up from the left, vertical bars from the bottom
+    let length = match segment_bar_config.orientation {
+        ProgressBarOrientation::Horizontal => width,
+        ProgressBarOrientation::Vertical => height,
+    };
+    let spacing = segment_bar_config.spacing as f32;
+    let pitch = (length as f32 + spacing) / segment_count as f32;
+
+    for segment in 0..segment_count {
+        let start = (segment as f32 * pitch).round() as u32;
+        let end = ((segment + 1) as f32 * pitch - spacing).round() as u32;
+        if end <= start {
+            continue;
+        }
+
+        let segment_rect = match segment_bar_config.orientation {
+            ProgressBarOrientation::Horizontal => {
+                Rect::at(start as i32, 0).of_size(end - start, height.max(1))
+            }
+            ProgressBarOrientation::Vertical => {
+                Rect::at(0, (height - end) as i32).of_size(width.max(1), end - start)
+            }
+        };
+        let color = if segment < lit_segment_count {
+            on_color
+        } else {
+            off_color
+        };
+        imageproc::drawing::draw_filled_rect_mut(&mut image, segment_rect, color);
+    }
+
+    image
+}
+
+/// Returns the number of segments of the bar, defaults to 10 if not set
+pub fn get_segment_count(segment_bar_config: &SegmentBarConfig) -> u32 {
+    if segment_bar_config.segment_count > 0 {
+        segment_bar_config.segment_count
+    } else {
+        DEFAULT_SEGMENT_COUNT
+    }
+}
+
+/// Returns the number of lit segments for the sensor value
+/// The position of the value within the value range is rounded to the nearest segment.
+pub fn get_lit_segment_count(segment_bar_config: &SegmentBarConfig, sensor_value: f64) -> u32 {
+    let value_range = segment_bar_config.max_sensor_value - segment_bar_config.min_sensor_value;
+    if value_range <= 0.0 || sensor_value.is_nan() {
+        return 0;
+    }
+
+    let fill_ratio =
+        ((sensor_value - segment_bar_config.min_sensor_value) / value_range).clamp(0.0, 1.0);
+    (fill_ratio * get_segment_count(segment_bar_config) as f64).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const ON: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const OFF: Rgba<u8> = Rgba([40, 40, 40, 255]);
+
+    fn segment_bar_config(orientation: ProgressBarOrientation) -> SegmentBarConfig {
+        let (width, height) = match orientation {
+            ProgressBarOrientation::Horizontal => (100, 10),
+            ProgressBarOrientation::Vertical => (10, 100),
+        };
+        SegmentBarConfig {
+            min_sensor_value: 0.0,
+            max_sensor_value: 100.0,
+            width,
+            height,
+            segment_count: 10,
+            on_color: "#00FF00".to_string(),
+            off_color: "#282828".to_string(),
+            spacing: 2,
+            orientation,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the colors at the center of each segment, in the order the segments light up
+    fn segment_colors(image: &RgbaImage, orientation: &ProgressBarOrientation) -> Vec<Rgba<u8>> {
+        (0..10)
+            .map(|segment| (segment as f32 * 10.2).round() as u32 + 4)
+            .map(|position| match orientation {
+                ProgressBarOrientation::Horizontal => *image.get_pixel(position, 5),
+                ProgressBarOrientation::Vertical => *image.get_pixel(5, 99 - position),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lit_segments_match_the_normalized_value() {
+        for orientation in [
+            ProgressBarOrientation::Horizontal,
+            ProgressBarOrientation::Vertical,
+        ] {
+            let config = segment_bar_config(orientation.clone());
+
+            for (value, lit_segments) in [(0.0, 0), (60.0, 6), (64.0, 6), (100.0, 10), (150.0, 10)]
+            {
+                assert_eq!(get_lit_segment_count(&config, value), lit_segments);
+
+                let colors = segment_colors(&render(&config, Some(value)), &orientation);
+                let expected: Vec<Rgba<u8>> = (0..10)
+                    .map(|segment| if segment < lit_segments { ON } else { OFF })
+                    .collect();
+                assert_eq!(colors, expected, "{:?} at {}", orientation, value);
+            }
+
+            let colors = segment_colors(&render(&config, None), &orientation);
+            assert!(colors.iter().all(|color| *color == OFF));
+        }
+    }
+
+    #[test]
+    fn test_spacing_between_segments_stays_transparent() {
+        let image = render(
+            &segment_bar_config(ProgressBarOrientation::Horizontal),
+            Some(100.0),
+        );
+
+        // The first segment ends at 8, the second starts at 10
+        assert_eq!(image.get_pixel(7, 5), &ON);
+        assert_eq!(image.get_pixel(8, 5)[3], 0);
+        assert_eq!(image.get_pixel(9, 5)[3], 0);
+        assert_eq!(image.get_pixel(10, 5), &ON);
+    }
+}
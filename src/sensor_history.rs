@@ -0,0 +1,264 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{SensorType, SensorValue};
+
+/// Builds the sensor value history frame by frame, keeping at most the given number of entries
+/// The min, max and average of the numeric values of each sensor are maintained on every push,
+/// so looking them up does not require a pass over the whole history.
+pub struct SensorHistoryBuilder {
+    capacity: usize,
+    /// The entries of the history, the latest entry at the front
+    history: VecDeque<Vec<SensorValue>>,
+    /// The sequence number of the next pushed entry
+    next_sequence: u64,
+    aggregates: HashMap<String, SensorAggregates>,
+}
+
+impl SensorHistoryBuilder {
+    /// Creates a new history builder, keeping at most `capacity` entries
+    pub fn new(capacity: usize) -> Self {
+        SensorHistoryBuilder {
+            capacity,
+            history: VecDeque::with_capacity(capacity),
+            next_sequence: 0,
+            aggregates: HashMap::new(),
+        }
+    }
+
+    /// Adds the latest sensor values to the history
+    /// If the history is full, the oldest entry is evicted and removed from the aggregates
+    pub fn push(&mut self, sensor_values: Vec<SensorValue>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        for sensor_value in &sensor_values {
+            if sensor_value.sensor_type != SensorType::Number {
+                continue;
+            }
+            // NaN and infinite values would poison the sum and the extrema, so they are skipped
+            match sensor_value.value.parse::<f64>() {
+                Ok(number) if number.is_finite() => self
+                    .aggregates
+                    .entry(sensor_value.id.clone())
+                    .or_default()
+                    .push(sequence, number),
+                _ => continue,
+            }
+        }
+        self.history.push_front(sensor_values);
+
+        if self.history.len() > self.capacity {
+            self.history.pop_back();
+
+            // All values pushed before the oldest remaining entry are evicted
+            let window_start = sequence + 1 - self.capacity as u64;
+            for aggregates in self.aggregates.values_mut() {
+                aggregates.evict_before(window_start);
+            }
+            self.aggregates
+                .retain(|_, aggregates| !aggregates.is_empty());
+        }
+    }
+
+    /// Returns the sensor value history, the latest entry first
+    /// The entries are borrowed, they are only moved if the history wrapped around since the last call
+    pub fn history(&mut self) -> &[Vec<SensorValue>] {
+        self.history.make_contiguous()
+    }
+
+    /// Returns the number of entries in the history
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Returns true if the history has no entries
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// Returns the aggregates of the numeric values of the sensor within the history
+    /// Returns None if the history contains no numeric value for the sensor
+    pub fn aggregates(&self, sensor_id: &str) -> Option<&SensorAggregates> {
+        self.aggregates.get(sensor_id)
+    }
+}
+
+/// Running aggregates of the numeric values of a single sensor within a window
+/// The sum is updated on push and eviction, the extrema are kept in monotonic deques,
+/// so each value is added and removed at most once and every lookup is constant time.
+#[derive(Default)]
+pub struct SensorAggregates {
+    /// The values within the window with their sequence number, the oldest at the front
+    values: VecDeque<(u64, f64)>,
+    sum: f64,
+    /// Candidates for the minimum, increasing in value, the current minimum at the front
+    min_candidates: VecDeque<(u64, f64)>,
+    /// Candidates for the maximum, decreasing in value, the current maximum at the front
+    max_candidates: VecDeque<(u64, f64)>,
+}
+
+impl SensorAggregates {
+    /// Adds a value with the given sequence number, which has to be greater than all previous ones
+    fn push(&mut self, sequence: u64, value: f64) {
+        self.values.push_back((sequence, value));
+        self.sum += value;
+
+        // Values that are not smaller than the new one can never be the minimum again
+        while self
+            .min_candidates
+            .back()
+            .is_some_and(|(_, candidate)| *candidate >= value)
+        {
+            self.min_candidates.pop_back();
+        }
+        self.min_candidates.push_back((sequence, value));
+
+        // Values that are not greater than the new one can never be the maximum again
+        while self
+            .max_candidates
+            .back()
+            .is_some_and(|(_, candidate)| *candidate <= value)
+        {
+            self.max_candidates.pop_back();
+        }
+        self.max_candidates.push_back((sequence, value));
+    }
+
+    /// Removes all values with a sequence number lower than the given window start
+    fn evict_before(&mut self, window_start: u64) {
+        while let Some((sequence, value)) = self.values.front().copied() {
+            if sequence >= window_start {
+                break;
+            }
+            self.values.pop_front();
+            self.sum -= value;
+        }
+        while self
+            .min_candidates
+            .front()
+            .is_some_and(|(sequence, _)| *sequence < window_start)
+        {
+            self.min_candidates.pop_front();
+        }
+        while self
+            .max_candidates
+            .front()
+            .is_some_and(|(sequence, _)| *sequence < window_start)
+        {
+            self.max_candidates.pop_front();
+        }
+
+        // Prevent the running sum from drifting, once the window is empty
+        if self.values.is_empty() {
+            self.sum = 0.0;
+        }
+    }
+
+    /// Returns the number of values within the window
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if there are no values within the window
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the minimum value within the window
+    pub fn min(&self) -> Option<f64> {
+        self.min_candidates.front().map(|(_, value)| *value)
+    }
+
+    /// Returns the maximum value within the window
+    pub fn max(&self) -> Option<f64> {
+        self.max_candidates.front().map(|(_, value)| *value)
+    }
+
+    /// Returns the average of the values within the window
+    pub fn avg(&self) -> Option<f64> {
+        if self.values.is_empty() {
+            return None;
+        }
+        Some(self.sum / self.values.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number_value(sensor_id: &str, value: &str) -> SensorValue {
+        SensorValue {
+            id: sensor_id.to_string(),
+            value: value.to_string(),
+            sensor_type: SensorType::Number,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_aggregates_match_the_values_within_the_window() {
+        let values = [5.0, 3.0, 8.0, 1.0, 9.0, 9.0, 2.0, 7.0, 4.0, 6.0, 0.5, 3.5];
+        let capacity = 4;
+        let mut history_builder = SensorHistoryBuilder::new(capacity);
+
+        for (index, value) in values.iter().enumerate() {
+            history_builder.push(vec![number_value("cpu", &value.to_string())]);
+
+            let window = &values[(index + 1).saturating_sub(capacity)..=index];
+            let aggregates = history_builder.aggregates("cpu").unwrap();
+            assert_eq!(aggregates.len(), window.len());
+            assert_eq!(
+                aggregates.min(),
+                window.iter().copied().reduce(f64::min),
+                "min after push {}",
+                index
+            );
+            assert_eq!(
+                aggregates.max(),
+                window.iter().copied().reduce(f64::max),
+                "max after push {}",
+                index
+            );
+            let avg = window.iter().sum::<f64>() / window.len() as f64;
+            assert!((aggregates.avg().unwrap() - avg).abs() < 1e-9);
+        }
+        assert_eq!(history_builder.len(), capacity);
+    }
+
+    #[test]
+    fn test_non_finite_values_are_skipped() {
+        let mut history_builder = SensorHistoryBuilder::new(8);
+
+        for value in ["2", "NaN", "inf", "-inf", "4"] {
+            history_builder.push(vec![number_value("cpu", value)]);
+        }
+
+        let aggregates = history_builder.aggregates("cpu").unwrap();
+        assert_eq!(aggregates.len(), 2);
+        assert_eq!(aggregates.min(), Some(2.0));
+        assert_eq!(aggregates.max(), Some(4.0));
+        assert_eq!(aggregates.avg(), Some(3.0));
+        assert_eq!(history_builder.len(), 5);
+    }
+
+    #[test]
+    fn test_history_is_latest_entry_first() {
+        let mut history_builder = SensorHistoryBuilder::new(2);
+
+        for value in ["1", "2", "3"] {
+            history_builder.push(vec![number_value("cpu", value)]);
+        }
+
+        let values: Vec<&str> = history_builder
+            .history()
+            .iter()
+            .map(|sensor_values| sensor_values[0].value.as_str())
+            .collect();
+        assert_eq!(values, ["3", "2"]);
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use font_loader::system_fonts;
+
+/// Font family of the fonts data used by the tests
+pub const TEST_FONT_FAMILY: &str = "DejaVu Sans";
+
+/// Loads the test font from the system fonts
+pub fn load_font_data() -> Vec<u8> {
+    let property = system_fonts::FontPropertyBuilder::new()
+        .family(TEST_FONT_FAMILY)
+        .build();
+    let (font_data, _) = system_fonts::get(&property).expect("The test font is not installed");
+    font_data
+}
+
+/// Returns the fonts data containing the test font
+pub fn fonts_data() -> HashMap<String, Vec<u8>> {
+    HashMap::from([(TEST_FONT_FAMILY.to_string(), load_font_data())])
+}
+
+/// Loads the test font from the system fonts and parses it
+pub fn load_font() -> rusttype::Font<'static> {
+    rusttype::Font::try_from_vec(load_font_data()).expect("The test font can not be parsed")
+}
+
+/// Returns the bounds of the visible pixels of the image as (min x, min y, max x, max y), inclusive
+/// Returns None if no pixel is visible
+pub fn visible_bounds(image: &image::RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[3] > 0)
+        .fold(None, |bounds, (x, y, _)| {
+            Some(match bounds {
+                None => (x, y, x, y),
+                Some((min_x, min_y, max_x, max_y)) => {
+                    (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+                }
+            })
+        })
+}
+
+/// Returns true if the image has at least one pixel that is not fully transparent
+pub fn has_visible_pixels(image: &image::RgbaImage) -> bool {
+    image.pixels().any(|pixel| pixel[3] > 0)
+}
+
+/// Creates an empty temporary folder for the assets of a test, unique per test name and process
+pub fn temp_asset_dir(test_name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("sensor-core-{}-{}", test_name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("Failed to create the temporary asset dir");
+    dir
+}
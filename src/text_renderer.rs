@@ -1,42 +1,313 @@
-use image::{ImageBuffer, Rgba};
-use imageproc::drawing;
-use rusttype::Font;
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
-use crate::{hex_to_rgba, SensorType, SensorValue, SensorValueModifier, TextAlign, TextConfig};
+use image::{ImageBuffer, Rgba, RgbaImage};
+use rusttype::{point, Font, PositionedGlyph, Scale};
+
+use crate::{
+    hex_to_rgba_or_default, ConditionalTextConfig, ConditionalTextRange, HistoryAggregates,
+    RoundingMode, SensorType, SensorValue, SensorValueModifier, TextAlign, TextConfig,
+    TextOrientation, UnitConversion, VerticalAlign,
+};
 
 /// Renders the text element to a png image.
 /// Render Pipeline:
-///     1. Draw text on empty rgba buffer on display size
+///     1. Draw text on empty rgba buffer, sized to the measured text but at most the display size
 ///     2. Calculate bounding box of text
 ///     3. Crop buffer to the visible bounding box of the text
 ///     4. Create a new Image buffer in the size of the text element
-///     5. Overlay the text image on the new image buffer according to the text alignment
+///     5. Rotate the text image if the text is oriented vertically
+///     6. Overlay the text image on the new image buffer according to the horizontal and vertical text alignment
 pub fn render(
     image_width: u32,
     image_height: u32,
     text_config: &TextConfig,
     sensor_value_history: &[Vec<SensorValue>],
     font: &Font,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    render_text(
+        image_width,
+        image_height,
+        text_config,
+        sensor_value_history,
+        None,
+        font,
+        None,
+        &TextStyle::new(text_config),
+    )
+}
+
+/// Renders the text element to a png image, like `render`
+/// The glyphs are rasterized once and taken from the glyph cache on subsequent renders
+pub fn render_with_glyph_cache(
+    image_width: u32,
+    image_height: u32,
+    text_config: &TextConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    font: &Font,
+    glyph_cache: &GlyphCache,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    render_text(
+        image_width,
+        image_height,
+        text_config,
+        sensor_value_history,
+        None,
+        font,
+        Some(glyph_cache),
+        &TextStyle::new(text_config),
+    )
+}
+
+/// Renders the text element, like `render`
+/// The min, max and average placeholders are taken from the given aggregates of the sensor,
+/// instead of a pass over the history. If a glyph cache is given, the glyphs are taken from it.
+pub fn render_with_history_aggregates(
+    image_width: u32,
+    image_height: u32,
+    text_config: &TextConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    history_aggregates: Option<&HistoryAggregates>,
+    font: &Font,
+    glyph_cache: Option<&GlyphCache>,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    render_text(
+        image_width,
+        image_height,
+        text_config,
+        sensor_value_history,
+        history_aggregates,
+        font,
+        glyph_cache,
+        &TextStyle::new(text_config),
+    )
+}
+
+/// Renders the text element, like `render_with_history_aggregates`
+/// The rendered image is taken from the text render cache, if neither the config, the image size,
+/// the aggregates nor the inputs of the sensor of the element changed since it was rendered last,
+/// see `SensorKey`. The element id has to be unique for each text, that is rendered per frame.
+/// If a glyph cache is given, the glyphs are taken from it.
+#[allow(clippy::too_many_arguments)]
+pub fn render_with_cache(
+    element_id: &str,
+    (image_width, image_height): (u32, u32),
+    text_config: &TextConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    history_aggregates: Option<&HistoryAggregates>,
+    font: &Font,
+    glyph_cache: Option<&GlyphCache>,
+    text_render_cache: &TextRenderCache,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let sensor_key = SensorKey::new(text_config, sensor_value_history);
+
+    let style = match text_render_cache.lookup(
+        element_id,
+        text_config,
+        (image_width, image_height),
+        history_aggregates,
+        &sensor_key,
+    ) {
+        TextRenderLookup::Hit(text_image) => return text_image,
+        TextRenderLookup::Miss(style) => style,
+    };
+
+    let text_image = render_text(
+        image_width,
+        image_height,
+        text_config,
+        sensor_value_history,
+        history_aggregates,
+        font,
+        glyph_cache,
+        &style,
+    );
+    text_render_cache.insert(
+        element_id,
+        TextRenderEntry {
+            text_config: text_config.clone(),
+            image_size: (image_width, image_height),
+            style,
+            history_aggregates: history_aggregates.cloned(),
+            sensor_key,
+            text_image: text_image.clone(),
+        },
+    );
+
+    text_image
+}
+
+/// The inputs of the sensor, that affect the rendered text
+/// Only the latest value is shown, unless the text uses aggregates of the history. Then the
+/// history is taken into account by a fingerprint of the values, instead of a copy of it.
+/// The timestamps are left out, as they are not shown.
+#[derive(PartialEq)]
+struct SensorKey {
+    latest_value: Option<SensorValue>,
+    history_fingerprint: Option<u64>,
+}
+
+impl SensorKey {
+    fn new(text_config: &TextConfig, sensor_value_history: &[Vec<SensorValue>]) -> Self {
+        let sensor_id = text_config.sensor_id.as_str();
+        let latest_value =
+            get_latest_value(sensor_id, sensor_value_history).map(|sensor_value| SensorValue {
+                timestamp: None,
+                ..sensor_value
+            });
+        let uses_aggregates = text_config.format.contains("{value-")
+            || text_config.value_modifier != SensorValueModifier::None;
+        let history_fingerprint =
+            uses_aggregates.then(|| get_history_fingerprint(sensor_id, sensor_value_history));
+
+        SensorKey {
+            latest_value,
+            history_fingerprint,
+        }
+    }
+}
+
+/// Returns a hash of the values of the sensor in each entry of the history
+fn get_history_fingerprint(sensor_id: &str, sensor_value_history: &[Vec<SensorValue>]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for sensor_values in sensor_value_history {
+        match sensor_values
+            .iter()
+            .find(|sensor_value| sensor_value.id == sensor_id)
+        {
+            Some(sensor_value) => {
+                true.hash(&mut hasher);
+                sensor_value.value.hash(&mut hasher);
+                sensor_value.unit.hash(&mut hasher);
+                (sensor_value.sensor_type == SensorType::Number).hash(&mut hasher);
+            }
+            None => false.hash(&mut hasher),
+        }
+    }
+    hasher.finish()
+}
+
+/// The font scale and the parsed font color of a text element
+#[derive(Clone, Copy)]
+struct TextStyle {
+    font_scale: Scale,
+    font_color: Rgba<u8>,
+}
+
+impl TextStyle {
+    fn new(text_config: &TextConfig) -> Self {
+        TextStyle {
+            font_scale: Scale::uniform(text_config.font_size as f32),
+            font_color: hex_to_rgba_or_default(&text_config.font_color),
+        }
+    }
+}
+
+/// Renders the text element, if a glyph cache is given the glyphs are taken from it
+#[allow(clippy::too_many_arguments)]
+fn render_text(
+    image_width: u32,
+    image_height: u32,
+    text_config: &TextConfig,
+    sensor_value_history: &[Vec<SensorValue>],
+    history_aggregates: Option<&HistoryAggregates>,
+    font: &Font,
+    glyph_cache: Option<&GlyphCache>,
+    style: &TextStyle,
 ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     // Initialize image buffer
-    let font_scale = rusttype::Scale::uniform(text_config.font_size as f32);
-    let font_color: Rgba<u8> = hex_to_rgba(&text_config.font_color);
+    let font_scale = style.font_scale;
+    let font_color = style.font_color;
     let sensor_id = &text_config.sensor_id;
 
-    // Replace placeholders in text format
-    let text = replace_placeholders(text_config, sensor_id, sensor_value_history);
+    // Replace placeholders in text format and lay out the glyphs
+    // The history is converted and aggregated once, for all parts of the text
+    // If the unit is styled, it is laid out as a separate run between the text before and after it
+    // Unless the text is wrapped, each character of the text is laid out as one glyph
+    let max_line_width = match text_config.orientation {
+        TextOrientation::Horizontal => text_config.width,
+        TextOrientation::Vertical => text_config.height,
+    };
+    let sensor_value_history = convert_history(text_config, sensor_id, sensor_value_history);
+    let aggregates = SensorAggregates::new(
+        sensor_id,
+        &sensor_value_history,
+        text_config,
+        history_aggregates,
+    );
+    let (text, glyphs) = match (&text_config.unit_style, text_config.format.find("{unit}")) {
+        (Some(unit_style), Some(unit_index)) => {
+            let (format_before, format_after) = text_config.format.split_at(unit_index);
+            let format_after = &format_after["{unit}".len()..];
+            let text_before = replace_placeholders_with(format_before, &aggregates);
+            let unit = replace_placeholders_with("{unit}", &aggregates);
+            let text_after = replace_placeholders_with(format_after, &aggregates);
 
-    let mut image = image::RgbaImage::new(image_width, image_height);
+            let unit_font_scale = if unit_style.font_scale > 0.0 {
+                unit_style.font_scale
+            } else {
+                1.0
+            };
+            let runs = [
+                TextRun::new(&text_before, font_scale, 0.0),
+                TextRun::new(
+                    &unit,
+                    Scale::uniform(text_config.font_size as f32 * unit_font_scale),
+                    text_config.font_size as f32 * unit_style.baseline_offset,
+                ),
+                TextRun::new(&text_after, font_scale, 0.0),
+            ];
+            if text_config.wrap {
+                let glyphs =
+                    layout_wrapped_runs(font, &runs, max_line_width, &text_config.alignment);
+                (None, glyphs)
+            } else {
+                let glyphs = layout_runs(font, &runs, true);
+                (Some(text_before + &unit + &text_after), glyphs)
+            }
+        }
+        _ => {
+            let text = replace_placeholders_with(&text_config.format, &aggregates);
+            if text_config.wrap {
+                let glyphs = layout_wrapped_glyphs(
+                    font,
+                    font_scale,
+                    &text,
+                    max_line_width,
+                    &text_config.alignment,
+                );
+                (None, glyphs)
+            } else {
+                let glyphs = layout_glyphs(font, font_scale, &text, true);
+                (Some(text), glyphs)
+            }
+        }
+    };
 
-    // 1. Draw text on empty rgba buffer on display size
-    drawing::draw_text_mut(
+    // 1. Draw text on empty rgba buffer, sized to the measured text but at most the display size
+    // Glyphs reaching left of the caret or above the line are shifted into the buffer,
+    // so no glyph is clipped. Anything beyond the display size is clipped.
+    let (text_width, text_height) = measure_glyphs(&glyphs);
+    let (origin_x, origin_y) = get_glyphs_origin(&glyphs);
+    let draw_x = TEXT_BUFFER_PADDING - origin_x;
+    let draw_y = TEXT_BUFFER_PADDING - origin_y;
+    let mut image = image::RgbaImage::new(
+        ((draw_x + TEXT_BUFFER_PADDING) as u32 + text_width).min(image_width),
+        ((draw_y + TEXT_BUFFER_PADDING) as u32 + text_height).min(image_height),
+    );
+    draw_glyphs(
         &mut image,
         font_color,
-        25,
-        7,
-        font_scale,
-        font,
-        text.as_str(),
+        draw_x,
+        draw_y,
+        &glyphs,
+        !text_config.aliased,
+        glyph_cache.map(|glyph_cache| (glyph_cache, text_config.font_family.as_str())),
     );
 
     // 2. Calculate bounding box of text
@@ -55,72 +326,819 @@ pub fn render(
     // 4. Create a new Image buffer in the size of the text element
     let mut image = image::RgbaImage::new(text_config.width, text_config.height);
 
-    // 5. Overlay the text image on the new image buffer according to the text alignment
-    // Center text vertically
-    let y: u32 = if text_config.height > text_image.height() {
-        (text_config.height - text_image.height()) / 2
-    } else {
-        0
+    // 5. Rotate the text image if the text is oriented vertically
+    // Vertical text runs from bottom to top
+    let text_image = match text_config.orientation {
+        TextOrientation::Horizontal => text_image,
+        TextOrientation::Vertical => image::imageops::rotate270(&text_image),
     };
-    let x: u32 = if text_config.width > text_image.width() {
-        text_config.width - text_image.width()
-    } else {
-        0
+
+    // 6. Overlay the text image on the new image buffer according to the horizontal and vertical text alignment
+    let free_width = text_config.width.saturating_sub(text_image.width());
+    let free_height = text_config.height.saturating_sub(text_image.height());
+    let (x, y): (u32, u32) = match text_config.orientation {
+        // Align text horizontally and vertically
+        TextOrientation::Horizontal => {
+            let y = match text_config.vertical_alignment {
+                VerticalAlign::Top => 0,
+                VerticalAlign::Middle => free_height / 2,
+                VerticalAlign::Bottom => free_height,
+            };
+            match text_config.alignment {
+                TextAlign::Left => (0, y),
+                TextAlign::Center => (free_width / 2, y),
+                TextAlign::Right => (free_width, y),
+            }
+        }
+        // Align vertically, the text starts at the bottom
+        // The top of the rotated text faces left, so the vertical alignment places it horizontally
+        TextOrientation::Vertical => {
+            let x = match text_config.vertical_alignment {
+                VerticalAlign::Top => 0,
+                VerticalAlign::Middle => free_width / 2,
+                VerticalAlign::Bottom => free_width,
+            };
+            match text_config.alignment {
+                TextAlign::Left => (x, free_height),
+                TextAlign::Center => (x, free_height / 2),
+                TextAlign::Right => (x, 0),
+            }
+        }
+    };
+
+    // Align the decimal point of the first number at the configured position instead, if any
+    let decimal_point_caret = text
+        .as_deref()
+        .and_then(|text| get_decimal_point_caret(text, &glyphs));
+    let x = match (
+        &text_config.orientation,
+        text_config.decimal_point_x,
+        decimal_point_caret,
+    ) {
+        (TextOrientation::Horizontal, Some(decimal_point_x), Some(decimal_point_caret)) => {
+            let caret_in_text_image = (draw_x as f32 + decimal_point_caret).round() as i64
+                - text_bounding_box.left() as i64;
+            decimal_point_x as i64 - caret_in_text_image
+        }
+        _ => x as i64,
+    };
+    image::imageops::overlay(&mut image, &text_image, x, y as i64);
+
+    image
+}
+
+/// Returns the horizontal caret position of the decimal point of the first number in the text
+/// Numbers without a decimal point are aligned at their end, so the position after the last digit is returned.
+/// Each character of the text has to be laid out as one glyph. Returns None if the text has no number.
+fn get_decimal_point_caret(text: &str, glyphs: &[PositionedGlyph]) -> Option<f32> {
+    let characters: Vec<char> = text.chars().collect();
+    if characters.len() != glyphs.len() {
+        return None;
+    }
+
+    let first_digit = characters.iter().position(char::is_ascii_digit)?;
+    let integer_end = characters[first_digit..]
+        .iter()
+        .position(|character| !character.is_ascii_digit())
+        .map_or(characters.len(), |length| first_digit + length);
+
+    // The caret is left of the decimal point, or right of the last digit of an integer
+    match glyphs.get(integer_end) {
+        Some(glyph) => Some(glyph.position().x),
+        None => {
+            let last_digit = &glyphs[integer_end - 1];
+            Some(last_digit.position().x + last_digit.unpositioned().h_metrics().advance_width)
+        }
+    }
+}
+
+/// Represents a run of text, laid out with its own scale on a shifted baseline
+pub struct TextRun<'t> {
+    pub text: &'t str,
+    pub scale: Scale,
+    /// Shift of the baseline in pixels, positive values raise the run
+    pub baseline_shift: f32,
+}
+
+impl<'t> TextRun<'t> {
+    pub fn new(text: &'t str, scale: Scale, baseline_shift: f32) -> Self {
+        TextRun {
+            text,
+            scale,
+            baseline_shift,
+        }
+    }
+}
+
+/// Lays out the given text glyph by glyph in a single line
+/// The glyphs are positioned relative to the top left corner of the line
+/// If kerning is enabled, the caret is adjusted by the kerning of each glyph pair
+pub fn layout_glyphs<'a>(
+    font: &Font<'a>,
+    scale: Scale,
+    text: &str,
+    kerning: bool,
+) -> Vec<PositionedGlyph<'a>> {
+    layout_runs(font, &[TextRun::new(text, scale, 0.0)], kerning)
+}
+
+/// Lays out the given text in multiple lines, each at most as wide as the given width
+/// Lines are broken at explicit line breaks and between words, a word wider than the line keeps its own line.
+/// The lines are stacked using the line height of the font and aligned within the widest line.
+pub fn layout_wrapped_glyphs<'a>(
+    font: &Font<'a>,
+    scale: Scale,
+    text: &str,
+    max_line_width: u32,
+    alignment: &TextAlign,
+) -> Vec<PositionedGlyph<'a>> {
+    layout_wrapped_runs(
+        font,
+        &[TextRun::new(text, scale, 0.0)],
+        max_line_width,
+        alignment,
+    )
+}
+
+/// A part of a line, that is laid out with the scale and baseline shift of the run at the index
+type RunPiece = (usize, String);
+
+/// Lays out the given text runs in multiple lines, like `layout_wrapped_glyphs`
+/// A word can span several runs, e.g. a value directly followed by its styled unit.
+/// The lines are stacked using the line height of the tallest run.
+pub fn layout_wrapped_runs<'a>(
+    font: &Font<'a>,
+    runs: &[TextRun],
+    max_line_width: u32,
+    alignment: &TextAlign,
+) -> Vec<PositionedGlyph<'a>> {
+    let line_height = runs
+        .iter()
+        .map(|run| {
+            let v_metrics = font.v_metrics(run.scale);
+            v_metrics.ascent - v_metrics.descent + v_metrics.line_gap + run.baseline_shift.abs()
+        })
+        .fold(0.0, f32::max);
+
+    // Split the runs into paragraphs at explicit line breaks and each paragraph into words
+    let mut paragraphs: Vec<Vec<Vec<RunPiece>>> = vec![];
+    let mut paragraph = vec![];
+    let mut word = vec![];
+    for (run_index, run) in runs.iter().enumerate() {
+        for character in run.text.chars() {
+            if !character.is_whitespace() {
+                push_piece(&mut word, run_index, character.encode_utf8(&mut [0; 4]));
+                continue;
+            }
+            if !word.is_empty() {
+                paragraph.push(std::mem::take(&mut word));
+            }
+            if character == '\n' {
+                paragraphs.push(std::mem::take(&mut paragraph));
+            }
+        }
+    }
+    if !word.is_empty() {
+        paragraph.push(word);
+    }
+    paragraphs.push(paragraph);
+
+    let layout_line = |line: &[RunPiece]| {
+        let line_runs: Vec<TextRun> = line
+            .iter()
+            .map(|(run_index, text)| {
+                TextRun::new(
+                    text,
+                    runs[*run_index].scale,
+                    runs[*run_index].baseline_shift,
+                )
+            })
+            .collect();
+        let glyphs = layout_runs(font, &line_runs, true);
+        let line_width = measure_glyphs(&glyphs).0;
+        (glyphs, line_width)
     };
-    match text_config.alignment {
-        TextAlign::Left => {
-            image::imageops::overlay(&mut image, &text_image, 0, y as i64);
+
+    // Greedily fill each line with as many words as fit
+    let mut lines: Vec<Vec<RunPiece>> = vec![];
+    for paragraph in paragraphs {
+        let mut line = vec![];
+        for word in paragraph {
+            let mut candidate = line.clone();
+            if let Some(&(run_index, _)) = line.last() {
+                push_piece(&mut candidate, run_index, " ");
+            }
+            for (run_index, text) in &word {
+                push_piece(&mut candidate, *run_index, text);
+            }
+            if !line.is_empty() && layout_line(&candidate).1 > max_line_width {
+                lines.push(std::mem::replace(&mut line, word));
+            } else {
+                line = candidate;
+            }
+        }
+        lines.push(line);
+    }
+
+    let laid_out_lines: Vec<(Vec<PositionedGlyph>, u32)> =
+        lines.iter().map(|line| layout_line(line)).collect();
+    let block_width = laid_out_lines
+        .iter()
+        .map(|(_, line_width)| *line_width)
+        .max()
+        .unwrap_or(0);
+
+    // Move each line down by its line index and align it within the widest line
+    let mut glyphs = vec![];
+    for (line_index, (line_glyphs, line_width)) in laid_out_lines.into_iter().enumerate() {
+        let free_width = (block_width - line_width) as f32;
+        let offset_x = match alignment {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => (free_width / 2.0).floor(),
+            TextAlign::Right => free_width,
+        };
+        let offset_y = (line_index as f32 * line_height).round();
+        glyphs.extend(line_glyphs.into_iter().map(|glyph| {
+            let position = glyph.position();
+            glyph
+                .into_unpositioned()
+                .positioned(point(position.x + offset_x, position.y + offset_y))
+        }));
+    }
+
+    glyphs
+}
+
+/// Appends the text to the line, merging it into the last piece if it belongs to the same run
+fn push_piece(line: &mut Vec<RunPiece>, run_index: usize, text: &str) {
+    match line.last_mut() {
+        Some((last_run_index, piece)) if *last_run_index == run_index => piece.push_str(text),
+        _ => line.push((run_index, text.to_string())),
+    }
+}
+
+/// Lays out the given text runs one after another in a single line
+/// All runs share a common baseline, shifted per run, which is placed so no run exceeds the top
+/// If kerning is enabled, the caret is adjusted by the kerning of each glyph pair within a run
+pub fn layout_runs<'a>(
+    font: &Font<'a>,
+    runs: &[TextRun],
+    kerning: bool,
+) -> Vec<PositionedGlyph<'a>> {
+    let baseline = runs
+        .iter()
+        .map(|run| font.v_metrics(run.scale).ascent + run.baseline_shift)
+        .fold(0.0, f32::max);
+    let mut caret = 0.0;
+    let mut glyphs = vec![];
+
+    for run in runs {
+        let mut last_glyph_id = None;
+        for character in run.text.chars() {
+            let glyph = font.glyph(character).scaled(run.scale);
+            if let (true, Some(last_glyph_id)) = (kerning, last_glyph_id) {
+                caret += font.pair_kerning(run.scale, last_glyph_id, glyph.id());
+            }
+            last_glyph_id = Some(glyph.id());
+
+            let advance_width = glyph.h_metrics().advance_width;
+            glyphs.push(glyph.positioned(point(caret, baseline - run.baseline_shift)));
+            caret += advance_width;
+        }
+    }
+
+    glyphs
+}
+
+/// Returns the width of the given text in pixels, when rendered in a single line
+pub fn get_text_width(font: &Font, scale: Scale, text: &str) -> u32 {
+    measure_text(font, scale, text).0
+}
+
+/// Measures the extent of the given text in pixels, when rendered in a single line
+/// Returns the right and bottom edge of all glyphs, relative to the top left corner of the line
+pub fn measure_text(font: &Font, scale: Scale, text: &str) -> (u32, u32) {
+    measure_glyphs(&layout_glyphs(font, scale, text, true))
+}
+
+/// Padding around the text in the buffer it is drawn on, before it is cropped to its bounding box
+const TEXT_BUFFER_PADDING: i32 = 1;
+
+/// Returns the left and top edge of all glyphs, relative to the top left corner of the line
+/// The edges are negative if glyphs reach left of the caret or above the line, otherwise zero
+fn get_glyphs_origin(glyphs: &[PositionedGlyph]) -> (i32, i32) {
+    glyphs
+        .iter()
+        .filter_map(|glyph| glyph.pixel_bounding_box())
+        .fold((0, 0), |(left, top), bounding_box| {
+            (left.min(bounding_box.min.x), top.min(bounding_box.min.y))
+        })
+}
+
+/// Measures the extent of the given laid out glyphs in pixels
+/// Returns the right and bottom edge of all glyphs, relative to the top left corner of the line
+fn measure_glyphs(glyphs: &[PositionedGlyph]) -> (u32, u32) {
+    glyphs
+        .iter()
+        .filter_map(|glyph| glyph.pixel_bounding_box())
+        .fold((0, 0), |(width, height), bounding_box| {
+            (
+                width.max(bounding_box.max.x.max(0) as u32),
+                height.max(bounding_box.max.y.max(0) as u32),
+            )
+        })
+}
+
+/// Draws the given text on the image, positioned at the given coordinates
+/// Each pixel is blended with the font color weighted by the glyph coverage
+pub(crate) fn draw_text_mut(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    color: Rgba<u8>,
+    x: i32,
+    y: i32,
+    scale: Scale,
+    font: &Font,
+    text: &str,
+) {
+    draw_glyphs(
+        image,
+        color,
+        x,
+        y,
+        &layout_glyphs(font, scale, text, true),
+        true,
+        None,
+    );
+}
+
+/// Draws the given laid out glyphs on the image, positioned at the given coordinates
+/// Each pixel is blended with the font color weighted by the glyph coverage
+/// If a glyph cache and the font family are given, the coverage is taken from the cache
+fn draw_glyphs(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    color: Rgba<u8>,
+    x: i32,
+    y: i32,
+    glyphs: &[PositionedGlyph],
+    antialias: bool,
+    glyph_cache: Option<(&GlyphCache, &str)>,
+) {
+    let image_width = image.width() as i32;
+    let image_height = image.height() as i32;
+
+    for glyph in glyphs {
+        let bounding_box = match glyph.pixel_bounding_box() {
+            Some(bounding_box) => bounding_box,
+            None => continue,
+        };
+
+        let mut draw_pixel = |glyph_x: u32, glyph_y: u32, coverage: f32| {
+            // Without anti-aliasing, pixels are either fully covered or not at all
+            let coverage = match antialias {
+                true => coverage,
+                false if coverage >= 0.5 => 1.0,
+                false => 0.0,
+            };
+            let image_x = glyph_x as i32 + bounding_box.min.x + x;
+            let image_y = glyph_y as i32 + bounding_box.min.y + y;
+
+            if (0..image_width).contains(&image_x) && (0..image_height).contains(&image_y) {
+                let pixel = image.get_pixel_mut(image_x as u32, image_y as u32);
+                for (channel, color_channel) in pixel.0.iter_mut().zip(color.0) {
+                    *channel = (*channel as f32 * (1.0 - coverage)
+                        + color_channel as f32 * coverage)
+                        .round()
+                        .clamp(0.0, 255.0) as u8;
+                }
+            }
+        };
+
+        match glyph_cache {
+            Some((glyph_cache, font_family)) => {
+                let bitmap = glyph_cache.get_or_rasterize(font_family, glyph);
+                for (index, coverage) in bitmap.coverage.iter().enumerate() {
+                    let index = index as u32;
+                    draw_pixel(index % bitmap.width, index / bitmap.width, *coverage);
+                }
+            }
+            None => glyph.draw(draw_pixel),
+        }
+    }
+}
+
+/// The maximum number of glyphs in a glyph cache, before it is cleared
+const MAX_CACHED_GLYPHS: usize = 4096;
+
+/// Identifies a rasterized glyph by its font family, glyph id, scale and sub-pixel position
+/// The sub-pixel position is part of the key, so cached glyphs are identical to freshly rasterized ones
+#[derive(PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font_family: String,
+    glyph_id: u16,
+    scale: (u32, u32),
+    sub_pixel_position: (u32, u32),
+}
+
+/// Represents the coverage of a rasterized glyph within its pixel bounding box, row by row
+struct GlyphBitmap {
+    width: u32,
+    coverage: Vec<f32>,
+}
+
+/// Maximum number of text elements in the text render cache
+const MAX_CACHED_TEXTS: usize = 256;
+
+/// Caches the rendered images of text elements by element id, so unchanged texts are not rendered again
+/// If the font data of a font family changes, the cache has to be cleared.
+/// Once the cache is full, it is cleared and populated again.
+#[derive(Default)]
+pub struct TextRenderCache {
+    entries: Mutex<HashMap<String, TextRenderEntry>>,
+    render_count: AtomicUsize,
+}
+
+/// The last rendered image of a text element, with everything it was rendered from
+struct TextRenderEntry {
+    text_config: TextConfig,
+    image_size: (u32, u32),
+    style: TextStyle,
+    history_aggregates: Option<HistoryAggregates>,
+    sensor_key: SensorKey,
+    text_image: RgbaImage,
+}
+
+/// The result of looking up a text element in the text render cache
+enum TextRenderLookup {
+    Hit(RgbaImage),
+    Miss(TextStyle),
+}
+
+impl TextRenderCache {
+    /// Creates a new, empty text render cache
+    pub fn new() -> Self {
+        TextRenderCache::default()
+    }
+
+    /// Returns the number of cached text elements
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .len()
+    }
+
+    /// Returns true if no text elements are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns how often a text was rendered, because it was not cached or changed
+    pub fn render_count(&self) -> usize {
+        self.render_count.load(Ordering::Relaxed)
+    }
+
+    /// Removes all rendered texts from the cache
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+    }
+
+    /// Returns the cached image of the element, if it was rendered from the same inputs
+    /// Otherwise returns the style to render it with. The style only depends on the config,
+    /// so it is kept if only the sensor values changed.
+    fn lookup(
+        &self,
+        element_id: &str,
+        text_config: &TextConfig,
+        image_size: (u32, u32),
+        history_aggregates: Option<&HistoryAggregates>,
+        sensor_key: &SensorKey,
+    ) -> TextRenderLookup {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        match entries.get(element_id) {
+            Some(entry) if entry.text_config == *text_config && entry.image_size == image_size => {
+                if entry.history_aggregates.as_ref() == history_aggregates
+                    && entry.sensor_key == *sensor_key
+                {
+                    TextRenderLookup::Hit(entry.text_image.clone())
+                } else {
+                    TextRenderLookup::Miss(entry.style)
+                }
+            }
+            _ => TextRenderLookup::Miss(TextStyle::new(text_config)),
         }
-        TextAlign::Center => {
-            let x = x / 2;
-            image::imageops::overlay(&mut image, &text_image, x as i64, y as i64);
+    }
+
+    /// Caches the rendered entry of the element
+    fn insert(&self, element_id: &str, entry: TextRenderEntry) {
+        self.render_count.fetch_add(1, Ordering::Relaxed);
+
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        if entries.len() >= MAX_CACHED_TEXTS && !entries.contains_key(element_id) {
+            entries.clear();
         }
-        TextAlign::Right => {
-            image::imageops::overlay(&mut image, &text_image, x as i64, y as i64);
+        entries.insert(element_id.to_string(), entry);
+    }
+}
+
+/// Caches rasterized glyphs by font family, size and glyph, so repeated texts are not rasterized again
+/// The color is applied when the glyphs are drawn. If the font data of a font family changes,
+/// the cache has to be cleared. Once the cache is full, it is cleared and populated again.
+#[derive(Default)]
+pub struct GlyphCache {
+    glyphs: Mutex<HashMap<GlyphKey, Arc<GlyphBitmap>>>,
+}
+
+impl GlyphCache {
+    /// Creates a new, empty glyph cache
+    pub fn new() -> Self {
+        GlyphCache::default()
+    }
+
+    /// Returns the number of cached glyphs
+    pub fn len(&self) -> usize {
+        self.glyphs
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .len()
+    }
+
+    /// Returns true if no glyphs are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all rasterized glyphs from the cache
+    pub fn clear(&self) {
+        self.glyphs
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+    }
+
+    /// Returns the rasterized glyph, rasterizing and caching it on first use
+    fn get_or_rasterize(&self, font_family: &str, glyph: &PositionedGlyph) -> Arc<GlyphBitmap> {
+        let position = glyph.position();
+        let scale = glyph.scale();
+        let key = GlyphKey {
+            font_family: font_family.to_string(),
+            glyph_id: glyph.id().0,
+            scale: (scale.x.to_bits(), scale.y.to_bits()),
+            sub_pixel_position: (position.x.fract().to_bits(), position.y.fract().to_bits()),
+        };
+
+        let mut glyphs = self.glyphs.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(bitmap) = glyphs.get(&key) {
+            return bitmap.clone();
         }
+
+        let (width, height) = glyph
+            .pixel_bounding_box()
+            .map(|bounding_box| (bounding_box.width() as u32, bounding_box.height() as u32))
+            .unwrap_or((0, 0));
+        let mut coverage = vec![0.0; (width * height) as usize];
+        glyph.draw(|glyph_x, glyph_y, glyph_coverage| {
+            coverage[(glyph_y * width + glyph_x) as usize] = glyph_coverage;
+        });
+        let bitmap = Arc::new(GlyphBitmap { width, coverage });
+
+        if glyphs.len() >= MAX_CACHED_GLYPHS {
+            glyphs.clear();
+        }
+        glyphs.insert(key, bitmap.clone());
+        bitmap
     }
+}
+
+/// Rasterizes a single glyph, e.g. an icon of an icon font, tightly cropped to its bounds
+/// The glyph is drawn in the given color, its coverage is stored in the alpha channel
+/// Returns an empty image if the glyph has no visible outline, e.g. for a space
+pub fn render_glyph(codepoint: char, font: &Font, size: u32, color: Rgba<u8>) -> RgbaImage {
+    let glyph = font
+        .glyph(codepoint)
+        .scaled(Scale::uniform(size as f32))
+        .positioned(point(0.0, 0.0));
+    let bounding_box = match glyph.pixel_bounding_box() {
+        Some(bounding_box) => bounding_box,
+        None => return RgbaImage::new(0, 0),
+    };
+
+    let mut image = RgbaImage::new(bounding_box.width() as u32, bounding_box.height() as u32);
+    glyph.draw(|glyph_x, glyph_y, coverage| {
+        let mut pixel_color = color;
+        pixel_color[3] = (color[3] as f32 * coverage).round().clamp(0.0, 255.0) as u8;
+        image.put_pixel(glyph_x, glyph_y, pixel_color);
+    });
 
     image
 }
 
-/// Replaces the placeholders in the text format with the actual values
-/// FIXME: The special placeholders like {value-avg} may be calculated multiple times
-///        This is not a problem for now because 95% of the time they are not or rarely used
-///        But if we encounter performance issues, we should optimize this (Esp. if the history is long)
-fn replace_placeholders(
+/// All placeholders, that are supported in text formats
+const SUPPORTED_PLACEHOLDERS: &[&str] = &[
+    "{value}",
+    "{value-avg}",
+    "{value-min}",
+    "{value-max}",
+    "{value-median}",
+    "{value-stddev}",
+    "{unit}",
+    "{label}",
+];
+
+/// Placeholders of numbers, that support a precision spec
+/// The grammar is `{name:.N}`, where N are the decimals, e.g. `{value:.1}` or `{value-avg:.0}`
+const PRECISION_PLACEHOLDERS: &[&str] = &[
+    "value",
+    "value-avg",
+    "value-min",
+    "value-max",
+    "value-median",
+    "value-stddev",
+];
+
+/// The maximum number of decimals of a precision spec
+const MAX_PRECISION: u32 = 15;
+
+/// Returns all placeholders, that are supported in text formats
+/// Numeric placeholders additionally support a precision spec, e.g. `{value:.1}`
+pub fn supported_placeholders() -> &'static [&'static str] {
+    SUPPORTED_PLACEHOLDERS
+}
+
+/// Validates the placeholders in the given text format
+/// Returns all placeholder tokens, that are not supported, in order of their occurrence
+pub fn validate_format(format: &str) -> Vec<String> {
+    let mut unknown_placeholders = vec![];
+    let mut remaining = format;
+
+    while let Some(start) = remaining.find('{') {
+        let end = match remaining[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let placeholder = &remaining[start..=end];
+        if !SUPPORTED_PLACEHOLDERS.contains(&placeholder)
+            && parse_precision_placeholder(placeholder).is_none()
+        {
+            unknown_placeholders.push(placeholder.to_string());
+        }
+        remaining = &remaining[end + 1..];
+    }
+
+    unknown_placeholders
+}
+
+/// Parses a placeholder with a precision spec, e.g. `{value-avg:.1}`, into its name and decimals
+/// Returns None if the placeholder has no precision spec or does not support one
+fn parse_precision_placeholder(placeholder: &str) -> Option<(&str, u32)> {
+    let (name, precision) = placeholder
+        .strip_prefix('{')?
+        .strip_suffix('}')?
+        .split_once(":.")?;
+    if !PRECISION_PLACEHOLDERS.contains(&name)
+        || precision.is_empty()
+        || !precision.bytes().all(|digit| digit.is_ascii_digit())
+    {
+        return None;
+    }
+    let decimals = precision.parse().ok()?;
+    (decimals <= MAX_PRECISION).then_some((name, decimals))
+}
+
+/// Replaces the placeholders with a precision spec, e.g. `{value:.1}`, with the formatted values
+/// Values of non-numeric sensors are inserted unchanged, placeholders without a value show N/A
+fn replace_precision_placeholders(
     text_config: &TextConfig,
+    text_format: &str,
     sensor_id: &str,
     sensor_value_history: &[Vec<SensorValue>],
+    aggregates: &SensorAggregates,
 ) -> String {
-    let mut text_format = text_config.format.clone();
+    let format_decimals = |number: Option<f64>, decimals: u32| match number {
+        Some(number) => format_decimals(number, decimals, &text_config.rounding_mode),
+        None => "N/A".to_string(),
+    };
+
+    let mut text = String::with_capacity(text_format.len());
+    let mut remaining = text_format;
+    while let Some(start) = remaining.find('{') {
+        let end = match remaining[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        text.push_str(&remaining[..start]);
+
+        let placeholder = &remaining[start..=end];
+        match parse_precision_placeholder(placeholder) {
+            Some((name, decimals)) => {
+                let value = match (name, &text_config.value_modifier) {
+                    ("value", SensorValueModifier::None) => {
+                        match get_latest_value(sensor_id, sensor_value_history) {
+                            Some(sensor_value)
+                                if sensor_value.sensor_type == SensorType::Number =>
+                            {
+                                match sensor_value.value.parse::<f64>() {
+                                    Ok(number) => format_decimals(Some(number), decimals),
+                                    Err(_) => sensor_value.value,
+                                }
+                            }
+                            Some(sensor_value) => sensor_value.value,
+                            None => "N/A".to_string(),
+                        }
+                    }
+                    ("value", SensorValueModifier::Avg) | ("value-avg", _) => {
+                        format_decimals(aggregates.number_avg(), decimals)
+                    }
+                    ("value", SensorValueModifier::Min) | ("value-min", _) => {
+                        format_decimals(aggregates.number_min(), decimals)
+                    }
+                    ("value-median", _) => {
+                        format_decimals(get_number_median(aggregates.numbers()), decimals)
+                    }
+                    ("value-stddev", _) => {
+                        format_decimals(get_number_stddev(aggregates.numbers()), decimals)
+                    }
+                    _ => format_decimals(aggregates.number_max(), decimals),
+                };
+                text.push_str(&value);
+            }
+            None => text.push_str(placeholder),
+        }
+        remaining = &remaining[end + 1..];
+    }
+    text.push_str(remaining);
+
+    text
+}
+
+/// Returns the history with the values of the sensor converted to the target unit, if a unit
+/// conversion is configured, see `convert_sensor_values`
+fn convert_history<'h>(
+    text_config: &TextConfig,
+    sensor_id: &str,
+    sensor_value_history: &'h [Vec<SensorValue>],
+) -> Cow<'h, [Vec<SensorValue>]> {
+    match text_config.unit_conversion {
+        UnitConversion::None => Cow::Borrowed(sensor_value_history),
+        ref unit_conversion => Cow::Owned(convert_sensor_values(
+            sensor_id,
+            sensor_value_history,
+            unit_conversion,
+        )),
+    }
+}
+
+/// Replaces the placeholders in the text format with the actual values
+/// The numeric history is parsed once and each aggregate is computed at most once,
+/// no matter how often it is referenced in the text format. The aggregates can be shared
+/// by several parts of a text, so the history is parsed once for all of them.
+/// Numeric placeholders with a precision spec, e.g. `{value:.1}`, are shown with the given decimals
+/// The history of the aggregates has to be converted to the target unit already, see `convert_history`
+fn replace_placeholders_with(text_format: &str, aggregates: &SensorAggregates) -> String {
+    let text_config = aggregates.text_config;
+    let sensor_id = aggregates.sensor_id;
+    let sensor_value_history = aggregates.sensor_value_history;
+    let mut text_format = if text_format.contains(":.") {
+        replace_precision_placeholders(
+            text_config,
+            text_format,
+            sensor_id,
+            sensor_value_history,
+            aggregates,
+        )
+    } else {
+        text_format.to_string()
+    };
 
     if text_format.contains("{value-avg}") {
-        text_format = text_format.replace(
-            "{value-avg}",
-            get_value_avg(sensor_id, sensor_value_history).as_str(),
-        );
+        text_format = text_format.replace("{value-avg}", aggregates.avg());
     }
 
     if text_format.contains("{value-min}") {
-        text_format = text_format.replace(
-            "{value-min}",
-            get_value_min(sensor_id, sensor_value_history).as_str(),
-        );
+        text_format = text_format.replace("{value-min}", aggregates.min());
     }
 
     if text_format.contains("{value-max}") {
-        text_format = text_format.replace(
-            "{value-max}",
-            get_value_max(sensor_id, sensor_value_history).as_str(),
-        );
+        text_format = text_format.replace("{value-max}", aggregates.max());
+    }
+
+    if text_format.contains("{value-median}") {
+        text_format = text_format.replace("{value-median}", aggregates.median());
+    }
+
+    if text_format.contains("{value-stddev}") {
+        text_format = text_format.replace("{value-stddev}", aggregates.stddev());
     }
 
     if text_format.contains("{value}") {
         let value = match text_config.value_modifier {
-            SensorValueModifier::None => get_value(sensor_id, sensor_value_history),
-            SensorValueModifier::Avg => get_value_avg(sensor_id, sensor_value_history),
-            SensorValueModifier::Max => get_value_max(sensor_id, sensor_value_history),
-            SensorValueModifier::Min => get_value_min(sensor_id, sensor_value_history),
+            SensorValueModifier::None => get_value(sensor_id, sensor_value_history, text_config),
+            SensorValueModifier::Avg => aggregates.avg().to_string(),
+            SensorValueModifier::Max => aggregates.max().to_string(),
+            SensorValueModifier::Min => aggregates.min().to_string(),
         };
         text_format = text_format.replace("{value}", value.as_str());
     }
@@ -130,9 +1148,168 @@ fn replace_placeholders(
             text_format.replace("{unit}", get_unit(sensor_id, sensor_value_history).as_str());
     }
 
+    if text_format.contains("{label}") {
+        text_format = text_format.replace(
+            "{label}",
+            get_label(sensor_id, sensor_value_history).as_str(),
+        );
+    }
+
     text_format
 }
 
+/// Lazily computed aggregates of the numeric sensor value history of a single sensor
+/// The history is parsed on first use, each aggregate is computed on first use
+struct SensorAggregates<'a> {
+    sensor_id: &'a str,
+    sensor_value_history: &'a [Vec<SensorValue>],
+    text_config: &'a TextConfig,
+    history_aggregates: Option<&'a HistoryAggregates>,
+    numbers: OnceCell<Vec<f64>>,
+    min: OnceCell<String>,
+    max: OnceCell<String>,
+    avg: OnceCell<String>,
+    median: OnceCell<String>,
+    stddev: OnceCell<String>,
+}
+
+impl<'a> SensorAggregates<'a> {
+    fn new(
+        sensor_id: &'a str,
+        sensor_value_history: &'a [Vec<SensorValue>],
+        text_config: &'a TextConfig,
+        history_aggregates: Option<&'a HistoryAggregates>,
+    ) -> Self {
+        SensorAggregates {
+            sensor_id,
+            sensor_value_history,
+            text_config,
+            history_aggregates,
+            numbers: OnceCell::new(),
+            min: OnceCell::new(),
+            max: OnceCell::new(),
+            avg: OnceCell::new(),
+            median: OnceCell::new(),
+            stddev: OnceCell::new(),
+        }
+    }
+
+    /// Returns the numeric sensor values of the history, parsed once
+    fn numbers(&self) -> &[f64] {
+        self.numbers
+            .get_or_init(|| get_sensor_values_as_number(self.sensor_id, self.sensor_value_history))
+    }
+
+    /// Returns the aggregates precomputed by the history builder, converted to the target unit
+    /// They are only used for the sensor of the text element, which they were computed for
+    fn history_aggregates(&self) -> Option<HistoryAggregates> {
+        if self.sensor_id != self.text_config.sensor_id {
+            return None;
+        }
+        let history_aggregates = self.history_aggregates?;
+        let convert = |number: f64| match self.text_config.unit_conversion {
+            UnitConversion::None => number,
+            ref unit_conversion => round_number(
+                unit_conversion.convert_value(number),
+                9,
+                &RoundingMode::HalfEven,
+            ),
+        };
+        // The unit conversions are increasing linear functions, so the aggregates convert directly
+        Some(HistoryAggregates {
+            min: convert(history_aggregates.min),
+            max: convert(history_aggregates.max),
+            avg: convert(history_aggregates.avg),
+        })
+    }
+
+    fn number_min(&self) -> Option<f64> {
+        match self.history_aggregates() {
+            Some(history_aggregates) => Some(history_aggregates.min),
+            None => get_number_min(self.numbers()),
+        }
+    }
+
+    fn number_max(&self) -> Option<f64> {
+        match self.history_aggregates() {
+            Some(history_aggregates) => Some(history_aggregates.max),
+            None => get_number_max(self.numbers()),
+        }
+    }
+
+    fn number_avg(&self) -> Option<f64> {
+        match self.history_aggregates() {
+            Some(history_aggregates) => Some(history_aggregates.avg),
+            None => get_number_avg(self.numbers()),
+        }
+    }
+
+    fn min(&self) -> &str {
+        self.min
+            .get_or_init(|| format_aggregate(self.number_min(), self.text_config))
+    }
+
+    fn max(&self) -> &str {
+        self.max
+            .get_or_init(|| format_aggregate(self.number_max(), self.text_config))
+    }
+
+    fn avg(&self) -> &str {
+        self.avg
+            .get_or_init(|| format_aggregate(self.number_avg(), self.text_config))
+    }
+
+    fn median(&self) -> &str {
+        self.median
+            .get_or_init(|| get_value_median(self.numbers(), self.text_config))
+    }
+
+    fn stddev(&self) -> &str {
+        self.stddev
+            .get_or_init(|| get_value_stddev(self.numbers(), self.text_config))
+    }
+}
+
+/// Returns the history of the numeric values of the sensor, converted to the target unit
+/// Values of other sensors are left out, non-numeric values are kept unchanged
+fn convert_sensor_values(
+    sensor_id: &str,
+    sensor_value_history: &[Vec<SensorValue>],
+    unit_conversion: &UnitConversion,
+) -> Vec<Vec<SensorValue>> {
+    sensor_value_history
+        .iter()
+        .map(|sensor_values| {
+            sensor_values
+                .iter()
+                .filter(|sensor_value| sensor_value.id == sensor_id)
+                .map(|sensor_value| {
+                    let number = match sensor_value.sensor_type {
+                        SensorType::Number => sensor_value.value.parse::<f64>().ok(),
+                        _ => None,
+                    };
+                    match number {
+                        Some(number) => {
+                            // Rounded to drop floating point noise, e.g. of 21.3 °C in °F
+                            let value = round_number(
+                                unit_conversion.convert_value(number),
+                                9,
+                                &RoundingMode::HalfEven,
+                            );
+                            SensorValue {
+                                value: value.to_string(),
+                                unit: unit_conversion.convert_unit(&sensor_value.unit),
+                                ..sensor_value.clone()
+                            }
+                        }
+                        None => sensor_value.clone(),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
 /// Returns the sensor unit of the latest sensor value
 fn get_unit(sensor_id: &str, sensor_value_history: &[Vec<SensorValue>]) -> String {
     match get_latest_value(sensor_id, sensor_value_history) {
@@ -141,162 +1318,924 @@ fn get_unit(sensor_id: &str, sensor_value_history: &[Vec<SensorValue>]) -> Strin
     }
 }
 
-// Returns the latest sensor value
-fn get_value(sensor_id: &str, sensor_value_history: &[Vec<SensorValue>]) -> String {
+/// Returns the sensor label of the latest sensor value
+fn get_label(sensor_id: &str, sensor_value_history: &[Vec<SensorValue>]) -> String {
     match get_latest_value(sensor_id, sensor_value_history) {
-        Some(value) => value.value,
-        None => "N/A".to_string(),
+        Some(value) => value.label,
+        None => "".to_string(),
     }
 }
 
-/// Returns the minimum sensor value of all sensor values in the history
-fn get_value_min(sensor_id: &str, sensor_value_history: &[Vec<SensorValue>]) -> String {
-    let number_values_history = get_sensor_values_as_number(sensor_id, sensor_value_history);
-
-    // If there are no values, return N/A
-    if number_values_history.is_empty() {
+/// Returns the latest sensor value
+/// Values of numeric sensors are formatted like the aggregates, see `format_number`
+fn get_value(
+    sensor_id: &str,
+    sensor_value_history: &[Vec<SensorValue>],
+    text_config: &TextConfig,
+) -> String {
+    let Some(sensor_value) = get_latest_value(sensor_id, sensor_value_history) else {
         return "N/A".to_string();
+    };
+    let number = match sensor_value.sensor_type {
+        SensorType::Number => sensor_value.value.parse::<f64>().ok(),
+        _ => None,
+    };
+    match number {
+        Some(number) if number.is_finite() => format_number(number, text_config),
+        _ => sensor_value.value,
     }
+}
 
-    // Get the minimum value
-    let min = number_values_history
-        .iter()
-        .min_by(|a, b| a.partial_cmp(b).unwrap())
-        .unwrap();
+/// Returns the aggregate of the sensor values in the history, formatted for display
+/// If there are no values, N/A is returned
+fn format_aggregate(aggregate: Option<f64>, text_config: &TextConfig) -> String {
+    aggregate
+        .map(|aggregate| format_number(aggregate, text_config))
+        .unwrap_or_else(|| "N/A".to_string())
+}
 
-    format!("{:.2}", min).to_string()
+/// Returns the median sensor value of all sensor values in the history, formatted for display
+/// If there are no values, N/A is returned
+fn get_value_median(number_values_history: &[f64], text_config: &TextConfig) -> String {
+    get_number_median(number_values_history)
+        .map(|median| format_number(median, text_config))
+        .unwrap_or_else(|| "N/A".to_string())
 }
 
-/// Returns the maximum sensor value of all sensor values in the history
-fn get_value_max(sensor_id: &str, sensor_value_history: &[Vec<SensorValue>]) -> String {
-    let number_values_history = get_sensor_values_as_number(sensor_id, sensor_value_history);
+/// Returns the population standard deviation of all sensor values in the history, formatted for display
+/// If there are no values, N/A is returned
+fn get_value_stddev(number_values_history: &[f64], text_config: &TextConfig) -> String {
+    get_number_stddev(number_values_history)
+        .map(|stddev| format_number(stddev, text_config))
+        .unwrap_or_else(|| "N/A".to_string())
+}
 
-    // If there are no values, return N/A
-    if number_values_history.is_empty() {
-        return "N/A".to_string();
-    }
+/// Returns the minimum of the numbers, or None if there are no numbers
+fn get_number_min(numbers: &[f64]) -> Option<f64> {
+    numbers.iter().copied().reduce(f64::min)
+}
 
-    // Get the maximum value
-    let max = number_values_history
-        .iter()
-        .max_by(|a, b| a.partial_cmp(b).unwrap())
-        .unwrap();
+/// Returns the maximum of the numbers, or None if there are no numbers
+fn get_number_max(numbers: &[f64]) -> Option<f64> {
+    numbers.iter().copied().reduce(f64::max)
+}
 
-    format!("{:.2}", max).to_string()
+/// Returns the average of the numbers, or None if there are no numbers
+fn get_number_avg(numbers: &[f64]) -> Option<f64> {
+    if numbers.is_empty() {
+        return None;
+    }
+    Some(numbers.iter().sum::<f64>() / numbers.len() as f64)
 }
 
-/// Returns the average sensor value of all sensor values in the history
-fn get_value_avg(sensor_id: &str, sensor_value_history: &[Vec<SensorValue>]) -> String {
-    let number_values_history = get_sensor_values_as_number(sensor_id, sensor_value_history);
+/// Returns the median of the numbers, or None if there are no numbers
+/// For an even amount of numbers, the median is interpolated between the two middle numbers
+fn get_number_median(numbers: &[f64]) -> Option<f64> {
+    if numbers.is_empty() {
+        return None;
+    }
+    let mut sorted_numbers = numbers.to_vec();
+    sorted_numbers.sort_by(f64::total_cmp);
 
-    // If there are no values, return N/A
-    if number_values_history.is_empty() {
-        return "N/A".to_string();
+    let middle = sorted_numbers.len() / 2;
+    if sorted_numbers.len().is_multiple_of(2) {
+        Some((sorted_numbers[middle - 1] + sorted_numbers[middle]) / 2.0)
+    } else {
+        Some(sorted_numbers[middle])
     }
+}
 
-    let avg = number_values_history.iter().sum::<f64>() / number_values_history.len() as f64;
+/// Returns the population standard deviation of the numbers, or None if there are no numbers
+fn get_number_stddev(numbers: &[f64]) -> Option<f64> {
+    let avg = get_number_avg(numbers)?;
+    let variance = numbers
+        .iter()
+        .map(|number| (number - avg).powi(2))
+        .sum::<f64>()
+        / numbers.len() as f64;
+    Some(variance.sqrt())
+}
 
-    format!("{:.2}", avg).to_string()
+/// Formats a number for display
+/// Integer values are shown without decimals, unless decimals are forced,
+/// all other values are shown with two decimals, rounded by the rounding mode of the text config
+fn format_number(value: f64, text_config: &TextConfig) -> String {
+    let decimals = if !text_config.force_decimals && value.fract() == 0.0 {
+        0
+    } else {
+        2
+    };
+    format_decimals(value, decimals, &text_config.rounding_mode)
+}
+
+/// Formats a number with the given amount of decimals, rounded by the rounding mode
+fn format_decimals(value: f64, decimals: u32, rounding_mode: &RoundingMode) -> String {
+    let value = round_number(value, decimals, rounding_mode);
+    format!("{:.*}", decimals as usize, value)
+}
+
+/// Rounds the number to the given amount of decimals using the rounding mode
+pub fn round_number(value: f64, decimals: u32, rounding_mode: &RoundingMode) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    let scaled_value = value * factor;
+    let rounded_value = match rounding_mode {
+        RoundingMode::HalfUp => scaled_value.round(),
+        RoundingMode::HalfEven => scaled_value.round_ties_even(),
+        RoundingMode::Floor => scaled_value.floor(),
+        RoundingMode::Ceil => scaled_value.ceil(),
+    };
+    rounded_value / factor
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of times the numeric history was parsed on this thread, to verify the memoization
+    static NUMERIC_HISTORY_PARSE_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
 }
 
 fn get_sensor_values_as_number(
     sensor_id: &str,
     sensor_value_history: &[Vec<SensorValue>],
 ) -> Vec<f64> {
+    #[cfg(test)]
+    NUMERIC_HISTORY_PARSE_COUNT.with(|count| count.set(count.get() + 1));
+
     let values = sensor_value_history
         .iter()
         .flat_map(|sensor_values| sensor_values.iter().find(|&s| s.id == sensor_id))
         .filter(|sensor_value| sensor_value.sensor_type == SensorType::Number)
-        .map(|sensor_value| sensor_value.value.parse::<f64>().unwrap())
+        .filter_map(|sensor_value| sensor_value.value.parse::<f64>().ok())
         .collect::<Vec<f64>>();
     values
 }
 
+/// Selects the first conditional text range containing the latest value of the sensor
+/// Returns None if there is no numeric value for the sensor or no range contains it
+pub fn select_conditional_text<'c>(
+    conditional_text_config: &'c ConditionalTextConfig,
+    sensor_id: &str,
+    sensor_value_history: &[Vec<SensorValue>],
+) -> Option<&'c ConditionalTextRange> {
+    let value: f64 = sensor_value_history
+        .first()?
+        .iter()
+        .find(|sensor_value| sensor_value.id == sensor_id)?
+        .value
+        .parse()
+        .ok()?;
+
+    conditional_text_config.ranges.iter().find(|range| {
+        range.min_value.is_none_or(|min_value| value >= min_value)
+            && range.max_value.is_none_or(|max_value| value < max_value)
+    })
+}
+
 fn get_latest_value(
     sensor_id: &str,
     sensor_value_history: &[Vec<SensorValue>],
 ) -> Option<SensorValue> {
-    sensor_value_history[0]
+    sensor_value_history
+        .first()?
         .iter()
         .find(|&s| s.id == sensor_id)
         .cloned()
 }
 
 /// Calculates the bounding box of the text in the image
-/// This is done by detecting the first and last non-transparent pixel in each direction
+/// This is done in a single pass, by detecting the first and last non-transparent pixel of each row
+/// If the image is fully transparent, the bounding box covers the whole image
 fn get_bounding_box(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> imageproc::rect::Rect {
-    let mut min_x = 0;
-    let mut min_y = 0;
-    let mut max_x = image.width();
-    let mut max_y = image.height();
-
-    // Detect bounding box from left
-    for x in 0..image.width() {
-        let mut line_empty = true;
-        for y in 0..image.height() {
-            let pixel = image.get_pixel(x, y);
-            if pixel != &Rgba([0, 0, 0, 0]) {
-                line_empty = false;
-                break;
-            }
-        }
+    let is_visible = |pixel: &[u8]| pixel.iter().any(|channel| *channel != 0);
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
 
-        if !line_empty {
-            min_x = x;
-            break;
+    if image.width() > 0 {
+        let row_length = image.width() as usize * 4;
+        for (y, row) in image.as_raw().chunks_exact(row_length).enumerate() {
+            let mut pixels = row.chunks_exact(4);
+            let Some(first_x) = pixels.position(is_visible) else {
+                continue;
+            };
+            // The row has a visible pixel, so searching from the right always finds one
+            let last_x = pixels
+                .rposition(is_visible)
+                .map_or(first_x, |x| first_x + 1 + x);
+
+            let y = y as u32;
+            let (first_x, last_x) = (first_x as u32, last_x as u32);
+            bounds = Some(match bounds {
+                None => (first_x, y, last_x + 1, y + 1),
+                Some((min_x, min_y, max_x, _)) => {
+                    (min_x.min(first_x), min_y, max_x.max(last_x + 1), y + 1)
+                }
+            });
         }
     }
 
-    // Detect bounding box from top
-    for y in 0..image.height() {
-        let mut line_empty = true;
-        for x in 0..image.width() {
-            let pixel = image.get_pixel(x, y);
-            if pixel != &Rgba([0, 0, 0, 0]) {
-                line_empty = false;
-                break;
-            }
-        }
+    let (min_x, min_y, max_x, max_y) = bounds.unwrap_or((0, 0, image.width(), image.height()));
+    imageproc::rect::Rect::at(min_x as i32, min_y as i32).of_size(max_x - min_x, max_y - min_y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_font, visible_bounds};
+
+    /// Replaces the placeholders like `render_text`, converting and aggregating the history first
+    fn replace_placeholders(
+        text_config: &TextConfig,
+        text_format: &str,
+        sensor_id: &str,
+        sensor_value_history: &[Vec<SensorValue>],
+        history_aggregates: Option<&HistoryAggregates>,
+    ) -> String {
+        let sensor_value_history = convert_history(text_config, sensor_id, sensor_value_history);
+        let aggregates = SensorAggregates::new(
+            sensor_id,
+            &sensor_value_history,
+            text_config,
+            history_aggregates,
+        );
+        replace_placeholders_with(text_format, &aggregates)
+    }
+
+    fn number_history(sensor_id: &str, values: &[&str]) -> Vec<Vec<SensorValue>> {
+        values
+            .iter()
+            .map(|value| {
+                vec![SensorValue {
+                    id: sensor_id.to_string(),
+                    value: value.to_string(),
+                    sensor_type: SensorType::Number,
+                    ..Default::default()
+                }]
+            })
+            .collect()
+    }
+
+    fn format_value(text_config: &TextConfig, value: &str) -> String {
+        replace_placeholders(
+            text_config,
+            "{value}",
+            "cpu",
+            &number_history("cpu", &[value]),
+            None,
+        )
+    }
+
+    /// Calculates the bounding box by scanning each column and row from the edges,
+    /// like the original implementation of `get_bounding_box`
+    fn get_bounding_box_by_scanning(
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    ) -> imageproc::rect::Rect {
+        let is_visible = |x: u32, y: u32| *image.get_pixel(x, y) != Rgba([0, 0, 0, 0]);
+        let visible_columns: Vec<u32> = (0..image.width())
+            .filter(|x| (0..image.height()).any(|y| is_visible(*x, y)))
+            .collect();
+        let visible_rows: Vec<u32> = (0..image.height())
+            .filter(|y| (0..image.width()).any(|x| is_visible(x, *y)))
+            .collect();
+        let (min_x, max_x) = match (visible_columns.first(), visible_columns.last()) {
+            (Some(min_x), Some(max_x)) => (*min_x, max_x + 1),
+            _ => (0, image.width()),
+        };
+        let (min_y, max_y) = match (visible_rows.first(), visible_rows.last()) {
+            (Some(min_y), Some(max_y)) => (*min_y, max_y + 1),
+            _ => (0, image.height()),
+        };
+        imageproc::rect::Rect::at(min_x as i32, min_y as i32).of_size(max_x - min_x, max_y - min_y)
+    }
+
+    #[test]
+    fn test_integer_value_is_shown_without_decimals() {
+        let text_config = TextConfig::default();
+
+        assert_eq!(format_value(&text_config, "1500"), "1500");
+        assert_eq!(format_value(&text_config, "1500.00"), "1500");
+    }
+
+    #[test]
+    fn test_float_value_is_shown_with_two_decimals() {
+        let text_config = TextConfig::default();
+
+        assert_eq!(format_value(&text_config, "3.14159"), "3.14");
+    }
 
-        if !line_empty {
-            min_y = y - 1;
-            break;
+    #[test]
+    fn test_forced_decimals_are_shown_for_integer_value() {
+        let text_config = TextConfig {
+            force_decimals: true,
+            ..Default::default()
+        };
+
+        assert_eq!(format_value(&text_config, "1500"), "1500.00");
+    }
+
+    #[test]
+    fn test_text_value_is_shown_unchanged() {
+        let text_config = TextConfig::default();
+        let history = vec![vec![SensorValue {
+            id: "version".to_string(),
+            value: "1.10".to_string(),
+            sensor_type: SensorType::Text,
+            ..Default::default()
+        }]];
+
+        let text = replace_placeholders(&text_config, "{value}", "version", &history, None);
+
+        assert_eq!(text, "1.10");
+    }
+
+    #[test]
+    fn test_aggregate_placeholders_parse_history_once() {
+        let text_config = TextConfig::default();
+        let history = number_history("cpu", &["10", "30", "20"]);
+        NUMERIC_HISTORY_PARSE_COUNT.with(|count| count.set(0));
+
+        let text = replace_placeholders(
+            &text_config,
+            "{value-min} {value-max} {value-avg}",
+            "cpu",
+            &history,
+            None,
+        );
+
+        assert_eq!(text, "10 30 20");
+        assert_eq!(NUMERIC_HISTORY_PARSE_COUNT.with(|count| count.get()), 1);
+    }
+
+    #[test]
+    fn test_aggregate_placeholders_use_history_aggregates() {
+        let text_config = TextConfig {
+            sensor_id: "cpu".to_string(),
+            unit_conversion: UnitConversion::CelsiusToFahrenheit,
+            ..Default::default()
+        };
+        let history_aggregates = HistoryAggregates {
+            min: 0.0,
+            max: 100.0,
+            avg: 50.0,
+        };
+        let history = number_history("cpu", &["10", "30", "20"]);
+        NUMERIC_HISTORY_PARSE_COUNT.with(|count| count.set(0));
+
+        let text = replace_placeholders(
+            &text_config,
+            "{value-min} {value-max} {value-avg:.1}",
+            "cpu",
+            &history,
+            Some(&history_aggregates),
+        );
+
+        assert_eq!(text, "32 212 122.0");
+        assert_eq!(NUMERIC_HISTORY_PARSE_COUNT.with(|count| count.get()), 0);
+    }
+
+    #[test]
+    fn test_vertical_text_is_taller_than_wide() {
+        let font = load_font();
+        let text_config = TextConfig {
+            format: "Temperature".to_string(),
+            font_size: 16,
+            font_color: "#FFFFFF".to_string(),
+            width: 40,
+            height: 200,
+            orientation: TextOrientation::Vertical,
+            ..Default::default()
+        };
+
+        let image = render(40, 200, &text_config, &[], &font);
+
+        let (min_x, min_y, max_x, max_y) = visible_bounds(&image).unwrap();
+        assert!(max_y - min_y > 2 * (max_x - min_x));
+    }
+
+    #[test]
+    fn test_kerning_narrows_kern_sensitive_pairs() {
+        let font = load_font();
+        let scale = Scale::uniform(48.0);
+
+        let kerned_width = measure_glyphs(&layout_glyphs(&font, scale, "AV", true)).0;
+        let unkerned_width = measure_glyphs(&layout_glyphs(&font, scale, "AV", false)).0;
+
+        assert!(kerned_width < unkerned_width);
+    }
+
+    #[test]
+    fn test_text_render_does_not_depend_on_the_display_size() {
+        let font = load_font();
+        let text_config = TextConfig {
+            format: "42 °C".to_string(),
+            font_size: 20,
+            font_color: "#FFFFFF".to_string(),
+            width: 100,
+            height: 30,
+            alignment: TextAlign::Center,
+            ..Default::default()
+        };
+
+        let small_display_image = render(100, 30, &text_config, &[], &font);
+        let large_display_image = render(1920, 480, &text_config, &[], &font);
+
+        assert!(visible_bounds(&small_display_image).is_some());
+        assert!(small_display_image == large_display_image);
+    }
+
+    #[test]
+    fn test_known_format_has_no_unknown_placeholders() {
+        let format = "{label}: {value} {unit} (avg {value-avg:.1}, max {value-max})";
+
+        assert!(validate_format(format).is_empty());
+        for placeholder in ["{value}", "{unit}", "{label}", "{value-avg}"] {
+            assert!(supported_placeholders().contains(&placeholder));
         }
     }
 
-    // Detect bounding box from right
-    for x in (0..image.width()).rev() {
-        let mut line_empty = true;
-        for y in (0..image.height()).rev() {
-            let pixel = image.get_pixel(x, y);
-            if pixel != &Rgba([0, 0, 0, 0]) {
-                line_empty = false;
-                break;
-            }
+    #[test]
+    fn test_misspelled_placeholder_is_flagged() {
+        let format = "{value} {unti} {value-avr:.1}";
+
+        assert_eq!(validate_format(format), ["{unti}", "{value-avr:.1}"]);
+    }
+
+    #[test]
+    fn test_styled_unit_is_smaller_and_raised() {
+        let font = load_font();
+        let runs = [
+            TextRun::new("40", Scale::uniform(40.0), 0.0),
+            TextRun::new("C", Scale::uniform(20.0), 10.0),
+        ];
+
+        let glyphs = layout_runs(&font, &runs, true);
+
+        let value_bounds = glyphs[0].pixel_bounding_box().unwrap();
+        let unit_bounds = glyphs[2].pixel_bounding_box().unwrap();
+        assert!(unit_bounds.height() < value_bounds.height());
+        assert!(unit_bounds.max.y < value_bounds.max.y - 5);
+        assert!(unit_bounds.min.x >= glyphs[1].pixel_bounding_box().unwrap().max.x);
+    }
+
+    #[test]
+    fn test_rounding_mode_decides_halfway_values() {
+        let format_rounded = |rounding_mode: RoundingMode| {
+            let text_config = TextConfig {
+                rounding_mode,
+                ..Default::default()
+            };
+            replace_placeholders(
+                &text_config,
+                "{value:.0}",
+                "cpu",
+                &number_history("cpu", &["2.5"]),
+                None,
+            )
+        };
+
+        assert_eq!(round_number(2.5, 0, &RoundingMode::HalfUp), 3.0);
+        assert_eq!(round_number(2.5, 0, &RoundingMode::HalfEven), 2.0);
+        assert_eq!(format_rounded(RoundingMode::HalfUp), "3");
+        assert_eq!(format_rounded(RoundingMode::HalfEven), "2");
+    }
+
+    #[test]
+    fn test_glyph_is_rendered_cropped_to_its_bounds() {
+        let font = load_font();
+        let color = Rgba([0, 128, 255, 255]);
+
+        let image = render_glyph('A', &font, 32, color);
+        let space_image = render_glyph(' ', &font, 32, color);
+
+        let bounding_box = font
+            .glyph('A')
+            .scaled(Scale::uniform(32.0))
+            .positioned(point(0.0, 0.0))
+            .pixel_bounding_box()
+            .unwrap();
+        assert_eq!(
+            image.dimensions(),
+            (bounding_box.width() as u32, bounding_box.height() as u32)
+        );
+        // The glyph touches every edge of the cropped image
+        assert_eq!(
+            visible_bounds(&image),
+            Some((0, 0, image.width() - 1, image.height() - 1))
+        );
+        assert!(image
+            .pixels()
+            .all(|pixel| pixel[3] == 0 || pixel.0[..3] == color.0[..3]));
+        assert!(image.pixels().any(|pixel| pixel[3] == 255));
+        assert_eq!(space_image.dimensions(), (0, 0));
+    }
+
+    #[test]
+    fn test_large_glyph_in_narrow_element_is_not_clipped() {
+        let font = load_font();
+        let text_config = TextConfig {
+            format: "W".to_string(),
+            font_size: 48,
+            font_color: "#FFFFFF".to_string(),
+            width: 50,
+            height: 60,
+            ..Default::default()
+        };
+
+        let image = render(50, 60, &text_config, &number_history("cpu", &["1"]), &font);
+
+        let glyph = render_glyph('W', &font, 48, Rgba([255, 255, 255, 255]));
+        let (min_x, _, max_x, _) = visible_bounds(&image).unwrap();
+        assert!(glyph.width() <= 50);
+        assert_eq!(max_x - min_x + 1, glyph.width());
+    }
+
+    #[test]
+    fn test_bounding_box_of_glyph_touching_the_top_row() {
+        let glyph = render_glyph('A', &load_font(), 24, Rgba([255, 255, 255, 255]));
+        let mut image = ImageBuffer::new(glyph.width() + 10, glyph.height() + 10);
+        image::imageops::overlay(&mut image, &glyph, 3, 0);
+
+        let bounding_box = get_bounding_box(&image);
+
+        assert_eq!((bounding_box.left(), bounding_box.top()), (3, 0));
+        assert_eq!(
+            (bounding_box.width(), bounding_box.height()),
+            glyph.dimensions()
+        );
+    }
+
+    #[test]
+    fn test_bounding_box_matches_scanning_each_column_and_row() {
+        let font = load_font();
+        for (text, x, y) in [
+            ("42", 5, 3),
+            ("Hello World", 0, 0),
+            ("-12.5 °C", 17, 9),
+            ("gjpqy", 2, 20),
+            ("", 0, 0),
+        ] {
+            let mut image = ImageBuffer::new(160, 50);
+            draw_text_mut(
+                &mut image,
+                Rgba([255, 255, 255, 255]),
+                x,
+                y,
+                Scale::uniform(20.0),
+                &font,
+                text,
+            );
+
+            assert_eq!(
+                get_bounding_box(&image),
+                get_bounding_box_by_scanning(&image),
+                "text {:?}",
+                text
+            );
         }
+    }
+
+    #[test]
+    fn test_precision_spec_formats_the_decimals() {
+        let text_config = TextConfig::default();
+        let text_history = vec![vec![SensorValue {
+            id: "cpu".to_string(),
+            value: "idle".to_string(),
+            sensor_type: SensorType::Text,
+            ..Default::default()
+        }]];
+        let format = |format: &str, history: &[Vec<SensorValue>]| {
+            replace_placeholders(&text_config, format, "cpu", history, None)
+        };
+        let number_history = number_history("cpu", &["3.14159"]);
+
+        assert_eq!(format("{value:.0}", &number_history), "3");
+        assert_eq!(format("{value:.3}", &number_history), "3.142");
+        // Values of text sensors are inserted unchanged
+        assert_eq!(format("{value:.2} mode", &text_history), "idle mode");
+    }
+
+    #[test]
+    fn test_glyph_cache_renders_like_the_uncached_path() {
+        let font = load_font();
+        let glyph_cache = GlyphCache::new();
+        let text_config = TextConfig {
+            format: "{value} °C".to_string(),
+            font_size: 24,
+            font_color: "#FF8000".to_string(),
+            width: 150,
+            height: 40,
+            ..Default::default()
+        };
+
+        for value in ["21.5", "22.25", "21.5"] {
+            let history = number_history("cpu", &[value]);
+            let text_config = TextConfig {
+                sensor_id: "cpu".to_string(),
+                ..text_config.clone()
+            };
 
-        if !line_empty {
-            max_x = x + 1;
-            break;
+            let image = render(150, 40, &text_config, &history, &font);
+            let cached_image =
+                render_with_glyph_cache(150, 40, &text_config, &history, &font, &glyph_cache);
+
+            assert!(cached_image == image, "value {}", value);
         }
+        assert!(!glyph_cache.is_empty());
+    }
+
+    #[test]
+    fn test_decimal_points_of_different_numbers_line_up() {
+        let font = load_font();
+        let text_config = TextConfig {
+            sensor_id: "cpu".to_string(),
+            format: "{value}".to_string(),
+            font_size: 20,
+            font_color: "#FFFFFF".to_string(),
+            width: 120,
+            height: 30,
+            decimal_point_x: Some(60),
+            ..Default::default()
+        };
+        let render_value = |value: &str| {
+            render(
+                120,
+                30,
+                &text_config,
+                &number_history("cpu", &[value]),
+                &font,
+            )
+        };
+
+        let short_image = render_value("3.5");
+        let long_image = render_value("123.5");
+
+        // Both end in ".50", so the text right of the decimal point ends at the same x,
+        // give or take a pixel for the sub-pixel position of the glyphs
+        let (short_min_x, _, short_max_x, _) = visible_bounds(&short_image).unwrap();
+        let (long_min_x, _, long_max_x, _) = visible_bounds(&long_image).unwrap();
+        assert!(short_max_x > 60 && short_max_x.abs_diff(long_max_x) <= 1);
+        assert!(short_min_x < 60 && long_min_x + 10 < short_min_x);
+    }
+
+    #[test]
+    fn test_unit_conversion_converts_the_value_and_relabels_the_unit() {
+        let format_converted = |unit_conversion: UnitConversion, value: &str, unit: &str| {
+            let text_config = TextConfig {
+                unit_conversion,
+                ..Default::default()
+            };
+            let history = vec![vec![SensorValue {
+                id: "sensor".to_string(),
+                value: value.to_string(),
+                unit: unit.to_string(),
+                sensor_type: SensorType::Number,
+                ..Default::default()
+            }]];
+            replace_placeholders(&text_config, "{value} {unit}", "sensor", &history, None)
+        };
+
+        assert_eq!(
+            format_converted(UnitConversion::CelsiusToFahrenheit, "100", "°C"),
+            "212 °F"
+        );
+        assert_eq!(
+            format_converted(UnitConversion::CelsiusToFahrenheit, "21.3", "°C"),
+            "70.34 °F"
+        );
+        assert_eq!(
+            format_converted(UnitConversion::BytesToMegabits, "12500000", "B/s"),
+            "100 Mbit/s"
+        );
+        assert_eq!(
+            format_converted(UnitConversion::BytesToMegabytes, "2500000", "B"),
+            "2.50 MB"
+        );
+    }
+
+    #[test]
+    fn test_unchanged_text_reuses_the_cached_render() {
+        let font = load_font();
+        let text_render_cache = TextRenderCache::new();
+        let text_config = TextConfig {
+            sensor_id: "cpu".to_string(),
+            format: "{value} %".to_string(),
+            font_size: 20,
+            font_color: "#FFFFFF".to_string(),
+            width: 100,
+            height: 30,
+            ..Default::default()
+        };
+        let render_cached = |value: &str| {
+            render_with_cache(
+                "text",
+                (100, 30),
+                &text_config,
+                &number_history("cpu", &[value]),
+                None,
+                &font,
+                None,
+                &text_render_cache,
+            )
+        };
+
+        let image = render_cached("42");
+        let cached_image = render_cached("42");
+        assert_eq!(text_render_cache.render_count(), 1);
+        assert!(cached_image == image);
+
+        let changed_image = render_cached("43");
+        assert_eq!(text_render_cache.render_count(), 2);
+        assert!(changed_image != image);
+        assert_eq!(text_render_cache.len(), 1);
     }
 
-    // Detect bounding box from bottom
-    for y in (0..image.height()).rev() {
-        let mut line_empty = true;
-        for x in (0..image.width()).rev() {
-            let pixel = image.get_pixel(x, y);
-            if pixel != &Rgba([0, 0, 0, 0]) {
-                line_empty = false;
-                break;
+    #[test]
+    fn test_older_history_only_invalidates_cached_texts_with_aggregates() {
+        let font = load_font();
+        let text_render_cache = TextRenderCache::new();
+        let text_config = |format: &str| TextConfig {
+            sensor_id: "cpu".to_string(),
+            format: format.to_string(),
+            font_size: 20,
+            font_color: "#FFFFFF".to_string(),
+            width: 100,
+            height: 30,
+            ..Default::default()
+        };
+        let value_config = text_config("{value}");
+        let max_config = text_config("{value-max}");
+        let render_cached = |element_id: &str, text_config: &TextConfig, values: &[&str]| {
+            render_with_cache(
+                element_id,
+                (100, 30),
+                text_config,
+                &number_history("cpu", values),
+                None,
+                &font,
+                None,
+                &text_render_cache,
+            );
+        };
+
+        render_cached("value", &value_config, &["42", "10"]);
+        render_cached("max", &max_config, &["42", "10"]);
+        assert_eq!(text_render_cache.render_count(), 2);
+
+        // Only the older values changed, the latest value is the same
+        render_cached("value", &value_config, &["42", "90"]);
+        assert_eq!(text_render_cache.render_count(), 2);
+        render_cached("max", &max_config, &["42", "90"]);
+        assert_eq!(text_render_cache.render_count(), 3);
+    }
+
+    #[test]
+    fn test_styled_unit_parses_the_history_once() {
+        let font = load_font();
+        let text_config = TextConfig {
+            sensor_id: "cpu".to_string(),
+            format: "{value-min} {unit} {value-max}".to_string(),
+            font_size: 20,
+            font_color: "#FFFFFF".to_string(),
+            width: 200,
+            height: 30,
+            unit_conversion: UnitConversion::CelsiusToFahrenheit,
+            unit_style: Some(crate::UnitStyle {
+                font_scale: 0.5,
+                baseline_offset: 0.0,
+            }),
+            ..Default::default()
+        };
+        let history = number_history("cpu", &["10", "30", "20"]);
+        NUMERIC_HISTORY_PARSE_COUNT.with(|count| count.set(0));
+
+        let image = render(200, 30, &text_config, &history, &font);
+
+        assert!(visible_bounds(&image).is_some());
+        assert_eq!(NUMERIC_HISTORY_PARSE_COUNT.with(|count| count.get()), 1);
+    }
+
+    /// Returns the width of each line of the laid out glyphs, from top to bottom
+    fn line_widths(glyphs: &[PositionedGlyph]) -> Vec<u32> {
+        let mut lines = std::collections::BTreeMap::new();
+        for glyph in glyphs {
+            let line_width = lines.entry(glyph.position().y.round() as i32).or_insert(0);
+            if let Some(bounding_box) = glyph.pixel_bounding_box() {
+                *line_width = bounding_box.max.x.max(*line_width);
             }
         }
+        lines
+            .into_values()
+            .map(|line_width| line_width as u32)
+            .collect()
+    }
 
-        if !line_empty {
-            max_y = y + 1;
-            break;
-        }
+    #[test]
+    fn test_wrapped_text_fits_the_line_width() {
+        let font = load_font();
+        let glyphs = layout_wrapped_glyphs(
+            &font,
+            Scale::uniform(20.0),
+            "one two three four five",
+            80,
+            &TextAlign::Left,
+        );
+
+        let line_widths = line_widths(&glyphs);
+        assert!(line_widths.len() >= 3, "{:?}", line_widths);
+        assert!(
+            line_widths.iter().all(|&width| width <= 80),
+            "{:?}",
+            line_widths
+        );
     }
 
-    imageproc::rect::Rect::at(min_x as i32, min_y as i32).of_size(max_x - min_x, max_y - min_y)
+    #[test]
+    fn test_wrapped_text_breaks_at_explicit_line_breaks() {
+        let font = load_font();
+        let glyphs = layout_wrapped_glyphs(
+            &font,
+            Scale::uniform(20.0),
+            "one\ntwo three\nfour",
+            200,
+            &TextAlign::Left,
+        );
+
+        let line_widths = line_widths(&glyphs);
+        assert_eq!(line_widths.len(), 3, "{:?}", line_widths);
+        assert!(line_widths.iter().all(|&width| width <= 200));
+    }
+
+    #[test]
+    fn test_styled_unit_is_wrapped_with_the_text() {
+        let font = load_font();
+        let text_config = TextConfig {
+            sensor_id: "cpu".to_string(),
+            format: "CPU load\n{value} {unit} now".to_string(),
+            font_size: 20,
+            font_color: "#FFFFFF".to_string(),
+            width: 70,
+            height: 100,
+            wrap: true,
+            unit_style: Some(crate::UnitStyle {
+                font_scale: 0.5,
+                baseline_offset: 0.0,
+            }),
+            ..Default::default()
+        };
+        let unit_scale = Scale::uniform(10.0);
+        let runs = [
+            TextRun::new("CPU load\n42 ", Scale::uniform(20.0), 0.0),
+            TextRun::new("%", unit_scale, 0.0),
+            TextRun::new(" now", Scale::uniform(20.0), 0.0),
+        ];
+
+        let glyphs = layout_wrapped_runs(&font, &runs, text_config.width, &TextAlign::Left);
+
+        let line_widths = line_widths(&glyphs);
+        assert_eq!(line_widths.len(), 4, "{:?}", line_widths);
+        assert!(
+            line_widths.iter().all(|&width| width <= text_config.width),
+            "{:?}",
+            line_widths
+        );
+        let unit_glyph = glyphs
+            .iter()
+            .find(|glyph| glyph.scale() == unit_scale)
+            .unwrap();
+        assert!(unit_glyph.position().y > glyphs[0].position().y);
+
+        let mut history = number_history("cpu", &["42"]);
+        history[0][0].unit = "%".to_string();
+        let image = render(70, 100, &text_config, &history, &font);
+        let (min_x, min_y, max_x, max_y) = visible_bounds(&image).unwrap();
+        assert!(max_x - min_x <= text_config.width);
+        // The text is stacked in several lines
+        assert!(max_y - min_y > 40, "{:?}", (min_y, max_y));
+    }
+
+    #[test]
+    fn test_vertical_alignment_places_the_cropped_text() {
+        let font = load_font();
+        let history = number_history("cpu", &["42"]);
+        let render_aligned = |vertical_alignment: VerticalAlign| {
+            let text_config = TextConfig {
+                sensor_id: "cpu".to_string(),
+                format: "{value}".to_string(),
+                font_size: 20,
+                font_color: "#FFFFFF".to_string(),
+                width: 100,
+                height: 60,
+                vertical_alignment,
+                ..Default::default()
+            };
+            let image = render(100, 60, &text_config, &history, &font);
+            let (_, min_y, _, max_y) = visible_bounds(&image).unwrap();
+            let free_height = text_config.height - (max_y - min_y + 1);
+            (min_y, free_height)
+        };
+
+        let (top_y, free_height) = render_aligned(VerticalAlign::Top);
+        assert_eq!(top_y, 0);
+        let (middle_y, _) = render_aligned(VerticalAlign::Middle);
+        assert_eq!(middle_y, free_height / 2);
+        let (bottom_y, _) = render_aligned(VerticalAlign::Bottom);
+        assert_eq!(bottom_y, free_height);
+    }
 }